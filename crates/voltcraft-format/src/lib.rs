@@ -0,0 +1,365 @@
+// Byte-level decoding of the logger files, on core alone so that it runs without std (e.g. on a
+// microcontroller reading the SD card of the logger); dating the readings is left to the caller.
+// The analyzer re-exports it as voltcraft::raw.
+#![cfg_attr(not(test), no_std)]
+use core::fmt;
+
+pub const MAGIC_NUMBER: [u8; 3] = [0xE0, 0xC5, 0xEA];
+pub const END_OF_DATA: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+pub const BLOCK_HEADER_SIZE: usize = 8; // magic number and start time
+pub const READING_SIZE: usize = 5;
+
+// Reasons the bytes of a data file cannot be decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RawError {
+    InvalidHeader,
+    UnexpectedEof { offset: usize },
+    InvalidTimestamp { offset: usize },
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawError::InvalidHeader => write!(f, "invalid data file header"),
+            RawError::UnexpectedEof { offset } => {
+                write!(f, "unexpected end of data at offset {}", offset)
+            }
+            RawError::InvalidTimestamp { offset } => {
+                write!(f, "invalid timestamp at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl core::error::Error for RawError {}
+
+// The start time of a data block as shown by the logger clock, which knows no time zone
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClockTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl ClockTime {
+    // Month, day, year (since 2000), hour and minute, as stored in a block header
+    fn decode([month, day, year, hour, minute]: [u8; 5]) -> Self {
+        ClockTime {
+            year: 2000 + year as u16,
+            month,
+            day,
+            hour,
+            minute,
+        }
+    }
+
+    // Whether the clock can show this time (a corrupted header usually can't)
+    pub fn is_valid(&self) -> bool {
+        let leap = self.year.is_multiple_of(4)
+            && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400));
+        let days = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return false,
+        };
+        (1..=days).contains(&self.day) && self.hour < 24 && self.minute < 60
+    }
+}
+
+// A reading as stored: the power is computed from the voltage, current and power factor
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Reading {
+    pub voltage: f64,      // volts
+    pub current: f64,      // ampers
+    pub power_factor: f64, // cos phi
+}
+
+// The values of a reading exactly as stored, in the units of the file format
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    pub voltage: u16,     // V/10
+    pub current: u16,     // mA
+    pub power_factor: u8, // 1/100
+}
+
+impl Registers {
+    // Voltage and current, both big endian, and power factor
+    pub fn decode([v_hi, v_lo, c_hi, c_lo, power_factor]: [u8; READING_SIZE]) -> Self {
+        Registers {
+            voltage: u16::from_be_bytes([v_hi, v_lo]),
+            current: u16::from_be_bytes([c_hi, c_lo]),
+            power_factor,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; READING_SIZE] {
+        let [v_hi, v_lo] = self.voltage.to_be_bytes();
+        let [c_hi, c_lo] = self.current.to_be_bytes();
+        [v_hi, v_lo, c_hi, c_lo, self.power_factor]
+    }
+
+    // The reading they stand for, scaled
+    pub fn reading(&self) -> Reading {
+        Reading {
+            voltage: self.voltage as f64 / 10.0,
+            current: self.current as f64 / 1000.0,
+            power_factor: self.power_factor as f64 / 100.0,
+        }
+    }
+}
+
+impl Reading {
+    // Active power (kW)
+    pub fn power(&self) -> f64 {
+        self.voltage * self.current * self.power_factor / 1000.0
+    }
+
+    // Apparent power (kVA)
+    pub fn apparent_power(&self) -> f64 {
+        self.voltage * self.current / 1000.0
+    }
+}
+
+// A region of the data that could not be decoded and was skipped
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RawDiagnostic {
+    pub offset: usize,   // where decoding failed
+    pub skipped: usize,  // bytes skipped until the next data block (or the end of the data)
+    pub error: RawError, // why decoding failed
+}
+
+// What the data holds at an offset, in file order
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Record {
+    Block {
+        offset: usize,
+        clock: ClockTime,
+    }, // header of a data block, dating the readings after it
+    // One sample interval after the previous one, scaled and as stored
+    Reading {
+        offset: usize,
+        reading: Reading,
+        registers: Registers,
+    },
+    Skipped(RawDiagnostic), // corrupted data (lenient mode only) or an incomplete last record
+}
+
+/// Iterator over the records of a data file, without dating the readings. Errors end the iteration
+/// unless it is lenient, in which case corrupted data is skipped up to the next data block. A file
+/// copied while the logger was recording lacks the end of data marker and may end within a record:
+/// the iteration then ends after skipping that record.
+///
+/// ```
+/// use voltcraft_format::{Record, Records};
+///
+/// // A data block started on 2014-09-11 18:43, with one reading (224.6V, 0.446A, cos phi 0.87)
+/// let file = [
+///     0xE0, 0xC5, 0xEA, 0x09, 0x0B, 0x0E, 0x12, 0x2B, 0x08, 0xC6, 0x01, 0xBE, 0x57, 0xFF, 0xFF,
+///     0xFF, 0xFF,
+/// ];
+/// let mut records = Records::new(&file, false);
+/// let Some(Ok(Record::Block { clock, .. })) = records.next() else {
+///     panic!("no data block");
+/// };
+/// assert_eq!((clock.year, clock.month, clock.day, clock.hour), (2014, 9, 11, 18));
+/// let Some(Ok(Record::Reading { reading, .. })) = records.next() else {
+///     panic!("no reading");
+/// };
+/// assert_eq!(reading.voltage, 224.6);
+/// assert!(records.next().is_none() && !records.missing_end_marker());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    data: &'a [u8],
+    offset: usize,
+    lenient: bool,
+    missing_end_marker: bool,
+    finished: bool,
+}
+
+impl<'a> Records<'a> {
+    pub fn new(data: &'a [u8], lenient: bool) -> Self {
+        Records {
+            data,
+            offset: 0,
+            lenient,
+            missing_end_marker: false,
+            finished: false,
+        }
+    }
+
+    // Whether the data ended without the end of data marker (known once the iteration is over)
+    pub fn missing_end_marker(&self) -> bool {
+        self.missing_end_marker
+    }
+
+    // Fail unless lenient, otherwise skip the data from the given offset up to the next data
+    // block (e.g. a block whose start time the caller cannot date), ending the iteration if there
+    // is none
+    pub fn resync(&mut self, offset: usize, error: RawError) -> Result<RawDiagnostic, RawError> {
+        if !self.lenient {
+            self.finished = true;
+            return Err(error);
+        }
+        let next = (offset + 1..self.data.len()).find(|&o| self.is_datablock(o));
+        match next {
+            Some(next) => self.offset = next,
+            None => self.finished = true,
+        }
+        Ok(RawDiagnostic {
+            offset,
+            skipped: next.unwrap_or(self.data.len()) - offset,
+            error,
+        })
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>, RawError> {
+        // The data block header should be at the beginning of the file
+        if self.offset == 0 && !self.is_datablock(0) {
+            self.finished = true;
+            return Err(RawError::InvalidHeader);
+        }
+        let offset = self.offset;
+        if self.bytes::<4>(offset) == Some(END_OF_DATA) {
+            self.finished = true;
+            return Ok(None);
+        }
+        let is_datablock = self.is_datablock(offset);
+        let size = if is_datablock {
+            BLOCK_HEADER_SIZE
+        } else {
+            READING_SIZE
+        };
+        let remaining = self.data.len().saturating_sub(offset);
+        if remaining < size {
+            self.finished = true;
+            self.missing_end_marker = true;
+            return Ok((remaining > 0).then_some(Record::Skipped(RawDiagnostic {
+                offset,
+                skipped: remaining,
+                error: RawError::UnexpectedEof { offset },
+            })));
+        }
+        self.offset += size;
+        if !is_datablock {
+            let registers = Registers::decode(self.bytes(offset).unwrap_or_default());
+            return Ok(Some(Record::Reading {
+                offset,
+                reading: registers.reading(),
+                registers,
+            }));
+        }
+        let clock = ClockTime::decode(self.bytes(offset + 3).unwrap_or_default());
+        if clock.is_valid() {
+            return Ok(Some(Record::Block { offset, clock }));
+        }
+        // The readings of the block can't be dated, so skip the whole block
+        let error = RawError::InvalidTimestamp { offset: offset + 3 };
+        self.resync(offset, error).map(|d| Some(Record::Skipped(d)))
+    }
+
+    // The bytes at the given offset, if the data doesn't end before
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.data.get(offset..offset + N)?.try_into().ok()
+    }
+
+    fn is_datablock(&self, offset: usize) -> bool {
+        self.bytes::<3>(offset) == Some(MAGIC_NUMBER)
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<Record, RawError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_time_validity() {
+        let clock = |year, month, day, hour, minute| ClockTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+        };
+        assert!(clock(2014, 9, 11, 18, 43).is_valid());
+        assert!(clock(2016, 2, 29, 0, 0).is_valid());
+        assert!(clock(2000, 2, 29, 23, 59).is_valid());
+        assert!(!clock(2100, 2, 29, 0, 0).is_valid());
+        assert!(!clock(2014, 13, 11, 18, 43).is_valid());
+        assert!(!clock(2014, 4, 31, 18, 43).is_valid());
+        assert!(!clock(2014, 9, 0, 18, 43).is_valid());
+        assert!(!clock(2014, 9, 11, 24, 0).is_valid());
+        assert!(!clock(2014, 9, 11, 18, 60).is_valid());
+    }
+
+    #[test]
+    fn raw_records() {
+        // A block with a corrupted start time (month 13) followed by an intact one, cut within
+        // its reading
+        let data = [
+            0xE0, 0xC5, 0xEA, 0x0D, 0x0B, 0x0E, 0x12, 0x2B, 0x08, 0xC6, 0x01, 0xBE, 0x57, //
+            0xE0, 0xC5, 0xEA, 0x09, 0x0B, 0x0E, 0x12, 0x2B, 0x08, 0xC6, 0x01, 0xBE, 0x57, //
+            0x08, 0xC6,
+        ];
+        let mut strict = Records::new(&data, false);
+        assert_eq!(
+            strict.next(),
+            Some(Err(RawError::InvalidTimestamp { offset: 3 }))
+        );
+        assert_eq!(strict.next(), None);
+
+        let mut lenient = Records::new(&data, true);
+        let skipped = |offset, skipped, error| {
+            Some(Ok(Record::Skipped(RawDiagnostic {
+                offset,
+                skipped,
+                error,
+            })))
+        };
+        assert_eq!(
+            lenient.next(),
+            skipped(0, 13, RawError::InvalidTimestamp { offset: 3 })
+        );
+        assert!(matches!(
+            lenient.next(),
+            Some(Ok(Record::Block { offset: 13, .. }))
+        ));
+        let Some(Ok(Record::Reading {
+            offset,
+            reading,
+            registers,
+        })) = lenient.next()
+        else {
+            panic!("no reading");
+        };
+        assert_eq!(
+            (offset, reading.voltage, reading.current),
+            (21, 224.6, 0.446)
+        );
+        assert_eq!((registers.voltage, registers.power_factor), (2246, 87));
+        assert_eq!(registers.encode(), data[21..26]);
+        assert!((reading.power() - 224.6 * 0.446 * 0.87 / 1000.0).abs() < 1e-12);
+        assert_eq!(
+            lenient.next(),
+            skipped(26, 2, RawError::UnexpectedEof { offset: 26 })
+        );
+        assert_eq!(lenient.next(), None);
+        assert!(lenient.missing_end_marker());
+    }
+}
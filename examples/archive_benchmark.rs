@@ -0,0 +1,64 @@
+// Measure the archive format on a folder: its size against the data files and the decoded
+// readings, the time to encode and decode it, and that it decodes to the same readings:
+// cargo run --release --example archive_benchmark [folder]
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use voltcraft_energy_analyzer::voltcraft::archive;
+use voltcraft_energy_analyzer::voltcraft::data::PowerEvent;
+use voltcraft_energy_analyzer::voltcraft::dataset::VoltcraftDataset;
+
+const ROUNDS: u32 = 20;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let folder = env::args_os().nth(1).map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_data1"),
+        PathBuf::from,
+    );
+    let dataset = VoltcraftDataset::from_dir(&folder)?;
+    let events = dataset.events;
+    let mut file_bytes = 0;
+    for entry in fs::read_dir(&folder)? {
+        file_bytes += entry?.metadata()?.len() as usize;
+    }
+
+    let start = Instant::now();
+    let mut bytes = Vec::new();
+    for _ in 0..ROUNDS {
+        bytes = archive::encode(&events);
+    }
+    let encoding = start.elapsed() / ROUNDS;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        archive::decode(&bytes)?;
+    }
+    let decoding = start.elapsed() / ROUNDS;
+    archive::verify(&events, &bytes)?;
+
+    let memory = events.len() * size_of::<PowerEvent>();
+    println!(
+        "{} readings, archived in {} bytes",
+        events.len(),
+        bytes.len()
+    );
+    println!(
+        "{:.2} bytes a reading: {:.1}x smaller than the data files ({} bytes), {:.1}x smaller than the readings in memory ({} bytes)",
+        bytes.len() as f64 / events.len() as f64,
+        file_bytes as f64 / bytes.len() as f64,
+        file_bytes,
+        memory as f64 / bytes.len() as f64,
+        memory
+    );
+    println!(
+        "Encoding: {:.1} ms ({:.0} readings/s), decoding: {:.1} ms ({:.0} readings/s)",
+        encoding.as_secs_f64() * 1000.0,
+        events.len() as f64 / encoding.as_secs_f64(),
+        decoding.as_secs_f64() * 1000.0,
+        events.len() as f64 / decoding.as_secs_f64()
+    );
+    println!("Verified: the archive decodes to the same readings");
+    Ok(())
+}
@@ -0,0 +1,39 @@
+// Assemble a pipeline of one's own: the readings of a folder, only those at night, smoothed over
+// a quarter of an hour and written as CSV (to the temporary folder by default), then the energy
+// they account for:
+// cargo run --example custom_pipeline [folder] [output.csv]
+use chrono::{Duration, Timelike};
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use voltcraft_energy_analyzer::voltcraft::pipeline::{
+    CsvSink, Deadband, DirSource, Filter, Pipeline, Smooth,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args_os().skip(1);
+    let folder = args.next().map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_data1"),
+        PathBuf::from,
+    );
+    let output = args.next().map_or_else(
+        || env::temp_dir().join("voltcraft_night.csv"),
+        PathBuf::from,
+    );
+
+    let events = Pipeline::new()
+        .transform(Deadband(0.002))
+        .transform(Filter(|e| e.timestamp.hour() < 6))
+        .transform(Smooth(Duration::minutes(15)))
+        .sink(CsvSink::create(&output)?)
+        .run(DirSource::new(&folder))?;
+
+    let energy = events.iter().map(|e| e.power).sum::<f64>() / 60.0;
+    println!(
+        "{} night readings written to {} ({:.3} kWh)",
+        events.len(),
+        output.display(),
+        energy
+    );
+    Ok(())
+}
@@ -0,0 +1,38 @@
+// Price the consumption of a data file on a day/night tariff, one line per day:
+// cargo run --example custom_tariff_cost [file]
+use chrono::Timelike;
+use itertools::Itertools;
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use voltcraft_energy_analyzer::voltcraft::data::VoltcraftData;
+
+const DAY_RATE: f64 = 0.25; // per kWh, 07:00 to 23:00
+const NIGHT_RATE: f64 = 0.12; // per kWh, 23:00 to 07:00
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let file = env::args_os().nth(1).map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_data1/A04FC8D2.BIN"),
+        PathBuf::from,
+    );
+    let (events, _) = VoltcraftData::from_file(&file, 1024 * 1024)?.parse()?;
+
+    let mut total = 0.0;
+    for (date, day) in &events.iter().group_by(|e| e.timestamp.date_naive()) {
+        let (mut energy, mut cost) = (0.0, 0.0);
+        for e in day {
+            // Each reading stands for one minute of consumption
+            let kwh = e.power / 60.0;
+            let rate = match e.timestamp.hour() {
+                7..=22 => DAY_RATE,
+                _ => NIGHT_RATE,
+            };
+            energy += kwh;
+            cost += kwh * rate;
+        }
+        println!("{}: {:.3} kWh, {:.2}", date, energy, cost);
+        total += cost;
+    }
+    println!("Total: {:.2}", total);
+    Ok(())
+}
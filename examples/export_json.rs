@@ -0,0 +1,35 @@
+// Print the daily statistics of a data file as JSON, for other tools to pick up:
+// cargo run --example export_json [file] > daily.json
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use voltcraft_energy_analyzer::voltcraft::data::VoltcraftData;
+use voltcraft_energy_analyzer::voltcraft::stats::VoltcraftStatistics;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let file = env::args_os().nth(1).map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_data1/A04FC8D2.BIN"),
+        PathBuf::from,
+    );
+    let (mut events, _) = VoltcraftData::from_file(&file, 1024 * 1024)?.parse()?;
+    let stats = VoltcraftStatistics::new(&mut events);
+
+    // Only numbers and dates are written, so no escaping is needed
+    let days = stats
+        .daily_stats()
+        .iter()
+        .map(|d| {
+            format!(
+                r#"  {{"date":"{}","active_energy_kwh":{:.4},"avg_voltage":{:.2},"min_voltage":{:.1},"max_voltage":{:.1},"max_power_kw":{:.4}}}"#,
+                d.date,
+                d.stats.total_active_power,
+                d.stats.avg_voltage,
+                d.stats.min_voltage.voltage,
+                d.stats.max_voltage.voltage,
+                d.stats.max_active_power.power
+            )
+        })
+        .collect::<Vec<_>>();
+    println!("[\n{}\n]", days.join(",\n"));
+    Ok(())
+}
@@ -0,0 +1,61 @@
+// Decode every data file of a folder, merge them into a single chronological series and print
+// the overall statistics, like the command line tool does:
+// cargo run --example merge_folder [folder]
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use voltcraft_energy_analyzer::voltcraft::data::VoltcraftData;
+use voltcraft_energy_analyzer::voltcraft::merge::ChronologicalMerge;
+use voltcraft_energy_analyzer::voltcraft::stats::VoltcraftStatistics;
+use voltcraft_energy_analyzer::voltcraft::transform::{trim_overlaps, OverlapPolicy};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let folder = env::args_os().nth(1).map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_data1"),
+        PathBuf::from,
+    );
+
+    // The files are named in download order, which trim_overlaps relies on
+    let mut paths = fs::read_dir(&folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    let mut files = Vec::new();
+    for path in paths {
+        let name = path.display().to_string();
+        match VoltcraftData::from_file(&path, 1024 * 1024).and_then(|d| d.parse()) {
+            Ok((mut events, _)) => {
+                events.sort_by_key(|e| e.timestamp);
+                files.push(events);
+            }
+            Err(e) => println!("Skipping {}: {}", name, e),
+        }
+    }
+
+    // The logger may hand out the same minutes in several downloads
+    for overlap in trim_overlaps(&mut files, OverlapPolicy::Newer).dropped {
+        println!(
+            "{} readings of file #{} superseded by file #{}",
+            overlap.count, overlap.superseded, overlap.by
+        );
+    }
+    let mut events = ChronologicalMerge::new(files.into_iter().map(Vec::into_iter).collect())
+        .collect::<Vec<_>>();
+    events.dedup_by(|a, b| a.timestamp == b.timestamp);
+
+    let stats = VoltcraftStatistics::new(&mut events);
+    let overall = stats
+        .overall_stats()
+        .ok_or_else(|| format!("No readings in {}", folder.display()))?;
+    let blackouts = stats.blackout_stats();
+    println!("Period: {} - {}", overall.start, overall.end);
+    println!("Active energy: {:.3} kWh", overall.stats.total_active_power);
+    println!("Average voltage: {:.1} V", overall.stats.avg_voltage);
+    println!(
+        "Blackouts: {} ({} minutes)",
+        blackouts.blackout_count,
+        blackouts.total_blackout_duration.num_minutes()
+    );
+    Ok(())
+}
@@ -0,0 +1,30 @@
+// Decode a single data file and print its first readings:
+// cargo run --example parse_one_file [file]
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use voltcraft_energy_analyzer::voltcraft::data::VoltcraftData;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let file = env::args_os().nth(1).map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("sample_data1/A04FC8D2.BIN"),
+        PathBuf::from,
+    );
+    let data = VoltcraftData::from_file(&file, 1024 * 1024)?;
+    let (events, report) = data.parse()?;
+    println!("{}: {} records", file.display(), report.record_count);
+    for e in events.iter().take(10) {
+        println!(
+            "{} {:.1}V {:.3}A cos(phi) {:.2} {:.1}W",
+            e.timestamp.format("%Y-%m-%d %H:%M"),
+            e.voltage,
+            e.current,
+            e.power_factor,
+            e.power * 1000.0
+        );
+    }
+    if events.len() > 10 {
+        println!("... and {} more", events.len() - 10);
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// Package the given output files, plus the effective configuration, into a single ZIP archive
+pub fn save_bundle(
+    filename: impl AsRef<Path>,
+    output_dir: &Path,
+    files: &[String],
+    config: &[(String, String)],
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(filename)?);
+    let entry_options =
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for file in files {
+        zip.start_file(file.as_str(), entry_options)?;
+        io::copy(&mut File::open(output_dir.join(file))?, &mut zip)?;
+    }
+    // Record how the outputs were produced, so the archive is self-describing
+    zip.start_file("voltcraft_config.txt", entry_options)?;
+    for (key, value) in config {
+        writeln!(zip, "{} = {}", key, value)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
@@ -0,0 +1,1130 @@
+use crate::console::{ColorChoice, Theme};
+use crate::export::{DetailLevel, UptimeFormat};
+use crate::voltcraft::data::{Calibration, ParseMode, PowerFactorPolicy, ValidationConfig};
+use crate::voltcraft::generate::Generator;
+use crate::voltcraft::grid::GridProfile;
+use crate::voltcraft::phases::BalanceLimits;
+use crate::voltcraft::pipeline::{HistoryColumn, HistoryLayout};
+use crate::voltcraft::profile::ApplianceProfile;
+use crate::voltcraft::stats::{BlackoutDetection, RobustAverage};
+use crate::voltcraft::tariff::{BillingRounding, OffPeakHours};
+use crate::voltcraft::transform::{DedupPolicy, ExcludedPeriod, GapFilling, OverlapPolicy};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Duration, FixedOffset, NaiveDateTime};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub struct Options {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub detail: DetailLevel,
+    pub history: HistoryLayout, // of the text parameter history
+    pub deadband: Option<f64>,  // W
+    pub power_factor_policy: PowerFactorPolicy,
+    pub calibration: Calibration, // of the logger against a reference meter
+    pub consistency_tolerance: f64, // relative
+    pub digest: bool,
+    pub price: Option<f64>,          // per kWh
+    pub off_peak_price: Option<f64>, // per kWh
+    pub off_peak_hours: OffPeakHours,
+    pub billing_rounding: Option<BillingRounding>, // of the billed energy, as the utility does
+    pub power_limit: Option<f64>,                  // kW
+    pub ups_capacity: Option<f64>,                 // kWh
+    pub ups_efficiency: f64,                       // of the inverter (0-1)
+    pub overwrite: OverwritePolicy,
+    pub timestamped_output: bool,
+    pub color: ColorChoice,
+    pub theme: Theme,
+    pub progress_json: bool,
+    pub verbose: bool, // report the performance of the run
+    pub scatter: bool,
+    pub weekly_profile: bool,
+    pub resample: Option<u32>, // minutes, interval of the resampled readings
+    pub smooth: Option<u32>,   // minutes, window of the rolling mean of the smoothed readings
+    pub reactive: bool,        // daily reactive energy and its heatmap by hour of the week
+    pub uptime: Option<UptimeFormat>, // export of the blackouts for uptime monitoring tools
+    pub archive: bool,         // compact archive of the readings
+    pub grid: GridProfile,     // supply the logger was plugged into
+    pub flicker_threshold: f64, // V
+    pub sag_voltage: f64,      // V
+    pub high_load: f64,        // kW
+    pub blackout_detection: BlackoutDetection,
+    pub split_by: SplitBy,
+    pub bundle: bool,
+    pub max_file_size: u64,          // bytes
+    pub sample: Option<usize>, // decode every Nth reading only, for a quick approximate preview
+    pub state_file: Option<PathBuf>, // files decoded by the previous runs, only new ones are decoded
+    pub stale_after: Option<u32>,    // days without new readings imported before warning
+    pub parse_mode: ParseMode,
+    pub validation: ValidationConfig,
+    pub device_capacity: usize, // records
+    pub overlap_policy: OverlapPolicy,
+    pub dedup_policy: DedupPolicy, // for minutes read more than once once merged
+    pub fill_gaps: Option<GapFilling>, // of the short gaps of the merged readings
+    pub exclude: Vec<ExcludedPeriod>, // days left out of the statistics
+    pub robust: Option<RobustAverage>, // of the daily power and voltage, alongside the means
+    pub appliance: Option<ApplianceProfile>,
+    pub timezone: Option<FixedOffset>, // zone the logger clock was set in (None: local time)
+    pub clock_references: Vec<(NaiveDateTime, NaiveDateTime)>, // (logger clock, true time) pairs
+    pub phase_dirs: Vec<PathBuf>, // data of the loggers on the other phases of a three-phase supply
+    pub balance_limits: BalanceLimits,
+}
+
+// How the statistics report is split into several files
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SplitBy {
+    None,  // a single report for the entire period
+    Month, // one report per calendar month
+}
+
+impl FromStr for SplitBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(SplitBy::None),
+            "month" => Ok(SplitBy::Month),
+            _ => Err(format!("Invalid split '{}' (expected none or month)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SplitBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SplitBy::None => write!(f, "none"),
+            SplitBy::Month => write!(f, "month"),
+        }
+    }
+}
+
+// What to do when output files already exist
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    Never,  // refuse to run
+    Ask,    // ask for confirmation (refuse when not running interactively)
+    Always, // silently replace the existing files
+}
+
+impl FromStr for OverwritePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "never" => Ok(OverwritePolicy::Never),
+            "ask" => Ok(OverwritePolicy::Ask),
+            "always" => Ok(OverwritePolicy::Always),
+            _ => Err(format!(
+                "Invalid overwrite policy '{}' (expected never, ask or always)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OverwritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverwritePolicy::Never => write!(f, "never"),
+            OverwritePolicy::Ask => write!(f, "ask"),
+            OverwritePolicy::Always => write!(f, "always"),
+        }
+    }
+}
+
+// Settings of the generate subcommand, writing a synthetic data file instead of analyzing data
+pub struct GenerateOptions {
+    pub output_dir: PathBuf,
+    pub generator: Generator,
+    pub overwrite: OverwritePolicy,
+}
+
+pub enum Command {
+    Run(Options),
+    PrintConfig(Options),
+    Doctor(Options),
+    Generate(GenerateOptions),
+    Features,
+    Help,
+}
+
+impl Options {
+    // The effective configuration (defaults merged with the command line), as key/value pairs
+    pub fn effective_config(&self) -> Vec<(String, String)> {
+        let optional = |v: Option<f64>| v.map_or(String::from("none"), |v| v.to_string());
+        vec![
+            (String::from("input"), self.input_dir.display().to_string()),
+            (
+                String::from("output"),
+                self.output_dir.display().to_string(),
+            ),
+            (String::from("detail"), self.detail.to_string()),
+            (
+                String::from("history-timestamp"),
+                self.history.timestamp_format.clone(),
+            ),
+            (
+                String::from("history-columns"),
+                self.history
+                    .columns
+                    .iter()
+                    .map(HistoryColumn::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (String::from("deadband"), optional(self.deadband)),
+            (
+                String::from("power-factor"),
+                self.power_factor_policy.to_string(),
+            ),
+            (
+                String::from("calibrate-voltage"),
+                self.calibration.voltage.to_string(),
+            ),
+            (
+                String::from("calibrate-current"),
+                self.calibration.current.to_string(),
+            ),
+            (
+                String::from("calibrate-power"),
+                self.calibration.power.to_string(),
+            ),
+            (
+                String::from("consistency-tolerance"),
+                (self.consistency_tolerance * 100.0).to_string(),
+            ),
+            (String::from("digest"), self.digest.to_string()),
+            (String::from("price"), optional(self.price)),
+            (
+                String::from("off-peak-price"),
+                optional(self.off_peak_price),
+            ),
+            (
+                String::from("off-peak-hours"),
+                self.off_peak_hours.to_string(),
+            ),
+            (
+                String::from("billing-rounding"),
+                self.billing_rounding
+                    .map_or(String::from("none"), |r| r.to_string()),
+            ),
+            (String::from("power-limit"), optional(self.power_limit)),
+            (String::from("ups-capacity"), optional(self.ups_capacity)),
+            (
+                String::from("ups-efficiency"),
+                self.ups_efficiency.to_string(),
+            ),
+            (String::from("overwrite"), self.overwrite.to_string()),
+            (
+                String::from("timestamped-output"),
+                self.timestamped_output.to_string(),
+            ),
+            (String::from("color"), self.color.to_string()),
+            (String::from("theme"), self.theme.to_string()),
+            (
+                String::from("progress-json"),
+                self.progress_json.to_string(),
+            ),
+            (String::from("verbose"), self.verbose.to_string()),
+            (String::from("scatter"), self.scatter.to_string()),
+            (
+                String::from("weekly-profile"),
+                self.weekly_profile.to_string(),
+            ),
+            (String::from("reactive"), self.reactive.to_string()),
+            (
+                String::from("resample"),
+                self.resample
+                    .map_or(String::from("none"), |m| format!("{}min", m)),
+            ),
+            (
+                String::from("smooth"),
+                self.smooth
+                    .map_or(String::from("none"), |m| format!("{}min", m)),
+            ),
+            (
+                String::from("uptime"),
+                self.uptime.map_or(String::from("none"), |f| f.to_string()),
+            ),
+            (String::from("archive"), self.archive.to_string()),
+            (String::from("grid"), self.grid.to_string()),
+            (
+                String::from("flicker-threshold"),
+                self.flicker_threshold.to_string(),
+            ),
+            (String::from("sag-voltage"), self.sag_voltage.to_string()),
+            (String::from("high-load"), self.high_load.to_string()),
+            (
+                String::from("blackout-threshold"),
+                self.blackout_detection.threshold.num_minutes().to_string(),
+            ),
+            (
+                String::from("blackout-detection"),
+                self.blackout_detection.algorithm.to_string(),
+            ),
+            (String::from("split-by"), self.split_by.to_string()),
+            (String::from("bundle"), self.bundle.to_string()),
+            (
+                String::from("max-file-size"),
+                (self.max_file_size / 1024).to_string(),
+            ),
+            (
+                String::from("sample"),
+                self.sample
+                    .map_or(String::from("none"), |n| format!("every={}", n)),
+            ),
+            (
+                String::from("state"),
+                self.state_file
+                    .as_ref()
+                    .map_or(String::from("none"), |p| p.display().to_string()),
+            ),
+            (
+                String::from("stale-after"),
+                self.stale_after
+                    .map_or(String::from("none"), |d| d.to_string()),
+            ),
+            (String::from("parse-mode"), self.parse_mode.to_string()),
+            (
+                String::from("min-voltage"),
+                self.validation.min_voltage.to_string(),
+            ),
+            (
+                String::from("max-voltage"),
+                self.validation.max_voltage.to_string(),
+            ),
+            (
+                String::from("implausible-voltage"),
+                self.validation.policy.to_string(),
+            ),
+            (
+                String::from("max-current"),
+                self.validation.max_current.to_string(),
+            ),
+            (
+                String::from("implausible-current"),
+                self.validation.current_policy.to_string(),
+            ),
+            (
+                String::from("device-capacity"),
+                self.device_capacity.to_string(),
+            ),
+            (String::from("overlap"), self.overlap_policy.to_string()),
+            (String::from("dedup"), self.dedup_policy.to_string()),
+            (
+                String::from("fill-gaps"),
+                self.fill_gaps
+                    .map_or(String::from("none"), |g| g.to_string()),
+            ),
+            (
+                String::from("exclude"),
+                if self.exclude.is_empty() {
+                    String::from("none")
+                } else {
+                    self.exclude
+                        .iter()
+                        .map(ExcludedPeriod::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            ),
+            (
+                String::from("robust"),
+                self.robust.map_or(String::from("none"), |a| a.to_string()),
+            ),
+            (
+                String::from("appliance"),
+                self.appliance
+                    .map_or(String::from("none"), |p| p.to_string()),
+            ),
+            (
+                String::from("tz"),
+                self.timezone
+                    .map_or(String::from("local"), |tz| tz.to_string()),
+            ),
+            (
+                String::from("clock-reference"),
+                if self.clock_references.is_empty() {
+                    String::from("none")
+                } else {
+                    self.clock_references
+                        .iter()
+                        .map(|(logger, actual)| format!("{}={}", logger, actual))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            ),
+            (
+                String::from("phase"),
+                if self.phase_dirs.is_empty() {
+                    String::from("none")
+                } else {
+                    self.phase_dirs
+                        .iter()
+                        .map(|dir| dir.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+            ),
+            (
+                String::from("max-phase-imbalance"),
+                (self.balance_limits.max_imbalance * 100.0).to_string(),
+            ),
+            (
+                String::from("max-neutral-current"),
+                self.balance_limits.max_neutral_current.to_string(),
+            ),
+        ]
+    }
+}
+
+// The arguments are taken as given by the OS, so that paths need not be valid Unicode
+pub fn parse_args(args: &[OsString]) -> Result<Command, String> {
+    if args.get(1).is_some_and(|a| a == "generate") {
+        return parse_generate_args(&args[1..]);
+    }
+    let mut positional = Vec::<PathBuf>::new();
+    let mut detail = DetailLevel::Normal;
+    let mut history = HistoryLayout::default();
+    let mut history_columns = None;
+    let mut deadband = None;
+    let mut power_factor_policy = PowerFactorPolicy::Clamp;
+    let mut calibration = Calibration::default();
+    let mut consistency_tolerance = 0.01;
+    let mut digest = false;
+    let mut price = None;
+    let mut off_peak_price = None;
+    let mut off_peak_hours = OffPeakHours { start: 22, end: 6 };
+    let mut billing_rounding = None;
+    let mut power_limit = None;
+    let mut ups_capacity = None;
+    let mut ups_efficiency = 0.9;
+    let mut print_config = false;
+    // The doctor subcommand takes the options and folders of a run, as does its --doctor alias
+    let mut doctor = args.get(1).is_some_and(|a| a == "doctor");
+    let mut overwrite = OverwritePolicy::Ask;
+    let mut timestamped_output = false;
+    let mut color = ColorChoice::Auto;
+    let mut theme = Theme::Dark;
+    let mut progress_json = false;
+    let mut verbose = false;
+    let mut scatter = false;
+    let mut weekly_profile = false;
+    let mut resample = None;
+    let mut smooth = None;
+    let mut reactive = false;
+    let mut uptime = None;
+    let mut archive = false;
+    // The voltage thresholds default to those of the grid
+    let mut grid = GridProfile::default();
+    let mut flicker_threshold = None;
+    let mut sag_voltage = None;
+    let (mut min_voltage, mut max_voltage) = (None, None);
+    let mut high_load = 1.0;
+    let mut blackout_detection = BlackoutDetection::default();
+    let mut split_by = SplitBy::None;
+    let mut bundle = false;
+    let mut max_file_size = 1024 * 1024;
+    let mut sample = None;
+    let mut state_file = None;
+    let mut stale_after = None;
+    let mut parse_mode = ParseMode::Strict;
+    let mut validation = ValidationConfig::default();
+    let mut device_capacity = 262_144;
+    let mut overlap_policy = OverlapPolicy::Newer;
+    let mut dedup_policy = DedupPolicy::KeepFirst;
+    let mut fill_gaps = None;
+    let mut exclude = Vec::new();
+    let mut robust = None;
+    let mut timezone = None;
+    let mut clock_references = Vec::new();
+    let mut phase_dirs = Vec::new();
+    let mut balance_limits = BalanceLimits::default();
+    let mut appliance = None;
+
+    let mut it = args.iter().skip(if doctor { 2 } else { 1 });
+    while let Some(arg) = it.next() {
+        // Options are always valid Unicode, anything else is a path
+        let Some(arg) = arg.to_str() else {
+            positional.push(PathBuf::from(arg));
+            continue;
+        };
+        if arg.eq_ignore_ascii_case("-h")
+            || arg.eq_ignore_ascii_case("--help")
+            || arg.eq_ignore_ascii_case("/?")
+        {
+            return Ok(Command::Help);
+        }
+        if arg == "--features" {
+            return Ok(Command::Features);
+        }
+        match arg {
+            "--detail" => {
+                detail = option_value(&mut it, arg)?.parse()?;
+            }
+            "--history-timestamp" => {
+                let value = option_value(&mut it, arg)?;
+                if value.is_empty() || StrftimeItems::new(value).any(|i| i == Item::Error) {
+                    return Err(format!(
+                        "Invalid timestamp format '{}' (expected a strftime format, e.g. %Y-%m-%d %H:%M)",
+                        value
+                    ));
+                }
+                history.timestamp_format = value.to_string();
+            }
+            "--history-columns" => {
+                history_columns = Some(
+                    option_value(&mut it, arg)?
+                        .split(',')
+                        .map(str::parse::<HistoryColumn>)
+                        .collect::<Result<_, _>>()?,
+                );
+            }
+            "--deadband" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(w) if w >= 0.0 => deadband = Some(w),
+                    _ => return Err(format!("Invalid deadband '{}' (expected watts)", value)),
+                }
+            }
+            "--parse-mode" => {
+                parse_mode = option_value(&mut it, arg)?.parse()?;
+            }
+            "--min-voltage" | "--max-voltage" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(v) if v >= 0.0 && arg == "--min-voltage" => min_voltage = Some(v),
+                    Ok(v) if v >= 0.0 => max_voltage = Some(v),
+                    _ => return Err(format!("Invalid voltage '{}' (expected volts)", value)),
+                }
+            }
+            "--implausible-voltage" => {
+                validation.policy = option_value(&mut it, arg)?.parse()?;
+            }
+            "--max-current" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(a) if a > 0.0 => validation.max_current = a,
+                    _ => return Err(format!("Invalid current '{}' (expected ampers)", value)),
+                }
+            }
+            "--implausible-current" => {
+                validation.current_policy = option_value(&mut it, arg)?.parse()?;
+            }
+            "--appliance" => {
+                appliance = Some(option_value(&mut it, arg)?.parse()?);
+            }
+            "--overlap" => {
+                overlap_policy = option_value(&mut it, arg)?.parse()?;
+            }
+            "--dedup" => {
+                dedup_policy = option_value(&mut it, arg)?.parse()?;
+            }
+            "--fill-gaps" => {
+                fill_gaps = Some(option_value(&mut it, arg)?.parse()?);
+            }
+            "--exclude" => exclude.push(option_value(&mut it, arg)?.parse()?),
+            "--robust" => robust = Some(option_value(&mut it, arg)?.parse()?),
+            "--phase" => phase_dirs.push(PathBuf::from(path_value(&mut it, arg)?)),
+            "--state" => state_file = Some(PathBuf::from(path_value(&mut it, arg)?)),
+            "--stale-after" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<u32>() {
+                    Ok(days) if days > 0 => stale_after = Some(days),
+                    _ => {
+                        return Err(format!(
+                            "Invalid staleness '{}' (expected a number of days)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--max-phase-imbalance" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(p) if p >= 0.0 => balance_limits.max_imbalance = p / 100.0,
+                    _ => {
+                        return Err(format!(
+                            "Invalid phase imbalance '{}' (expected a percentage)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--max-neutral-current" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(a) if a >= 0.0 => balance_limits.max_neutral_current = a,
+                    _ => return Err(format!("Invalid current '{}' (expected ampers)", value)),
+                }
+            }
+            "--clock-reference" => {
+                clock_references.push(parse_clock_reference(option_value(&mut it, arg)?)?);
+            }
+            "--tz" => {
+                let value = option_value(&mut it, arg)?;
+                timezone = match value.to_ascii_lowercase().as_str() {
+                    "local" => None,
+                    "utc" | "z" => FixedOffset::east_opt(0),
+                    _ => match value.parse::<FixedOffset>() {
+                        Ok(offset) => Some(offset),
+                        _ => {
+                            return Err(format!(
+                                "Invalid time zone '{}' (expected local, utc or an offset such as +02:00)",
+                                value
+                            ))
+                        }
+                    },
+                };
+            }
+            "--power-factor" => {
+                power_factor_policy = option_value(&mut it, arg)?.parse()?;
+            }
+            "--calibrate-voltage" => {
+                calibration.voltage = option_value(&mut it, arg)?.parse()?;
+            }
+            "--calibrate-current" => {
+                calibration.current = option_value(&mut it, arg)?.parse()?;
+            }
+            "--calibrate-power" => {
+                calibration.power = option_value(&mut it, arg)?.parse()?;
+            }
+            "--consistency-tolerance" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(p) if p >= 0.0 => consistency_tolerance = p / 100.0,
+                    _ => {
+                        return Err(format!(
+                            "Invalid consistency tolerance '{}' (expected a percentage)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--digest" => digest = true,
+            "--print-effective-config" => print_config = true,
+            "--doctor" => doctor = true,
+            "--overwrite" => {
+                overwrite = option_value(&mut it, arg)?.parse()?;
+            }
+            "--timestamped-output" => timestamped_output = true,
+            "--color" => {
+                color = option_value(&mut it, arg)?.parse()?;
+            }
+            "--progress-json" => progress_json = true,
+            "--verbose" | "-v" => verbose = true,
+            "--scatter" => scatter = true,
+            "--weekly-profile" => weekly_profile = true,
+            "--reactive" => reactive = true,
+            "--resample" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<u32>() {
+                    Ok(m) if (1..=1440).contains(&m) => resample = Some(m),
+                    _ => {
+                        return Err(format!(
+                        "Invalid resampling interval '{}' (expected 1 to 1440 minutes, e.g. 15)",
+                        value
+                    ))
+                    }
+                }
+            }
+            "--smooth" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<u32>() {
+                    Ok(m) if (1..=1440).contains(&m) => smooth = Some(m),
+                    _ => {
+                        return Err(format!(
+                            "Invalid smoothing window '{}' (expected 1 to 1440 minutes, e.g. 15)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--archive" => archive = true,
+            "--uptime" => {
+                uptime = Some(option_value(&mut it, arg)?.parse()?);
+            }
+            "--bundle" if cfg!(feature = "bundle") => bundle = true,
+            "--bundle" => {
+                return Err(String::from(
+                    "Option '--bundle' is not available in this build (enable the bundle feature)",
+                ))
+            }
+            "--blackout-threshold" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<i64>() {
+                    Ok(m) if m >= 0 => blackout_detection.threshold = Duration::minutes(m),
+                    _ => {
+                        return Err(format!(
+                            "Invalid blackout threshold '{}' (expected minutes)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--blackout-detection" => {
+                blackout_detection.algorithm = option_value(&mut it, arg)?.parse()?;
+            }
+            "--device-capacity" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<usize>() {
+                    Ok(n) if n > 0 => device_capacity = n,
+                    _ => {
+                        return Err(format!(
+                            "Invalid device capacity '{}' (expected a number of records)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--max-file-size" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<u64>() {
+                    Ok(k) if k > 0 => max_file_size = k * 1024,
+                    _ => {
+                        return Err(format!(
+                            "Invalid maximum file size '{}' (expected KiB)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--sample" => {
+                let value = option_value(&mut it, arg)?;
+                match value.strip_prefix("every=").map(str::parse::<usize>) {
+                    Some(Ok(n)) if n > 0 => sample = Some(n),
+                    _ => {
+                        return Err(format!(
+                            "Invalid sampling '{}' (expected every=N, e.g. every=10)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--split-by" => {
+                split_by = option_value(&mut it, arg)?.parse()?;
+            }
+            "--grid" => {
+                grid = option_value(&mut it, arg)?.parse()?;
+            }
+            "--sag-voltage" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(v) if v > 0.0 => sag_voltage = Some(v),
+                    _ => return Err(format!("Invalid sag voltage '{}' (expected volts)", value)),
+                }
+            }
+            "--high-load" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(kw) if kw >= 0.0 => high_load = kw,
+                    _ => return Err(format!("Invalid high load '{}' (expected kW)", value)),
+                }
+            }
+            "--flicker-threshold" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(v) if v >= 0.0 => flicker_threshold = Some(v),
+                    _ => {
+                        return Err(format!(
+                            "Invalid flicker threshold '{}' (expected volts)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--theme" => {
+                theme = option_value(&mut it, arg)?.parse()?;
+            }
+            "--off-peak-price" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(p) if p >= 0.0 => off_peak_price = Some(p),
+                    _ => {
+                        return Err(format!(
+                            "Invalid off-peak price '{}' (expected price per kWh)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--power-limit" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(kw) if kw > 0.0 => power_limit = Some(kw),
+                    _ => return Err(format!("Invalid power limit '{}' (expected kW)", value)),
+                }
+            }
+            "--ups-capacity" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(kwh) if kwh > 0.0 => ups_capacity = Some(kwh),
+                    _ => {
+                        return Err(format!(
+                            "Invalid battery capacity '{}' (expected kWh)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--ups-efficiency" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(e) if e > 0.0 && e <= 1.0 => ups_efficiency = e,
+                    _ => {
+                        return Err(format!(
+                            "Invalid inverter efficiency '{}' (expected a fraction, e.g. 0.9)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--off-peak-hours" => {
+                off_peak_hours = option_value(&mut it, arg)?.parse()?;
+            }
+            "--billing-rounding" => {
+                billing_rounding = Some(option_value(&mut it, arg)?.parse()?);
+            }
+            "--price" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(p) if p >= 0.0 => price = Some(p),
+                    _ => {
+                        return Err(format!(
+                            "Invalid price '{}' (expected price per kWh)",
+                            value
+                        ))
+                    }
+                }
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let plausible = grid.validation();
+    validation.min_voltage = min_voltage.unwrap_or(plausible.min_voltage);
+    validation.max_voltage = max_voltage.unwrap_or(plausible.max_voltage);
+    if validation.min_voltage >= validation.max_voltage {
+        return Err(format!(
+            "Invalid voltage range {}V-{}V (the minimum must be below the maximum)",
+            validation.min_voltage, validation.max_voltage
+        ));
+    }
+
+    match clock_references[..] {
+        [] | [_] => {}
+        [(first, _), (second, _)] if first != second => {}
+        [_, _] => {
+            return Err(String::from(
+                "The clock references must be taken at different logger clock times",
+            ))
+        }
+        _ => {
+            return Err(String::from(
+                "Option '--clock-reference' can be given at most twice",
+            ))
+        }
+    }
+
+    if !phase_dirs.is_empty() && phase_dirs.len() != 2 {
+        return Err(String::from(
+            "Option '--phase' must be given twice, once for each of the other two phases",
+        ));
+    }
+
+    if stale_after.is_some() && state_file.is_none() {
+        return Err(String::from(
+            "Option '--stale-after' requires a state file (--state)",
+        ));
+    }
+
+    // The readings decoded for a preview are apart, gaps would be filled throughout
+    if fill_gaps.is_some() && sample.is_some() {
+        return Err(String::from(
+            "Option '--fill-gaps' can't be used for a preview (--sample)",
+        ));
+    }
+
+    if off_peak_price.is_some() && price.is_none() {
+        return Err(String::from(
+            "Option '--off-peak-price' requires the peak price (--price)",
+        ));
+    }
+
+    // The derived quantities are written when tracking the reactive load, unless the columns are given
+    match history_columns {
+        Some(columns) => history.columns = columns,
+        None if reactive => history.columns.extend(HistoryColumn::DERIVED),
+        None => {}
+    }
+
+    let (input_dir, output_dir) = match positional.len() {
+        // We have both the input and the output folder
+        2 => (positional[0].clone(), positional[1].clone()),
+        // We only have the input folder
+        1 => (positional[0].clone(), PathBuf::from("./")),
+        // No folder given
+        0 => (PathBuf::from("./"), PathBuf::from("./")),
+        _ => return Err(String::from("Too many arguments")),
+    };
+
+    let options = Options {
+        input_dir,
+        output_dir,
+        detail,
+        history,
+        deadband,
+        power_factor_policy,
+        calibration,
+        consistency_tolerance,
+        digest,
+        price,
+        off_peak_price,
+        off_peak_hours,
+        billing_rounding,
+        power_limit,
+        ups_capacity,
+        ups_efficiency,
+        overwrite,
+        timestamped_output,
+        color,
+        theme,
+        progress_json,
+        verbose,
+        scatter,
+        weekly_profile,
+        resample,
+        smooth,
+        reactive,
+        uptime,
+        archive,
+        grid,
+        // 5V on 230V grids
+        flicker_threshold: flicker_threshold.unwrap_or_else(|| grid.scaled(5.0)),
+        sag_voltage: sag_voltage.unwrap_or_else(|| grid.low_voltage()),
+        high_load,
+        blackout_detection,
+        split_by,
+        bundle,
+        max_file_size,
+        sample,
+        state_file,
+        stale_after,
+        parse_mode,
+        validation,
+        device_capacity,
+        overlap_policy,
+        dedup_policy,
+        fill_gaps,
+        exclude,
+        robust,
+        appliance,
+        timezone,
+        clock_references,
+        phase_dirs,
+        balance_limits,
+    };
+    if print_config {
+        Ok(Command::PrintConfig(options))
+    } else if doctor {
+        Ok(Command::Doctor(options))
+    } else {
+        Ok(Command::Run(options))
+    }
+}
+
+// Parse the arguments following the generate subcommand (the first one)
+fn parse_generate_args(args: &[OsString]) -> Result<Command, String> {
+    let mut positional = Vec::<PathBuf>::new();
+    let mut generator = Generator::new();
+    let mut overwrite = OverwritePolicy::Ask;
+
+    let mut it = args.iter().skip(1);
+    while let Some(arg) = it.next() {
+        // Options are always valid Unicode, anything else is a path
+        let Some(arg) = arg.to_str() else {
+            positional.push(PathBuf::from(arg));
+            continue;
+        };
+        if arg.eq_ignore_ascii_case("-h")
+            || arg.eq_ignore_ascii_case("--help")
+            || arg.eq_ignore_ascii_case("/?")
+        {
+            return Ok(Command::Help);
+        }
+        match arg {
+            "--days" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<u32>() {
+                    Ok(n) if n > 0 => generator = generator.days(n),
+                    _ => return Err(format!("Invalid number of days '{}'", value)),
+                }
+            }
+            "--start" => {
+                let value = option_value(&mut it, arg)?;
+                match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                    Ok(date) => generator = generator.start(date),
+                    _ => {
+                        return Err(format!(
+                            "Invalid start date '{}' (expected YYYY-MM-DD)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--load-shape" => {
+                generator = generator.load_shape(option_value(&mut it, arg)?.parse()?);
+            }
+            "--blackouts-per-day" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(n) if (0.0..=100.0).contains(&n) => {
+                        generator = generator.blackouts_per_day(n)
+                    }
+                    _ => return Err(format!("Invalid number of blackouts '{}'", value)),
+                }
+            }
+            "--voltage-noise" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<f64>() {
+                    Ok(v) if (0.0..=50.0).contains(&v) => generator = generator.voltage_noise(v),
+                    _ => {
+                        return Err(format!(
+                            "Invalid voltage noise '{}' (expected volts)",
+                            value
+                        ))
+                    }
+                }
+            }
+            "--seed" => {
+                let value = option_value(&mut it, arg)?;
+                match value.parse::<u64>() {
+                    Ok(seed) => generator = generator.seed(seed),
+                    _ => return Err(format!("Invalid seed '{}'", value)),
+                }
+            }
+            "--overwrite" => {
+                overwrite = option_value(&mut it, arg)?.parse()?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let output_dir = match positional.len() {
+        1 => positional[0].clone(),
+        0 => PathBuf::from("./"),
+        _ => return Err(String::from("Too many arguments")),
+    };
+    Ok(Command::Generate(GenerateOptions {
+        output_dir,
+        generator,
+        overwrite,
+    }))
+}
+
+// A logger clock time and the true time at that moment, e.g. 2014-09-11T18:43=2014-09-11T18:40
+fn parse_clock_reference(value: &str) -> Result<(NaiveDateTime, NaiveDateTime), String> {
+    const FORMATS: [&str; 4] = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ];
+    let time = |s: &str| {
+        FORMATS
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(s.trim(), f).ok())
+    };
+    value
+        .split_once('=')
+        .and_then(|(logger, actual)| Some((time(logger)?, time(actual)?)))
+        .ok_or_else(|| {
+            format!(
+                "Invalid clock reference '{}' (expected <logger time>=<true time>, e.g. 2014-09-11T18:43=2014-09-11T18:40)",
+                value
+            )
+        })
+}
+
+// Fetch the value following an option, failing if the command line ends prematurely
+fn option_value<'a>(
+    it: &mut impl Iterator<Item = &'a OsString>,
+    option: &str,
+) -> Result<&'a str, String> {
+    path_value(it, option)?
+        .to_str()
+        .ok_or_else(|| format!("Invalid value for option '{}' (not valid Unicode)", option))
+}
+
+// Fetch the path following an option, which needn't be valid Unicode
+fn path_value<'a>(
+    it: &mut impl Iterator<Item = &'a OsString>,
+    option: &str,
+) -> Result<&'a OsString, String> {
+    it.next()
+        .ok_or_else(|| format!("Missing value for option '{}'", option))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{parse_args, Command};
+    use crate::voltcraft::pipeline::{HistoryColumn, HistoryLayout};
+    use std::ffi::OsString;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        let args = ["voltcraft_energy_analyzer"]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn doctor() {
+        for args in [
+            ["doctor", "--detail", "full", "input", "output"],
+            ["--doctor", "--detail", "full", "input", "output"],
+        ] {
+            let Ok(Command::Doctor(options)) = parse(&args) else {
+                panic!("not the doctor command: {:?}", args);
+            };
+            assert_eq!(options.input_dir, Path::new("input"));
+            assert_eq!(options.output_dir, Path::new("output"));
+        }
+        // Only as the first argument, otherwise it is a folder
+        assert!(matches!(parse(&["input", "doctor"]), Ok(Command::Run(_))));
+    }
+
+    #[test]
+    fn features() {
+        assert!(matches!(parse(&["--features"]), Ok(Command::Features)));
+        // Whatever the other arguments
+        assert!(matches!(
+            parse(&["input", "output", "--features", "--detail", "full"]),
+            Ok(Command::Features)
+        ));
+    }
+
+    #[test]
+    fn history_columns() {
+        let columns = |args: &[&str]| match parse(args) {
+            Ok(Command::Run(options)) => options.history.columns,
+            _ => panic!("invalid options {:?}", args),
+        };
+        assert_eq!(columns(&[]), HistoryLayout::default().columns);
+        // Tracking the reactive load adds the derived quantities, unless the columns are given
+        let reactive = columns(&["--reactive"]);
+        assert_eq!(reactive[..5], HistoryLayout::default().columns[..]);
+        assert_eq!(reactive[5..], HistoryColumn::DERIVED);
+        assert_eq!(
+            columns(&["--reactive", "--history-columns", "power"]),
+            [HistoryColumn::Power]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use colored::{ColoredString, Colorize};
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+// When to use colors in the console output
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,   // only when writing to a terminal and NO_COLOR is not set
+    Always, // always
+    Never,  // never
+}
+
+// Terminal background the severity colors are chosen for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "Invalid color choice '{}' (expected auto, always or never)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColorChoice::Auto => write!(f, "auto"),
+            ColorChoice::Always => write!(f, "always"),
+            ColorChoice::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            _ => Err(format!("Invalid theme '{}' (expected dark or light)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Theme::Dark => write!(f, "dark"),
+            Theme::Light => write!(f, "light"),
+        }
+    }
+}
+
+// Configure the console output; must be called before anything is printed
+pub fn init(color: ColorChoice, theme: Theme) {
+    let enabled = match color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        // Honor https://no-color.org and don't garble redirected output
+        ColorChoice::Auto => {
+            env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                && !env::var("TERM").is_ok_and(|t| t == "dumb")
+                && io::stdout().is_terminal()
+        }
+    };
+    colored::control::set_override(enabled);
+    THEME.set(theme).ok();
+}
+
+fn theme() -> Theme {
+    *THEME.get().unwrap_or(&Theme::Dark)
+}
+
+// Successful outcome
+pub fn ok(s: &str) -> ColoredString {
+    s.green()
+}
+
+// Something the user should look at, but processing continues
+pub fn warning(s: &str) -> ColoredString {
+    match theme() {
+        Theme::Dark => s.yellow(),
+        Theme::Light => s.magenta(),
+    }
+}
+
+// Failure
+pub fn error(s: &str) -> ColoredString {
+    s.red()
+}
+
+// Names and values (files, folders, figures) embedded in messages
+pub fn highlight(s: &str) -> ColoredString {
+    match theme() {
+        Theme::Dark => s.bright_white(),
+        Theme::Light => s.bold(),
+    }
+}
+
+// Command-line syntax in help texts
+pub fn accent(s: &str) -> ColoredString {
+    match theme() {
+        Theme::Dark => s.yellow(),
+        Theme::Light => s.blue(),
+    }
+}
@@ -0,0 +1,250 @@
+use crate::cli::Options;
+use crate::console::{error, highlight, ok, warning};
+use crate::voltcraft;
+use crate::voltcraft::data::VoltcraftData;
+use crate::voltcraft::sample::sample_data;
+use std::fs;
+use std::path::Path;
+
+// Outcome of one environment check
+enum Status {
+    Ok,
+    Warning, // the run may still work, or work differently than expected
+    Failed,  // the run will fail
+}
+
+// Check the environment the analyzer runs in (folders, decoder, build), so support questions can
+// start from the output. Returns the number of failed checks.
+pub fn run(options: &Options) -> usize {
+    let mut failed = 0;
+    let mut check = |status: Status, name: &str, details: String| {
+        let label = match status {
+            Status::Ok => ok("  OK  "),
+            Status::Warning => warning(" WARN "),
+            Status::Failed => {
+                failed += 1;
+                error(" FAIL ")
+            }
+        };
+        println!("[{}] {}: {}", label, name, details);
+    };
+
+    // Invalid options are reported before getting here
+    check(
+        Status::Ok,
+        "Configuration",
+        format!(
+            "valid ({} settings, see --print-effective-config)",
+            options.effective_config().len()
+        ),
+    );
+
+    match sample_data().parse() {
+        Ok((events, _)) => check(
+            Status::Ok,
+            "Decoder",
+            format!("embedded sample decoded ({} readings)", events.len()),
+        ),
+        Err(e) => check(Status::Failed, "Decoder", format!("embedded sample: {}", e)),
+    }
+
+    let (status, details) = check_input(options);
+    check(status, "Input", details);
+
+    let (status, details) = check_output(&options.output_dir);
+    check(status, "Output", details);
+
+    let enabled = voltcraft::capabilities().collect::<Vec<_>>();
+    check(
+        Status::Ok,
+        "Features",
+        if enabled.is_empty() {
+            String::from("none (default build)")
+        } else {
+            enabled.join(", ")
+        },
+    );
+
+    failed
+}
+
+// Whether the input can be listed, and whether it holds at least one valid data file
+fn check_input(options: &Options) -> (Status, String) {
+    let input = &options.input_dir;
+    if input.as_os_str() == "-" {
+        return (Status::Ok, String::from("standard input"));
+    }
+    if crate::is_zip(input) {
+        return check_zip(input, options.max_file_size);
+    }
+    if input.is_file() {
+        return check_image(input, options.max_file_size);
+    }
+    let folder = input.display().to_string();
+    let files = match fs::read_dir(input) {
+        Ok(entries) => {
+            let mut files = entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        }
+        Err(e) => {
+            return (
+                Status::Failed,
+                format!("cannot read folder {} ({})", highlight(&folder), e),
+            )
+        }
+    };
+    // The first file that decodes shows the data is usable
+    let valid = files.iter().find_map(|f| {
+        let data = VoltcraftData::from_file(f, options.max_file_size).ok()?;
+        let (events, _) = data.parse().ok()?;
+        Some((f.display().to_string(), events.len()))
+    });
+    match valid {
+        Some((name, count)) => (
+            Status::Ok,
+            format!(
+                "folder {} readable, {} files, {} decoded ({} readings)",
+                highlight(&folder),
+                files.len(),
+                highlight(&name),
+                count
+            ),
+        ),
+        None => (
+            Status::Warning,
+            format!(
+                "folder {} readable, but none of its {} files is a valid data file",
+                highlight(&folder),
+                files.len()
+            ),
+        ),
+    }
+}
+
+#[cfg(feature = "sdcard")]
+fn check_image(path: &Path, max_size: u64) -> (Status, String) {
+    use crate::voltcraft::sdcard::SdCardImage;
+
+    let image = path.display().to_string();
+    let files = SdCardImage::open(path).and_then(|image| {
+        let files = image.data_files()?;
+        let valid = files
+            .iter()
+            .filter(|f| image.read(f, max_size).is_ok())
+            .count();
+        Ok((files.len(), valid))
+    });
+    match files {
+        Ok((count, valid)) if valid > 0 => (
+            Status::Ok,
+            format!(
+                "SD card image {} readable, {} of {} data files valid",
+                highlight(&image),
+                valid,
+                count
+            ),
+        ),
+        Ok(_) => (
+            Status::Warning,
+            format!(
+                "SD card image {} readable, but holds no valid data file",
+                highlight(&image)
+            ),
+        ),
+        Err(e) => (
+            Status::Failed,
+            format!("cannot read SD card image {} ({})", highlight(&image), e),
+        ),
+    }
+}
+
+#[cfg(not(feature = "sdcard"))]
+fn check_image(path: &Path, _: u64) -> (Status, String) {
+    (
+        Status::Failed,
+        format!(
+            "{} is a file; reading SD card images requires the sdcard feature",
+            highlight(&path.display().to_string())
+        ),
+    )
+}
+
+#[cfg(feature = "zip")]
+fn check_zip(path: &Path, max_size: u64) -> (Status, String) {
+    use crate::voltcraft::ziparchive::ZipBundle;
+
+    let archive = path.display().to_string();
+    match ZipBundle::open(path) {
+        Ok(bundle) => {
+            let files = bundle.data_files();
+            let valid = files
+                .iter()
+                .filter(|f| bundle.read(f, max_size).is_ok())
+                .count();
+            if valid > 0 {
+                (
+                    Status::Ok,
+                    format!(
+                        "ZIP archive {} readable, {} of {} data files valid",
+                        highlight(&archive),
+                        valid,
+                        files.len()
+                    ),
+                )
+            } else {
+                (
+                    Status::Warning,
+                    format!(
+                        "ZIP archive {} readable, but holds no valid data file",
+                        highlight(&archive)
+                    ),
+                )
+            }
+        }
+        Err(e) => (
+            Status::Failed,
+            format!("cannot read ZIP archive {} ({})", highlight(&archive), e),
+        ),
+    }
+}
+
+#[cfg(not(feature = "zip"))]
+fn check_zip(path: &Path, _: u64) -> (Status, String) {
+    (
+        Status::Failed,
+        format!(
+            "{} is a ZIP archive; reading it requires the zip feature",
+            highlight(&path.display().to_string())
+        ),
+    )
+}
+
+// Whether files can be created in the output folder, without leaving anything behind
+fn check_output(folder: &Path) -> (Status, String) {
+    let output = folder.display().to_string();
+    if !folder.exists() {
+        return (
+            Status::Warning,
+            format!(
+                "folder {} does not exist yet, it will be created",
+                highlight(&output)
+            ),
+        );
+    }
+    let probe = folder.join(".voltcraft_doctor");
+    match fs::write(&probe, b"").and_then(|_| fs::remove_file(&probe)) {
+        Ok(()) => (
+            Status::Ok,
+            format!("folder {} writable", highlight(&output)),
+        ),
+        Err(e) => (
+            Status::Failed,
+            format!("cannot write to folder {} ({})", highlight(&output), e),
+        ),
+    }
+}
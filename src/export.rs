@@ -1,51 +1,469 @@
 use crate::voltcraft::data::PowerEvent;
-use crate::voltcraft::stats::{BlackoutInfo, DailyPowerInfo, OverallPowerInfo};
+use crate::voltcraft::dataset::DatasetFile;
+use crate::voltcraft::duration::format_duration;
+use crate::voltcraft::phases::{BalanceLimits, PhaseReadings, ThreePhaseInfo};
+use crate::voltcraft::pipeline::{CsvSink, EventSink, HistoryLayout, PipelineError, TextSink};
+use crate::voltcraft::resample::ResampledEvent;
+use crate::voltcraft::stats::{
+    BillingInfo, BlackoutInfo, ConsistencyInfo, CorrelationInfo, CycleInfo, DailyPowerInfo,
+    FileGap, FlickerInfo, HourlyPowerInfo, LoadShiftingInfo, OverallPowerInfo, PeakShavingInfo,
+    PowerBlackout, ReactiveInfo, RobustAverage, RobustInfo, SagCoincidenceInfo, UpsInfo,
+    VoltageDeviationInfo, WeeklyProfile,
+};
+use crate::voltcraft::tariff::{BillingPeriod, RoundingMode};
+use crate::voltcraft::transform::{Conflict, Exclusion, Overlap};
+use chrono::{DateTime, FixedOffset, Utc};
+use itertools::Itertools;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DetailLevel {
+    Summary, // overall figures only
+    Normal,  // overall figures, daily statistics and blackout history
+    Full,    // everything, plus hourly tables and per-blackout context
+}
+
+impl FromStr for DetailLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "summary" => Ok(DetailLevel::Summary),
+            "normal" => Ok(DetailLevel::Normal),
+            "full" => Ok(DetailLevel::Full),
+            _ => Err(format!(
+                "Invalid detail level '{}' (expected summary, normal or full)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DetailLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DetailLevel::Summary => write!(f, "summary"),
+            DetailLevel::Normal => write!(f, "normal"),
+            DetailLevel::Full => write!(f, "full"),
+        }
+    }
+}
+
+// Format of the blackouts exported for uptime monitoring tools
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UptimeFormat {
+    Csv,  // state changes, as timestamp,up|down
+    Kuma, // heartbeats with the fields of Uptime Kuma, as JSON
+}
+
+impl FromStr for UptimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(UptimeFormat::Csv),
+            "kuma" => Ok(UptimeFormat::Kuma),
+            _ => Err(format!(
+                "Invalid uptime format '{}' (expected csv or kuma)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for UptimeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UptimeFormat::Csv => write!(f, "csv"),
+            UptimeFormat::Kuma => write!(f, "kuma"),
+        }
+    }
+}
+
+pub struct StatisticsReport {
+    pub overall: OverallPowerInfo,
+    pub daily: Vec<DailyPowerInfo>,
+    pub hourly: Vec<HourlyPowerInfo>,
+    pub blackouts: BlackoutInfo,
+    pub consistency: ConsistencyInfo,
+    pub correlation: CorrelationInfo,
+    pub flicker: FlickerInfo,
+    pub sags: SagCoincidenceInfo,
+    pub deviation: VoltageDeviationInfo, // from the tolerance band of the grid
+    pub cycles: Option<CycleInfo>,       // operating cycles, when an appliance profile was selected
+    pub load_shifting: Option<LoadShiftingInfo>, // for schedulable appliances on a time-of-use tariff
+    pub billing: Option<BillingInfo>, // billed energy, when the rounding of the utility was given
+    pub peak_shaving: Option<PeakShavingInfo>, // when a power limit was given
+    pub ups: Option<UpsInfo>,         // when a battery capacity was given
+    pub three_phase: Option<ThreePhaseInfo>, // when the loggers on the other phases were given
+    pub distortion_trend: Option<f64>, // change of the daily distortion proxy per 30 days
+    pub reactive: ReactiveInfo,       // reactive energy by day and by hour of the week
+    pub robust: Option<RobustInfo>,   // daily averages resisting spikes, when one was selected
+    pub file_gaps: Vec<FileGap>,
+    pub sources: Vec<String>, // input files, indexed by the source of the power events
+    pub unfiltered: Option<OverallPowerInfo>, // overall statistics before noise filtering
+    pub excluded: Vec<Exclusion>, // periods left out, with the readings dropped
+    pub filled: Option<(usize, f64)>, // readings filled in and their energy (kWh), if gaps were filled
+    pub config: Vec<(String, String)>, // effective configuration used for the report
+}
+
+// What an input file held and what it contributed to the merged readings, for the file summary
+pub struct FileSummary<'a> {
+    pub file: &'a DatasetFile,
+    pub merged_readings: usize, // readings left once merged and deduplicated
+    pub merged_energy: f64,     // kWh of those
+}
 
 pub fn save_parameter_history_txt(
-    filename: &str,
+    filename: impl AsRef<Path>,
+    power_events: &[PowerEvent],
+    layout: &HistoryLayout,
+) -> Result<(), io::Error> {
+    TextSink::create(filename)
+        .and_then(|sink| sink.layout(layout.clone()).write_events(power_events))
+        .map_err(into_io_error)
+}
+
+// With the derived quantities of each reading if asked for, and whether it was filled in if gaps
+// were filled
+pub fn save_parameter_history_csv(
+    filename: impl AsRef<Path>,
     power_events: &[PowerEvent],
+    derived: bool,
+    filled: bool,
 ) -> Result<(), io::Error> {
+    CsvSink::create(filename)
+        .and_then(|sink| {
+            sink.derived(derived)
+                .filled(filled)
+                .write_events(power_events)
+        })
+        .map_err(into_io_error)
+}
+
+// The sinks only fail on writing
+fn into_io_error(error: PipelineError) -> io::Error {
+    match error {
+        PipelineError::Io(error) => error,
+        error => io::Error::other(error),
+    }
+}
+
+// One row per conflict, a minute read more than once with different values, with both readings and
+// the files they were read from
+pub fn save_conflicts_csv(
+    filename: impl AsRef<Path>,
+    conflicts: &[Conflict],
+    sources: &[String],
+) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    let mut header = vec![String::from("Timestamp")];
+    for reading in 1..=2 {
+        header.extend(
+            [
+                "Voltage (V)",
+                "Current (A)",
+                "cosPHI",
+                "Active Power (kW)",
+                "File",
+            ]
+            .map(|column| format!("{} {}", column, reading)),
+        );
+    }
+    wtr.write_record(&header)?;
+    for conflict in conflicts {
+        let mut record = vec![conflict
+            .first
+            .timestamp
+            .format("%Y-%m-%d %H:%M")
+            .to_string()];
+        for pe in [&conflict.first, &conflict.second] {
+            let file = pe.source.and_then(|s| sources.get(s.file));
+            record.extend([
+                pe.voltage.to_string(),
+                pe.current.to_string(),
+                pe.power_factor.to_string(),
+                pe.power.to_string(),
+                file.cloned().unwrap_or_default(),
+            ]);
+        }
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// Mains power as a monitored service: up from the first reading, down at the start of each blackout
+// and up again at the first reading after it. Timestamps are RFC 3339, with the UTC offset of the
+// logger clock, so that the tools place them right whatever their zone.
+pub fn save_uptime_csv(
+    filename: impl AsRef<Path>,
+    start: DateTime<FixedOffset>,
+    blackouts: &[PowerBlackout],
+) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    wtr.write_record(["timestamp", "state"])?;
+    wtr.write_record([start.to_rfc3339().as_str(), "up"])?;
+    for blackout in blackouts {
+        wtr.write_record([blackout.timestamp.to_rfc3339().as_str(), "down"])?;
+        wtr.write_record([blackout.after.timestamp.to_rfc3339().as_str(), "up"])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// The same state changes as heartbeats with the fields of the Uptime Kuma heartbeat table (status 1
+// for up and 0 for down, time in UTC, the message, and the duration of the previous state in
+// seconds), all marked important as they change the state
+pub fn save_uptime_json(
+    filename: impl AsRef<Path>,
+    start: DateTime<FixedOffset>,
+    blackouts: &[PowerBlackout],
+) -> Result<(), io::Error> {
+    let heartbeat =
+        |time: DateTime<FixedOffset>, up: bool, msg: String, duration: chrono::Duration| {
+            format!(
+                r#"  {{"status":{},"time":"{}","msg":"{}","duration":{},"important":true}}"#,
+                up as u8,
+                time.with_timezone(&Utc).format("%Y-%m-%d %H:%M:%S"),
+                msg,
+                duration.num_seconds()
+            )
+        };
+    let mut heartbeats = vec![heartbeat(
+        start,
+        true,
+        String::from("Mains power on (first reading)"),
+        chrono::Duration::zero(),
+    )];
+    let mut since = start;
+    for blackout in blackouts {
+        heartbeats.push(heartbeat(
+            blackout.timestamp,
+            false,
+            String::from("Blackout: no reading from the logger"),
+            blackout.timestamp - since,
+        ));
+        heartbeats.push(heartbeat(
+            blackout.after.timestamp,
+            true,
+            format!(
+                "Mains power back after {}",
+                format_duration(blackout.duration)
+            ),
+            blackout.after.timestamp - blackout.timestamp,
+        ));
+        since = blackout.after.timestamp;
+    }
     let mut f = File::create(filename)?;
-    writeln!(f, "== PARAMETER HISTORY ==")?;
-    writeln!(f)?;
+    writeln!(f, "[")?;
+    writeln!(f, "{}", heartbeats.join(",\n"))?;
+    writeln!(f, "]")?;
+    Ok(())
+}
+
+// One row per minute recorded on all the phases of a three-phase supply, with its current imbalance
+// and estimated neutral current, and whether they are above the limits
+pub fn save_phase_balance_csv(
+    filename: impl AsRef<Path>,
+    readings: &[PhaseReadings],
+    limits: BalanceLimits,
+) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    let phase_count = readings.first().map_or(0, |r| r.phases.len());
+    let mut header = (1..=phase_count)
+        .map(|phase| format!("Current L{} (A)", phase))
+        .collect::<Vec<_>>();
+    header.extend(
+        [
+            "Imbalance (%)",
+            "Neutral Current (A)",
+            "Unbalanced",
+            "Neutral Overload",
+        ]
+        .map(String::from),
+    );
+    wtr.write_record(std::iter::once(String::from("Timestamp")).chain(header))?;
+    let optional = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.2}", v));
+    for r in readings {
+        let imbalance = r.current_imbalance();
+        let neutral = r.neutral_current();
+        let mut record = vec![r.timestamp.format("%Y-%m-%d %H:%M").to_string()];
+        record.extend(r.phases.iter().map(|e| e.current.to_string()));
+        record.extend([
+            optional(imbalance.map(|i| i * 100.0)),
+            optional(neutral),
+            imbalance
+                .is_some_and(|i| i > limits.max_imbalance)
+                .to_string(),
+            neutral
+                .is_some_and(|n| n > limits.max_neutral_current)
+                .to_string(),
+        ]);
+        wtr.write_record(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn save_voltage_power_csv(
+    filename: impl AsRef<Path>,
+    power_events: &[PowerEvent],
+) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    wtr.write_record(["Active Power (kW)", "Voltage (V)"])?;
     for pe in power_events {
-        writeln!(
-            f,
-            "{} U={:.1}V I={:.3}A cosPHI={:.2} P={:.3}kW S={:.3}kVA",
-            pe.timestamp.format("[%Y-%m-%d %H:%M]"),
-            pe.voltage,
-            pe.current,
-            pe.power_factor,
-            pe.power,
-            pe.apparent_power
+        wtr.write_record([pe.power.to_string(), pe.voltage.to_string()])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// One row per day with readings: the energy, and the mean and robust average of the power and of
+// the voltage side by side, to see how much short spikes skew the means
+pub fn save_daily_csv(
+    filename: impl AsRef<Path>,
+    daily: &[DailyPowerInfo],
+    robust: &RobustInfo,
+) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    let label = robust_label(robust.average);
+    wtr.write_record([
+        String::from("Date"),
+        String::from("Energy (kWh)"),
+        String::from("Mean Power (kW)"),
+        format!("{} Power (kW)", label),
+        String::from("Mean Voltage (V)"),
+        format!("{} Voltage (V)", label),
+    ])?;
+    for (day, robust) in daily.iter().zip(&robust.daily) {
+        wtr.write_record([
+            day.date.format("%Y-%m-%d").to_string(),
+            day.stats.total_active_power.to_string(),
+            day.stats.avg_active_power.to_string(),
+            robust.power.to_string(),
+            day.stats.avg_voltage.to_string(),
+            robust.voltage.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// One row per day with readings: the active, reactive and apparent energy, for the daily trend of
+// the reactive load
+pub fn save_reactive_csv(filename: impl AsRef<Path>, info: &ReactiveInfo) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    wtr.write_record([
+        "Date",
+        "Readings",
+        "Active Energy (kWh)",
+        "Reactive Energy (kvarh)",
+        "Apparent Energy (kVAh)",
+        "Reactive Share (Q/S)",
+        "tanPHI",
+    ])?;
+    let optional = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.4}", v));
+    for day in &info.daily {
+        wtr.write_record([
+            day.date.format("%Y-%m-%d").to_string(),
+            day.load.readings.to_string(),
+            day.load.active_energy.to_string(),
+            day.load.reactive_energy.to_string(),
+            day.load.apparent_energy.to_string(),
+            optional(day.load.reactive_share()),
+            optional(day.load.tan_phi()),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// The average reactive power (kvar) by hour of the week: a row per day, a column per hour, empty
+// for the hours without readings
+pub fn save_reactive_heatmap_csv(
+    filename: impl AsRef<Path>,
+    info: &ReactiveInfo,
+) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    wtr.write_record(
+        std::iter::once(String::from("Weekday")).chain((0..24).map(|h| format!("{:02}:00", h))),
+    )?;
+    for (day, hours) in info.heatmap.iter().enumerate() {
+        wtr.write_record(
+            std::iter::once(WEEKDAYS[day].to_string()).chain(hours.iter().map(|load| {
+                load.avg_reactive_power()
+                    .map_or(String::new(), |p| format!("{:.4}", p))
+            })),
         )?;
     }
+    wtr.flush()?;
     Ok(())
 }
 
-pub fn save_parameter_history_csv(
-    filename: &str,
-    power_events: &[PowerEvent],
+// One row per interval holding readings (see resample), for tools that want 15 minute data
+pub fn save_resampled_csv(
+    filename: impl AsRef<Path>,
+    resampled: &[ResampledEvent],
 ) -> Result<(), io::Error> {
     let mut wtr = csv::Writer::from_path(filename)?;
-    wtr.write_record(&[
-        "Timestamp",
-        "Voltage (V)",
-        "Current (A)",
-        "cosPHI",
-        "Active Power (kW)",
-        "Apparent Power (kVA)",
+    wtr.write_record([
+        "Start",
+        "Readings",
+        "Average Voltage (V)",
+        "Average Current (A)",
+        "Average Active Power (kW)",
+        "Maximum Active Power (kW)",
+        "Average Apparent Power (kVA)",
+        "Average Reactive Power (kvar)",
+        "Energy (kWh)",
     ])?;
-    for pe in power_events {
-        wtr.write_record(&[
-            pe.timestamp.format("%Y-%m-%d %H:%M").to_string(),
-            pe.voltage.to_string(),
-            pe.current.to_string(),
-            pe.power_factor.to_string(),
-            pe.power.to_string(),
-            pe.apparent_power.to_string(),
+    for r in resampled {
+        wtr.write_record([
+            r.start.format("%Y-%m-%d %H:%M").to_string(),
+            r.readings.to_string(),
+            r.avg_voltage.to_string(),
+            r.avg_current.to_string(),
+            r.avg_power.to_string(),
+            r.max_power.to_string(),
+            r.avg_apparent_power.to_string(),
+            format!("{:.4}", r.avg_reactive_power),
+            r.energy.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// One row per minute of the week holding readings (there are 10080 of them with a full week of data)
+pub fn save_weekly_profile_csv(
+    filename: impl AsRef<Path>,
+    profile: &WeeklyProfile,
+) -> Result<(), io::Error> {
+    let mut wtr = csv::Writer::from_path(filename)?;
+    wtr.write_record([
+        "Weekday",
+        "Time",
+        "Readings",
+        "Average Active Power (kW)",
+        "Maximum Active Power (kW)",
+    ])?;
+    for (index, slot) in profile.slots().iter().enumerate() {
+        if slot.count == 0 {
+            continue;
+        }
+        wtr.write_record([
+            WEEKDAYS[index / 1440].to_string(),
+            format!("{:02}:{:02}", index % 1440 / 60, index % 60),
+            slot.count.to_string(),
+            slot.mean.to_string(),
+            slot.max.to_string(),
         ])?;
     }
     wtr.flush()?;
@@ -53,11 +471,15 @@ pub fn save_parameter_history_csv(
 }
 
 pub fn save_statistics(
-    filename: &str,
-    overall_stats: &OverallPowerInfo,
-    daily_stats: &[DailyPowerInfo],
-    blackout_stats: &BlackoutInfo,
+    filename: impl AsRef<Path>,
+    report: &StatisticsReport,
+    detail: DetailLevel,
 ) -> Result<(), io::Error> {
+    let overall_stats = &report.overall;
+    let daily_stats = &report.daily;
+    let hourly_stats = &report.hourly;
+    let blackout_stats = &report.blackouts;
+    let unfiltered_stats = report.unfiltered.as_ref();
     let mut f = File::create(filename)?;
     // Statistics for the entire period
     writeln!(f, "==== OVERALL STATISTICS ==================")?;
@@ -80,6 +502,13 @@ pub fn save_statistics(
             )?;
         }
     }
+    for x in &report.excluded {
+        writeln!(
+            f,
+            "Excluded: {} ({} reading(s), {:.2}kWh), left out of the statistics and projections.",
+            x.period, x.readings, x.energy
+        )?;
+    }
     writeln!(f)?;
     writeln!(f, "- ACTIVE POWER")?;
     writeln!(
@@ -87,6 +516,13 @@ pub fn save_statistics(
         "Total energy consumption: {:.2}kWh.",
         overall_stats.stats.total_active_power
     )?;
+    if let Some((count, energy)) = report.filled {
+        writeln!(
+            f,
+            "Of which {:.2}kWh estimated for {} minute(s) filled in, missing in short gaps.",
+            energy, count
+        )?;
+    }
     writeln!(
         f,
         "Peak power was {:.2}kW and occured on {}.",
@@ -102,6 +538,14 @@ pub fn save_statistics(
         "Minute by minute average power: {:.2}kW.",
         overall_stats.stats.avg_active_power
     )?;
+    if let Some(u) = unfiltered_stats {
+        writeln!(
+            f,
+            "Unfiltered energy consumption: {:.2}kWh ({:.2}kWh removed by the noise deadband).",
+            u.stats.total_active_power,
+            u.stats.total_active_power - overall_stats.stats.total_active_power
+        )?;
+    }
     writeln!(f)?;
     writeln!(f, "- APPARENT POWER")?;
     writeln!(
@@ -124,6 +568,14 @@ pub fn save_statistics(
         "Minute by minute average power: {:.2}kVA.",
         overall_stats.stats.avg_apparent_power
     )?;
+    if let Some(u) = unfiltered_stats {
+        writeln!(
+            f,
+            "Unfiltered energy consumption: {:.2}kVAh ({:.2}kVAh removed by the noise deadband).",
+            u.stats.total_apparent_power,
+            u.stats.total_apparent_power - overall_stats.stats.total_apparent_power
+        )?;
+    }
     writeln!(f)?;
     writeln!(f, "- VOLTAGE")?;
     writeln!(
@@ -154,6 +606,54 @@ pub fn save_statistics(
     writeln!(f)?;
     writeln!(f)?;
 
+    write_consistency(&mut f, &report.consistency, &report.sources, detail)?;
+    writeln!(f)?;
+
+    if let Some(cycles) = &report.cycles {
+        write_cycles(&mut f, cycles, detail)?;
+        writeln!(f)?;
+    }
+
+    if let Some(shifting) = &report.load_shifting {
+        write_load_shifting(&mut f, shifting)?;
+        writeln!(f)?;
+    }
+
+    if let Some(billing) = &report.billing {
+        write_billing(&mut f, billing, detail)?;
+        writeln!(f)?;
+    }
+
+    if let Some(shaving) = &report.peak_shaving {
+        write_peak_shaving(&mut f, shaving)?;
+        writeln!(f)?;
+    }
+
+    if let Some(ups) = &report.ups {
+        write_ups(&mut f, ups)?;
+        writeln!(f)?;
+    }
+
+    if let Some(three_phase) = &report.three_phase {
+        write_three_phase(&mut f, three_phase)?;
+        writeln!(f)?;
+    }
+
+    write_power_quality(&mut f, report)?;
+    writeln!(f)?;
+
+    if detail == DetailLevel::Summary {
+        // The summary ends with the blackout totals, without the daily breakdown
+        writeln!(f, "==== BLACKOUTS ===========================")?;
+        writeln!(
+            f,
+            "{} blackout(s) for a total of {}.",
+            blackout_stats.blackout_count,
+            format_duration(blackout_stats.total_blackout_duration)
+        )?;
+        return Ok(());
+    }
+
     writeln!(f, "==== DAILY STATISTICS ====================")?;
     // Daily statistics
     for interval in daily_stats {
@@ -205,6 +705,46 @@ pub fn save_statistics(
                 .timestamp
                 .format("[%Y-%m-%d %H:%M]")
         )?;
+        // Alongside the means above, to tell the spikes from the base load
+        let robust = report.robust.as_ref().map(|r| {
+            let day = r.daily.iter().find(|d| d.date == interval.date);
+            (r.average, day)
+        });
+        if let Some((average, Some(day))) = robust {
+            writeln!(
+                f,
+                "    {}: power {:.2}kW | voltage {:.1}V",
+                robust_label(average),
+                day.power,
+                day.voltage
+            )?;
+        }
+        if let Some(d) = interval.stats.distortion_proxy() {
+            writeln!(f, "    Distortion proxy (1 - P/S): {:.1}%", d * 100.0)?;
+        }
+        let reactive = report
+            .reactive
+            .daily
+            .iter()
+            .find(|d| d.date == interval.date)
+            .map(|d| d.load)
+            .unwrap_or_default();
+        if let Some(share) = reactive.reactive_share() {
+            writeln!(
+                f,
+                "    Reactive energy: {:.2}kvarh ({:.1}% of the apparent energy)",
+                reactive.reactive_energy,
+                share * 100.0
+            )?;
+        }
+        if detail == DetailLevel::Full {
+            write_hourly_table(
+                &mut f,
+                hourly_stats
+                    .iter()
+                    .filter(|h| h.hour.date_naive() == interval.date),
+            )?;
+        }
         writeln!(f)?;
     }
 
@@ -225,19 +765,1673 @@ pub fn save_statistics(
             be.timestamp.format("[%Y-%m-%d %H:%M]"),
             format_duration(be.duration),
         )?;
+        if detail == DetailLevel::Full {
+            writeln!(
+                f,
+                "    Before: U={:.1}V P={:.3}kW on {} | After: U={:.1}V P={:.3}kW on {}",
+                be.before.voltage,
+                be.before.power,
+                be.before.timestamp.format("[%Y-%m-%d %H:%M]"),
+                be.after.voltage,
+                be.after.power,
+                be.after.timestamp.format("[%Y-%m-%d %H:%M]")
+            )?;
+        }
+    }
+    if let Some(mtbf) = blackout_stats.mtbf {
+        write_blackout_patterns(&mut f, blackout_stats, mtbf)?;
+    }
+
+    writeln!(f)?;
+    writeln!(f)?;
+    // Periods not covered by any data file
+    writeln!(f, "==== FILE COVERAGE GAPS ==================")?;
+    writeln!(
+        f,
+        "{} gap(s) between data files for a total of {}.",
+        report.file_gaps.len(),
+        format_duration(
+            report
+                .file_gaps
+                .iter()
+                .fold(chrono::Duration::zero(), |sum, x| sum + x.duration)
+        )
+    )?;
+    writeln!(f)?;
+    for g in &report.file_gaps {
+        writeln!(
+            f,
+            "{} Duration: {} (after {}, before {})",
+            g.timestamp.format("[%Y-%m-%d %H:%M]"),
+            format_duration(g.duration),
+            g.previous_file,
+            g.next_file
+        )?;
+    }
+
+    writeln!(f)?;
+    writeln!(f)?;
+    // Settings the report was generated with, so it can be reproduced
+    writeln!(f, "==== CONFIGURATION =======================")?;
+    for (key, value) in &report.config {
+        writeln!(f, "{} = {}", key, value)?;
     }
     Ok(())
 }
 
-fn format_duration(duration: chrono::Duration) -> String {
-    let minutes = (duration.num_seconds() / 60) % 60;
-    let hours = (duration.num_seconds() / 3600) % 24;
-    let days = duration.num_seconds() / 86400;
-    if days > 0 {
-        format!("{:0>2}d:{:0>2}h:{:0>2}m", days, hours, minutes)
-    } else if hours > 0 {
-        format!("{:0>2}h:{:0>2}m", hours, minutes)
-    } else {
-        format!("{:0>2}m", minutes)
+pub fn save_digest(
+    filename: impl AsRef<Path>,
+    day: &DailyPowerInfo,
+    blackout_stats: &BlackoutInfo,
+    price: Option<f64>,
+) -> Result<(), io::Error> {
+    let mut f = File::create(filename)?;
+    let blackouts = blackout_stats
+        .blackouts
+        .iter()
+        .filter(|b| b.timestamp.date_naive() == day.date)
+        .collect::<Vec<_>>();
+    writeln!(f, "Voltcraft digest for {}", day.date.format("%Y-%m-%d"))?;
+    match price {
+        None => writeln!(f, "Energy: {:.2}kWh", day.stats.total_active_power)?,
+        Some(p) => writeln!(
+            f,
+            "Energy: {:.2}kWh | Cost: {:.2}",
+            day.stats.total_active_power,
+            day.stats.total_active_power * p
+        )?,
+    }
+    writeln!(
+        f,
+        "Peak: {:.2}kW at {}",
+        day.stats.max_active_power.power,
+        day.stats.max_active_power.timestamp.format("%H:%M")
+    )?;
+    writeln!(
+        f,
+        "Minimum voltage: {:.1}V at {}",
+        day.stats.min_voltage.voltage,
+        day.stats.min_voltage.timestamp.format("%H:%M")
+    )?;
+    writeln!(
+        f,
+        "Blackouts: {} ({})",
+        blackouts.len(),
+        format_duration(
+            blackouts
+                .iter()
+                .fold(chrono::Duration::zero(), |sum, x| sum + x.duration)
+        )
+    )?;
+    writeln!(
+        f,
+        "Recorded: {} ({:.1}%)",
+        format_duration(day.stats.total_duration),
+        day.stats.total_duration.num_seconds() as f64 * 100.0 / 86400.0
+    )?;
+    Ok(())
+}
+
+// Statistics of a quick preview, from one reading in every (see ParseOptions::stride)
+pub fn save_preview(
+    filename: impl AsRef<Path>,
+    overall_stats: &OverallPowerInfo,
+    every: usize,
+    readings: usize, // decoded
+) -> Result<(), io::Error> {
+    let mut f = File::create(filename)?;
+    writeln!(f, "==== APPROXIMATE STATISTICS ==============")?;
+    writeln!(
+        f,
+        "Preview from one reading in {} ({} readings decoded): the energy and the time recorded are estimates, the extremes those of the readings decoded.",
+        every, readings
+    )?;
+    writeln!(
+        f,
+        "Interval: {}-{} ({}), about {} recorded.",
+        overall_stats.start.format("[%Y-%m-%d %H:%M]"),
+        overall_stats.end.format("[%Y-%m-%d %H:%M]"),
+        format_duration(overall_stats.end - overall_stats.start),
+        format_duration(overall_stats.stats.total_duration)
+    )?;
+    if let Some(d) = overall_stats.avg_daily_power_consumption {
+        writeln!(f, "Average consumption: about {:.2}kWh/day.", d)?;
+    }
+    writeln!(f)?;
+    writeln!(
+        f,
+        "Active energy: about {:.2}kWh, average power {:.2}kW, peak {:.2}kW on {}.",
+        overall_stats.stats.total_active_power,
+        overall_stats.stats.avg_active_power,
+        overall_stats.stats.max_active_power.power,
+        overall_stats
+            .stats
+            .max_active_power
+            .timestamp
+            .format("[%Y-%m-%d %H:%M]")
+    )?;
+    writeln!(
+        f,
+        "Apparent energy: about {:.2}kVAh, average power {:.2}kVA.",
+        overall_stats.stats.total_apparent_power, overall_stats.stats.avg_apparent_power
+    )?;
+    writeln!(
+        f,
+        "Voltage: average {:.1}V, minimum {:.1}V on {}, maximum {:.1}V on {}.",
+        overall_stats.stats.avg_voltage,
+        overall_stats.stats.min_voltage.voltage,
+        overall_stats
+            .stats
+            .min_voltage
+            .timestamp
+            .format("[%Y-%m-%d %H:%M]"),
+        overall_stats.stats.max_voltage.voltage,
+        overall_stats
+            .stats
+            .max_voltage
+            .timestamp
+            .format("[%Y-%m-%d %H:%M]")
+    )?;
+    Ok(())
+}
+
+// Data quality of each input file as found while decoding, to audit large archives
+pub fn save_diagnostics(
+    filename: impl AsRef<Path>,
+    files: &[DatasetFile],
+    conflicts: &[Conflict], // minutes whose readings differ, once merged
+) -> Result<(), io::Error> {
+    let mut f = File::create(filename)?;
+    let reports = files
+        .iter()
+        .filter_map(|d| d.outcome.as_ref().ok())
+        .collect::<Vec<_>>();
+    writeln!(f, "==== PARSE DIAGNOSTICS ===================")?;
+    writeln!(
+        f,
+        "{} file(s): {} decoded, {} failed",
+        files.len(),
+        reports.len(),
+        files.len() - reports.len()
+    )?;
+    writeln!(
+        f,
+        "{} record(s) in {} data block(s), {} byte(s) skipped, {} sample(s) dropped by validation",
+        reports.iter().map(|r| r.record_count).sum::<usize>(),
+        reports.iter().map(|r| r.sessions.len()).sum::<usize>(),
+        reports.iter().map(|r| r.skipped_bytes()).sum::<usize>(),
+        reports.iter().map(|r| r.dropped_samples).sum::<usize>()
+    )?;
+    for diagnostics in files {
+        writeln!(f)?;
+        writeln!(f, "- {}", diagnostics.name)?;
+        let report = match &diagnostics.outcome {
+            Ok(report) => report,
+            Err(e) => {
+                writeln!(f, "  Failed: {}", e)?;
+                continue;
+            }
+        };
+        writeln!(
+            f,
+            "  Records: {} in {} data block(s)",
+            report.record_count,
+            report.sessions.len()
+        )?;
+        match report.time_range() {
+            Some((start, end)) => writeln!(
+                f,
+                "  Period: {} to {}",
+                start.format("%Y-%m-%d %H:%M"),
+                end.format("%Y-%m-%d %H:%M")
+            )?,
+            None => writeln!(f, "  Period: no readings")?,
+        }
+        writeln!(f, "  Skipped: {} byte(s)", report.skipped_bytes())?;
+        writeln!(
+            f,
+            "  Power factor above 1.0: {} sample(s)",
+            report.power_factor_anomalies
+        )?;
+        writeln!(
+            f,
+            "  Implausible voltage: {} sample(s)",
+            report.implausible_voltages
+        )?;
+        writeln!(
+            f,
+            "  Implausible current: {} sample(s)",
+            report.implausible_currents
+        )?;
+        writeln!(
+            f,
+            "  Dropped by validation: {} sample(s)",
+            report.dropped_samples
+        )?;
+        if !report.calibration.is_identity() {
+            let calibration = &report.calibration;
+            writeln!(
+                f,
+                "  Calibration: voltage {}, current {}, power {}",
+                calibration.voltage, calibration.current, calibration.power
+            )?;
+        }
+        if report.missing_end_marker {
+            writeln!(
+                f,
+                "  No end of data marker: probably copied while the logger was recording"
+            )?;
+        }
+        for diagnostic in &report.diagnostics {
+            writeln!(
+                f,
+                "  Corrupted data at offset {}: {}",
+                diagnostic.offset, diagnostic
+            )?;
+        }
+    }
+    if !conflicts.is_empty() {
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} conflict(s), minutes read more than once with different values (see the conflicts CSV file):",
+            conflicts.len()
+        )?;
+        let reading = |e: &PowerEvent| {
+            let file = e
+                .source
+                .and_then(|s| files.get(s.file))
+                .map_or("unknown file", |f| f.name.as_str());
+            format!(
+                "{:.1}V {:.3}A cos {:.2} ({})",
+                e.voltage, e.current, e.power_factor, file
+            )
+        };
+        for conflict in conflicts {
+            writeln!(
+                f,
+                "- {}: {} / {}",
+                conflict.first.timestamp.format("%Y-%m-%d %H:%M"),
+                reading(&conflict.first),
+                reading(&conflict.second)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The readings dropped for minutes recorded more than once, by pair of files (see remove_duplicates)
+pub fn save_duplicates(
+    filename: impl AsRef<Path>,
+    duplicates: &[Overlap],
+    sources: &[String],
+) -> Result<(), io::Error> {
+    let mut f = File::create(filename)?;
+    let count = duplicates.iter().map(|d| d.count).sum::<usize>();
+    let conflicting = duplicates.iter().map(|d| d.differing).sum::<usize>();
+    writeln!(f, "==== DUPLICATE READINGS ==================")?;
+    writeln!(
+        f,
+        "{} reading(s) dropped for minutes recorded more than once:",
+        count
+    )?;
+    writeln!(f, "  {} exact duplicate(s)", count - conflicting)?;
+    writeln!(
+        f,
+        "  {} conflicting duplicate(s), with different values",
+        conflicting
+    )?;
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    // Only the files with duplicates, numbered by their index among the input files
+    let files = duplicates
+        .iter()
+        .flat_map(|d| [d.superseded, d.by])
+        .sorted()
+        .dedup()
+        .collect::<Vec<_>>();
+    writeln!(f)?;
+    writeln!(f, "- FILES")?;
+    for &file in &files {
+        let name = sources.get(file).map_or("(unknown)", String::as_str);
+        writeln!(f, "  #{} {}", file + 1, name)?;
+    }
+    writeln!(f)?;
+    writeln!(f, "- OVERLAP MATRIX")?;
+    writeln!(
+        f,
+        "Readings of the file of each row dropped for those of the file of each column (conflicting ones in parentheses):"
+    )?;
+    let header = files
+        .iter()
+        .map(|file| format!("{:<12}", format!("#{}", file + 1)))
+        .join("");
+    writeln!(f, "        {}", header.trim_end())?;
+    for &row in &files {
+        let cells = files
+            .iter()
+            .map(|&column| {
+                let cell = duplicates
+                    .iter()
+                    .find(|d| (d.superseded, d.by) == (row, column))
+                    .map_or(String::from("-"), |d| {
+                        format!("{} ({})", d.count, d.differing)
+                    });
+                format!("{:<12}", cell)
+            })
+            .join("");
+        writeln!(f, "  {:<6}{}", format!("#{}", row + 1), cells.trim_end())?;
+    }
+    Ok(())
+}
+
+// One line per input file: what it held, what was kept of it once merged with the others and
+// what decoding warned about
+pub fn file_summary_table(files: &[FileSummary]) -> String {
+    let header = [
+        "File",
+        "Records",
+        "From",
+        "To",
+        "kWh",
+        "Merged",
+        "Merged kWh",
+        "Warnings",
+    ]
+    .map(String::from);
+    let rows = files
+        .iter()
+        .map(|summary| {
+            let file = summary.file.name.clone();
+            let report = match &summary.file.outcome {
+                Ok(report) => report,
+                Err(e) => {
+                    let empty = ["-", "-", "-", "-", "-", "-"].map(String::from);
+                    let [records, from, to, energy, merged, merged_energy] = empty;
+                    let warnings = format!("failed: {}", e);
+                    return [
+                        file,
+                        records,
+                        from,
+                        to,
+                        energy,
+                        merged,
+                        merged_energy,
+                        warnings,
+                    ];
+                }
+            };
+            let (from, to) = match report.time_range() {
+                Some((start, end)) => (
+                    start.format("%Y-%m-%d %H:%M").to_string(),
+                    end.format("%Y-%m-%d %H:%M").to_string(),
+                ),
+                None => (String::from("-"), String::from("-")),
+            };
+            let mut warnings = Vec::new();
+            if report.is_empty() {
+                warnings.push(String::from("header only, no readings"));
+            }
+            if !report.diagnostics.is_empty() {
+                warnings.push(format!("{} corrupted region(s)", report.diagnostics.len()));
+            }
+            if report.missing_end_marker {
+                warnings.push(String::from("no end of data marker"));
+            }
+            for (count, what) in [
+                (report.power_factor_anomalies, "power factor above 1.0"),
+                (report.implausible_voltages, "implausible voltage"),
+                (report.implausible_currents, "implausible current"),
+                (report.skipped_local_times.len(), "dated in a skipped hour"),
+            ] {
+                if count > 0 {
+                    warnings.push(format!("{} {}", count, what));
+                }
+            }
+            [
+                file,
+                report.record_count.to_string(),
+                from,
+                to,
+                format!("{:.3}", summary.file.scan.map_or(0.0, |s| s.energy)),
+                summary.merged_readings.to_string(),
+                format!("{:.3}", summary.merged_energy),
+                if warnings.is_empty() {
+                    String::from("-")
+                } else {
+                    warnings.join(", ")
+                },
+            ]
+        })
+        .collect::<Vec<_>>();
+    let widths = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    // Names and text left aligned, figures right aligned
+    std::iter::once(&header)
+        .chain(&rows)
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, &width))| match column {
+                    0 | 2 | 3 | 7 => format!("{:<width$}", cell),
+                    _ => format!("{:>width$}", cell),
+                })
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .join("\n")
+}
+
+// The file summary, with the totals of the merged readings
+pub fn save_file_summary(
+    filename: impl AsRef<Path>,
+    files: &[FileSummary],
+) -> Result<(), io::Error> {
+    let mut f = File::create(filename)?;
+    writeln!(f, "==== INPUT FILES =========================")?;
+    // Files holding only a header are valid, but contribute nothing
+    let empty = files
+        .iter()
+        .filter(|s| s.file.outcome.as_ref().is_ok_and(|r| r.is_empty()))
+        .count();
+    writeln!(
+        f,
+        "{} file(s){}: {} reading(s) merged, {:.3} kWh",
+        files.len(),
+        if empty > 0 {
+            format!(" ({} without readings)", empty)
+        } else {
+            String::new()
+        },
+        files.iter().map(|s| s.merged_readings).sum::<usize>(),
+        files.iter().map(|s| s.merged_energy).sum::<f64>()
+    )?;
+    writeln!(f)?;
+    writeln!(f, "{}", file_summary_table(files))?;
+    Ok(())
+}
+
+fn write_power_quality(f: &mut File, report: &StatisticsReport) -> Result<(), io::Error> {
+    writeln!(f, "==== POWER QUALITY =======================")?;
+    writeln!(f, "- VOLTAGE VS. LOAD")?;
+    match (report.correlation.coefficient, report.correlation.slope) {
+        (Some(r), Some(slope)) => {
+            writeln!(
+                f,
+                "Correlation between active power and voltage: {:.2} ({} samples).",
+                r, report.correlation.sample_count
+            )?;
+            writeln!(f, "Voltage changes by {:.1}V per kW of load.", slope)?;
+            // A strong negative correlation means the voltage sags with our own load, i.e. the
+            // impedance of the installation (or the last mile) is to blame rather than the grid
+            let verdict = if r <= -0.5 {
+                "Voltage sags strongly with the monitored load: check the wiring and connections of the installation."
+            } else if r <= -0.2 {
+                "Voltage sags moderately with the monitored load."
+            } else {
+                "Voltage varies independently of the monitored load: variations most likely come from the grid."
+            };
+            writeln!(f, "{}", verdict)?;
+        }
+        _ => writeln!(
+            f,
+            "Not enough variation in the data to correlate voltage with load."
+        )?,
+    }
+    writeln!(f)?;
+    write_deviation(f, &report.deviation)?;
+    write_sags(f, &report.sags)?;
+    write_flicker(f, &report.flicker)?;
+    write_distortion(f, report)?;
+    write_reactive(f, &report.reactive)?;
+    Ok(())
+}
+
+// Reactive energy, its daily trend and a heatmap of when reactive (e.g. inductive) loads run
+fn write_reactive(f: &mut File, info: &ReactiveInfo) -> Result<(), io::Error> {
+    writeln!(f, "- REACTIVE LOAD")?;
+    let total = &info.total;
+    let (Some(share), Some((day, hour, peak))) = (total.reactive_share(), info.peak()) else {
+        writeln!(f, "No apparent power recorded.")?;
+        writeln!(f)?;
+        return Ok(());
+    };
+    writeln!(
+        f,
+        "Reactive energy: {:.2}kvarh, {:.1}% of the apparent energy (Q/S).",
+        total.reactive_energy,
+        share * 100.0
+    )?;
+    if let Some(t) = info.trend {
+        writeln!(
+            f,
+            "Daily trend: {:+.2} percentage points per 30 days ({}).",
+            t * 100.0,
+            if t > 0.0 {
+                "rising"
+            } else {
+                "falling or steady"
+            }
+        )?;
+    }
+    writeln!(
+        f,
+        "Highest average reactive power: {:.3}kvar, on {} {:02}:00-{:02}:59.",
+        peak, WEEKDAYS[day], hour, hour
+    )?;
+    if let Some(tan_phi) = total.tan_phi() {
+        // Utilities that bill reactive energy usually do so above tan phi 0.4 (cos phi 0.93)
+        writeln!(
+            f,
+            "Reactive over active energy: tanPHI {:.2} ({}).",
+            tan_phi,
+            if tan_phi > 0.4 {
+                "above 0.4, where reactive energy is commonly billed: power factor correction may pay off"
+            } else {
+                "below 0.4, where reactive energy is commonly billed: power factor correction is unlikely to pay off"
+            }
+        )?;
+    }
+    // One character per hour, from '.' (none) to '@' (the highest average reactive power)
+    const SHADES: &[u8] = b".:-=+*#%@";
+    writeln!(
+        f,
+        "Average reactive power by hour of the week ('.' none to '@' {:.3}kvar, blank without readings):",
+        peak
+    )?;
+    writeln!(f, "    0     6     12    18")?;
+    for (day, hours) in info.heatmap.iter().enumerate() {
+        let row = hours
+            .iter()
+            .map(|load| match load.avg_reactive_power() {
+                Some(power) if peak > 0.0 => {
+                    let shade = (power / peak * (SHADES.len() - 1) as f64).round() as usize;
+                    SHADES[shade] as char
+                }
+                Some(_) => SHADES[0] as char,
+                None => ' ',
+            })
+            .collect::<String>();
+        writeln!(f, "{}", format!("{} {}", WEEKDAYS[day], row).trim_end())?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_distortion(f: &mut File, report: &StatisticsReport) -> Result<(), io::Error> {
+    writeln!(f, "- LOAD NON-LINEARITY")?;
+    writeln!(
+        f,
+        "The logger only records RMS values, so harmonics can't be measured directly. As a proxy, the share of"
+    )?;
+    writeln!(
+        f,
+        "apparent energy not converted to active energy (1 - P/S) grows with reactive and non-linear loads such"
+    )?;
+    writeln!(
+        f,
+        "as switching power supplies, LED drivers and variable speed motors. A rising trend hints at such loads."
+    )?;
+    if let Some(d) = report.overall.stats.distortion_proxy() {
+        writeln!(
+            f,
+            "Distortion proxy for the entire period: {:.1}%.",
+            d * 100.0
+        )?;
+    }
+    if let Some(t) = report.distortion_trend {
+        writeln!(
+            f,
+            "Daily trend: {:+.2} percentage points per 30 days ({}).",
+            t * 100.0,
+            if t > 0.0 {
+                "rising"
+            } else {
+                "falling or steady"
+            }
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_deviation(f: &mut File, deviation: &VoltageDeviationInfo) -> Result<(), io::Error> {
+    let grid = &deviation.grid;
+    writeln!(f, "- VOLTAGE DEVIATION")?;
+    writeln!(
+        f,
+        "Grid: {}V/{}Hz, voltage within {}% of the nominal voltage ({:.1}V-{:.1}V).",
+        grid.nominal_voltage,
+        grid.frequency,
+        grid.tolerance,
+        grid.low_voltage(),
+        grid.high_voltage()
+    )?;
+    let share = |minutes: usize| {
+        if deviation.minute_count > 0 {
+            minutes as f64 * 100.0 / deviation.minute_count as f64
+        } else {
+            0.0
+        }
+    };
+    writeln!(
+        f,
+        "{} minute(s) below the band ({:.2}%) and {} above it ({:.2}%), of {} minutes.",
+        deviation.under_minutes,
+        share(deviation.under_minutes),
+        deviation.over_minutes,
+        share(deviation.over_minutes),
+        deviation.minute_count
+    )?;
+    if let (Some(lowest), Some(highest)) = (deviation.lowest, deviation.highest) {
+        writeln!(
+            f,
+            "The voltage ranged from {:+.1}% to {:+.1}% of the nominal voltage.",
+            lowest, highest
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_sags(f: &mut File, sags: &SagCoincidenceInfo) -> Result<(), io::Error> {
+    let percent = |part: usize, total: usize| {
+        if total > 0 {
+            part as f64 * 100.0 / total as f64
+        } else {
+            0.0
+        }
+    };
+    writeln!(f, "- VOLTAGE SAGS VS. LOAD")?;
+    writeln!(
+        f,
+        "{} minute(s) with the voltage below {:.1}V ({:.2}% of {} minutes).",
+        sags.sag_minutes,
+        sags.sag_voltage,
+        percent(sags.sag_minutes, sags.minute_count),
+        sags.minute_count
+    )?;
+    if sags.sag_minutes > 0 {
+        let low_load_sags = sags.sag_minutes - sags.high_load_sags;
+        writeln!(
+            f,
+            "{} of them ({:.1}%) with the load above {:.2}kW, {} ({:.1}%) at a lower load.",
+            sags.high_load_sags,
+            percent(sags.high_load_sags, sags.sag_minutes),
+            sags.high_load,
+            low_load_sags,
+            percent(low_load_sags, sags.sag_minutes)
+        )?;
+        // Comparing the sag rates rather than the counts makes up for the load being high in
+        // only a few minutes
+        match (sags.sag_rate(true), sags.sag_rate(false)) {
+            (Some(high), Some(low)) => {
+                writeln!(
+                    f,
+                    "Sags occur in {:.2}% of the high load minutes and {:.2}% of the other minutes.",
+                    high * 100.0,
+                    low * 100.0
+                )?;
+                let verdict = if high > 2.0 * low {
+                    "Sags are far more frequent at high load: the installation (wiring, connections) is the likely cause."
+                } else {
+                    "Sags are not tied to the monitored load: they most likely come from the grid."
+                };
+                writeln!(f, "{}", verdict)?;
+            }
+            _ => writeln!(
+                f,
+                "The load is always on the same side of {:.2}kW, so sags can't be attributed.",
+                sags.high_load
+            )?,
+        }
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_flicker(f: &mut File, flicker: &FlickerInfo) -> Result<(), io::Error> {
+    writeln!(f, "- RAPID VOLTAGE VARIATIONS")?;
+    writeln!(
+        f,
+        "{} minute(s) with a voltage change above {:.1}V from the previous minute ({:.2}% of {} compared minutes).",
+        flicker.minute_count,
+        flicker.threshold,
+        if flicker.compared_count > 0 {
+            flicker.minute_count as f64 * 100.0 / flicker.compared_count as f64
+        } else {
+            0.0
+        },
+        flicker.compared_count
+    )?;
+    if !flicker.worst_streaks.is_empty() {
+        writeln!(f, "Worst streaks:")?;
+    }
+    for s in &flicker.worst_streaks {
+        writeln!(
+            f,
+            "{} {} consecutive minute(s), largest change {:.1}V",
+            s.timestamp.format("[%Y-%m-%d %H:%M]"),
+            s.minutes,
+            s.max_change
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_cycles(f: &mut File, info: &CycleInfo, detail: DetailLevel) -> Result<(), io::Error> {
+    let profile = &info.profile;
+    writeln!(f, "==== APPLIANCE CYCLES ====================")?;
+    writeln!(
+        f,
+        "Profile: {} (running above {:.0}W, cycles of {}-{} minutes expected).",
+        profile.name, profile.running_power, profile.typical_cycle.0, profile.typical_cycle.1
+    )?;
+    if info.cycles.is_empty() {
+        writeln!(f, "No cycles found.")?;
+    } else {
+        let count = info.cycles.len() as f64;
+        writeln!(
+            f,
+            "{} cycle(s), {:.0} minute(s) and {:.3}kWh on average.",
+            info.cycles.len(),
+            info.cycles.iter().map(|c| c.minutes).sum::<i64>() as f64 / count,
+            info.cycles.iter().map(|c| c.energy).sum::<f64>() / count
+        )?;
+    }
+    writeln!(
+        f,
+        "{} cycle(s) outside the typical duration.",
+        info.unusual_cycles.len()
+    )?;
+    if detail == DetailLevel::Full {
+        for c in &info.unusual_cycles {
+            writeln!(
+                f,
+                "{} {} minute(s), {:.3}kWh",
+                c.timestamp.format("[%Y-%m-%d %H:%M]"),
+                c.minutes,
+                c.energy
+            )?;
+        }
+    }
+    writeln!(
+        f,
+        "{} reading(s) above the {:.0}W expected at most.",
+        info.over_max_count, profile.max_power
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_load_shifting(f: &mut File, info: &LoadShiftingInfo) -> Result<(), io::Error> {
+    let tariff = &info.tariff;
+    writeln!(f, "==== LOAD SHIFTING =======================")?;
+    writeln!(
+        f,
+        "Off-peak hours: {:02}:00-{:02}:00 at {:.2} per kWh (peak: {:.2} per kWh).",
+        tariff.off_peak_hours.start,
+        tariff.off_peak_hours.end,
+        tariff.off_peak_price,
+        tariff.peak_price
+    )?;
+    if info.peak_cycles == 0 {
+        writeln!(f, "All cycles already started in off-peak hours.")?;
+    } else {
+        writeln!(
+            f,
+            "{} cycle(s) started in peak hours, using {:.3}kWh.",
+            info.peak_cycles, info.peak_energy
+        )?;
+        writeln!(
+            f,
+            "Starting them in off-peak hours would save ~{:.2} over the period (~{:.2}/month).",
+            info.savings, info.monthly_savings
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_billing(f: &mut File, info: &BillingInfo, detail: DetailLevel) -> Result<(), io::Error> {
+    let rounding = &info.rounding;
+    // The billed energy with the decimals of the metering step
+    let decimals = rounding
+        .step
+        .to_string()
+        .split_once('.')
+        .map_or(0, |(_, d)| d.len());
+    let cost = |cost: Option<f64>| cost.map_or(String::new(), |c| format!(", cost {:.2}", c));
+    writeln!(f, "==== BILLED ENERGY =======================")?;
+    writeln!(
+        f,
+        "Energy billed per {}, rounded {} {}kWh{}.",
+        match rounding.period {
+            BillingPeriod::Day => "day",
+            BillingPeriod::Month => "month",
+        },
+        match rounding.mode {
+            RoundingMode::Nearest => "to the nearest",
+            RoundingMode::Up => "up to",
+            RoundingMode::Down => "down to",
+        },
+        rounding.step,
+        info.price
+            .map_or(String::new(), |p| format!(" at {:.2} per kWh", p))
+    )?;
+    if detail != DetailLevel::Summary {
+        for period in &info.periods {
+            let date = match rounding.period {
+                BillingPeriod::Day => period.start.format("[%Y-%m-%d]"),
+                BillingPeriod::Month => period.start.format("[%Y-%m]"),
+            };
+            writeln!(
+                f,
+                "{} {:.3}kWh recorded, {:.*}kWh billed{}",
+                date,
+                period.energy,
+                decimals,
+                period.billed,
+                cost(period.cost)
+            )?;
+        }
+    }
+    writeln!(
+        f,
+        "Total: {:.3}kWh recorded, {:.*}kWh billed ({:+.3}kWh){}.",
+        info.energy(),
+        decimals,
+        info.billed(),
+        info.billed() - info.energy(),
+        cost(info.cost())
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_peak_shaving(f: &mut File, info: &PeakShavingInfo) -> Result<(), io::Error> {
+    writeln!(f, "==== PEAK SHAVING ========================")?;
+    writeln!(f, "Power limit: {:.2}kW.", info.limit)?;
+    match info.worst {
+        None => writeln!(f, "The load never exceeded the limit.")?,
+        Some(worst) => {
+            writeln!(
+                f,
+                "{} minute(s) above the limit, on {} day(s).",
+                info.affected_minutes, info.affected_days
+            )?;
+            writeln!(
+                f,
+                "{:.3}kWh above the limit would need shifting or curtailing.",
+                info.excess_energy
+            )?;
+            writeln!(
+                f,
+                "Largest excess: {:.2}kW on {}.",
+                worst.power - info.limit,
+                worst.timestamp.format("[%Y-%m-%d %H:%M]")
+            )?;
+        }
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_three_phase(f: &mut File, info: &ThreePhaseInfo) -> Result<(), io::Error> {
+    let percent = |imbalance: Option<f64>| {
+        imbalance.map_or(String::from("n/a"), |i| format!("{:.1}%", i * 100.0))
+    };
+    writeln!(f, "==== THREE-PHASE SUPPLY ==================")?;
+    writeln!(
+        f,
+        "{} minute(s) recorded on all the phases.",
+        info.aligned_minutes
+    )?;
+    writeln!(
+        f,
+        "Readings left out, as missing on another phase: {}.",
+        info.unaligned
+            .iter()
+            .enumerate()
+            .map(|(phase, count)| format!("L{} {}", phase + 1, count))
+            .join(", ")
+    )?;
+    writeln!(f, "Total energy consumption: {:.2}kWh.", info.total_energy)?;
+    writeln!(
+        f,
+        "Peak combined power was {:.2}kW and occured on {}.",
+        info.max_power.1,
+        info.max_power.0.format("[%Y-%m-%d %H:%M]")
+    )?;
+    writeln!(
+        f,
+        "Phase imbalance: {} of the energy, {} of the current on average.",
+        percent(info.energy_imbalance),
+        percent(info.avg_current_imbalance)
+    )?;
+    if let Some((timestamp, imbalance)) = info.max_current_imbalance {
+        writeln!(
+            f,
+            "Largest current imbalance was {:.1}% and occured on {}.",
+            imbalance * 100.0,
+            timestamp.format("[%Y-%m-%d %H:%M]")
+        )?;
+    }
+    if let (Some(avg), Some((timestamp, max))) =
+        (info.avg_neutral_current, info.max_neutral_current)
+    {
+        writeln!(
+            f,
+            "Estimated neutral current (phase currents 120° apart): {:.2}A on average, peak {:.2}A on {}.",
+            avg,
+            max,
+            timestamp.format("[%Y-%m-%d %H:%M]")
+        )?;
+    }
+    let share = |minutes: usize| minutes as f64 * 100.0 / info.aligned_minutes as f64;
+    writeln!(
+        f,
+        "{} minute(s) ({:.2}%) with the current imbalance above {:.0}%, {} minute(s) ({:.2}%) with the neutral current above {:.1}A.",
+        info.unbalanced_minutes,
+        share(info.unbalanced_minutes),
+        info.limits.max_imbalance * 100.0,
+        info.neutral_overload_minutes,
+        share(info.neutral_overload_minutes),
+        info.limits.max_neutral_current
+    )?;
+    writeln!(f, "    Phase | Energy  | Current | Voltage (avg/min/max)")?;
+    for (phase, p) in info.phases.iter().enumerate() {
+        writeln!(
+            f,
+            "    L{}    | {:.2}kWh | {:.2}A | {:.1}V / {:.1}V / {:.1}V",
+            phase + 1,
+            p.energy,
+            p.avg_current,
+            p.avg_voltage,
+            p.min_voltage.voltage,
+            p.max_voltage.voltage
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+// When the blackouts tend to happen, e.g. to show the utility that outages cluster at some hours
+fn write_blackout_patterns(
+    f: &mut File,
+    blackout_stats: &BlackoutInfo,
+    mtbf: chrono::Duration,
+) -> Result<(), io::Error> {
+    let bar = |count: usize| {
+        format!("{:>4} {}", count, "#".repeat(count))
+            .trim_end()
+            .to_string()
+    };
+    writeln!(f)?;
+    writeln!(f, "Mean time between failures: {}.", format_duration(mtbf))?;
+    writeln!(f, "Blackouts by hour of the day they started:")?;
+    for (hour, count) in blackout_stats.by_hour.iter().enumerate() {
+        writeln!(f, "    {:0>2}:00 {}", hour, bar(*count))?;
+    }
+    writeln!(f, "Blackouts by weekday they started:")?;
+    for (day, count) in WEEKDAYS.iter().zip(blackout_stats.by_weekday) {
+        writeln!(f, "    {}   {}", day, bar(count))?;
+    }
+    Ok(())
+}
+
+fn write_ups(f: &mut File, info: &UpsInfo) -> Result<(), io::Error> {
+    writeln!(f, "==== UPS RUNTIME =========================")?;
+    writeln!(
+        f,
+        "Battery of {:.2}kWh, inverter efficiency {:.0}%.",
+        info.capacity,
+        info.efficiency * 100.0
+    )?;
+    writeln!(
+        f,
+        "{} of {} blackout(s) would have been carried through.",
+        info.carried_count,
+        info.blackouts.len()
+    )?;
+    writeln!(f)?;
+    for ub in &info.blackouts {
+        writeln!(
+            f,
+            "{} Duration: {} | Load: {:.3}kW | Runtime: {} | {}",
+            ub.blackout.timestamp.format("[%Y-%m-%d %H:%M]"),
+            format_duration(ub.blackout.duration),
+            ub.load,
+            ub.runtime
+                .map_or(String::from("unlimited"), format_duration),
+            if ub.carried { "carried" } else { "not carried" }
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_consistency(
+    f: &mut File,
+    consistency: &ConsistencyInfo,
+    sources: &[String],
+    detail: DetailLevel,
+) -> Result<(), io::Error> {
+    writeln!(f, "==== DATA QUALITY ========================")?;
+    writeln!(
+        f,
+        "{} record(s) with active power above apparent power (P > S).",
+        consistency.active_exceeds_apparent.len()
+    )?;
+    writeln!(
+        f,
+        "{} record(s) with apparent power deviating from U*I by more than {:.1}%.",
+        consistency.apparent_mismatch.len(),
+        consistency.tolerance * 100.0
+    )?;
+    writeln!(f, "{} record(s) checked.", consistency.checked_count)?;
+    if detail == DetailLevel::Full {
+        for pe in consistency
+            .active_exceeds_apparent
+            .iter()
+            .chain(consistency.apparent_mismatch.iter())
+        {
+            writeln!(
+                f,
+                "{} U={:.1}V I={:.3}A cosPHI={:.2} P={:.3}kW S={:.3}kVA{}",
+                pe.timestamp.format("[%Y-%m-%d %H:%M]"),
+                pe.voltage,
+                pe.current,
+                pe.power_factor,
+                pe.power,
+                pe.apparent_power,
+                format_source(pe, sources)
+            )?;
+        }
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+fn write_hourly_table<'a>(
+    f: &mut File,
+    hourly_stats: impl Iterator<Item = &'a HourlyPowerInfo>,
+) -> Result<(), io::Error> {
+    writeln!(
+        f,
+        "    Hour  | Energy   | Average | Maximum | Voltage (avg/min/max)"
+    )?;
+    for h in hourly_stats {
+        writeln!(
+            f,
+            "    {} | {:.3}kWh | {:.3}kW | {:.3}kW | {:.1}V / {:.1}V / {:.1}V",
+            h.hour.format("%H:%M"),
+            h.stats.total_active_power,
+            h.stats.avg_active_power,
+            h.stats.max_active_power.power,
+            h.stats.avg_voltage,
+            h.stats.min_voltage.voltage,
+            h.stats.max_voltage.voltage
+        )?;
+    }
+    Ok(())
+}
+
+// How a robust average is named in the reports, e.g. "Trimmed mean (10%)"
+fn robust_label(average: RobustAverage) -> String {
+    match average {
+        RobustAverage::Median => String::from("Median"),
+        RobustAverage::TrimmedMean(percent) => format!("Trimmed mean ({}%)", percent),
+    }
+}
+
+// Where a reading was decoded from, for the listings of suspicious readings
+fn format_source(pe: &PowerEvent, sources: &[String]) -> String {
+    match pe.source {
+        Some(source) => match sources.get(source.file) {
+            Some(file) => format!(" ({} at offset {})", file, source.offset),
+            None => format!(" (at offset {})", source.offset),
+        },
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{parse_args, Command};
+    use crate::voltcraft::data::{ParseMode, ParseOptions, VoltcraftData};
+    use crate::voltcraft::dataset::{DatasetBuilder, DatasetOptions, VoltcraftDataset};
+    use crate::voltcraft::merge::ChronologicalMerge;
+    use crate::voltcraft::phases::align_phases;
+    use crate::voltcraft::pipeline::HistoryColumn;
+    use crate::voltcraft::resample::resample;
+    use crate::voltcraft::stats::VoltcraftStatistics;
+    use crate::voltcraft::transform::{fill_gaps, remove_duplicates};
+    use std::ffi::OsString;
+    use std::fs;
+
+    // Two blocks of synthetic readings around midnight, separated by a blackout
+    fn synthetic_fixture() -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut sample = 0u16;
+        for (timestamp, count) in [([9, 11, 14, 23, 50], 15), ([9, 12, 14, 0, 30], 15)] {
+            data.extend_from_slice(&[0xE0, 0xC5, 0xEA]);
+            data.extend_from_slice(&timestamp);
+            for _ in 0..count {
+                let voltage = 2300 + sample * 7 % 50 - 25; // 0.1V
+                let current = sample * 37 % 900 + 50; // mA
+                let power_factor = sample * 13 % 50 + 50; // 0.01
+                data.extend_from_slice(&voltage.to_be_bytes());
+                data.extend_from_slice(&current.to_be_bytes());
+                data.push(power_factor as u8);
+                sample += 1;
+            }
+        }
+        data.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        data
+    }
+
+    fn real_fixture() -> Vec<u8> {
+        fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/sample_data1/A04FC8D2.BIN"
+        ))
+        .unwrap()
+    }
+
+    // The given files decoded in lenient mode and merged
+    fn dataset(files: &[(&str, Vec<u8>)]) -> VoltcraftDataset {
+        let options =
+            DatasetOptions::new().parse_options(ParseOptions::new().mode(ParseMode::Lenient));
+        let mut builder = DatasetBuilder::new(options);
+        for (name, data) in files {
+            builder.add(*name, Ok(VoltcraftData::from_raw(data.clone())));
+        }
+        builder.build().unwrap()
+    }
+
+    // Run the given exporter into a temporary file and return what it wrote
+    fn export(save: impl FnOnce(&Path) -> Result<(), io::Error>) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export");
+        save(&path).unwrap();
+        fs::read_to_string(path).unwrap()
+    }
+
+    // The statistics report as the command line would compute it with the given options
+    fn report(data: Vec<u8>, args: &[&str]) -> (Vec<PowerEvent>, StatisticsReport) {
+        let (mut events, _) = VoltcraftData::from_raw(data).parse().unwrap();
+        let args = ["voltcraft_energy_analyzer"]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let Ok(Command::Run(options)) = parse_args(&args) else {
+            panic!("invalid options {:?}", args);
+        };
+        events.dedup_by(|a, b| a.timestamp == b.timestamp);
+        let report = crate::compute_report(
+            &mut events.clone(),
+            &[],
+            &options,
+            &[],
+            &["fixture.bin".to_string()],
+        )
+        .unwrap();
+        (events, report)
+    }
+
+    #[test]
+    fn synthetic_exports() {
+        let (events, report) = report(synthetic_fixture(), &[]);
+        insta::assert_snapshot!(
+            "synthetic_history_txt",
+            export(|f| save_parameter_history_txt(f, &events, &HistoryLayout::default()))
+        );
+        let layout = HistoryLayout {
+            timestamp_format: String::from("%d.%m.%Y %H:%M:%S"),
+            columns: vec![HistoryColumn::Power, HistoryColumn::Voltage],
+        };
+        insta::assert_snapshot!(
+            "synthetic_history_txt_layout",
+            export(|f| save_parameter_history_txt(f, &events[..3], &layout))
+        );
+        // The values as stored, where the decoder kept them
+        let layout = HistoryLayout {
+            columns: vec![HistoryColumn::Voltage, HistoryColumn::Registers],
+            ..layout
+        };
+        let (kept, _) = VoltcraftData::from_raw(synthetic_fixture())
+            .parse_with(&ParseOptions::new().keep_registers(true))
+            .unwrap();
+        let history = export(|f| save_parameter_history_txt(f, &[events[0], kept[1]], &layout));
+        let lines = history.lines().skip(2).collect::<Vec<_>>();
+        assert!(lines[0].ends_with("RAW=-"), "{}", lines[0]);
+        let registers = kept[1].registers.unwrap();
+        assert!(lines[1].ends_with(&format!(
+            "U={:.1}V RAW={}/{}/{}",
+            kept[1].voltage, registers.voltage, registers.current, registers.power_factor
+        )));
+        insta::assert_snapshot!(
+            "synthetic_history_csv",
+            export(|f| save_parameter_history_csv(f, &events, false, false))
+        );
+        // The derived quantities, on demand
+        let history = export(|f| save_parameter_history_csv(f, &events[..1], true, false));
+        let derived = events[0].derived();
+        assert!(history.lines().nth(1).unwrap().ends_with(&format!(
+            ",{:.4},{:.2},{:.3}",
+            derived.reactive_power,
+            derived.phase_angle,
+            derived.tan_phi.unwrap()
+        )));
+        insta::assert_snapshot!(
+            "synthetic_voltage_power_csv",
+            export(|f| save_voltage_power_csv(f, &events))
+        );
+        insta::assert_snapshot!(
+            "synthetic_reactive_csv",
+            export(|f| save_reactive_csv(f, &report.reactive))
+        );
+        insta::assert_snapshot!(
+            "synthetic_reactive_heatmap_csv",
+            export(|f| save_reactive_heatmap_csv(f, &report.reactive))
+        );
+        insta::assert_snapshot!(
+            "synthetic_resampled_csv",
+            export(|f| save_resampled_csv(
+                f,
+                &resample(&events, chrono::Duration::minutes(15)).unwrap()
+            ))
+        );
+        let profile = VoltcraftStatistics::new(&mut events.clone()).weekly_profile();
+        insta::assert_snapshot!(
+            "synthetic_weekly_profile_csv",
+            export(|f| save_weekly_profile_csv(f, &profile))
+        );
+        for detail in [DetailLevel::Summary, DetailLevel::Normal, DetailLevel::Full] {
+            insta::assert_snapshot!(
+                format!("synthetic_stats_{}", detail),
+                export(|f| save_statistics(f, &report, detail))
+            );
+        }
+        insta::assert_snapshot!(
+            "synthetic_digest",
+            export(|f| save_digest(f, &report.daily[0], &report.blackouts, Some(0.25)))
+        );
+    }
+
+    #[test]
+    fn source_references() {
+        let (events, _) = VoltcraftData::from_raw(synthetic_fixture())
+            .parse_with(&ParseOptions::new().source_file(1))
+            .unwrap();
+        let sources = ["A.BIN".to_string(), "B.BIN".to_string()];
+        assert_eq!(format_source(&events[1], &sources), " (B.BIN at offset 13)");
+        assert_eq!(format_source(&events[1], &[]), " (at offset 13)");
+    }
+
+    #[test]
+    fn real_exports() {
+        let (_, report) = report(real_fixture(), &[]);
+        insta::assert_snapshot!(
+            "real_stats_full",
+            export(|f| save_statistics(f, &report, DetailLevel::Full))
+        );
+        insta::assert_snapshot!(
+            "real_digest",
+            export(|f| save_digest(f, report.daily.last().unwrap(), &report.blackouts, None))
+        );
+    }
+
+    #[test]
+    fn appliance_exports() {
+        let (_, report) = report(real_fixture(), &["--appliance", "fridge"]);
+        insta::assert_snapshot!(
+            "real_stats_fridge",
+            export(|f| save_statistics(f, &report, DetailLevel::Full))
+        );
+    }
+
+    #[test]
+    fn peak_shaving_exports() {
+        let (_, report) = report(real_fixture(), &["--power-limit", "1"]);
+        insta::assert_snapshot!(
+            "real_stats_peak_shaving",
+            export(|f| save_statistics(f, &report, DetailLevel::Summary))
+        );
+    }
+
+    #[test]
+    fn billing_exports() {
+        let args = ["--billing-rounding", "day:up", "--price", "0.25"];
+        let (_, report) = report(real_fixture(), &args);
+        insta::assert_snapshot!(
+            "real_stats_billing",
+            export(|f| save_statistics(f, &report, DetailLevel::Summary))
+        );
+        // With a line for each day otherwise
+        let stats = export(|f| save_statistics(f, &report, DetailLevel::Normal));
+        assert_eq!(
+            stats.matches("kWh billed, cost").count(),
+            report.daily.len()
+        );
+        let billing = report.billing.unwrap();
+        assert!(billing.periods.iter().all(|p| p.billed >= p.energy));
+    }
+
+    #[test]
+    fn robust_exports() {
+        let (_, report) = report(real_fixture(), &["--robust", "trimmed:5"]);
+        let robust = report.robust.as_ref().unwrap();
+        assert_eq!(robust.average, RobustAverage::TrimmedMean(5.0));
+        insta::assert_snapshot!(
+            "real_daily_csv",
+            export(|f| save_daily_csv(f, &report.daily, robust))
+        );
+        let stats = export(|f| save_statistics(f, &report, DetailLevel::Normal));
+        assert_eq!(
+            stats.matches("Trimmed mean (5%): power").count(),
+            report.daily.len()
+        );
+        assert_eq!(robust_label(RobustAverage::Median), "Median");
+    }
+
+    #[test]
+    fn excluded_exports() {
+        use chrono::Datelike;
+        let args = [
+            "--exclude",
+            "2014-09-11",
+            "--exclude",
+            "2014-10-01..2014-10-05",
+        ];
+        let (events, report) = report(real_fixture(), &args);
+        assert_eq!(report.excluded.len(), 1);
+        let excluded = events.iter().filter(|e| e.timestamp.day() == 11).count();
+        assert_eq!(report.excluded[0].readings, excluded);
+        assert_eq!(report.daily.len(), 1);
+        let stats = export(|f| save_statistics(f, &report, DetailLevel::Summary));
+        assert!(stats.contains(&format!(
+            "Excluded: 2014-09-11 ({} reading(s), {:.2}kWh), left out",
+            excluded, report.excluded[0].energy
+        )));
+    }
+
+    #[test]
+    fn filled_exports() {
+        let args = ["voltcraft_energy_analyzer", "--fill-gaps", "2:zero"].map(OsString::from);
+        let Ok(Command::Run(options)) = parse_args(&args) else {
+            panic!("invalid options");
+        };
+        let (mut events, _) = VoltcraftData::from_raw(synthetic_fixture())
+            .parse()
+            .unwrap();
+        events.drain(3..5);
+        assert_eq!(fill_gaps(&mut events, options.fill_gaps.unwrap()), 2);
+        let history = export(|f| save_parameter_history_txt(f, &events, &HistoryLayout::default()));
+        let flagged = history.lines().filter(|l| l.ends_with(" FILLED")).count();
+        assert_eq!(flagged, 2);
+        let history = export(|f| save_parameter_history_csv(f, &events, false, true));
+        assert!(history.lines().nth(4).unwrap().ends_with(",true"));
+        let report =
+            crate::compute_report(&mut events, &[], &options, &[], &[String::new()]).unwrap();
+        assert_eq!(report.filled, Some((2, 0.0)));
+        let stats = export(|f| save_statistics(f, &report, DetailLevel::Summary));
+        assert!(stats.contains("Of which 0.00kWh estimated for 2 minute(s) filled in"));
+    }
+
+    #[test]
+    fn ups_exports() {
+        let (_, report) = report(synthetic_fixture(), &["--ups-capacity", "0.05"]);
+        insta::assert_snapshot!(
+            "synthetic_stats_ups",
+            export(|f| save_statistics(f, &report, DetailLevel::Summary))
+        );
+    }
+
+    #[test]
+    fn sag_exports() {
+        let args = ["--sag-voltage", "230", "--high-load", "0.1"];
+        let (_, report) = report(synthetic_fixture(), &args);
+        insta::assert_snapshot!(
+            "synthetic_stats_sags",
+            export(|f| save_statistics(f, &report, DetailLevel::Summary))
+        );
+    }
+
+    #[test]
+    fn grid_exports() {
+        // A band tight enough for some of the synthetic minutes to fall outside it
+        let (_, report) = report(synthetic_fixture(), &["--grid", "230V/60Hz/0.5%"]);
+        assert_eq!(report.sags.sag_voltage, 228.85);
+        assert!(report.deviation.under_minutes > 0 && report.deviation.over_minutes > 0);
+        insta::assert_snapshot!(
+            "synthetic_stats_grid",
+            export(|f| save_statistics(f, &report, DetailLevel::Summary))
+        );
+    }
+
+    #[test]
+    fn three_phase_exports() {
+        let args = [
+            "voltcraft_energy_analyzer",
+            "--phase",
+            "L2",
+            "--phase",
+            "L3",
+        ]
+        .map(OsString::from);
+        let Ok(Command::Run(options)) = parse_args(&args) else {
+            panic!("invalid options {:?}", args);
+        };
+        // The other phases: less loaded, and at a higher voltage
+        let (events, _) = VoltcraftData::from_raw(synthetic_fixture())
+            .parse()
+            .unwrap();
+        let phase = |load: f64, volts: f64| {
+            events
+                .iter()
+                .map(|e| {
+                    let (voltage, current) = (e.voltage + volts, e.current * load);
+                    PowerEvent {
+                        voltage,
+                        current,
+                        power: voltage * current * e.power_factor / 1000.0,
+                        apparent_power: voltage * current / 1000.0,
+                        ..*e
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let phases = [phase(0.5, 3.0), phase(0.8, -2.0)];
+        let report = crate::compute_report(
+            &mut events.clone(),
+            &phases,
+            &options,
+            &[],
+            &["fixture.bin".to_string()],
+        )
+        .unwrap();
+        insta::assert_snapshot!(
+            "synthetic_stats_three_phase",
+            export(|f| save_statistics(f, &report, DetailLevel::Summary))
+        );
+        let readings = align_phases(&[&events, &phases[0], &phases[1]]);
+        insta::assert_snapshot!(
+            "synthetic_phase_balance_csv",
+            export(|f| save_phase_balance_csv(f, &readings, options.balance_limits))
+        );
+    }
+
+    #[test]
+    fn diagnostics_export() {
+        // The synthetic readings with a corrupted block header, a file that isn't a data file, the
+        // synthetic readings copied while the logger was recording (ending within a reading), and
+        // a file holding their first block twice, with a reading read differently the second time
+        let mut corrupted = synthetic_fixture();
+        corrupted[83..91].copy_from_slice(&[0xE0, 0xC5, 0xEA, 0x0D, 0x0B, 0x0E, 0x12, 0x2B]);
+        let unterminated = synthetic_fixture()[..85].to_vec();
+        let mut repeated = synthetic_fixture()[..83].repeat(2);
+        repeated[83 + 8 + 1] += 3; // 0.3V more
+        repeated.extend_from_slice(&[0xFF; 4]);
+        let dataset = dataset(&[
+            ("A.BIN", synthetic_fixture()),
+            ("B.BIN", corrupted),
+            ("C.BIN", b"not a data file".to_vec()),
+            ("D.BIN", unterminated),
+            ("E.BIN", repeated),
+        ]);
+        insta::assert_snapshot!(
+            "synthetic_diagnostics",
+            export(|f| save_diagnostics(f, &dataset.files, &dataset.conflicts))
+        );
+    }
+
+    #[test]
+    fn duplicates_export() {
+        // The synthetic readings downloaded twice, with a few of them read differently, and a
+        // reading repeated within the second download
+        let parse = |file| {
+            VoltcraftData::from_raw(synthetic_fixture())
+                .parse_with(&ParseOptions::new().source_file(file))
+                .unwrap()
+                .0
+        };
+        let first = parse(0);
+        let mut second = parse(1);
+        for e in second.iter_mut().step_by(10) {
+            e.voltage += 1.0;
+        }
+        second.insert(5, second[5]);
+        let mut events =
+            ChronologicalMerge::new(vec![first.into_iter(), second.into_iter()]).collect();
+        let duplicates = remove_duplicates(&mut events);
+        let sources = ["A.BIN".to_string(), "B.BIN".to_string()];
+        insta::assert_snapshot!(
+            "synthetic_duplicates",
+            export(|f| save_duplicates(f, &duplicates, &sources))
+        );
+    }
+
+    #[test]
+    fn preview_export() {
+        let (mut events, _) = VoltcraftData::from_raw(synthetic_fixture())
+            .parse_with(&ParseOptions::new().stride(4))
+            .unwrap();
+        let stats = VoltcraftStatistics::new(&mut events)
+            .sampled_stats(4)
+            .unwrap();
+        insta::assert_snapshot!(
+            "synthetic_preview",
+            export(|f| save_preview(f, &stats, 4, events.len()))
+        );
+    }
+
+    #[test]
+    fn uptime_export() {
+        // A logger clock set to UTC+2, so that the heartbeats are moved to UTC
+        let options = ParseOptions::new().timezone(chrono::FixedOffset::east_opt(7200).unwrap());
+        let (mut events, _) = VoltcraftData::from_raw(synthetic_fixture())
+            .parse_with(&options)
+            .unwrap();
+        let start = events[0].timestamp;
+        let blackouts = VoltcraftStatistics::new(&mut events)
+            .blackout_stats()
+            .blackouts;
+        insta::assert_snapshot!(
+            "synthetic_uptime_csv",
+            export(|f| save_uptime_csv(f, start, &blackouts))
+        );
+        insta::assert_snapshot!(
+            "synthetic_uptime_json",
+            export(|f| save_uptime_json(f, start, &blackouts))
+        );
+    }
+
+    #[test]
+    fn conflicts_export() {
+        // The synthetic readings downloaded twice, with a few of them read differently the second
+        // time, and a reading of the second download repeated differently
+        let dataset = {
+            let mut second = synthetic_fixture();
+            for offset in [8 + 1, 8 + 5 * 10 + 1, 83 + 8 + 5 * 3 + 3] {
+                second[offset] += 2;
+            }
+            second.truncate(second.len() - 4);
+            second.extend_from_slice(&synthetic_fixture()[83..83 + 8 + 5]);
+            let current = second.len() - 2;
+            second[current] += 1;
+            second.extend_from_slice(&[0xFF; 4]);
+            dataset(&[("A.BIN", synthetic_fixture()), ("B.BIN", second)])
+        };
+        let sources = dataset
+            .files
+            .iter()
+            .map(|f| f.name.clone())
+            .collect::<Vec<_>>();
+        insta::assert_snapshot!(
+            "synthetic_conflicts",
+            export(|f| save_conflicts_csv(f, &dataset.conflicts, &sources))
+        );
+    }
+
+    #[test]
+    fn file_summary_export() {
+        // The synthetic readings downloaded twice, the second time while the logger was still
+        // recording (so only the first block, without the end of data marker), which supersedes
+        // the first download where they overlap, a file that isn't a data file and one saved
+        // before the logger recorded anything (a data block header only)
+        let header_only = [&synthetic_fixture()[..8], &[0xFF; 4]].concat();
+        let dataset = dataset(&[
+            ("A.BIN", synthetic_fixture()),
+            ("B.BIN", synthetic_fixture()[..85].to_vec()),
+            ("C.BIN", b"not a data file".to_vec()),
+            ("D.BIN", header_only),
+        ]);
+        let summaries = dataset
+            .files
+            .iter()
+            .zip(dataset.contributions())
+            .map(|(file, (merged_readings, merged_energy))| FileSummary {
+                file,
+                merged_readings,
+                merged_energy,
+            })
+            .collect::<Vec<_>>();
+        insta::assert_snapshot!(
+            "synthetic_file_summary",
+            export(|f| save_file_summary(f, &summaries))
+        );
+    }
+
+    #[test]
+    fn load_shifting_exports() {
+        let args = [
+            "--appliance",
+            "washing-machine",
+            "--price",
+            "0.25",
+            "--off-peak-price",
+            "0.12",
+        ];
+        let (_, report) = report(real_fixture(), &args);
+        insta::assert_snapshot!(
+            "real_stats_load_shifting",
+            export(|f| save_statistics(f, &report, DetailLevel::Summary))
+        );
     }
 }
@@ -0,0 +1,5 @@
+// Decoder and statistics for the files generated by the Voltcraft Energy Logger 4000, usable on
+// their own; the command line tool and its report exporters live in the binary. Without the std
+// feature, only the byte-level decoder is built, as a no_std library.
+#![cfg_attr(not(feature = "std"), no_std)]
+pub mod voltcraft;
@@ -1,172 +1,1454 @@
+#[cfg(feature = "bundle")]
+mod bundle;
+mod cli;
+mod console;
+mod doctor;
 mod export;
-mod voltcraft;
+mod perf;
+mod progress;
 
+use chrono::{Duration, Local, NaiveDateTime, TimeZone};
+use cli::{parse_args, Command, GenerateOptions, Options, OverwritePolicy, SplitBy};
 use colored::*;
-use glob::glob;
+use console::{accent, error, highlight, ok, warning, ColorChoice, Theme};
+use glob::Pattern;
+use itertools::{Either, Itertools};
+use perf::StageTimer;
+use progress::ProgressEvent;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use std::env;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-use voltcraft::data::{PowerEvent, VoltcraftData};
-use voltcraft::stats::VoltcraftStatistics;
+use voltcraft::archive;
+use voltcraft::data::{ParseError, ParseOptions, PowerEvent, TimeCorrection, VoltcraftData};
+use voltcraft::dataset::{DatasetBuilder, DatasetOptions, VoltcraftDataset};
+use voltcraft::encoder::VoltcraftEncoder;
+use voltcraft::phases::{align_phases, three_phase_stats};
+use voltcraft::pipeline::HistoryColumn;
+use voltcraft::resample::resample;
+#[cfg(feature = "sdcard")]
+use voltcraft::sdcard::SdCardImage;
+use voltcraft::state::{content_hash, CachedFile, DatasetState, ImportLog, StateError};
+use voltcraft::stats::{
+    billed_energy, compute_file_gaps, distortion_trend, load_shifting, FileCoverage,
+    VoltcraftStatistics,
+};
+use voltcraft::tariff::TimeOfUseTariff;
+use voltcraft::transform::{apply_deadband, exclude_periods, fill_gaps, remove_duplicates, smooth};
+#[cfg(feature = "zip")]
+use voltcraft::ziparchive::ZipBundle;
+use voltcraft_energy_analyzer::voltcraft;
 
-use export::{save_parameter_history_csv, save_parameter_history_txt, save_statistics};
+use export::{
+    file_summary_table, save_conflicts_csv, save_daily_csv, save_diagnostics, save_digest,
+    save_duplicates, save_file_summary, save_parameter_history_csv, save_parameter_history_txt,
+    save_phase_balance_csv, save_preview, save_reactive_csv, save_reactive_heatmap_csv,
+    save_resampled_csv, save_statistics, save_uptime_csv, save_uptime_json, save_voltage_power_csv,
+    save_weekly_profile_csv, FileSummary, StatisticsReport, UptimeFormat,
+};
 
 const PARAMETER_HISTORY_FILE_TEXT: &str = "voltcraft_history.txt";
 const PARAMETER_HISTORY_FILE_CSV: &str = "voltcraft_history.csv";
 const STATS_FILE_TEXT: &str = "voltcraft_stats.txt";
+const STATS_FILE_PREFIX: &str = "voltcraft_stats"; // followed by the period, when splitting reports
+const DIGEST_FILE_TEXT: &str = "voltcraft_digest.txt";
+const DUPLICATES_FILE_TEXT: &str = "voltcraft_duplicates.txt";
+const CONFLICTS_FILE_CSV: &str = "voltcraft_conflicts.csv";
+const DIAGNOSTICS_FILE_TEXT: &str = "voltcraft_diagnostics.txt";
+const FILE_SUMMARY_FILE_TEXT: &str = "voltcraft_files.txt";
+const VOLTAGE_POWER_FILE_CSV: &str = "voltcraft_voltage_power.csv";
+const WEEKLY_PROFILE_FILE_CSV: &str = "voltcraft_weekly_profile.csv";
+const RESAMPLED_FILE_CSV: &str = "voltcraft_resampled.csv";
+const SMOOTHED_FILE_CSV: &str = "voltcraft_smoothed.csv";
+const DAILY_FILE_CSV: &str = "voltcraft_daily.csv";
+const REACTIVE_FILE_CSV: &str = "voltcraft_reactive.csv";
+const REACTIVE_HEATMAP_FILE_CSV: &str = "voltcraft_reactive_heatmap.csv";
+const PHASE_BALANCE_FILE_CSV: &str = "voltcraft_phase_balance.csv";
+const UPTIME_FILE_CSV: &str = "voltcraft_uptime.csv";
+const UPTIME_FILE_JSON: &str = "voltcraft_uptime.json";
+const ARCHIVE_FILE: &str = "voltcraft_readings.vca";
+const PREVIEW_FILE_TEXT: &str = "voltcraft_preview.txt";
+const STDIN_INPUT: &str = "-"; // input folder argument reading a single data file from stdin
+const SYNTHETIC_FILE: &str = "SYNTHETIC.BIN"; // written by the generate subcommand
 
 fn main() {
-    // Print welcome text
-    display_welcome();
     // Process command-line arguments
-    let args: Vec<String> = env::args().collect();
-
-    let (mut input_dir, mut output_dir) = {
-        if args.len() == 3 {
-            // We have both the input and the output folder
-            (String::from(&args[1]), String::from(&args[2]))
-        } else if args.len() == 2 {
-            // We only have one argument, check whether help is requested
-            if args[1].eq_ignore_ascii_case("-h")
-                || args[1].eq_ignore_ascii_case("--help")
-                || args[1].eq_ignore_ascii_case("/?")
-            {
-                display_help();
+    let args: Vec<OsString> = env::args_os().collect();
+    let command = parse_args(&args);
+    // Configure the console before printing anything
+    match &command {
+        Ok(Command::Run(options))
+        | Ok(Command::PrintConfig(options))
+        | Ok(Command::Doctor(options)) => console::init(options.color, options.theme),
+        _ => console::init(ColorChoice::Auto, Theme::Dark),
+    }
+    if let Ok(Command::Run(options)) = &command {
+        if options.progress_json {
+            progress::enable();
+        }
+    }
+    // Print welcome text, but not before the feature list, which scripts read
+    if !matches!(command, Ok(Command::Features)) {
+        display_welcome();
+    }
+
+    let options = match command {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::PrintConfig(options)) => {
+            for (key, value) in options.effective_config() {
+                println!("{} = {}", key, value);
+            }
+            return;
+        }
+        Ok(Command::Doctor(options)) => {
+            match doctor::run(&options) {
+                0 => println!("{}", ok("All checks passed.")),
+                failed => println!("{}", error(&format!("{} of the checks failed.", failed))),
+            }
+            return;
+        }
+        Ok(Command::Generate(options)) => {
+            generate(&options);
+            return;
+        }
+        Ok(Command::Features) => {
+            for feature in voltcraft::capabilities() {
+                println!("{}", feature);
+            }
+            return;
+        }
+        Ok(Command::Help) => {
+            display_help();
+            return;
+        }
+        Err(e) => {
+            println!("{}", error(&e));
+            return;
+        }
+    };
+    let input_dir = &options.input_dir;
+    // Paths as shown in the console (and the progress events)
+    let input_name = input_dir.display().to_string();
+    // Where the data files are read from
+    let input = if input_dir.as_os_str() == STDIN_INPUT {
+        Input::Stdin
+    } else if is_zip(input_dir) {
+        match open_zip(input_dir) {
+            Ok(input) => input,
+            Err(e) => {
+                println!(
+                    "{} {} ({})",
+                    error("Failed to read ZIP archive"),
+                    error(&input_name),
+                    e
+                );
                 return;
             }
-            (String::from(&args[1]), String::from("./"))
-        } else {
-            // No folder given
-            (String::from("./"), String::from("./"))
         }
+    } else if input_dir.is_file() {
+        match open_image(input_dir) {
+            Ok(input) => input,
+            Err(e) => {
+                println!(
+                    "{} {} ({})",
+                    error("Failed to read SD card image"),
+                    error(&input_name),
+                    e
+                );
+                return;
+            }
+        }
+    } else {
+        Input::Folder
     };
+    let mut output_dir = options.output_dir.clone();
+
+    // Place the outputs in their own subfolder, if requested
+    if options.timestamped_output {
+        output_dir.push(Local::now().format("%Y-%m-%d_%H%M%S").to_string());
+    }
+    let output_name = output_dir.display().to_string();
 
     // Create output folder
     if fs::create_dir_all(&output_dir).is_err() {
         println!(
             "{} {}",
-            "Failed to create folder".red(),
-            output_dir.bright_red()
+            error("Failed to create folder"),
+            error(&output_name)
         );
         return;
     }
 
-    // Add a trailing / to folders (if doesn't exist already)
-    if !input_dir.ends_with('/') {
-        input_dir.push('/');
+    // Make sure we don't silently replace previous results
+    let mut output_files = vec![
+        PARAMETER_HISTORY_FILE_TEXT,
+        PARAMETER_HISTORY_FILE_CSV,
+        STATS_FILE_TEXT,
+        DUPLICATES_FILE_TEXT,
+        CONFLICTS_FILE_CSV,
+        DIAGNOSTICS_FILE_TEXT,
+        FILE_SUMMARY_FILE_TEXT,
+    ];
+    if options.digest {
+        output_files.push(DIGEST_FILE_TEXT);
+    }
+    if options.scatter {
+        output_files.push(VOLTAGE_POWER_FILE_CSV);
+    }
+    if options.weekly_profile {
+        output_files.push(WEEKLY_PROFILE_FILE_CSV);
+    }
+    if options.resample.is_some() {
+        output_files.push(RESAMPLED_FILE_CSV);
+    }
+    if options.smooth.is_some() {
+        output_files.push(SMOOTHED_FILE_CSV);
+    }
+    if options.robust.is_some() {
+        output_files.push(DAILY_FILE_CSV);
+    }
+    if options.reactive {
+        output_files.extend([REACTIVE_FILE_CSV, REACTIVE_HEATMAP_FILE_CSV]);
+    }
+    match options.uptime {
+        Some(UptimeFormat::Csv) => output_files.push(UPTIME_FILE_CSV),
+        Some(UptimeFormat::Kuma) => output_files.push(UPTIME_FILE_JSON),
+        None => {}
+    }
+    if options.archive {
+        output_files.push(ARCHIVE_FILE);
     }
-    if !output_dir.ends_with('/') {
-        output_dir.push('/');
+    if !options.phase_dirs.is_empty() {
+        output_files.push(PHASE_BALANCE_FILE_CSV);
+    }
+    if options.sample.is_some() {
+        output_files.push(PREVIEW_FILE_TEXT);
+    }
+    let mut existing = output_files
+        .into_iter()
+        .filter(|f| output_dir.join(f).exists())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if options.split_by == SplitBy::Month {
+        // Monthly reports are only known after parsing, so look for any previous ones
+        let pattern = Pattern::new(&format!("{}_????-??.txt", STATS_FILE_PREFIX)).unwrap();
+        existing.extend(
+            list_folder(&output_dir)
+                .iter()
+                .filter_map(|p| p.file_name()?.to_str())
+                .filter(|name| pattern.matches(name))
+                .map(String::from),
+        );
+    }
+    if !existing.is_empty() && !confirm_overwrite(&output_dir, &existing, options.overwrite) {
+        println!(
+            "{} {}",
+            warning("Existing files were left untouched in folder"),
+            highlight(&output_name)
+        );
+        return;
     }
 
-    println!(
-        "Reading data files from folder '{}'.",
-        input_dir.bright_white()
-    );
+    match input {
+        Input::Folder => println!(
+            "Reading data files from folder '{}'.",
+            highlight(&input_name)
+        ),
+        Input::Stdin => println!("Reading a data file from standard input."),
+        #[cfg(feature = "sdcard")]
+        Input::Image(_) => println!(
+            "Reading data files from SD card image '{}'.",
+            highlight(&input_name)
+        ),
+        #[cfg(feature = "zip")]
+        Input::Zip(_) => println!(
+            "Reading data files from ZIP archive '{}'.",
+            highlight(&input_name)
+        ),
+    }
     println!(
         "Writing statistics to folder '{}'.",
-        output_dir.bright_white()
+        highlight(&output_name)
     );
 
     let start_time = Instant::now();
-    // Initialize the vector that stores incoming power events
-    let mut power_events = Vec::<PowerEvent>::new();
-
-    // Parse input folder
-    input_dir.push('*');
+    let mut timer = StageTimer::new();
+    // Names of the output files written successfully
+    let mut written = Vec::<String>::new();
 
-    // Read the input directory and process each file
+    // Read the input directory (or standard input, SD card image or ZIP archive) and process each file
+    let files = match &input {
+        Input::Folder => list_data_files(input_dir),
+        Input::Stdin => vec![PathBuf::from("<stdin>")],
+        #[cfg(feature = "sdcard")]
+        Input::Image(image) => match image.data_files() {
+            Ok(files) => files.into_iter().map(PathBuf::from).collect(),
+            Err(e) => {
+                println!(
+                    "{} {} ({})",
+                    error("Failed to read SD card image"),
+                    error(&input_name),
+                    e
+                );
+                return;
+            }
+        },
+        #[cfg(feature = "zip")]
+        Input::Zip(bundle) => bundle.data_files().into_iter().map(PathBuf::from).collect(),
+    };
+    progress::emit(ProgressEvent::Started {
+        input: &input_name,
+        output: &output_name,
+        files: files.len(),
+    });
+    let mut parse_options = ParseOptions::new()
+        .mode(options.parse_mode)
+        .power_factor_policy(options.power_factor_policy)
+        .validation(options.validation)
+        .calibration(options.calibration)
+        .keep_registers(options.history.columns.contains(&HistoryColumn::Registers));
+    if let Some(timezone) = options.timezone {
+        parse_options = parse_options.timezone(timezone);
+    }
+    if let Some(correction) = time_correction(&options) {
+        parse_options = parse_options.time_correction(correction);
+    }
+    if let Some(every) = options.sample {
+        parse_options = parse_options.stride(every);
+    }
+    // The input files, kept undecoded until they are merged
+    let mut dataset = DatasetBuilder::new(
+        DatasetOptions::new()
+            .parse_options(parse_options)
+            .max_file_size(options.max_file_size)
+            .overlap_policy(options.overlap_policy)
+            .dedup_policy(options.dedup_policy),
+    );
+    // The files decoded by the previous runs, if kept
+    let mut state =
+        options
+            .state_file
+            .as_ref()
+            .map(|path| match DatasetState::load(path, &parse_options) {
+                Ok(state) => state,
+                Err(StateError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    DatasetState::new(&parse_options)
+                }
+                Err(e) => {
+                    println!(
+                        "{} ({}), all the files will be decoded.",
+                        warning("Ignoring the state file"),
+                        e
+                    );
+                    DatasetState::new(&parse_options)
+                }
+            });
+    let mut hashes = Vec::new(); // of the files read, to keep them in the state file
     let mut file_count = 0;
-    for e in glob(input_dir.as_str()).unwrap().filter_map(Result::ok) {
-        let file = e.display().to_string();
-        print!("Processing file: {}...", file);
+    let mut record_count = 0;
+    let mut input_bytes = 0; // of the data files read
+    let mut decoded_events = 0;
+    let mut read = Vec::new();
+    for (index, path) in files.iter().enumerate() {
+        let file = path.display().to_string();
+        progress::emit(ProgressEvent::FileStarted { file: &file, index });
         // Open the file
-        if let Ok(vdf) = VoltcraftData::from_file(&file) {
-            // Parse data
-            if let Ok(mut pev) = vdf.parse() {
-                power_events.append(&mut pev);
+        let data = match &input {
+            Input::Folder => read_data_file(path, options.max_file_size),
+            Input::Stdin => VoltcraftData::from_reader(io::stdin().lock(), options.max_file_size),
+            #[cfg(feature = "sdcard")]
+            Input::Image(image) => image.read(&file, options.max_file_size),
+            #[cfg(feature = "zip")]
+            Input::Zip(bundle) => bundle.read(&file, options.max_file_size),
+        };
+        input_bytes += data.as_ref().map_or(0, |d| d.as_bytes().len() as u64);
+        let hash = data
+            .as_ref()
+            .ok()
+            .filter(|_| state.is_some())
+            .map(|d| content_hash(d.as_bytes()));
+        hashes.push(hash);
+        // Files unchanged since the last run are not decoded again
+        let cached = state
+            .as_mut()
+            .zip(hash)
+            .and_then(|(state, hash)| state.take(&file, hash));
+        read.push((file, cached.map_or(Either::Right(data), Either::Left)));
+    }
+    // Parse data, streaming through the events without keeping them, on all the cores if built
+    // with the parallel feature; the files are reported on and merged in their order all the same
+    let scan = |(file, data): (String, Either<CachedFile, _>)| {
+        let scanned = data.map_right(|data| dataset.scan(file.clone(), data));
+        (file, scanned)
+    };
+    #[cfg(feature = "parallel")]
+    let scanned = read.into_par_iter().map(scan).collect::<Vec<_>>();
+    #[cfg(not(feature = "parallel"))]
+    let scanned = read.into_iter().map(scan).collect::<Vec<_>>();
+    for (file, scanned) in scanned {
+        print!("Processing file: {}...", file);
+        let is_cached = scanned.is_left();
+        let added = match scanned {
+            Either::Left(cached) => dataset.add_decoded(file.clone(), cached.report, cached.events),
+            Either::Right(scanned) => dataset.add_scanned(scanned),
+        };
+        decoded_events += added.scan.map_or(0, |s| s.events);
+        match &added.outcome {
+            Ok(report) => {
+                progress::emit(ProgressEvent::FileParsed {
+                    file: &file,
+                    events: added.scan.map_or(0, |s| s.events),
+                });
                 file_count += 1;
-                println!(" {}", "Ok".green());
-            } else {
-                println!(" {}", "Invalid".red());
+                record_count += report.record_count;
+                let mut notes = vec![if report.is_empty() {
+                    String::from("header only, no records")
+                } else {
+                    format!(
+                        "{} records in {} session(s)",
+                        report.record_count,
+                        report.sessions.len()
+                    )
+                }];
+                if let Some((start, end)) = report.time_range() {
+                    notes.push(format!(
+                        "{} to {}",
+                        start.format("%Y-%m-%d %H:%M"),
+                        end.format("%Y-%m-%d %H:%M")
+                    ));
+                }
+                if report.power_factor_anomalies > 0 {
+                    notes.push(format!(
+                        "{} samples with power factor above 1.0, {:?}",
+                        warning(&report.power_factor_anomalies.to_string()),
+                        options.power_factor_policy
+                    ));
+                }
+                if report.implausible_voltages > 0 {
+                    notes.push(format!(
+                        "{} samples with implausible voltage, {:?}",
+                        warning(&report.implausible_voltages.to_string()),
+                        options.validation.policy
+                    ));
+                }
+                if report.implausible_currents > 0 {
+                    notes.push(format!(
+                        "{} samples with a current above {}A (corrupted), {:?}",
+                        warning(&report.implausible_currents.to_string()),
+                        options.validation.max_current,
+                        options.validation.current_policy
+                    ));
+                }
+                if !report.skipped_local_times.is_empty() {
+                    notes.push(format!(
+                        "{} session(s) dated in the hour skipped when the clocks went forward, read with the UTC offset from before the change",
+                        warning(&report.skipped_local_times.len().to_string())
+                    ));
+                }
+                if report.missing_end_marker {
+                    notes.push(format!(
+                        "{}, probably copied while the logger was recording",
+                        warning("no end of data marker")
+                    ));
+                }
+                if is_cached {
+                    notes.push(String::from("unchanged since the last run"));
+                }
+                println!(" {} ({})", ok("Ok"), notes.join("; "));
+                for diagnostic in &report.diagnostics {
+                    println!("\t{} {}", warning("Skipped corrupted data:"), diagnostic);
+                }
+            }
+            Err(ParseError::Unreadable) => {
+                progress::emit(ProgressEvent::FileFailed {
+                    file: &file,
+                    reason: "unreadable",
+                });
+                println!(" {}", error("Failed to open"));
             }
+            Err(e) => {
+                progress::emit(ProgressEvent::FileFailed {
+                    file: &file,
+                    reason: "invalid",
+                });
+                println!(" {} ({})", error("Invalid"), e);
+            }
+        }
+    }
+
+    // Estimate how full the logger memory was, assuming the input folder holds a single download
+    if record_count > 0 {
+        let fill = record_count as f64 / options.device_capacity as f64 * 100.0;
+        let message = format!(
+            "Device memory usage estimate: {:.1}% ({} of {} records).",
+            fill, record_count, options.device_capacity
+        );
+        if fill >= 90.0 {
+            println!(
+                "{} {}",
+                warning(&message),
+                warning(
+                    "Download the data soon, the logger will start overwriting the oldest records."
+                )
+            );
         } else {
-            println!(" {}", "Failed to open".red());
+            println!("{}", message);
         }
     }
 
+    // Chronologically merge power items (we need this to spot power blackouts), keeping a single
+    // source for minutes covered by several files (the logger rewrote old data)
+    timer.end("Reading");
+    progress::emit(ProgressEvent::Stage { stage: "merging" });
+    // Keep the files decoded for the next run
+    if let Some(path) = &options.state_file {
+        let previous_log = state.as_ref().and_then(DatasetState::import_log);
+        let mut state = DatasetState::new(&parse_options);
+        // Track when new readings were last imported, to tell when the logger data is no longer
+        // downloaded (runs from a scheduler see the same files over and over then)
+        let now = Local::now().fixed_offset();
+        let latest_reading = dataset
+            .files()
+            .iter()
+            .filter_map(|f| f.scan?.coverage.map(|(_, end)| end))
+            .max();
+        let log = match latest_reading {
+            Some(latest) => Some(ImportLog::update(previous_log, latest, now)),
+            None => previous_log,
+        };
+        if let Some(log) = log {
+            state.set_import_log(log);
+            let stale_days = log.staleness(now).num_days();
+            if options
+                .stale_after
+                .is_some_and(|days| stale_days >= days as i64)
+            {
+                println!(
+                    "{}",
+                    warning(&format!(
+                        "No new readings for {} day(s): the latest, of {}, was imported on {}. Download the logger data!",
+                        stale_days,
+                        log.latest_reading.format("%Y-%m-%d %H:%M"),
+                        log.imported_at.format("%Y-%m-%d")
+                    ))
+                );
+                progress::emit(ProgressEvent::Stale {
+                    days: stale_days,
+                    latest_reading: &log.latest_reading.to_rfc3339(),
+                });
+            }
+        }
+        for (index, file) in dataset.files().iter().enumerate() {
+            if let (Some(hash), Ok(report)) = (hashes[index], &file.outcome) {
+                state.insert(CachedFile {
+                    name: file.name.clone(),
+                    hash,
+                    report: report.clone(),
+                    events: dataset.events(index).unwrap_or_default(),
+                });
+            }
+        }
+        print!(
+            "Saving state to file {}...",
+            highlight(&path.display().to_string())
+        );
+        match state.save(path) {
+            Ok(()) => println!(" {}", ok("Ok")),
+            Err(e) => println!(" {} ({})", error("Failed"), e),
+        }
+    }
+    // The events refer to their file by index
+    let source_names = dataset
+        .files()
+        .iter()
+        .map(|f| f.name.clone())
+        .collect::<Vec<_>>();
+    let dataset = match dataset.build() {
+        Ok(dataset) => {
+            timer.end("Merging");
+            dataset
+        }
+        Err(conflict) => {
+            let file = |e: &PowerEvent| e.source.map_or("?", |s| &source_names[s.file]);
+            println!(
+                "{} (in {} and {}, --dedup {}).",
+                error(&format!("Stopped on {}", conflict)),
+                file(&conflict.first),
+                file(&conflict.second),
+                options.dedup_policy
+            );
+            return;
+        }
+    };
+    for overlap in &dataset.overlaps {
+        println!(
+            "{} {} readings of file {} superseded by file {} ({} with different values).",
+            warning("Overlap:"),
+            overlap.count,
+            highlight(&dataset.files[overlap.superseded].name),
+            highlight(&dataset.files[overlap.by].name),
+            overlap.differing
+        );
+    }
+    // The readings (and their energy) kept of each file once merged
+    let contributions = dataset.contributions();
+    // Keep track of the period covered by each file
+    let file_coverage = dataset
+        .files
+        .iter()
+        .filter_map(|f| {
+            let (start, end) = f.scan?.coverage?;
+            Some(FileCoverage {
+                file: f.name.clone(),
+                start,
+                end,
+            })
+        })
+        .collect::<Vec<_>>();
+    let VoltcraftDataset {
+        events: mut power_events,
+        files,
+        overlaps,
+        duplicates: dropped,
+        conflicts,
+    } = dataset;
+
+    // Write what was found in each file, and the minutes whose readings differ, to audit the data
+    // quality of the input files
+    let target_path = output_dir.join(DIAGNOSTICS_FILE_TEXT);
+    print!(
+        "Saving parse diagnostics to file {}...",
+        highlight(DIAGNOSTICS_FILE_TEXT)
+    );
+    report_export(
+        &mut written,
+        DIAGNOSTICS_FILE_TEXT,
+        save_diagnostics(&target_path, &files, &conflicts).is_ok(),
+    );
+
     // Process power events accrued from the parsed data files
-    if !power_events.is_empty() {
-        // Chronologically sort power items (we need this to spot power blackouts)
-        print!("Sorting power data...");
-        power_events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-        println!(" {}", "Done".green());
+    if !file_coverage.is_empty() {
+        print!("Merging power data...");
+        println!(" {}", ok("Done"));
         // Remove duplicate events based on timestamp
+        progress::emit(ProgressEvent::Stage {
+            stage: "deduplicating",
+        });
         print!("Removing duplicates from power data...");
-        power_events.dedup_by(|a, b| a.timestamp == b.timestamp);
-        println!(" {}", "Done".green());
-        // Write power events to text file
-        let mut target_path = output_dir.clone();
-        target_path.push_str(PARAMETER_HISTORY_FILE_TEXT);
-        print!(
-            "Saving parameter history to text file {}...",
-            PARAMETER_HISTORY_FILE_TEXT.bright_white()
+        // Reported by input file, with the readings superseded by another file
+        let duplicates = overlaps.into_iter().chain(dropped).collect::<Vec<_>>();
+        println!(
+            " {} ({} duplicate(s), {} conflicting)",
+            ok("Done"),
+            duplicates.iter().map(|d| d.count).sum::<usize>(),
+            duplicates.iter().map(|d| d.differing).sum::<usize>()
         );
-        if save_parameter_history_txt(target_path.as_str(), &power_events).is_ok() {
-            println!(" {}", "Ok".green());
-        } else {
-            println!(" {}", "Failed".red());
-        }
-        // Write power events to CSV file
-        let mut target_path = output_dir.clone();
-        target_path.push_str(PARAMETER_HISTORY_FILE_CSV);
+        // Write the deduplication report, to audit how much the input files overlapped
+        let target_path = output_dir.join(DUPLICATES_FILE_TEXT);
         print!(
-            "Saving parameter history to CSV file {}...",
-            PARAMETER_HISTORY_FILE_CSV.bright_white()
+            "Saving duplicate readings report to file {}...",
+            highlight(DUPLICATES_FILE_TEXT)
         );
-        if save_parameter_history_csv(target_path.as_str(), &power_events).is_ok() {
-            println!(" {}", "Ok".green());
-        } else {
-            println!(" {}", "Failed".red());
-        }
-        // Compute statistics
-        let mut target_path = output_dir.clone();
-        target_path.push_str(STATS_FILE_TEXT);
-        let stats = VoltcraftStatistics::new(&mut power_events);
+        report_export(
+            &mut written,
+            DUPLICATES_FILE_TEXT,
+            save_duplicates(&target_path, &duplicates, &source_names).is_ok(),
+        );
+        // Write the minutes read more than once with different values, with both readings
+        let target_path = output_dir.join(CONFLICTS_FILE_CSV);
         print!(
-            "Saving statistics to file {}...",
-            STATS_FILE_TEXT.bright_white()
+            "Saving conflicting readings to CSV file {}...",
+            highlight(CONFLICTS_FILE_CSV)
         );
-        if save_statistics(
-            target_path.as_str(),
-            &stats.overall_stats(),
-            &stats.daily_stats(),
-            &stats.blackout_stats(),
-        )
-        .is_ok()
-        {
-            println!(" {}", "Ok".green());
+        report_export(
+            &mut written,
+            CONFLICTS_FILE_CSV,
+            save_conflicts_csv(&target_path, &conflicts, &source_names).is_ok(),
+        );
+        if let Some(every) = options.sample {
+            // Only the approximate statistics: the full reports would show the minutes between the
+            // readings decoded as blackouts
+            println!(
+                "{}",
+                warning(&format!(
+                    "Preview from one reading in {}: the statistics are approximate, run without --sample for the full reports.",
+                    every
+                ))
+            );
+            if let Some(stats) = VoltcraftStatistics::new(&mut power_events).sampled_stats(every) {
+                let target_path = output_dir.join(PREVIEW_FILE_TEXT);
+                print!(
+                    "Saving approximate statistics to file {}...",
+                    highlight(PREVIEW_FILE_TEXT)
+                );
+                report_export(
+                    &mut written,
+                    PREVIEW_FILE_TEXT,
+                    save_preview(&target_path, &stats, every, power_events.len()).is_ok(),
+                );
+            }
         } else {
-            println!(" {}", "Failed".red());
+            // Estimate the minutes missing in short gaps, so that the energy totals don't leave them out
+            if let Some(filling) = options.fill_gaps {
+                print!("Filling gaps of up to {} minute(s)...", filling.max_gap);
+                let filled = fill_gaps(&mut power_events, filling);
+                println!(" {} ({} reading(s) filled in)", ok("Done"), filled);
+            }
+            // Write power events to text file
+            let target_path = output_dir.join(PARAMETER_HISTORY_FILE_TEXT);
+            print!(
+                "Saving parameter history to text file {}...",
+                highlight(PARAMETER_HISTORY_FILE_TEXT)
+            );
+            report_export(
+                &mut written,
+                PARAMETER_HISTORY_FILE_TEXT,
+                save_parameter_history_txt(&target_path, &power_events, &options.history).is_ok(),
+            );
+            // Write power events to CSV file
+            let target_path = output_dir.join(PARAMETER_HISTORY_FILE_CSV);
+            print!(
+                "Saving parameter history to CSV file {}...",
+                highlight(PARAMETER_HISTORY_FILE_CSV)
+            );
+            report_export(
+                &mut written,
+                PARAMETER_HISTORY_FILE_CSV,
+                save_parameter_history_csv(
+                    &target_path,
+                    &power_events,
+                    options.reactive,
+                    options.fill_gaps.is_some(),
+                )
+                .is_ok(),
+            );
+            // Write voltage vs. active power scatter data to CSV file
+            if options.scatter {
+                let target_path = output_dir.join(VOLTAGE_POWER_FILE_CSV);
+                print!(
+                    "Saving voltage vs. power scatter data to CSV file {}...",
+                    highlight(VOLTAGE_POWER_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    VOLTAGE_POWER_FILE_CSV,
+                    save_voltage_power_csv(&target_path, &power_events).is_ok(),
+                );
+            }
+            // Write the average power by minute of the week to CSV file
+            if options.weekly_profile {
+                let profile = VoltcraftStatistics::new(&mut power_events).weekly_profile();
+                let target_path = output_dir.join(WEEKLY_PROFILE_FILE_CSV);
+                print!(
+                    "Saving weekly power profile to CSV file {}...",
+                    highlight(WEEKLY_PROFILE_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    WEEKLY_PROFILE_FILE_CSV,
+                    save_weekly_profile_csv(&target_path, &profile).is_ok(),
+                );
+            }
+            // Write the daily means and robust averages side by side to CSV file
+            if let Some(average) = options.robust {
+                let stats = VoltcraftStatistics::new(&mut power_events);
+                let target_path = output_dir.join(DAILY_FILE_CSV);
+                print!(
+                    "Saving daily statistics to CSV file {}...",
+                    highlight(DAILY_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    DAILY_FILE_CSV,
+                    save_daily_csv(
+                        &target_path,
+                        &stats.daily_stats(),
+                        &stats.robust_stats(average),
+                    )
+                    .is_ok(),
+                );
+            }
+            // Write the reactive energy by day and by hour of the week to CSV files
+            if options.reactive {
+                let reactive = VoltcraftStatistics::new(&mut power_events).reactive_stats();
+                print!(
+                    "Saving daily reactive load to CSV file {}...",
+                    highlight(REACTIVE_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    REACTIVE_FILE_CSV,
+                    save_reactive_csv(output_dir.join(REACTIVE_FILE_CSV), &reactive).is_ok(),
+                );
+                print!(
+                    "Saving reactive load heatmap to CSV file {}...",
+                    highlight(REACTIVE_HEATMAP_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    REACTIVE_HEATMAP_FILE_CSV,
+                    save_reactive_heatmap_csv(
+                        output_dir.join(REACTIVE_HEATMAP_FILE_CSV),
+                        &reactive,
+                    )
+                    .is_ok(),
+                );
+            }
+            // Write the readings aggregated into longer intervals to CSV file
+            if let Some(minutes) = options.resample {
+                let resampled =
+                    resample(&power_events, Duration::minutes(minutes as i64)).unwrap_or_default();
+                let target_path = output_dir.join(RESAMPLED_FILE_CSV);
+                print!(
+                    "Saving readings resampled to {} minutes to CSV file {}...",
+                    minutes,
+                    highlight(RESAMPLED_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    RESAMPLED_FILE_CSV,
+                    save_resampled_csv(&target_path, &resampled).is_ok(),
+                );
+            }
+            // Write the readings smoothed by a rolling mean to CSV file, to plot long trends
+            if let Some(minutes) = options.smooth {
+                let smoothed = smooth(&power_events, Duration::minutes(minutes as i64));
+                let target_path = output_dir.join(SMOOTHED_FILE_CSV);
+                print!(
+                    "Saving readings smoothed over {} minutes to CSV file {}...",
+                    minutes,
+                    highlight(SMOOTHED_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    SMOOTHED_FILE_CSV,
+                    save_parameter_history_csv(
+                        &target_path,
+                        &smoothed,
+                        options.reactive,
+                        options.fill_gaps.is_some(),
+                    )
+                    .is_ok(),
+                );
+            }
+            // Write the blackouts as state changes of the mains power, for uptime monitoring tools
+            if let Some(format) = options.uptime {
+                let blackouts = statistics(&mut power_events, &options)
+                    .blackout_stats()
+                    .blackouts;
+                let start = power_events[0].timestamp;
+                let (file, saved) = match format {
+                    UptimeFormat::Csv => (
+                        UPTIME_FILE_CSV,
+                        save_uptime_csv(output_dir.join(UPTIME_FILE_CSV), start, &blackouts),
+                    ),
+                    UptimeFormat::Kuma => (
+                        UPTIME_FILE_JSON,
+                        save_uptime_json(output_dir.join(UPTIME_FILE_JSON), start, &blackouts),
+                    ),
+                };
+                print!("Saving uptime history to file {}...", highlight(file));
+                report_export(&mut written, file, saved.is_ok());
+            }
+            // Write the readings to the compact archive format, once checked to decode back to them
+            if options.archive {
+                let target_path = output_dir.join(ARCHIVE_FILE);
+                print!(
+                    "Saving archive of the readings to file {}...",
+                    highlight(ARCHIVE_FILE)
+                );
+                let bytes = archive::encode(&power_events);
+                report_export(
+                    &mut written,
+                    ARCHIVE_FILE,
+                    archive::verify(&power_events, &bytes).is_ok()
+                        && fs::write(&target_path, &bytes).is_ok(),
+                );
+            }
+            // The loggers on the other phases, when analyzing a three-phase supply (the clock correction
+            // and the calibration are those of the logger of the input folder, so they don't apply)
+            let mut phase_options = ParseOptions::new()
+                .mode(options.parse_mode)
+                .power_factor_policy(options.power_factor_policy)
+                .validation(options.validation);
+            if let Some(timezone) = options.timezone {
+                phase_options = phase_options.timezone(timezone);
+            }
+            let phases = options
+                .phase_dirs
+                .iter()
+                .enumerate()
+                .map(|(index, folder)| {
+                    print!(
+                        "Reading phase L{} from folder '{}'...",
+                        index + 2,
+                        highlight(&folder.display().to_string())
+                    );
+                    let (mut events, failures) =
+                        load_phase(folder, &phase_options, options.max_file_size);
+                    if let Some(deadband) = options.deadband {
+                        apply_deadband(&mut events, deadband / 1000.0);
+                    }
+                    println!(" {} ({} readings)", ok("Done"), events.len());
+                    // As for the files of the input folder
+                    for (file, e) in failures {
+                        match e {
+                            ParseError::Unreadable => {
+                                println!("\t{} {}", error("Failed to open"), file.display())
+                            }
+                            e => println!("\t{} {} ({})", error("Invalid"), file.display(), e),
+                        }
+                    }
+                    events
+                })
+                .collect::<Vec<_>>();
+            // Write the balance of the phases by minute to CSV file
+            if !phases.is_empty() {
+                let all = std::iter::once(power_events.as_slice())
+                    .chain(phases.iter().map(Vec::as_slice))
+                    .collect::<Vec<_>>();
+                let target_path = output_dir.join(PHASE_BALANCE_FILE_CSV);
+                print!(
+                    "Saving phase balance to CSV file {}...",
+                    highlight(PHASE_BALANCE_FILE_CSV)
+                );
+                report_export(
+                    &mut written,
+                    PHASE_BALANCE_FILE_CSV,
+                    save_phase_balance_csv(
+                        &target_path,
+                        &align_phases(&all),
+                        options.balance_limits,
+                    )
+                    .is_ok(),
+                );
+            }
+            // Split the power data into the periods to report on, each with its own statistics file
+            let periods = match options.split_by {
+                SplitBy::None => vec![(String::from(STATS_FILE_TEXT), power_events)],
+                SplitBy::Month => power_events
+                    .into_iter()
+                    .group_by(|e| e.timestamp.format("%Y-%m").to_string())
+                    .into_iter()
+                    .map(|(month, events)| {
+                        (
+                            format!("{}_{}.txt", STATS_FILE_PREFIX, month),
+                            events.collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            };
+            let mut report = None;
+            for (stats_file, mut events) in periods {
+                let Some(period_report) = compute_report(
+                    &mut events,
+                    &phases,
+                    &options,
+                    &file_coverage,
+                    &source_names,
+                ) else {
+                    continue;
+                };
+                let target_path = output_dir.join(&stats_file);
+                print!("Saving statistics to file {}...", highlight(&stats_file));
+                report_export(
+                    &mut written,
+                    &stats_file,
+                    save_statistics(&target_path, &period_report, options.detail).is_ok(),
+                );
+                report = Some(period_report);
+            }
+            // Write the daily digest for the most recent complete day (yesterday, if available)
+            if let (true, Some(report)) = (options.digest, report) {
+                let today = Local::now().date_naive();
+                let day = report
+                    .daily
+                    .iter()
+                    .rev()
+                    .find(|d| d.date < today)
+                    .or(report.daily.last());
+                if let Some(day) = day {
+                    let target_path = output_dir.join(DIGEST_FILE_TEXT);
+                    print!("Saving digest to file {}...", highlight(DIGEST_FILE_TEXT));
+                    report_export(
+                        &mut written,
+                        DIGEST_FILE_TEXT,
+                        save_digest(&target_path, day, &report.blackouts, options.price).is_ok(),
+                    );
+                }
+            }
         }
     } else {
-        println!("{}", "No valid Voltcraft data files found.".yellow());
+        // Files holding only a header decode fine, but leave nothing to analyze
+        let empty = files
+            .iter()
+            .filter(|f| f.outcome.as_ref().is_ok_and(|r| r.is_empty()))
+            .count();
+        if empty > 0 {
+            println!(
+                "{}",
+                warning(&format!(
+                    "No readings found: {} file(s) hold only a header.",
+                    empty
+                ))
+            );
+        } else {
+            println!("{}", warning("No valid Voltcraft data files found."));
+        }
+    }
+
+    // Summarize what each input file held and contributed to the merged readings
+    let summaries = files
+        .iter()
+        .zip(contributions)
+        .map(|(file, (merged_readings, merged_energy))| FileSummary {
+            file,
+            merged_readings,
+            merged_energy,
+        })
+        .collect::<Vec<_>>();
+    if !summaries.is_empty() {
+        println!("Summary of the input files:");
+        println!("{}", file_summary_table(&summaries));
+    }
+    let target_path = output_dir.join(FILE_SUMMARY_FILE_TEXT);
+    print!(
+        "Saving input file summary to file {}...",
+        highlight(FILE_SUMMARY_FILE_TEXT)
+    );
+    report_export(
+        &mut written,
+        FILE_SUMMARY_FILE_TEXT,
+        save_file_summary(&target_path, &summaries).is_ok(),
+    );
+
+    // Package the outputs into a single archive
+    #[cfg(feature = "bundle")]
+    if options.bundle && !written.is_empty() {
+        let bundle_file = format!(
+            "voltcraft_bundle_{}.zip",
+            Local::now().format("%Y-%m-%d_%H%M%S")
+        );
+        let target_path = output_dir.join(&bundle_file);
+        print!("Saving bundle to file {}...", highlight(&bundle_file));
+        let success = bundle::save_bundle(
+            &target_path,
+            &output_dir,
+            &written,
+            &options.effective_config(),
+        )
+        .is_ok();
+        report_export(&mut written, &bundle_file, success);
+    }
+
+    timer.end("Reporting");
+    if options.verbose {
+        display_performance(&timer, input_bytes, decoded_events);
     }
 
     let duration = start_time.elapsed();
+    progress::emit(ProgressEvent::Finished {
+        files: file_count,
+        elapsed_ms: duration.as_millis(),
+    });
 
     if file_count > 0 {
         println!("Processed {} files in {:?}.", file_count, duration);
     }
-    println!("{}", "Finished.".green());
+    println!("{}", ok("Finished."));
+}
+
+// How fast the input files were read, how long each stage took and the memory used
+fn display_performance(timer: &StageTimer, input_bytes: u64, decoded_events: usize) {
+    println!("Performance:");
+    let reading = timer.stage("Reading").unwrap_or_default();
+    match perf::throughput(input_bytes, decoded_events, reading) {
+        Some((mb, events)) => println!(
+            "\tParsing: {} bytes, {} readings at {:.1}MB/s, {:.0} readings/s",
+            input_bytes, decoded_events, mb, events
+        ),
+        None => println!(
+            "\tParsing: {} bytes, {} readings",
+            input_bytes, decoded_events
+        ),
+    }
+    for line in timer.lines() {
+        println!("\t{}", line);
+    }
+    match perf::peak_rss() {
+        Some(bytes) => println!(
+            "\tPeak memory (resident): {:.1}MB",
+            bytes as f64 / 1_000_000.0
+        ),
+        None => println!("\tPeak memory (resident): not reported by this system"),
+    }
+}
+
+// The readings of the logger on another phase, from the data files of its folder, in
+// chronological order (the files are only expected to overlap by duplicate readings), and the
+// files that could not be read or decoded
+fn load_phase(
+    folder: &Path,
+    parse_options: &ParseOptions,
+    max_file_size: u64,
+) -> (Vec<PowerEvent>, Vec<(PathBuf, ParseError)>) {
+    let mut events = Vec::new();
+    let mut failures = Vec::new();
+    for path in list_data_files(folder) {
+        match read_data_file(&path, max_file_size).and_then(|data| data.parse_with(parse_options)) {
+            Ok((decoded, _)) => events.extend(decoded),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    events.sort_by_key(|e| e.timestamp);
+    remove_duplicates(&mut events);
+    (events, failures)
+}
+
+// The entries of a folder, sorted by name (none if it can't be read)
+fn list_folder(folder: &Path) -> Vec<PathBuf> {
+    let mut paths = fs::read_dir(folder)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
+
+// The data files of an input folder: its entries, with the files of the ZIP archives among them in
+// their place (named after the archive, e.g. export.zip/A04FC8D2.BIN)
+#[cfg(feature = "zip")]
+fn list_data_files(folder: &Path) -> Vec<PathBuf> {
+    list_folder(folder)
+        .into_iter()
+        .flat_map(|path| match is_zip(&path).then(|| ZipBundle::open(&path)) {
+            Some(Ok(bundle)) => bundle
+                .data_files()
+                .iter()
+                .map(|name| path.join(name))
+                .collect(),
+            _ => vec![path],
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "zip"))]
+fn list_data_files(folder: &Path) -> Vec<PathBuf> {
+    list_folder(folder)
+}
+
+// Read a file listed by list_data_files, from its archive if it is in one
+#[cfg(feature = "zip")]
+fn read_data_file(path: &Path, max_size: u64) -> Result<VoltcraftData, ParseError> {
+    match path.ancestors().skip(1).find(|folder| folder.is_file()) {
+        Some(archive) => {
+            let name = path
+                .strip_prefix(archive)
+                .map_err(|_| ParseError::Unreadable)?;
+            ZipBundle::open(archive)
+                .map_err(|_| ParseError::Unreadable)?
+                .read(&name.to_string_lossy(), max_size)
+        }
+        None => VoltcraftData::from_file(path, max_size),
+    }
+}
+
+#[cfg(not(feature = "zip"))]
+fn read_data_file(path: &Path, max_size: u64) -> Result<VoltcraftData, ParseError> {
+    VoltcraftData::from_file(path, max_size)
+}
+
+// The logger clock correction given by the reference times, read in the zone of the logger clock
+fn time_correction(options: &Options) -> Option<TimeCorrection> {
+    let at = |t: &NaiveDateTime| match options.timezone {
+        Some(timezone) => timezone.from_local_datetime(t).single(),
+        None => Local
+            .from_local_datetime(t)
+            .earliest()
+            .map(|t| t.fixed_offset()),
+    };
+    let references = options
+        .clock_references
+        .iter()
+        .map(|(logger, actual)| Some((at(logger)?, at(actual)?)))
+        .collect::<Option<Vec<_>>>()?;
+    match references[..] {
+        [(logger, actual)] => Some(TimeCorrection::offset(actual - logger)),
+        [first, second] => TimeCorrection::from_references(first, second),
+        _ => None,
+    }
+}
+
+// The statistics of the power events, with the blackouts detected as configured and left out of
+// the excluded periods, so that the report and the uptime history agree
+fn statistics<'a>(
+    power_events: &'a mut Vec<PowerEvent>,
+    options: &Options,
+) -> VoltcraftStatistics<'a> {
+    VoltcraftStatistics::new(power_events)
+        .blackout_detection(options.blackout_detection)
+        .excluded(&options.exclude)
+}
+
+// Compute the statistics report for the given power events, if there are any
+fn compute_report(
+    power_events: &mut Vec<PowerEvent>,
+    phases: &[Vec<PowerEvent>],
+    options: &Options,
+    file_coverage: &[FileCoverage],
+    sources: &[String],
+) -> Option<StatisticsReport> {
+    // Leave out the periods known to be unusual, listing them in the report
+    let excluded = exclude_periods(power_events, &options.exclude)
+        .into_iter()
+        .filter(|x| x.readings > 0)
+        .collect();
+    // Filter sensor noise, keeping the unfiltered figures for comparison
+    let mut unfiltered_stats = None;
+    // (an appliance profile provides the deadband when none is given)
+    let deadband = options
+        .deadband
+        .or(options.appliance.map(|p| p.standby_power));
+    if let Some(deadband) = deadband {
+        unfiltered_stats = VoltcraftStatistics::new(power_events).overall_stats();
+        print!("Applying noise deadband of {}W...", deadband);
+        let zeroed = apply_deadband(power_events, deadband / 1000.0);
+        println!(" {} ({} readings zeroed)", ok("Done"), zeroed);
+    }
+    // Compute statistics
+    progress::emit(ProgressEvent::Stage {
+        stage: "statistics",
+    });
+    // The readings of the input folder are those of the first phase
+    let three_phase = if phases.is_empty() {
+        None
+    } else {
+        let all = std::iter::once(&**power_events)
+            .chain(phases.iter().map(Vec::as_slice))
+            .collect::<Vec<_>>();
+        let info = three_phase_stats(&all, options.balance_limits);
+        if info.is_none() {
+            println!(
+                "{}",
+                warning("No minute was recorded on all the phases, the three-phase figures are left out.")
+            );
+        }
+        info
+    };
+    let stats = statistics(power_events, options);
+    let overall = stats.overall_stats()?;
+    let daily = stats.daily_stats();
+    // Only report the gaps between data files falling within the reported period
+    let file_gaps = compute_file_gaps(file_coverage)
+        .into_iter()
+        .filter(|g| {
+            g.timestamp > overall.start && g.timestamp <= overall.end + Duration::minutes(1)
+        })
+        .collect();
+    let cycles = options.appliance.map(|p| stats.cycle_stats(&p));
+    // Only the cycles of appliances that can be scheduled are worth shifting
+    let tariff = options
+        .price
+        .zip(options.off_peak_price)
+        .map(|(peak_price, off_peak_price)| TimeOfUseTariff {
+            peak_price,
+            off_peak_price,
+            off_peak_hours: options.off_peak_hours,
+        });
+    let load_shifting = cycles
+        .as_ref()
+        .filter(|c| c.profile.shiftable)
+        .zip(tariff)
+        .map(|(cycles, tariff)| {
+            load_shifting(
+                cycles,
+                &tariff,
+                stats.span(overall.start, overall.end) + Duration::minutes(1),
+            )
+        });
+    let billing = options
+        .billing_rounding
+        .map(|rounding| billed_energy(&daily, &rounding, options.price));
+    Some(StatisticsReport {
+        distortion_trend: distortion_trend(&daily),
+        reactive: stats.reactive_stats(),
+        robust: options.robust.map(|average| stats.robust_stats(average)),
+        daily,
+        hourly: stats.hourly_stats(),
+        blackouts: stats.blackout_stats(),
+        consistency: stats.consistency_stats(options.consistency_tolerance),
+        correlation: stats.correlation_stats(),
+        flicker: stats.flicker_stats(options.flicker_threshold),
+        sags: stats.sag_coincidence(options.sag_voltage, options.high_load),
+        deviation: stats.voltage_deviation(&options.grid),
+        cycles,
+        load_shifting,
+        billing,
+        peak_shaving: options.power_limit.map(|limit| stats.peak_shaving(limit)),
+        three_phase,
+        ups: options
+            .ups_capacity
+            .map(|capacity| stats.ups_runtime(capacity, options.ups_efficiency)),
+        file_gaps,
+        sources: sources.to_vec(),
+        unfiltered: unfiltered_stats,
+        excluded,
+        filled: options.fill_gaps.map(|_| {
+            let filled = power_events.iter().filter(|e| e.filled);
+            (
+                filled.clone().count(),
+                filled.map(|e| e.power).sum::<f64>() / 60.0,
+            )
+        }),
+        config: options.effective_config(),
+        overall,
+    })
+}
+
+// Where the data files are read from
+enum Input {
+    Folder,
+    Stdin,
+    #[cfg(feature = "sdcard")]
+    Image(SdCardImage),
+    #[cfg(feature = "zip")]
+    Zip(ZipBundle),
+}
+
+// Whether the input is a ZIP archive rather than an SD card image, by its signature
+fn is_zip(path: &Path) -> bool {
+    let mut signature = [0u8; 4];
+    fs::File::open(path)
+        .and_then(|mut f| io::Read::read_exact(&mut f, &mut signature))
+        .is_ok_and(|_| signature == *b"PK\x03\x04")
+}
+
+#[cfg(feature = "zip")]
+fn open_zip(path: &Path) -> io::Result<Input> {
+    ZipBundle::open(path).map(Input::Zip)
+}
+
+#[cfg(not(feature = "zip"))]
+fn open_zip(_: &Path) -> io::Result<Input> {
+    Err(io::Error::other(
+        "reading ZIP archives requires the zip feature",
+    ))
+}
+
+#[cfg(feature = "sdcard")]
+fn open_image(path: &Path) -> io::Result<Input> {
+    SdCardImage::open(path).map(Input::Image)
+}
+
+#[cfg(not(feature = "sdcard"))]
+fn open_image(_: &Path) -> io::Result<Input> {
+    Err(io::Error::other(
+        "reading SD card images requires the sdcard feature",
+    ))
+}
+
+// Report the outcome of writing an output file, keeping track of the files written
+fn report_export(written: &mut Vec<String>, file: &str, success: bool) {
+    if success {
+        written.push(String::from(file));
+        println!(" {}", ok("Ok"));
+    } else {
+        println!(" {}", error("Failed"));
+    }
+    progress::emit(ProgressEvent::ExportDone { file, success });
+}
+
+// Write a synthetic data file into the output folder, for demos and tests
+fn generate(options: &GenerateOptions) {
+    let output_dir = &options.output_dir;
+    if fs::create_dir_all(output_dir).is_err() {
+        println!(
+            "{} {}",
+            error("Failed to create folder"),
+            error(&output_dir.display().to_string())
+        );
+        return;
+    }
+    let path = output_dir.join(SYNTHETIC_FILE);
+    if path.exists()
+        && !confirm_overwrite(output_dir, &[SYNTHETIC_FILE.to_string()], options.overwrite)
+    {
+        return;
+    }
+    let events = options.generator.events();
+    let written = VoltcraftEncoder::new()
+        .encode(&events)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| fs::write(&path, bytes).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => println!(
+            "{} {} readings to {}",
+            ok("Generated"),
+            events.len(),
+            highlight(&path.display().to_string())
+        ),
+        Err(e) => println!(
+            "{} {} ({})",
+            error("Failed to generate"),
+            error(&path.display().to_string()),
+            e
+        ),
+    }
+}
+
+// Decide whether the given existing output files may be overwritten
+fn confirm_overwrite(output_dir: &Path, existing: &[String], policy: OverwritePolicy) -> bool {
+    match policy {
+        OverwritePolicy::Always => true,
+        OverwritePolicy::Never => {
+            println!(
+                "{} {} {}",
+                error("Output files already exist:"),
+                error(&existing.join(", ")),
+                error("(use --overwrite always to replace them)")
+            );
+            false
+        }
+        OverwritePolicy::Ask => {
+            if !io::stdin().is_terminal() {
+                println!(
+                    "{} {} {}",
+                    error("Output files already exist:"),
+                    error(&existing.join(", ")),
+                    error(
+                        "(use --overwrite always to replace them when not running interactively)"
+                    )
+                );
+                return false;
+            }
+            print!(
+                "{} {} in folder '{}'. Overwrite? [y/N] ",
+                warning("Output files already exist:"),
+                highlight(&existing.join(", ")),
+                highlight(&output_dir.display().to_string())
+            );
+            io::stdout().flush().ok();
+            let mut answer = String::new();
+            if io::stdin().lock().read_line(&mut answer).is_err() {
+                return false;
+            }
+            answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes")
+        }
+    }
 }
 
 fn display_welcome() {
     println!(
         "{} - {} {}\n{} | {}",
-        "Analyzer for Voltcraft Energy Logger 4000"
-            .bright_white()
-            .bold(),
+        highlight("Analyzer for Voltcraft Energy Logger 4000").bold(),
         "v1.0".bright_yellow().bold(),
         "(My first foray into the Rust programming language)".italic(),
         "Valer BOCAN, PhD, CSSLP".green(),
@@ -174,19 +1456,456 @@ fn display_welcome() {
     );
     println!(
         "Type {} | {} | {} to get help.\n",
-        "/?".yellow(),
-        "-h".yellow(),
-        "--help".yellow()
+        accent("/?"),
+        accent("-h"),
+        accent("--help")
     );
 }
 
 fn display_help() {
     println!("{} <input folder> <output folder>\n\t- Decode Voltcraft files from a folder and output statistics in different folder.",
-        "voltcraft_energy_analyzer".bright_white());
+        highlight("voltcraft_energy_analyzer"));
     println!("{} <input folder>\n\t- Decode Voltcraft files from a folder and output statistics in the current folder.",
-        "voltcraft_energy_analyzer".bright_white());
+        highlight("voltcraft_energy_analyzer"));
+    println!(
+        "{}\n\t- Decode Voltcraft files from and place the statistics in the current folder.",
+        highlight("voltcraft_energy_analyzer")
+    );
+    println!(
+        "{} <SD card image> <output folder>\n\t- Decode the Voltcraft files of a raw SD card image (requires the sdcard feature).",
+        highlight("voltcraft_energy_analyzer")
+    );
+    println!(
+        "{} <ZIP archive> <output folder>\n\t- Decode the Voltcraft files of a ZIP archive, without extracting it (requires the zip feature).",
+        highlight("voltcraft_energy_analyzer")
+    );
+    println!(
+        "{} {} <output folder>\n\t- Decode a single Voltcraft file piped to standard input.",
+        highlight("voltcraft_energy_analyzer"),
+        accent("-")
+    );
+    println!(
+        "{} {} [options] <input folder> <output folder>\n\t- Check the environment instead of processing the data (also {}).",
+        highlight("voltcraft_energy_analyzer"),
+        accent("doctor"),
+        accent("--doctor")
+    );
+    println!(
+        "{} {} [options] <output folder>\n\t- Write a synthetic data file ({}) instead: {} {}, {} {}, {} {}, {} {}, {} {}, {} {}.\n",
+        highlight("voltcraft_energy_analyzer"),
+        accent("generate"),
+        SYNTHETIC_FILE,
+        accent("--days"),
+        highlight("<n>"),
+        accent("--start"),
+        highlight("<YYYY-MM-DD>"),
+        accent("--load-shape"),
+        highlight("{flat,fridge,household}"),
+        accent("--blackouts-per-day"),
+        highlight("<n>"),
+        accent("--voltage-noise"),
+        highlight("<V>"),
+        accent("--seed"),
+        highlight("<n>")
+    );
+    println!("Options:");
+    println!(
+        "  {} {}\n\t- Statistics report verbosity: overall figures only, the default daily breakdown, or everything including hourly tables and blackout context.\n",
+        accent("--detail"),
+        highlight("{summary,normal,full}")
+    );
+    println!(
+        "  {} {}\n\t- Timestamp format of the text parameter history, e.g. \"%d.%m.%Y %H:%M\" (default: \"[%Y-%m-%d %H:%M]\").\n",
+        accent("--history-timestamp"),
+        highlight("<format>")
+    );
+    println!(
+        "  {} {}\n\t- Comma separated quantities of the text parameter history, in order, from voltage, current, cosphi, power, apparent-power, reactive-power, phase-angle, tanphi and registers, the values as stored to audit the decoder (default: voltage to apparent-power, and the derived quantities with --reactive).\n",
+        accent("--history-columns"),
+        highlight("<columns>")
+    );
+    println!(
+        "  {} {}\n\t- Appliance plugged into the logger, providing the noise deadband and the operating cycles reported.\n",
+        accent("--appliance"),
+        highlight("{fridge,washing-machine,ev-charger}")
+    );
+    println!(
+        "  {} {}\n\t- Treat readings below the given active power as sensor noise (zero) when computing statistics.\n",
+        accent("--deadband"),
+        highlight("<watts>")
+    );
+    println!(
+        "  {} {}\n\t- Reject files with corrupted data (default), or skip the corrupted regions and keep the readings that can be decoded.\n",
+        accent("--parse-mode"),
+        highlight("{strict,lenient}")
+    );
+    println!(
+        "  {} {}\n  {} {}\n\t- Plausible voltage range (default: 150V-276V on 230V grids: above, 10% of the nominal voltage beyond the tolerance band of the grid; below, scaled to its nominal voltage).\n",
+        accent("--min-voltage"),
+        highlight("<volts>"),
+        accent("--max-voltage"),
+        highlight("<volts>")
+    );
+    println!(
+        "  {} {}\n\t- How to handle samples outside the plausible voltage range (default: flag).\n",
+        accent("--implausible-voltage"),
+        highlight("{flag,drop}")
+    );
+    println!(
+        "  {} {}\n\t- Highest plausible current; more points to corrupted data (default: 16A, the rating of the logger).\n",
+        accent("--max-current"),
+        highlight("<ampers>")
+    );
+    println!(
+        "  {} {}\n\t- How to handle samples with a current above the plausible maximum (default: drop).\n",
+        accent("--implausible-current"),
+        highlight("{flag,drop}")
+    );
+    println!(
+        "  {} {}\n\t- Which file to keep readings from when several files cover the same minutes (default: newer).\n",
+        accent("--overlap"),
+        highlight("{newer,older}")
+    );
+    println!(
+        "  {} {}\n\t- Which reading to keep for a minute still read more than once once the files are merged, when the readings differ: the first or last in file order, their average, or stop (default: first).\n",
+        accent("--dedup"),
+        highlight("{first,last,average,fail}")
+    );
+    println!(
+        "  {} {}\n\t- Fill the gaps of at most that many missing minutes with readings interpolated between those around the gap, or drawing no power (zero); the readings filled in are flagged in the histories.\n",
+        accent("--fill-gaps"),
+        highlight("<minutes>[:interpolate|:zero]")
+    );
+    println!(
+        "  {} {}\n\t- Leave a day or a range of days out of the statistics and projections (e.g. a holiday, or 2023-12-24..2023-12-26); can be given several times.\n",
+        accent("--exclude"),
+        highlight("<date>[..<date>]")
+    );
+    println!(
+        "  {} {}\n\t- Also give the daily power and voltage as an average short spikes barely move, the median or the mean once the given share (default: 10%) of the lowest and highest readings is cut off, alongside the means in the report and in voltcraft_daily.csv.\n",
+        accent("--robust"),
+        highlight("<median|trimmed[:<percent>]>")
+    );
+    println!(
+        "  {} {}\n\t- Folder of the data files of the logger on another phase of a three-phase supply; give it twice, for L2 and L3 (the input folder is L1), to report the combined totals and the phase imbalance.\n",
+        accent("--phase"),
+        highlight("<folder>")
+    );
+    println!(
+        "  {} {}\n\t- Phase current imbalance above which a minute is flagged, with --phase (default: 20%).\n",
+        accent("--max-phase-imbalance"),
+        highlight("<percent>")
+    );
+    println!(
+        "  {} {}\n\t- Estimated neutral current above which a minute is flagged, with --phase (default: 10A).\n",
+        accent("--max-neutral-current"),
+        highlight("<ampers>")
+    );
+    println!(
+        "  {} {}\n\t- Logger clock time and the true time at that moment, e.g. 2014-09-11T18:43=2014-09-11T18:40. Once for a clock that is off, twice for a clock that drifts.\n",
+        accent("--clock-reference"),
+        highlight("<logger time>=<true time>")
+    );
+    println!(
+        "  {} {}\n\t- Zone the logger clock was set in, as a fixed offset: the reports show the logger clock whatever the zone of this computer (default: local).\n",
+        accent("--tz"),
+        highlight("{local,utc,<offset>}")
+    );
+    println!(
+        "  {} {}\n\t- How to handle samples with an impossible power factor above 1.0 (default: clamp).\n",
+        accent("--power-factor"),
+        highlight("{clamp,drop,keep}")
+    );
+    println!(
+        "  {} {}\n\t- Correct the voltage read by the logger to match a reference meter: gain, and optionally an offset in volts (default: 1,0).\n",
+        accent("--calibrate-voltage"),
+        highlight("<gain>[,<offset>]")
+    );
+    println!(
+        "  {} {}\n\t- Correct the current read by the logger: gain, and optionally an offset in ampers (default: 1,0).\n",
+        accent("--calibrate-current"),
+        highlight("<gain>[,<offset>]")
+    );
+    println!(
+        "  {} {}\n\t- Correct the power computed from the corrected voltage and current: gain, and optionally an offset in kW (default: 1,0).\n",
+        accent("--calibrate-power"),
+        highlight("<gain>[,<offset>]")
+    );
+    println!(
+        "  {} {}\n\t- Relative tolerance used when checking apparent power against voltage and current (default: 1%).\n",
+        accent("--consistency-tolerance"),
+        highlight("<percent>")
+    );
+    println!(
+        "  {}\n\t- Also write a compact digest of yesterday's figures, suitable for e-mail or push notifications.\n",
+        accent("--digest")
+    );
+    println!(
+        "  {} {}\n\t- Energy price per kWh, used to show costs.\n",
+        accent("--price"),
+        highlight("<price>")
+    );
     println!(
-        "{}\n\t- Decode Voltcraft files from and place the statistics in the current folder.\n",
-        "voltcraft_energy_analyzer".bright_white()
+        "  {} {}\n\t- Cheaper energy price per kWh in off-peak hours. With --appliance, estimates the savings of running its cycles in off-peak hours.\n",
+        accent("--off-peak-price"),
+        highlight("<price>")
     );
+    println!(
+        "  {} {}\n\t- Simulate capping the power at the given limit: minutes above it and energy that would need shifting.\n",
+        accent("--power-limit"),
+        highlight("<kW>")
+    );
+    println!(
+        "  {} {}\n\t- Battery capacity of a UPS: estimates how long it would have carried the load in each blackout.\n",
+        accent("--ups-capacity"),
+        highlight("<kWh>")
+    );
+    println!(
+        "  {} {}\n\t- Efficiency of the UPS inverter (default: 0.9).\n",
+        accent("--ups-efficiency"),
+        highlight("<fraction>")
+    );
+    println!(
+        "  {} {}\n\t- Off-peak hours of the tariff (default: 22-6, i.e. 22:00 to 06:00).\n",
+        accent("--off-peak-hours"),
+        highlight("<start>-<end>")
+    );
+    println!(
+        "  {} {}\n\t- Rounding of the billed energy per day or month, as the utility does it, with a metering step in kWh (default: 1), e.g. day:nearest or month:down:0.1. Adds the billed energy, and its cost with --price, to the statistics.\n",
+        accent("--billing-rounding"),
+        highlight("<day|month>:<nearest|up|down>[:<kWh>]")
+    );
+    println!(
+        "  {}\n\t- Print the configuration that would be used (defaults merged with the command line) and exit.\n",
+        accent("--print-effective-config")
+    );
+    println!(
+        "  {}\n\t- Check the input and output folders, the decoder and the build features, then exit, as the doctor subcommand does. Please include its output in bug reports.\n",
+        accent("--doctor")
+    );
+    println!(
+        "  {}\n\t- List the optional features compiled in (e.g. gzip, zip), one per line and nothing else, then exit.\n",
+        accent("--features")
+    );
+    println!(
+        "  {} {}\n\t- What to do when output files already exist (default: ask, which refuses when not running interactively).\n",
+        accent("--overwrite"),
+        highlight("{never,ask,always}")
+    );
+    println!(
+        "  {}\n\t- Write the outputs to a new subfolder of the output folder, named after the current date and time.\n",
+        accent("--timestamped-output")
+    );
+    println!(
+        "  {} {}\n\t- When to use colors in the console output (default: auto, i.e. only on a terminal and if NO_COLOR is not set).\n",
+        accent("--color"),
+        highlight("{auto,always,never}")
+    );
+    println!(
+        "  {} {}\n\t- Terminal background the console colors are chosen for (default: dark).\n",
+        accent("--theme"),
+        highlight("{dark,light}")
+    );
+    println!(
+        "  {}\n\t- Emit machine-readable progress events as JSON lines on stderr (for graphical frontends).\n",
+        accent("--progress-json")
+    );
+    println!(
+        "  {}, {}\n\t- Report the performance of the run: parsing throughput, the time taken by each stage and the peak memory used.\n",
+        accent("-v"),
+        accent("--verbose")
+    );
+    println!(
+        "  {}\n\t- Also write the voltage vs. active power scatter data to CSV, to look into voltage sags.\n",
+        accent("--scatter")
+    );
+    println!(
+        "  {}\n\t- Also write the average and maximum power by minute of the week to CSV, to spot weekly routines.\n",
+        accent("--weekly-profile")
+    );
+    println!(
+        "  {}\n\t- Also write the reactive energy by day to voltcraft_reactive.csv and the average reactive power by hour of the week to voltcraft_reactive_heatmap.csv, to see when inductive loads run. Adds the reactive power, phase angle and tan phi to the parameter histories.\n",
+        accent("--reactive")
+    );
+    println!(
+        "  {} {}\n\t- Also write the readings aggregated into intervals of that many minutes (e.g. 15) to voltcraft_resampled.csv: average voltage, current and power, maximum power and energy.\n",
+        accent("--resample"),
+        highlight("<minutes>")
+    );
+    println!(
+        "  {} {}\n\t- Also write the readings smoothed by a centered rolling mean over that many minutes (e.g. 15) to voltcraft_smoothed.csv, laid out as voltcraft_history.csv, to plot long trends without the minute-to-minute noise.\n",
+        accent("--smooth"),
+        highlight("<minutes>")
+    );
+    println!(
+        "  {} {}\n\t- Also export the blackouts as state changes of the mains power, for uptime monitoring dashboards: timestamp,up|down rows in voltcraft_uptime.csv (csv), or Uptime Kuma heartbeats in voltcraft_uptime.json (kuma).\n",
+        accent("--uptime"),
+        highlight("{csv,kuma}")
+    );
+    println!(
+        "  {}\n\t- Also write the readings to voltcraft_readings.vca, a compact archive (about a third of the size of the data files) checked to decode back to the same readings.\n",
+        accent("--archive")
+    );
+    println!(
+        "  {} {}\n\t- Supply the logger was plugged into: eu (230V/50Hz, within 10%, the default), na (120V/60Hz, within 5%) or <volts>/<hertz>[/<tolerance %>]. The voltage thresholds default to values suited to it, and the power quality section reports the minutes outside its tolerance band.\n",
+        accent("--grid"),
+        highlight("<profile>")
+    );
+    println!(
+        "  {} {}\n\t- Minute to minute voltage change counted as a rapid variation (flicker) in the power quality section (default: 5V on 230V grids, scaled to the nominal voltage of the grid).\n",
+        accent("--flicker-threshold"),
+        highlight("<volts>")
+    );
+    println!(
+        "  {} {}\n\t- Voltage below which a minute counts as a sag in the power quality section (default: the lower end of the tolerance band of the grid, 207V on 230V grids).\n",
+        accent("--sag-voltage"),
+        highlight("<volts>")
+    );
+    println!(
+        "  {} {}\n\t- Active power above which a minute counts as high load, to tell sags caused by the installation from grid sags (default: 1kW).\n",
+        accent("--high-load"),
+        highlight("<kW>")
+    );
+    println!(
+        "  {} {}\n\t- Gap between two readings above which the power was out (default: 1 minute).\n",
+        accent("--blackout-threshold"),
+        highlight("<minutes>")
+    );
+    println!(
+        "  {} {}\n\t- Count every gap above the threshold as a blackout (gap, the default), or only those after which the logger started a new data block, as it does when the power is back (session): gaps left by readings dropped by validation are then ignored.\n",
+        accent("--blackout-detection"),
+        highlight("{gap,session}")
+    );
+    println!(
+        "  {} {}\n\t- Write one statistics report per calendar month (e.g. voltcraft_stats_2014-08.txt) instead of a single one.\n",
+        accent("--split-by"),
+        highlight("{none,month}")
+    );
+    println!(
+        "  {}\n\t- Also package all outputs, with the configuration used, into a single timestamped ZIP archive (requires the bundle feature).\n",
+        accent("--bundle")
+    );
+    println!(
+        "  {} {}\n\t- Number of records the logger can store, used to estimate its memory usage (default: 262144).\n",
+        accent("--device-capacity"),
+        highlight("<records>")
+    );
+    println!(
+        "  {} {}\n\t- Skip input files larger than this, without reading them (default: 1024KiB).\n",
+        accent("--max-file-size"),
+        highlight("<KiB>")
+    );
+    println!(
+        "  {} {}\n\t- Quick preview of huge archives: decode only every Nth reading and write approximate statistics to voltcraft_preview.txt instead of the full reports.\n",
+        accent("--sample"),
+        highlight("every=<N>")
+    );
+    println!(
+        "  {} {}\n\t- Keep the files decoded in a state file, so that the next runs over a growing archive only decode the new or changed files.\n",
+        accent("--state"),
+        highlight("<file>")
+    );
+    println!(
+        "  {} {}\n\t- With --state, warn when no new readings were imported for that many days (the logger data was not downloaded).\n",
+        accent("--stale-after"),
+        highlight("<days>")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{parse_args, Command};
+    use crate::export::save_uptime_csv;
+    use crate::voltcraft::data::PowerEvent;
+    use crate::{compute_report, statistics};
+    use chrono::{Duration, FixedOffset, TimeZone};
+    use std::ffi::OsString;
+    use std::fs;
+    #[cfg(feature = "zip")]
+    use {
+        crate::{list_data_files, read_data_file},
+        std::fs::File,
+        std::io::Write,
+        zip::write::SimpleFileOptions,
+        zip::ZipWriter,
+    };
+
+    #[test]
+    fn uptime_excluded_periods() {
+        let args = [
+            "analyzer",
+            "--exclude",
+            "2014-09-12",
+            "--uptime",
+            "csv",
+            "in",
+            "out",
+        ];
+        let Ok(Command::Run(options)) = parse_args(&args.map(OsString::from)) else {
+            panic!("not the run command");
+        };
+        let reading = |day, hour, minute| PowerEvent {
+            timestamp: FixedOffset::east_opt(3 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2014, 9, day, hour, minute, 0)
+                .unwrap(),
+            voltage: 230.0,
+            current: 0.5,
+            power_factor: 0.8,
+            power: 0.092,
+            apparent_power: 0.115,
+            source: None,
+            registers: None,
+            filled: false,
+        };
+        // A gap across the excluded day, and a blackout of ten minutes the day after
+        let mut events = vec![
+            reading(11, 23, 59),
+            reading(12, 10, 0),
+            reading(13, 12, 0),
+            reading(13, 12, 11),
+        ];
+
+        let blackouts = statistics(&mut events, &options).blackout_stats().blackouts;
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("uptime.csv");
+        save_uptime_csv(&file, events[0].timestamp, &blackouts).unwrap();
+        let uptime = fs::read_to_string(&file).unwrap();
+        assert_eq!(uptime.matches("down").count(), 1);
+
+        let report = compute_report(&mut events, &[], &options, &[], &[]).unwrap();
+        assert_eq!(report.blackouts.blackout_count, 1);
+        assert_eq!(
+            report.blackouts.blackouts[0].timestamp,
+            blackouts[0].timestamp
+        );
+        assert_eq!(blackouts[0].duration, Duration::minutes(10));
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn folder_with_archive() {
+        let data = crate::voltcraft::sample::SAMPLE_FILE;
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("A04FC8D1.BIN"), data).unwrap();
+        let mut zip = ZipWriter::new(File::create(dir.path().join("export.zip")).unwrap());
+        zip.start_file("logger/A04FC8D2.BIN", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(data).unwrap();
+        zip.finish().unwrap();
+
+        let files = list_data_files(dir.path());
+        assert_eq!(
+            files,
+            [
+                dir.path().join("A04FC8D1.BIN"),
+                dir.path().join("export.zip").join("logger/A04FC8D2.BIN")
+            ]
+        );
+        for file in &files {
+            assert_eq!(read_data_file(file, 1024).unwrap().as_bytes(), data);
+        }
+        let missing = dir.path().join("export.zip").join("A04FC8D3.BIN");
+        assert!(read_data_file(&missing, 1024).is_err());
+    }
 }
@@ -0,0 +1,71 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+// How long each stage of a run took, for the performance report of the verbose mode
+pub struct StageTimer {
+    stages: Vec<(&'static str, Duration)>,
+    last: Instant, // end of the previous stage
+}
+
+impl StageTimer {
+    pub fn new() -> Self {
+        StageTimer {
+            stages: Vec::new(),
+            last: Instant::now(),
+        }
+    }
+
+    // End a stage, which started when the previous one ended
+    pub fn end(&mut self, stage: &'static str) {
+        let now = Instant::now();
+        self.stages.push((stage, now - self.last));
+        self.last = now;
+    }
+
+    pub fn stage(&self, stage: &str) -> Option<Duration> {
+        self.stages
+            .iter()
+            .find(|(name, _)| *name == stage)
+            .map(|(_, duration)| *duration)
+    }
+
+    // The stages in the order they ended, with the share of the run they took
+    pub fn lines(&self) -> Vec<String> {
+        let total = self
+            .stages
+            .iter()
+            .map(|(_, d)| d.as_secs_f64())
+            .sum::<f64>();
+        self.stages
+            .iter()
+            .map(|(name, duration)| {
+                let share = if total > 0.0 {
+                    duration.as_secs_f64() * 100.0 / total
+                } else {
+                    0.0
+                };
+                format!("{}: {:.3}s ({:.0}%)", name, duration.as_secs_f64(), share)
+            })
+            .collect()
+    }
+}
+
+// Bytes and readings decoded per second
+pub fn throughput(bytes: u64, events: usize, elapsed: Duration) -> Option<(f64, f64)> {
+    let seconds = elapsed.as_secs_f64();
+    (seconds > 0.0).then(|| {
+        (
+            bytes as f64 / 1_000_000.0 / seconds,
+            events as f64 / seconds,
+        )
+    })
+}
+
+// The most memory the process held at once (resident set, bytes), where the OS tells it
+pub fn peak_rss() -> Option<u64> {
+    // Linux (e.g. Raspberry Pi OS) reports it in kB as VmHWM
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
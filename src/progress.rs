@@ -0,0 +1,124 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// Machine-readable progress events, emitted as JSON lines on stderr for GUI wrappers
+pub enum ProgressEvent<'a> {
+    Started {
+        input: &'a str,
+        output: &'a str,
+        files: usize,
+    },
+    FileStarted {
+        file: &'a str,
+        index: usize,
+    },
+    FileParsed {
+        file: &'a str,
+        events: usize,
+    },
+    FileFailed {
+        file: &'a str,
+        reason: &'a str,
+    },
+    Stage {
+        stage: &'a str,
+    },
+    ExportDone {
+        file: &'a str,
+        success: bool,
+    },
+    Finished {
+        files: usize,
+        elapsed_ms: u128,
+    },
+    // No new readings imported for that many days (see --stale-after)
+    Stale {
+        days: i64,
+        latest_reading: &'a str,
+    },
+}
+
+impl ProgressEvent<'_> {
+    pub fn to_json(&self) -> String {
+        match self {
+            ProgressEvent::Started {
+                input,
+                output,
+                files,
+            } => format!(
+                r#"{{"event":"started","input":{},"output":{},"files":{}}}"#,
+                json_string(input),
+                json_string(output),
+                files
+            ),
+            ProgressEvent::FileStarted { file, index } => format!(
+                r#"{{"event":"file_started","file":{},"index":{}}}"#,
+                json_string(file),
+                index
+            ),
+            ProgressEvent::FileParsed { file, events } => format!(
+                r#"{{"event":"file_parsed","file":{},"events":{}}}"#,
+                json_string(file),
+                events
+            ),
+            ProgressEvent::FileFailed { file, reason } => format!(
+                r#"{{"event":"file_failed","file":{},"reason":{}}}"#,
+                json_string(file),
+                json_string(reason)
+            ),
+            ProgressEvent::Stage { stage } => {
+                format!(r#"{{"event":"stage","stage":{}}}"#, json_string(stage))
+            }
+            ProgressEvent::ExportDone { file, success } => format!(
+                r#"{{"event":"export_done","file":{},"success":{}}}"#,
+                json_string(file),
+                success
+            ),
+            ProgressEvent::Finished { files, elapsed_ms } => format!(
+                r#"{{"event":"finished","files":{},"elapsed_ms":{}}}"#,
+                files, elapsed_ms
+            ),
+            ProgressEvent::Stale {
+                days,
+                latest_reading,
+            } => format!(
+                r#"{{"event":"stale","days":{},"latest_reading":{}}}"#,
+                days,
+                json_string(latest_reading)
+            ),
+        }
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+// Write the event to stderr, if progress events were requested
+pub fn emit(event: ProgressEvent) {
+    if ENABLED.load(Ordering::Relaxed) {
+        let mut stderr = io::stderr().lock();
+        writeln!(stderr, "{}", event.to_json()).ok();
+        stderr.flush().ok();
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -0,0 +1,617 @@
+use crate::voltcraft::data::{PowerEvent, SourceId};
+use crate::voltcraft::raw::Registers;
+use chrono::{DateTime, Duration, FixedOffset};
+use thiserror::Error;
+
+const MAGIC: &[u8; 8] = b"VCARCH\0\0";
+const VERSION: u32 = 3;
+const FRAME_SIZE: usize = 32; // deltas Rice coded with the same parameter
+const MAX_DELTA_BITS: u32 = 17; // of a difference of two 16-bit values, zigzag encoded
+const ESCAPE: u32 = 16; // quotient from which a delta is written as is
+
+// Kinds of run
+const SERIES: u8 = 0; // readings as the logger stores them, a step apart
+const LITERAL: u8 = 1; // a reading stored as is (e.g. calibrated)
+
+/// Compact archive of readings, for long-term storage: the readings as stored by the logger (a
+/// tenth of a volt, a milliampere and a hundredth of power factor) are kept as series of minutes,
+/// each value as the difference with the previous one (Rice coded), as the voltage and the current
+/// change slowly: about a byte and a half a reading, against five in the data files. Other readings (calibrated, clamped, filled in) are kept as they are, as are the values as stored
+/// where the readings kept them, so the archive always decodes to the same readings; `verify`
+/// proves it for a given archive.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::archive;
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+///
+/// let (events, _) = sample_data().parse().unwrap();
+/// let bytes = archive::encode(&events);
+/// assert!(archive::verify(&events, &bytes).is_ok());
+/// let decoded = archive::decode(&bytes).unwrap();
+/// assert_eq!((decoded.len(), decoded[3].voltage), (9, events[3].voltage));
+/// ```
+pub fn encode(events: &[PowerEvent]) -> Vec<u8> {
+    let mut runs = Vec::<Run>::new();
+    for event in events {
+        let extended = match (runs.last_mut(), quantize(event)) {
+            (Some(Run::Series(series)), Some(values)) => series.extend(event, values),
+            _ => false,
+        };
+        if !extended {
+            runs.push(match quantize(event) {
+                Some(values) => Run::Series(Series::new(event, values)),
+                None => Run::Literal(*event),
+            });
+        }
+    }
+
+    let mut w = Vec::new();
+    w.extend_from_slice(MAGIC);
+    w.extend_from_slice(&VERSION.to_le_bytes());
+    put_varint(&mut w, runs.len() as u64);
+    for run in &runs {
+        match run {
+            Run::Series(series) => series.encode(&mut w),
+            Run::Literal(event) => {
+                w.push(LITERAL);
+                put_timestamp(&mut w, event.timestamp);
+                for value in [
+                    event.voltage,
+                    event.current,
+                    event.power_factor,
+                    event.power,
+                    event.apparent_power,
+                ] {
+                    w.extend_from_slice(&value.to_bits().to_le_bytes());
+                }
+                put_source(&mut w, event.source);
+                // Flags: the values as stored follow, the reading was filled in
+                w.push(event.registers.is_some() as u8 | (event.filled as u8) << 1);
+                if let Some(registers) = event.registers {
+                    w.extend_from_slice(&registers.encode());
+                }
+            }
+        }
+    }
+    w
+}
+
+// Reasons an archive can't be decoded, or doesn't hold the readings expected
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Error)]
+pub enum ArchiveError {
+    #[error("not an archive, or one written by another version")]
+    Invalid,
+    #[error("reading #{index} differs once decoded")]
+    Mismatch { index: usize },
+}
+
+// The readings of an archive (see encode). Decoding stops at the first value missing or out of
+// range, as the archive is then truncated or corrupted.
+pub fn decode(bytes: &[u8]) -> Result<Vec<PowerEvent>, ArchiveError> {
+    let mut r = Reader { bytes, offset: 0 };
+    let events = r.archive().ok_or(ArchiveError::Invalid)?;
+    if r.offset != bytes.len() {
+        return Err(ArchiveError::Invalid);
+    }
+    Ok(events)
+}
+
+// Check that an archive decodes to the given readings, to the bit (the UTC offsets and sources
+// included), before relying on it to store them
+pub fn verify(events: &[PowerEvent], archive: &[u8]) -> Result<(), ArchiveError> {
+    let decoded = decode(archive)?;
+    match events
+        .iter()
+        .zip(&decoded)
+        .position(|(a, b)| !identical(a, b))
+    {
+        Some(index) => Err(ArchiveError::Mismatch { index }),
+        None if events.len() != decoded.len() => Err(ArchiveError::Mismatch {
+            index: events.len().min(decoded.len()),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn identical(a: &PowerEvent, b: &PowerEvent) -> bool {
+    a.timestamp == b.timestamp
+        && a.timestamp.offset() == b.timestamp.offset()
+        && a.voltage.to_bits() == b.voltage.to_bits()
+        && a.current.to_bits() == b.current.to_bits()
+        && a.power_factor.to_bits() == b.power_factor.to_bits()
+        && a.power.to_bits() == b.power.to_bits()
+        && a.apparent_power.to_bits() == b.apparent_power.to_bits()
+        && a.source == b.source
+        && a.registers == b.registers
+        && a.filled == b.filled
+}
+
+enum Run {
+    Series(Series),
+    Literal(PowerEvent),
+}
+
+// Readings as stored by the logger, a fixed step apart, with the same UTC offset and decoded from
+// the same file at regular offsets
+struct Series {
+    start: DateTime<FixedOffset>,
+    last: DateTime<FixedOffset>,
+    step: i64, // seconds between readings (0 until there are two)
+    source: Option<SourceId>,
+    last_offset: usize,
+    source_step: usize,    // offset between readings in their file
+    registers: bool,       // the readings keep the values as stored (see PowerEvent::registers)
+    values: [Vec<u32>; 3], // voltage (V/10), current (mA) and power factor (1/100)
+}
+
+impl Series {
+    fn new(event: &PowerEvent, values: [u32; 3]) -> Self {
+        Series {
+            start: event.timestamp,
+            last: event.timestamp,
+            step: 0,
+            source: event.source,
+            last_offset: event.source.map_or(0, |s| s.offset),
+            source_step: 0,
+            registers: event.registers.is_some(),
+            values: values.map(|v| vec![v]),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.values[0].len()
+    }
+
+    // Add a reading that follows the series, if it does
+    fn extend(&mut self, event: &PowerEvent, values: [u32; 3]) -> bool {
+        let gap = event.timestamp - self.last;
+        if gap.subsec_nanos() != 0
+            || gap.num_seconds() <= 0
+            || (self.len() > 1 && gap.num_seconds() != self.step)
+            || event.timestamp.offset() != self.start.offset()
+            || event.registers.is_some() != self.registers
+        {
+            return false;
+        }
+        let source_step = match (self.source, event.source) {
+            (None, None) => 0,
+            (Some(first), Some(source))
+                if source.file == first.file && source.offset > self.last_offset =>
+            {
+                source.offset - self.last_offset
+            }
+            _ => return false,
+        };
+        if self.len() > 1 && source_step != self.source_step {
+            return false;
+        }
+        self.step = gap.num_seconds();
+        self.source_step = source_step;
+        self.last = event.timestamp;
+        self.last_offset = event.source.map_or(0, |s| s.offset);
+        for (series, value) in self.values.iter_mut().zip(values) {
+            series.push(value);
+        }
+        true
+    }
+
+    fn encode(&self, w: &mut Vec<u8>) {
+        w.push(SERIES);
+        put_timestamp(w, self.start);
+        put_varint(w, self.step as u64);
+        put_source(w, self.source);
+        put_varint(w, self.source_step as u64);
+        w.push(self.registers as u8);
+        put_varint(w, self.len() as u64);
+        for values in &self.values {
+            put_varint(w, values[0] as u64);
+        }
+        let mut bits = BitWriter::default();
+        for values in &self.values {
+            let deltas = values
+                .windows(2)
+                .map(|pair| zigzag(pair[1] as i64 - pair[0] as i64))
+                .collect::<Vec<_>>();
+            for frame in deltas.chunks(FRAME_SIZE) {
+                bits.frame(frame);
+            }
+        }
+        w.extend(bits.finish());
+    }
+}
+
+// The values of a reading as the logger stores them, if it was decoded unchanged (not calibrated
+// nor clamped) nor filled in, so that decoding them gives it back exactly, registers included
+fn quantize(event: &PowerEvent) -> Option<[u32; 3]> {
+    if event.filled {
+        return None;
+    }
+    let voltage = (event.voltage * 10.0).round();
+    let current = (event.current * 1000.0).round();
+    let power_factor = (event.power_factor * 100.0).round();
+    if !(0.0..=u16::MAX as f64).contains(&voltage)
+        || !(0.0..=u16::MAX as f64).contains(&current)
+        || !(0.0..=u8::MAX as f64).contains(&power_factor)
+    {
+        return None;
+    }
+    let values = [voltage as u32, current as u32, power_factor as u32];
+    let reading = registers(values).reading();
+    (event.registers.is_none_or(|r| r == registers(values))
+        && reading.voltage.to_bits() == event.voltage.to_bits()
+        && reading.current.to_bits() == event.current.to_bits()
+        && reading.power_factor.to_bits() == event.power_factor.to_bits()
+        && reading.power().to_bits() == event.power.to_bits()
+        && reading.apparent_power().to_bits() == event.apparent_power.to_bits())
+    .then_some(values)
+}
+
+// Values known to fit the file format (see quantize and Reader::series)
+fn registers([voltage, current, power_factor]: [u32; 3]) -> Registers {
+    Registers {
+        voltage: voltage as u16,
+        current: current as u16,
+        power_factor: power_factor as u8,
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+// LEB128
+fn put_varint(w: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        w.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    w.push(value as u8);
+}
+
+// Bits, least significant first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u64, width: u32) {
+        self.buffer |= value << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    // The Rice parameter k that makes the frame shortest, then each delta as its quotient by 2^k
+    // in unary and its remainder on k bits (as is past the escape quotient)
+    fn frame(&mut self, deltas: &[u64]) {
+        let k = (0..=MAX_DELTA_BITS)
+            .min_by_key(|&k| deltas.iter().map(|&d| rice_length(d, k)).sum::<u32>())
+            .unwrap_or(0);
+        self.put(k as u64, 5);
+        for &delta in deltas {
+            let quotient = delta >> k;
+            if quotient < ESCAPE as u64 {
+                self.put((1 << quotient) - 1, quotient as u32 + 1);
+                self.put(delta & ((1 << k) - 1), k);
+            } else {
+                self.put((1 << ESCAPE) - 1, ESCAPE);
+                self.put(delta, MAX_DELTA_BITS);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn rice_length(delta: u64, k: u32) -> u32 {
+    match delta >> k {
+        quotient if quotient < ESCAPE as u64 => quotient as u32 + 1 + k,
+        _ => ESCAPE + MAX_DELTA_BITS,
+    }
+}
+
+// Seconds since the epoch, nanoseconds and UTC offset
+fn put_timestamp(w: &mut Vec<u8>, timestamp: DateTime<FixedOffset>) {
+    put_varint(w, zigzag(timestamp.timestamp()));
+    put_varint(w, timestamp.timestamp_subsec_nanos() as u64);
+    put_varint(w, zigzag(timestamp.offset().local_minus_utc() as i64));
+}
+
+fn put_source(w: &mut Vec<u8>, source: Option<SourceId>) {
+    match source {
+        Some(source) => {
+            w.push(1);
+            put_varint(w, source.file as u64);
+            put_varint(w, source.offset as u64);
+        }
+        None => w.push(0),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.offset)?;
+        self.offset += 1;
+        Some(byte)
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.checked_add(N)?)?
+            .try_into()
+            .ok()?;
+        self.offset += N;
+        Some(bytes)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7F) as u64).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        self.varint()?.try_into().ok()
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_bits(u64::from_le_bytes(self.take()?)))
+    }
+
+    fn timestamp(&mut self) -> Option<DateTime<FixedOffset>> {
+        let seconds = unzigzag(self.varint()?);
+        let nanos = self.varint()?.try_into().ok()?;
+        let offset = FixedOffset::east_opt(unzigzag(self.varint()?).try_into().ok()?)?;
+        Some(DateTime::from_timestamp(seconds, nanos)?.with_timezone(&offset))
+    }
+
+    fn source(&mut self) -> Option<Option<SourceId>> {
+        Some(match self.u8()? {
+            0 => None,
+            1 => Some(SourceId {
+                file: self.usize()?,
+                offset: self.usize()?,
+            }),
+            _ => return None,
+        })
+    }
+
+    fn archive(&mut self) -> Option<Vec<PowerEvent>> {
+        if self.take::<8>()? != *MAGIC || u32::from_le_bytes(self.take()?) != VERSION {
+            return None;
+        }
+        let mut events = Vec::new();
+        for _ in 0..self.varint()? {
+            match self.u8()? {
+                SERIES => self.series(&mut events)?,
+                LITERAL => {
+                    let mut event = PowerEvent {
+                        timestamp: self.timestamp()?,
+                        voltage: self.f64()?,
+                        current: self.f64()?,
+                        power_factor: self.f64()?,
+                        power: self.f64()?,
+                        apparent_power: self.f64()?,
+                        source: self.source()?,
+                        registers: None,
+                        filled: false,
+                    };
+                    // Flags: the values as stored follow, the reading was filled in
+                    let flags = self.u8()?;
+                    if flags > 3 {
+                        return None;
+                    }
+                    if flags & 1 != 0 {
+                        event.registers = Some(Registers::decode(self.take()?));
+                    }
+                    event.filled = flags & 2 != 0;
+                    events.push(event);
+                }
+                _ => return None,
+            }
+        }
+        Some(events)
+    }
+
+    fn series(&mut self, events: &mut Vec<PowerEvent>) -> Option<()> {
+        let start = self.timestamp()?;
+        let step = Duration::try_seconds(self.varint()?.try_into().ok()?)?;
+        let source = self.source()?;
+        let source_step = self.usize()?;
+        let keep_registers = match self.u8()? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let count = self.usize()?;
+        // Every reading after the first takes a bit at least, which bounds the count of a
+        // corrupted archive
+        if count == 0 || count > self.bytes.len().saturating_mul(8) {
+            return None;
+        }
+        let first = [self.varint()?, self.varint()?, self.varint()?];
+        let mut bits = BitReader {
+            bytes: &self.bytes[self.offset..],
+            offset: 0,
+            buffer: 0,
+            bits: 0,
+        };
+        let mut channels: [Vec<u32>; 3] = Default::default();
+        let limits = [u16::MAX as i64, u16::MAX as i64, u8::MAX as i64];
+        for ((channel, first), limit) in channels.iter_mut().zip(first).zip(limits) {
+            let mut value = first as i64;
+            let mut deltas = Vec::with_capacity(count - 1);
+            let mut remaining = count - 1;
+            while remaining > 0 {
+                let frame = remaining.min(FRAME_SIZE);
+                bits.frame(frame, &mut deltas)?;
+                remaining -= frame;
+            }
+            channel.push(value.try_into().ok()?);
+            for delta in deltas {
+                value += unzigzag(delta);
+                if !(0..=limit).contains(&value) {
+                    return None;
+                }
+                channel.push(value as u32);
+            }
+        }
+        self.offset += bits.offset;
+        let [voltages, currents, power_factors] = channels;
+        let mut timestamp = start;
+        for (index, ((voltage, current), power_factor)) in voltages
+            .into_iter()
+            .zip(currents)
+            .zip(power_factors)
+            .enumerate()
+        {
+            let registers = registers([voltage, current, power_factor]);
+            let reading = registers.reading();
+            events.push(PowerEvent {
+                timestamp,
+                voltage: reading.voltage,
+                current: reading.current,
+                power_factor: reading.power_factor,
+                power: reading.power(),
+                apparent_power: reading.apparent_power(),
+                source: match source {
+                    Some(s) => Some(SourceId {
+                        file: s.file,
+                        offset: index.checked_mul(source_step)?.checked_add(s.offset)?,
+                    }),
+                    None => None,
+                },
+                registers: keep_registers.then_some(registers),
+                filled: false,
+            });
+            timestamp = timestamp.checked_add_signed(step)?;
+        }
+        Some(())
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    offset: usize, // of the next byte to read
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitReader<'_> {
+    fn get(&mut self, width: u32) -> Option<u64> {
+        while self.bits < width {
+            self.buffer |= (*self.bytes.get(self.offset)? as u64) << self.bits;
+            self.offset += 1;
+            self.bits += 8;
+        }
+        let value = self.buffer & ((1 << width) - 1);
+        self.buffer >>= width;
+        self.bits -= width;
+        Some(value)
+    }
+
+    fn frame(&mut self, count: usize, deltas: &mut Vec<u64>) -> Option<()> {
+        let k = self.get(5)? as u32;
+        if k > MAX_DELTA_BITS {
+            return None;
+        }
+        for _ in 0..count {
+            let mut quotient = 0;
+            while quotient < ESCAPE && self.get(1)? == 1 {
+                quotient += 1;
+            }
+            deltas.push(match quotient {
+                ESCAPE => self.get(MAX_DELTA_BITS)?,
+                _ => (quotient as u64) << k | self.get(k)?,
+            });
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voltcraft::data::{Calibration, Correction, ParseOptions, VoltcraftData};
+
+    #[test]
+    fn archive_roundtrip() {
+        let data = VoltcraftData::from_file("sample_data1/A04FC8D2.BIN", 1024 * 1024).unwrap();
+        let (mut events, _) = data.parse().unwrap();
+        let bytes = encode(&events);
+        assert_eq!(verify(&events, &bytes), Ok(()));
+        // A third of the data file at most, which takes 5 bytes a reading
+        assert!(bytes.len() * 3 < data.as_bytes().len());
+
+        // Calibrated readings are kept as they are, as are readings of no file, a second apart
+        // or in another zone
+        let calibration = Calibration {
+            voltage: Correction {
+                gain: 1.01,
+                offset: 0.0,
+            },
+            ..Default::default()
+        };
+        let (calibrated, _) = data
+            .parse_with(&ParseOptions::new().calibration(calibration))
+            .unwrap();
+        events[10] = calibrated[10];
+        events[20].source = None;
+        events[30].timestamp += Duration::seconds(1);
+        events[40].timestamp = events[40]
+            .timestamp
+            .with_timezone(&FixedOffset::east_opt(3600).unwrap());
+        // And the values as stored, where they were kept
+        let options = ParseOptions::new().keep_registers(true);
+        let (kept, _) = data.parse_with(&options).unwrap();
+        events[50..60].copy_from_slice(&kept[50..60]);
+        let (kept, _) = data.parse_with(&options.calibration(calibration)).unwrap();
+        events[60] = kept[60];
+        // And readings filled in, values as stored or not
+        events[60].filled = true;
+        events[70].filled = true;
+        let bytes = encode(&events);
+        assert_eq!(verify(&events, &bytes), Ok(()));
+        assert_eq!(
+            verify(&events[1..], &bytes),
+            Err(ArchiveError::Mismatch { index: 0 })
+        );
+        assert_eq!(
+            verify(&events[..100], &bytes),
+            Err(ArchiveError::Mismatch { index: 100 })
+        );
+        assert!(decode(&[]).is_err());
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]).err(),
+            Some(ArchiveError::Invalid)
+        );
+        assert_eq!(decode(&encode(&[])).unwrap().len(), 0);
+    }
+}
@@ -1,127 +1,976 @@
-use chrono::{Duration, Local, TimeZone};
-use std::fs;
+use crate::voltcraft::raw::{
+    ClockTime, RawDiagnostic, RawError, Record, Records, Registers, MAGIC_NUMBER,
+};
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone,
+};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC_NUMBER: [u8; 2] = [0x1F, 0x8B];
+
+/// The contents of a data file written by the logger, decoded on demand.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::data::VoltcraftData;
+/// use voltcraft_energy_analyzer::voltcraft::sample::SAMPLE_FILE;
+///
+/// let data = VoltcraftData::from_reader(SAMPLE_FILE, 1024 * 1024).unwrap();
+/// let (events, report) = data.parse().unwrap();
+/// assert_eq!(report.record_count, 9);
+/// assert_eq!(events[0].voltage, 224.6);
+/// ```
 pub struct VoltcraftData {
-    raw_data: Vec<u8>,
+    raw_data: RawData,
+}
+
+// The bytes of a data file, read into memory or mapped
+enum RawData {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl std::ops::Deref for RawData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            RawData::Owned(data) => data,
+            #[cfg(feature = "mmap")]
+            RawData::Mapped(map) => map,
+        }
+    }
+}
+
+/// Decoder settings, built by chaining setters on the defaults.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::data::{ParseMode, ParseOptions};
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+///
+/// let options = ParseOptions::new()
+///     .mode(ParseMode::Lenient)
+///     .nominal_voltage(120.0);
+/// let (events, report) = sample_data().parse_with(&options).unwrap();
+/// // The sample was recorded on a 230V grid, so none of its readings is plausible at 120V: they
+/// // are kept, but flagged
+/// assert_eq!(events.len(), 9);
+/// assert_eq!(report.implausible_voltages, 9);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParseOptions {
+    mode: ParseMode,
+    power_factor_policy: PowerFactorPolicy,
+    validation: ValidationConfig,
+    timezone: Option<FixedOffset>, // zone the logger clock was set in (None: local time)
+    sample_interval: Duration,     // time between two readings of a data block
+    source_file: usize,            // index of the file among those decoded, recorded in the events
+    time_correction: Option<TimeCorrection>, // of a logger clock that is off or drifts
+    calibration: Calibration,      // of the measurements, against a reference meter
+    stride: usize,                 // readings of a data block kept (every Nth), for quick previews
+    keep_registers: bool,          // record the values as stored in the events
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            mode: ParseMode::Strict,
+            power_factor_policy: PowerFactorPolicy::Clamp,
+            validation: ValidationConfig::default(),
+            timezone: None,
+            sample_interval: Duration::minutes(1),
+            source_file: 0,
+            time_correction: None,
+            calibration: Calibration::default(),
+            stride: 1,
+            keep_registers: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn power_factor_policy(mut self, policy: PowerFactorPolicy) -> Self {
+        self.power_factor_policy = policy;
+        self
+    }
+
+    pub fn validation(mut self, validation: ValidationConfig) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    // Scale the default plausible voltage range (meant for 230V grids) to the given nominal voltage
+    pub fn nominal_voltage(mut self, volts: f64) -> Self {
+        let default = ValidationConfig::default();
+        self.validation.min_voltage = default.min_voltage * volts / 230.0;
+        self.validation.max_voltage = default.max_voltage * volts / 230.0;
+        self
+    }
+
+    pub fn timezone(mut self, timezone: FixedOffset) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    pub fn sample_interval(mut self, interval: Duration) -> Self {
+        self.sample_interval = interval;
+        self
+    }
+
+    // Tag the decoded events with the index of the file, to trace them back once several files are merged
+    pub fn source_file(mut self, index: usize) -> Self {
+        self.source_file = index;
+        self
+    }
+
+    pub fn time_correction(mut self, correction: TimeCorrection) -> Self {
+        self.time_correction = Some(correction);
+        self
+    }
+
+    pub fn calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    // Keep only every Nth reading of each data block (the first, then every Nth after it), for a
+    // quick look at huge archives: the readings in between are counted but not validated nor
+    // returned, so statistics computed on the events are approximate (see
+    // VoltcraftStatistics::sampled_stats)
+    pub fn stride(mut self, every: usize) -> Self {
+        self.stride = every.max(1);
+        self
+    }
+
+    // Keep the values of each reading exactly as stored (PowerEvent::registers) besides the
+    // scaled ones, to audit the decoder or write the readings back bit for bit
+    pub fn keep_registers(mut self, keep: bool) -> Self {
+        self.keep_registers = keep;
+        self
+    }
+
+    // The time of a logger clock reading, corrected, and following the clock changes (e.g. within
+    // a data block) in local time
+    fn corrected(&self, timestamp: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let timestamp = self
+            .time_correction
+            .map_or(timestamp, |c| c.apply(timestamp));
+        match self.timezone {
+            Some(_) => timestamp,
+            None => timestamp.with_timezone(&Local).fixed_offset(),
+        }
+    }
+}
+
+// Maps the logger clock onto true time, for clocks that are off by a fixed offset or drift linearly
+// (e.g. run a few minutes a day fast), so the readings line up with other meters
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeCorrection {
+    reference: DateTime<FixedOffset>, // logger clock time at which the offset holds
+    offset: Duration,                 // true time minus logger clock time, at the reference
+    drift: f64, // time the logger clock gains per unit of time (fast: positive)
+}
+
+impl TimeCorrection {
+    // The logger clock is off by a constant offset (true time minus logger clock time)
+    pub fn offset(offset: Duration) -> Self {
+        TimeCorrection {
+            reference: DateTime::default(),
+            offset,
+            drift: 0.0,
+        }
+    }
+
+    // The logger clock compared twice with true time, as (logger clock, true time) pairs: the first
+    // pair gives the offset, the second the drift. None if both were taken at the same time.
+    pub fn from_references(
+        first: (DateTime<FixedOffset>, DateTime<FixedOffset>),
+        second: (DateTime<FixedOffset>, DateTime<FixedOffset>),
+    ) -> Option<Self> {
+        let logger_elapsed = (second.0 - first.0).num_milliseconds() as f64;
+        let true_elapsed = (second.1 - first.1).num_milliseconds() as f64;
+        (logger_elapsed != 0.0).then(|| TimeCorrection {
+            reference: first.0,
+            offset: first.1 - first.0,
+            drift: (logger_elapsed - true_elapsed) / logger_elapsed,
+        })
+    }
+
+    // True time of a logger clock time, to the second
+    pub fn apply(&self, timestamp: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let elapsed = (timestamp - self.reference).num_seconds() as f64;
+        timestamp + self.offset - Duration::seconds((self.drift * elapsed).round() as i64)
+    }
+}
+
+// Linear correction of a quantity measured by the logger: the true value is value * gain + offset
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Correction {
+    pub gain: f64,
+    pub offset: f64, // in the unit of the quantity
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Correction {
+            gain: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Correction {
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.gain + self.offset
+    }
+}
+
+impl FromStr for Correction {
+    type Err = String;
+
+    // <gain>[,<offset>], e.g. 0.97 for a quantity read 3% high
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (gain, offset) = s.split_once(',').unwrap_or((s, "0"));
+        match (gain.trim().parse::<f64>(), offset.trim().parse::<f64>()) {
+            (Ok(gain), Ok(offset)) if gain > 0.0 && gain.is_finite() && offset.is_finite() => {
+                Ok(Correction { gain, offset })
+            }
+            _ => Err(format!(
+                "Invalid correction '{}' (expected <gain>[,<offset>] with a positive gain, e.g. 0.97)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Correction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.gain, self.offset)
+    }
+}
+
+// Calibration of the logger against a reference meter. The voltage and current are corrected first,
+// the power computed from them, then the power correction applies to both the active and the
+// apparent power (the power factor is left as measured).
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Calibration {
+    pub voltage: Correction, // volts
+    pub current: Correction, // ampers
+    pub power: Correction,   // kW (kVA for the apparent power)
+}
+
+impl Calibration {
+    pub fn is_identity(&self) -> bool {
+        *self == Calibration::default()
+    }
+
+    // Voltage, current, power factor, active and apparent power, as decoded
+    fn apply(&self, reading: (f64, f64, f64, f64, f64)) -> (f64, f64, f64, f64, f64) {
+        if self.is_identity() {
+            return reading;
+        }
+        let (voltage, current, power_factor, _, _) = reading;
+        let voltage = self.voltage.apply(voltage);
+        let current = self.current.apply(current).max(0.0);
+        let power = self.power.apply(voltage * current * power_factor / 1000.0);
+        let apparent_power = self.power.apply(voltage * current / 1000.0);
+        (voltage, current, power_factor, power, apparent_power)
+    }
+}
+
+// Plausible range of the decoded readings, and what to do with the readings outside of it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ValidationConfig {
+    pub min_voltage: f64, // volts
+    pub max_voltage: f64, // volts
+    pub policy: ImplausiblePolicy,
+    pub max_current: f64, // ampers, the rating of the logger
+    pub current_policy: ImplausiblePolicy,
+}
+
+impl Default for ValidationConfig {
+    // Suited to 230V/50Hz grids
+    fn default() -> Self {
+        ValidationConfig {
+            min_voltage: 150.0,
+            // 10% of the nominal voltage beyond the band of EN 50160 (see GridProfile::validation)
+            max_voltage: 276.0,
+            // Kept, as the voltage may be a genuine brown-out: dropped, it would leave a gap that
+            // reads as a blackout
+            policy: ImplausiblePolicy::Flag,
+            // The logger measures up to 16A; more can only come from a corrupted current
+            max_current: 16.0,
+            current_policy: ImplausiblePolicy::Drop,
+        }
+    }
+}
+
+// What to do with implausible samples (decoding glitches or genuine brown-outs)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImplausiblePolicy {
+    Flag, // keep the sample, only count it
+    Drop, // discard the sample
+}
+
+impl FromStr for ImplausiblePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "flag" => Ok(ImplausiblePolicy::Flag),
+            "drop" => Ok(ImplausiblePolicy::Drop),
+            _ => Err(format!(
+                "Invalid implausible sample policy '{}' (expected flag or drop)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ImplausiblePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImplausiblePolicy::Flag => write!(f, "flag"),
+            ImplausiblePolicy::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+// How to deal with corrupted data in the middle of a file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseMode {
+    Strict,  // reject the whole file
+    Lenient, // skip to the next data block and keep what could be decoded
+}
+
+impl FromStr for ParseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(ParseMode::Strict),
+            "lenient" => Ok(ParseMode::Lenient),
+            _ => Err(format!(
+                "Invalid parse mode '{}' (expected strict or lenient)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ParseMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseMode::Strict => write!(f, "strict"),
+            ParseMode::Lenient => write!(f, "lenient"),
+        }
+    }
+}
+
+// What to do with samples whose power factor exceeds 1.0 (firmware glitches)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PowerFactorPolicy {
+    Clamp, // clamp the power factor to 1.0 and recompute the active power
+    Drop,  // discard the sample
+    Keep,  // keep the sample as decoded
+}
+
+impl FromStr for PowerFactorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(PowerFactorPolicy::Clamp),
+            "drop" => Ok(PowerFactorPolicy::Drop),
+            "keep" => Ok(PowerFactorPolicy::Keep),
+            _ => Err(format!(
+                "Invalid power factor policy '{}' (expected clamp, drop or keep)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PowerFactorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerFactorPolicy::Clamp => write!(f, "clamp"),
+            PowerFactorPolicy::Drop => write!(f, "drop"),
+            PowerFactorPolicy::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+// Reasons a Voltcraft data file cannot be decoded
+#[derive(Debug, Error, Copy, Clone, PartialEq)]
+pub enum ParseError {
+    #[error("cannot read file")]
+    Unreadable,
+    #[error("file of {size} bytes exceeds the limit of {limit} bytes")]
+    TooLarge { size: u64, limit: u64 },
+    #[error("invalid data file header, probably not a Voltcraft file")]
+    InvalidHeader,
+    #[error("unexpected end of data at offset {offset}")]
+    UnexpectedEof { offset: usize },
+    #[error("invalid timestamp at offset {offset}")]
+    InvalidTimestamp { offset: usize },
+}
+
+impl From<RawError> for ParseError {
+    fn from(error: RawError) -> Self {
+        match error {
+            RawError::InvalidHeader => ParseError::InvalidHeader,
+            RawError::UnexpectedEof { offset } => ParseError::UnexpectedEof { offset },
+            RawError::InvalidTimestamp { offset } => ParseError::InvalidTimestamp { offset },
+        }
+    }
+}
+
+// A corrupted region skipped in lenient mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParseDiagnostic {
+    pub offset: usize,     // where decoding failed
+    pub skipped: usize,    // bytes skipped until the next data block (or the end of the file)
+    pub error: ParseError, // why decoding failed
+}
+
+impl From<RawDiagnostic> for ParseDiagnostic {
+    fn from(diagnostic: RawDiagnostic) -> Self {
+        ParseDiagnostic {
+            offset: diagnostic.offset,
+            skipped: diagnostic.skipped,
+            error: diagnostic.error.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {} bytes skipped", self.error, self.skipped)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ParseReport {
+    pub record_count: usize, // readings stored in the file, including the discarded ones
+    pub power_factor_anomalies: usize, // samples with a power factor above 1.0
+    pub implausible_voltages: usize, // samples with a voltage outside the plausible range
+    pub implausible_currents: usize, // samples with a current above the rating of the logger
+    pub dropped_samples: usize, // samples discarded by validation (implausible voltage, current or power factor)
+    pub calibration: Calibration, // applied to the readings
+    pub stride: usize, // one reading decoded in that many (see ParseOptions::stride), 1 for all
+    pub diagnostics: Vec<ParseDiagnostic>, // corrupted regions skipped in lenient mode, and an incomplete last reading
+    pub sessions: Vec<RecordingSession>,   // data blocks, in file order
+    pub skipped_local_times: Vec<usize>, // offsets of block headers dated in an hour skipped when the clocks went forward
+    pub missing_end_marker: bool, // the file ends without the end of data marker (copied while the logger was recording)
+}
+
+impl ParseReport {
+    // Bytes of corrupted data skipped in lenient mode
+    pub fn skipped_bytes(&self) -> usize {
+        self.diagnostics.iter().map(|d| d.skipped).sum()
+    }
+
+    // The file holds no readings, only data block headers (the logger was plugged in and
+    // unplugged before the first minute elapsed): valid, but nothing to analyze
+    pub fn is_empty(&self) -> bool {
+        self.record_count == 0
+    }
+
+    // Earliest and latest timestamps of the readings stored in the file, if any
+    pub fn time_range(&self) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        let sessions = self.sessions.iter().filter(|s| s.event_count > 0);
+        let start = sessions.clone().map(|s| s.start).min()?;
+        let end = sessions.map(|s| s.end).max()?;
+        Some((start, end))
+    }
+}
+
+// A data block of the file: the logger starts a new one each time it starts recording (e.g. when
+// the power comes back), so the gaps between sessions are periods the device was not recording
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RecordingSession {
+    pub start: chrono::DateTime<FixedOffset>, // timestamp of the first reading
+    pub end: chrono::DateTime<FixedOffset>, // timestamp of the last reading (the start if there is none)
+    pub event_count: usize,                 // readings stored, including the discarded ones
+    pub file_offset: usize,                 // offset of the data block header
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PowerEvent {
-    pub timestamp: chrono::DateTime<Local>, // timestamp
-    pub voltage: f64,                       // volts
-    pub current: f64,                       // ampers
-    pub power_factor: f64,                  // cos(phi)
-    pub power: f64,                         // kW
-    pub apparent_power: f64,                // kVA
+    pub timestamp: chrono::DateTime<FixedOffset>, // logger clock time, with its UTC offset
+    pub voltage: f64,                             // volts
+    pub current: f64,                             // ampers
+    pub power_factor: f64,                        // cos(phi)
+    pub power: f64,                               // kW
+    pub apparent_power: f64,                      // kVA
+    pub source: Option<SourceId>,                 // where the reading was decoded from
+    pub registers: Option<Registers>, // as stored, if kept (see ParseOptions::keep_registers)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub filled: bool, // synthetic, inserted to fill a gap rather than read (see transform::fill_gaps)
 }
 
-impl VoltcraftData {
-    pub fn from_file(filename: &str) -> Result<VoltcraftData, &'static str> {
-        let contents = fs::read(filename);
-        match contents {
-            Err(_) => return Err("File not found"),
-            Ok(raw_data) => return Ok(VoltcraftData { raw_data }),
+// Origin of a reading: the file (an index given by the caller, see ParseOptions::source_file) and
+// the offset of the reading in it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceId {
+    pub file: usize,
+    pub offset: usize,
+}
+
+/// Quantities derived from the active and apparent power of a reading, for power quality analyses.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+///
+/// let (events, _) = sample_data().parse().unwrap();
+/// // 224.6V, 0.446A, cos phi 0.87
+/// let derived = events[0].derived();
+/// assert_eq!(format!("{:.4}", derived.reactive_power), "0.0494");
+/// assert_eq!(format!("{:.1}", derived.phase_angle), "29.5");
+/// assert_eq!(format!("{:.3}", derived.tan_phi.unwrap()), "0.567");
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DerivedPower {
+    pub reactive_power: f64, // kvar, unsigned as the logger doesn't tell inductive and capacitive loads apart
+    pub phase_angle: f64,    // degrees, between the voltage and the current
+    pub tan_phi: Option<f64>, // reactive over active power (None without active power)
+}
+
+impl PowerEvent {
+    // The reactive power, phase angle and tan phi, from the active and apparent power
+    pub fn derived(&self) -> DerivedPower {
+        let (power, apparent_power) = (self.power, self.apparent_power);
+        let reactive_power = (apparent_power.powi(2) - power.powi(2)).max(0.0).sqrt();
+        let phase_angle = if apparent_power > 0.0 {
+            (power / apparent_power)
+                .clamp(-1.0, 1.0)
+                .acos()
+                .to_degrees()
+        } else {
+            0.0
         };
+        DerivedPower {
+            reactive_power,
+            phase_angle,
+            tan_phi: (power != 0.0).then(|| reactive_power / power),
+        }
     }
+}
 
-    pub fn from_raw(raw_data: Vec<u8>) -> VoltcraftData {
-        VoltcraftData { raw_data }
-    }
-
-    pub fn parse(&self) -> Result<Vec<PowerEvent>, &'static str> {
-        let mut result = Vec::<PowerEvent>::new();
-        // The initial offset in the data block is zero
-        let mut offset = 0;
-        // Set the initial time somewhere in the past as it will be overwritten anyway
-        let mut start_time = chrono::Local.ymd(2000, 1, 1).and_hms(0, 0, 0);
-        // For each new power event we encounter, the timestamp is increased by one minute (the Voltcraft device records parameters each minute)
-        let mut minute_increment = 0;
-
-        // Check whether we have a valid data file (the data block header should be at the beginning of the file)
-        if !self.is_datablock(offset) {
-            return Err("Invalid data file, probably not a Voltcraft file");
-        }
-
-        loop {
-            // If we encounter the beginning of a data block, decode and memorize the timestamp
-            if self.is_datablock(offset) {
-                offset += 3;
-                start_time = self.decode_timestamp(offset);
-                minute_increment = 0;
-                offset += 5;
-                continue;
-            }
-            // Check whether we have reached the end of the Voltcraft data file
-            if self.is_endofdata(offset) {
-                break;
+impl VoltcraftData {
+    // Read a data file, rejecting files larger than max_size or without a Voltcraft header before reading them whole
+    pub fn from_file(path: impl AsRef<Path>, max_size: u64) -> Result<VoltcraftData, ParseError> {
+        let file = File::open(path).map_err(|_| ParseError::Unreadable)?;
+        let size = file.metadata().map_err(|_| ParseError::Unreadable)?.len();
+        if size > max_size {
+            return Err(ParseError::TooLarge {
+                size,
+                limit: max_size,
+            });
+        }
+        VoltcraftData::from_reader(file, max_size)
+    }
+
+    // Read a data file from any source (e.g. a pipe), failing if it holds more than max_size bytes
+    pub fn from_reader(mut reader: impl Read, max_size: u64) -> Result<VoltcraftData, ParseError> {
+        let mut header = [0u8; 3];
+        if reader.read_exact(&mut header).is_err() {
+            return Err(ParseError::InvalidHeader);
+        }
+        #[cfg(feature = "gzip")]
+        if header[..2] == GZIP_MAGIC_NUMBER {
+            // Decompress archived dumps on the fly; the size limit applies to the decompressed data
+            let mut decoder = flate2::read::GzDecoder::new(io::Cursor::new(header).chain(reader));
+            if decoder.read_exact(&mut header).is_err() {
+                return Err(ParseError::InvalidHeader);
             }
-            let power_data = self.decode_power(offset);
-            let power_timestamp = start_time + Duration::minutes(minute_increment);
-            minute_increment += 1; // Increment the timestamp by 1 minute
-            offset += 5; // Increment byte offset
+            return VoltcraftData::read_data(decoder, header, max_size);
+        }
+        VoltcraftData::read_data(reader, header, max_size)
+    }
 
-            result.push(PowerEvent {
-                timestamp: power_timestamp,
-                voltage: power_data.0,
-                current: power_data.1,
-                power_factor: power_data.2,
-                power: power_data.3,
-                apparent_power: power_data.4,
+    // Read the rest of a data file, given its first bytes
+    fn read_data(
+        reader: impl Read,
+        header: [u8; 3],
+        max_size: u64,
+    ) -> Result<VoltcraftData, ParseError> {
+        if header != MAGIC_NUMBER {
+            return Err(ParseError::InvalidHeader);
+        }
+        let mut raw_data = header.to_vec();
+        let mut limited = reader.take(max_size.saturating_sub(header.len() as u64));
+        limited
+            .read_to_end(&mut raw_data)
+            .map_err(|_| ParseError::Unreadable)?;
+        // Measure what is left without keeping it, to report the actual size
+        let excess = io::copy(&mut limited.into_inner(), &mut io::sink())
+            .map_err(|_| ParseError::Unreadable)?;
+        if excess > 0 {
+            return Err(ParseError::TooLarge {
+                size: raw_data.len() as u64 + excess,
+                limit: max_size,
             });
         }
-        Ok(result)
+        Ok(VoltcraftData::from_raw(raw_data))
     }
 
-    fn is_datablock(&self, off: usize) -> bool {
-        const MAGIC_NUMBER: [u8; 3] = [0xE0, 0xC5, 0xEA];
-        let header = &self.raw_data[off..off + 3];
-        header == MAGIC_NUMBER
+    pub fn from_raw(raw_data: Vec<u8>) -> VoltcraftData {
+        VoltcraftData {
+            raw_data: RawData::Owned(raw_data),
+        }
     }
 
-    fn is_endofdata(&self, off: usize) -> bool {
-        const END_OF_DATA: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
-        let eod = &self.raw_data[off..off + 4];
-        eod == END_OF_DATA
+    // Map a data file into memory instead of reading it, for archives of hundreds of megabytes
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap(path: impl AsRef<Path>) -> Result<VoltcraftData, ParseError> {
+        let file = File::open(path).map_err(|_| ParseError::Unreadable)?;
+        // SAFETY: the map is read-only; as with any mapped file, the data must not be
+        // truncated by another process while it is decoded
+        let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|_| ParseError::Unreadable)?;
+        if !map.starts_with(&MAGIC_NUMBER) {
+            return Err(ParseError::InvalidHeader);
+        }
+        Ok(VoltcraftData {
+            raw_data: RawData::Mapped(map),
+        })
     }
 
-    fn decode_timestamp(&self, off: usize) -> chrono::DateTime<Local> {
-        let month: u8 = self.raw_data[off];
-        let day: u8 = self.raw_data[off + 1];
-        let year: u8 = self.raw_data[off + 2];
-        let hour: u8 = self.raw_data[off + 3];
-        let minute: u8 = self.raw_data[off + 4];
-        chrono::Local
-            .ymd(year as i32 + 2000, month as u32, day as u32)
-            .and_hms(hour as u32, minute as u32, 0)
+    // As from_file, without blocking the async runtime while the file is read
+    #[cfg(feature = "tokio")]
+    pub async fn from_file_async(
+        path: impl AsRef<Path>,
+        max_size: u64,
+    ) -> Result<VoltcraftData, ParseError> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|_| ParseError::Unreadable)?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(|_| ParseError::Unreadable)?
+            .len();
+        if size > max_size {
+            return Err(ParseError::TooLarge {
+                size,
+                limit: max_size,
+            });
+        }
+        VoltcraftData::from_reader_async(file, max_size).await
     }
 
-    fn decode_power(&self, off: usize) -> (f64, f64, f64, f64, f64) {
-        // Decode voltage (2 bytes - Big Endian)
-        let voltage: [u8; 2] = self.raw_data[off..off + 2].try_into().unwrap();
-        let voltage = u16::from_be_bytes(voltage);
-        let voltage: f64 = voltage as f64 / 10.0; // volts
-        assert!(voltage > 150.0, "Low voltage at offset {}", off);
-        assert!(voltage < 250.0, "High voltage at offset {}", off);
+    // As from_reader, for an async source (e.g. an upload): the bytes are read without blocking,
+    // then checked (and decompressed) as from_reader does
+    #[cfg(feature = "tokio")]
+    pub async fn from_reader_async(
+        mut reader: impl tokio::io::AsyncRead + Unpin,
+        max_size: u64,
+    ) -> Result<VoltcraftData, ParseError> {
+        use tokio::io::AsyncReadExt;
 
-        // Decode current (2 bytes - Big Endian)
-        let current: [u8; 2] = self.raw_data[off + 2..off + 4].try_into().unwrap();
-        let current = u16::from_be_bytes(current);
-        let current: f64 = current as f64 / 1000.0; // ampers
+        let mut header = [0u8; 3];
+        if reader.read_exact(&mut header).await.is_err() {
+            return Err(ParseError::InvalidHeader);
+        }
+        #[cfg(feature = "gzip")]
+        let compressed = header[..2] == GZIP_MAGIC_NUMBER;
+        #[cfg(not(feature = "gzip"))]
+        let compressed = false;
+        if header != MAGIC_NUMBER && !compressed {
+            return Err(ParseError::InvalidHeader);
+        }
+        let mut raw_data = header.to_vec();
+        let mut limited = reader.take(max_size.saturating_sub(header.len() as u64));
+        limited
+            .read_to_end(&mut raw_data)
+            .await
+            .map_err(|_| ParseError::Unreadable)?;
+        let excess = tokio::io::copy(&mut limited.into_inner(), &mut tokio::io::sink())
+            .await
+            .map_err(|_| ParseError::Unreadable)?;
+        if excess > 0 {
+            return Err(ParseError::TooLarge {
+                size: raw_data.len() as u64 + excess,
+                limit: max_size,
+            });
+        }
+        VoltcraftData::from_reader(raw_data.as_slice(), max_size)
+    }
 
-        // Decode power factor (1 byte)
-        let power_factor: u8 = self.raw_data[off + 4];
-        let power_factor: f64 = power_factor as f64 / 100.0; // cos phi
+    // The raw bytes being decoded
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw_data
+    }
 
-        let power = voltage * current * power_factor / 1000.0; // kW
-        let apparent_power = voltage * current / 1000.0; // kVA
-        (voltage, current, power_factor, power, apparent_power)
+    // Decode with the default options
+    pub fn parse(&self) -> Result<(Vec<PowerEvent>, ParseReport), ParseError> {
+        self.parse_with(&ParseOptions::default())
+    }
+
+    pub fn parse_with(
+        &self,
+        options: &ParseOptions,
+    ) -> Result<(Vec<PowerEvent>, ParseReport), ParseError> {
+        let mut events = self.events_with(options);
+        let result = events.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok((result, events.into_report()))
+    }
+
+    // Stream the power events with the default options, without collecting them
+    pub fn events(&self) -> Events<'_> {
+        self.events_with(&ParseOptions::default())
+    }
+
+    pub fn events_with(&self, options: &ParseOptions) -> Events<'_> {
+        Events {
+            records: Records::new(self.as_bytes(), options.mode == ParseMode::Lenient),
+            options: *options,
+            // Set the initial time somewhere in the past as it will be overwritten anyway
+            start_time: chrono::Local
+                .with_ymd_and_hms(2000, 1, 1, 0, 0, 0)
+                .unwrap()
+                .fixed_offset(),
+            sample_increment: 0,
+            report: ParseReport {
+                calibration: options.calibration,
+                stride: options.stride,
+                ..ParseReport::default()
+            },
+            finished: false,
+        }
     }
 }
 
-#[cfg(test)]
+// The instant the start time of a data block stands for (see clock_time), in the zone of the
+// logger clock (None: local time)
+fn clock_timestamp(
+    clock: ClockTime,
+    timezone: Option<FixedOffset>,
+) -> Option<(DateTime<FixedOffset>, bool)> {
+    let clock = NaiveDate::from_ymd_opt(clock.year as i32, clock.month as u32, clock.day as u32)?
+        .and_hms_opt(clock.hour as u32, clock.minute as u32, 0)?;
+    match timezone {
+        Some(timezone) => clock_time(&timezone, clock),
+        None => clock_time(&Local, clock),
+    }
+}
 
+// The instant a time shown by the logger clock stands for in the zone it was set in, and whether
+// that time doesn't exist there. The logger clock knows nothing of daylight saving time: of the
+// times shown twice when the clocks go back, the earliest is taken; the times skipped when the
+// clocks go forward (which a clock that wasn't put forward yet still shows) keep the UTC offset
+// from before the change, so that 02:30 on a 02:00 to 03:00 change is read as 03:30.
+fn clock_time<Tz: TimeZone>(
+    zone: &Tz,
+    clock: NaiveDateTime,
+) -> Option<(DateTime<FixedOffset>, bool)> {
+    match zone.from_local_datetime(&clock) {
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => Some((t.fixed_offset(), false)),
+        LocalResult::None => {
+            // The clocks go forward by an hour, two at most
+            let before = zone
+                .offset_from_local_datetime(&(clock - Duration::hours(3)))
+                .earliest()?
+                .fix();
+            Some((before.from_local_datetime(&clock).single()?, true))
+        }
+    }
+}
+
+/// Iterator over the power events of a data file. Errors end the iteration in strict mode; in lenient
+/// mode the corrupted regions are skipped and recorded in the report.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+///
+/// let data = sample_data();
+/// let peak = data
+///     .events()
+///     .map(|e| e.map(|e| e.power))
+///     .try_fold(0.0, |peak, power| power.map(|p| f64::max(peak, p)))
+///     .unwrap();
+/// assert!(peak > 0.08 && peak < 0.1); // kW
+/// ```
+pub struct Events<'a> {
+    records: Records<'a>,
+    options: ParseOptions,
+    start_time: chrono::DateTime<FixedOffset>,
+    // For each new power event we encounter, the timestamp is increased by the sample interval (the Voltcraft device records parameters each minute)
+    sample_increment: i32,
+    report: ParseReport,
+    finished: bool,
+}
+
+impl Events<'_> {
+    // What was found while decoding so far (complete once the iterator is exhausted)
+    pub fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> ParseReport {
+        self.report
+    }
+
+    fn next_event(&mut self) -> Result<Option<PowerEvent>, ParseError> {
+        while let Some(record) = self.records.next() {
+            let (offset, reading, registers) = match record? {
+                // A data block starts: decode and memorize the timestamp
+                Record::Block { offset, clock } => {
+                    match clock_timestamp(clock, self.options.timezone) {
+                        Some((timestamp, skipped_time)) => {
+                            if skipped_time {
+                                self.report.skipped_local_times.push(offset);
+                            }
+                            let start = self.options.corrected(timestamp);
+                            self.report.sessions.push(RecordingSession {
+                                start,
+                                end: start,
+                                event_count: 0,
+                                file_offset: offset,
+                            });
+                            self.start_time = timestamp;
+                            self.sample_increment = 0;
+                        }
+                        // The readings of the block can't be dated, so skip the whole block
+                        None => {
+                            let error = RawError::InvalidTimestamp { offset: offset + 3 };
+                            let diagnostic = self.records.resync(offset, error)?;
+                            self.report.diagnostics.push(diagnostic.into());
+                        }
+                    }
+                    continue;
+                }
+                Record::Skipped(diagnostic) => {
+                    self.report.diagnostics.push(diagnostic.into());
+                    continue;
+                }
+                Record::Reading {
+                    offset,
+                    reading,
+                    registers,
+                } => (offset, reading, registers),
+            };
+            let mut power_data = self.options.calibration.apply((
+                reading.voltage,
+                reading.current,
+                reading.power_factor,
+                reading.power(),
+                reading.apparent_power(),
+            ));
+            self.report.record_count += 1;
+            let power_timestamp = self
+                .options
+                .corrected(self.start_time + self.options.sample_interval * self.sample_increment);
+            self.sample_increment += 1; // Increment the timestamp by one sample interval
+            let source = SourceId {
+                file: self.options.source_file,
+                offset,
+            };
+            if let Some(session) = self.report.sessions.last_mut() {
+                session.end = power_timestamp;
+                session.event_count += 1;
+            }
+            if !((self.sample_increment - 1) as usize).is_multiple_of(self.options.stride) {
+                continue;
+            }
+
+            let voltage = power_data.0;
+            let validation = &self.options.validation;
+            if voltage < validation.min_voltage || voltage > validation.max_voltage {
+                self.report.implausible_voltages += 1;
+                if validation.policy == ImplausiblePolicy::Drop {
+                    self.report.dropped_samples += 1;
+                    continue;
+                }
+            }
+            // The power is computed from the voltage, current and power factor, so it always
+            // matches them: a corrupted current only shows as one beyond what the logger measures
+            if power_data.1 > validation.max_current {
+                self.report.implausible_currents += 1;
+                if validation.current_policy == ImplausiblePolicy::Drop {
+                    self.report.dropped_samples += 1;
+                    continue;
+                }
+            }
+
+            // A power factor above 1.0 is physically impossible and would yield more active than apparent power
+            if power_data.2 > 1.0 {
+                self.report.power_factor_anomalies += 1;
+                match self.options.power_factor_policy {
+                    PowerFactorPolicy::Clamp => {
+                        power_data.2 = 1.0;
+                        power_data.3 = power_data.4;
+                    }
+                    PowerFactorPolicy::Drop => {
+                        self.report.dropped_samples += 1;
+                        continue;
+                    }
+                    PowerFactorPolicy::Keep => {}
+                }
+            }
+
+            return Ok(Some(PowerEvent {
+                timestamp: power_timestamp,
+                voltage: power_data.0,
+                current: power_data.1,
+                power_factor: power_data.2,
+                power: power_data.3,
+                apparent_power: power_data.4,
+                source: Some(source),
+                registers: self.options.keep_registers.then_some(registers),
+                filled: false,
+            }));
+        }
+        self.report.missing_end_marker = self.records.missing_end_marker();
+        Ok(None)
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<PowerEvent, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let event = self.next_event();
+        if !matches!(event, Ok(Some(_))) {
+            self.finished = true;
+        }
+        event.transpose()
+    }
+}
+
+#[cfg(test)]
 mod tests {
-    use crate::voltcraft::data::VoltcraftData;
-    use chrono::DateTime;
+    use crate::voltcraft::data::{
+        clock_time, clock_timestamp, Calibration, Correction, ImplausiblePolicy, ParseError,
+        ParseMode, ParseOptions, PowerFactorPolicy, TimeCorrection, ValidationConfig,
+        VoltcraftData,
+    };
+    use crate::voltcraft::raw::{Record, Records};
+    use crate::voltcraft::stats::VoltcraftStatistics;
+    use chrono::{
+        DateTime, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    };
     const TESTDATA: [u8; 17] = [
         // Header (magic number)
         0xE0, 0xC5, 0xEA, // Power data
@@ -131,20 +980,613 @@ mod tests {
 
     #[test]
     fn voltcraft_timestamp() {
-        let vd = VoltcraftData::from_raw(TESTDATA.to_vec());
-        let offset_timestamp = 3;
-        let ts = vd.decode_timestamp(offset_timestamp);
+        let Some(Ok(Record::Block { clock, .. })) = Records::new(&TESTDATA, false).next() else {
+            panic!("no data block");
+        };
+        let zone = FixedOffset::east_opt(3 * 3600).unwrap();
+        let (ts, _) = clock_timestamp(clock, Some(zone)).unwrap();
         let expected = DateTime::parse_from_rfc3339("2014-09-11T18:43:00+03:00").unwrap();
         assert_eq!(ts, expected);
+        // The offset is kept, so the logger clock reads the same whatever the zone of the computer
+        assert_eq!(ts.offset(), expected.offset());
+        // In local time, the logger clock reads the same as well
+        let (ts, _) = clock_timestamp(clock, None).unwrap();
+        assert_eq!(ts.naive_local(), expected.naive_local());
+    }
+
+    // Eastern European Time in 2014: UTC+2, and UTC+3 from March 30 to October 26 (01:00 UTC)
+    #[derive(Debug, Copy, Clone)]
+    struct Eet;
+
+    impl Eet {
+        const WINTER: i32 = 2 * 3600;
+        const SUMMER: i32 = 3 * 3600;
+    }
+
+    impl TimeZone for Eet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Eet
+        }
+
+        // The offset at the start of the day
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            // The offsets that give back this local time, the earliest instant first
+            let offsets = [Eet::SUMMER, Eet::WINTER]
+                .map(|s| FixedOffset::east_opt(s).unwrap())
+                .into_iter()
+                .filter(|o| {
+                    let utc = *local - Duration::seconds(o.local_minus_utc() as i64);
+                    self.offset_from_utc_datetime(&utc) == *o
+                })
+                .collect::<Vec<_>>();
+            match offsets[..] {
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest] => LocalResult::Ambiguous(earliest, latest),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let at = |month, day| {
+                NaiveDate::from_ymd_opt(2014, month, day)
+                    .unwrap()
+                    .and_hms_opt(1, 0, 0)
+                    .unwrap()
+            };
+            let summer = (at(3, 30)..at(10, 26)).contains(utc);
+            FixedOffset::east_opt(if summer { Eet::SUMMER } else { Eet::WINTER }).unwrap()
+        }
+    }
+
+    #[test]
+    fn voltcraft_daylight_saving_time() {
+        let clock = |s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        let at = |s| DateTime::parse_from_rfc3339(s).unwrap();
+        assert_eq!(
+            clock_time(&Eet, clock("2014-09-11 18:43")),
+            Some((at("2014-09-11T18:43:00+03:00"), false))
+        );
+        // Shown twice when the clocks go back: the earliest
+        assert_eq!(
+            clock_time(&Eet, clock("2014-10-26 03:30")),
+            Some((at("2014-10-26T03:30:00+03:00"), false))
+        );
+        // Skipped when the clocks go forward: winter time, flagged
+        assert_eq!(
+            clock_time(&Eet, clock("2014-03-30 03:30")),
+            Some((at("2014-03-30T03:30:00+02:00"), true))
+        );
+        assert_eq!(
+            clock_time(&Eet, clock("2014-03-30 04:00")),
+            Some((at("2014-03-30T04:00:00+03:00"), false))
+        );
+        // Days take the offset of their midnight
+        let day = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert_eq!(
+            Eet.offset_from_local_date(&day("2014-09-11")),
+            LocalResult::Single(FixedOffset::east_opt(Eet::SUMMER).unwrap())
+        );
+        assert_eq!(
+            Eet.offset_from_utc_date(&day("2014-10-27")),
+            FixedOffset::east_opt(Eet::WINTER).unwrap()
+        );
+    }
+
+    #[test]
+    fn voltcraft_calibration() {
+        // A logger reading the voltage 2V high and the power 3% high
+        let calibration = Calibration {
+            voltage: "1,-2".parse().unwrap(),
+            power: "0.97".parse().unwrap(),
+            ..Calibration::default()
+        };
+        let (events, report) = VoltcraftData::from_raw(TESTDATA.to_vec())
+            .parse_with(&ParseOptions::new().calibration(calibration))
+            .unwrap();
+        assert_eq!(report.calibration, calibration);
+        assert_eq!(events[0].voltage, 222.6);
+        assert_eq!(events[0].current, 0.446);
+        assert!((events[0].power - 0.97 * 222.6 * 0.446 * 0.87 / 1000.0).abs() < 1e-12);
+        assert!((events[0].apparent_power - 0.97 * 222.6 * 0.446 / 1000.0).abs() < 1e-12);
+
+        assert_eq!(
+            "1.03, 0.5".parse::<Correction>(),
+            Ok(Correction {
+                gain: 1.03,
+                offset: 0.5
+            })
+        );
+        for invalid in ["", "0", "-1", "1,x", "inf"] {
+            assert!(invalid.parse::<Correction>().is_err());
+        }
+    }
+
+    #[test]
+    fn voltcraft_time_correction() {
+        let zone = FixedOffset::east_opt(3 * 3600).unwrap();
+        let at = |s| DateTime::parse_from_rfc3339(s).unwrap();
+        // A logger clock 3 minutes fast
+        let options = ParseOptions::new()
+            .timezone(zone)
+            .time_correction(TimeCorrection::offset(Duration::minutes(-3)));
+        let (events, _) = VoltcraftData::from_raw(TESTDATA.to_vec())
+            .parse_with(&options)
+            .unwrap();
+        assert_eq!(events[0].timestamp, at("2014-09-11T18:40:00+03:00"));
+
+        // A logger clock set right, then running 3 minutes a day fast
+        let correction = TimeCorrection::from_references(
+            (
+                at("2014-09-01T12:00:00+03:00"),
+                at("2014-09-01T12:00:00+03:00"),
+            ),
+            (
+                at("2014-09-11T12:30:00+03:00"),
+                at("2014-09-11T12:00:00+03:00"),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            correction.apply(at("2014-09-06T12:15:00+03:00")),
+            at("2014-09-06T12:00:00+03:00")
+        );
+        assert_eq!(
+            correction.apply(at("2014-09-11T12:30:00+03:00")),
+            at("2014-09-11T12:00:00+03:00")
+        );
+        let same_time = (
+            at("2014-09-01T12:00:00+03:00"),
+            at("2014-09-01T12:05:00+03:00"),
+        );
+        assert!(TimeCorrection::from_references(same_time, same_time).is_none());
     }
 
     #[test]
     fn voltcraft_poweritem() {
+        let (events, _) = VoltcraftData::from_raw(TESTDATA.to_vec()).parse().unwrap();
+        let pw = events[0];
+        assert_eq!(pw.source.unwrap().offset, 8);
+        assert_eq!(pw.voltage, 224.6);
+        assert_eq!(pw.current, 0.446);
+        assert_eq!(pw.power_factor, 0.87);
+    }
+
+    #[test]
+    fn voltcraft_power_factor_policy() {
+        // Same data as above, but with a power factor of 1.10
+        let mut data = TESTDATA.to_vec();
+        data[12] = 0x6E;
+
+        let (events, report) = VoltcraftData::from_raw(data.clone()).parse().unwrap();
+        assert_eq!(report.power_factor_anomalies, 1);
+        assert_eq!(events[0].power_factor, 1.0);
+        assert_eq!(events[0].power, events[0].apparent_power);
+
+        let (events, report) = VoltcraftData::from_raw(data.clone())
+            .parse_with(&ParseOptions::new().power_factor_policy(PowerFactorPolicy::Drop))
+            .unwrap();
+        assert_eq!(report.power_factor_anomalies, 1);
+        assert_eq!(report.dropped_samples, 1);
+        assert!(events.is_empty());
+
+        let (events, _) = VoltcraftData::from_raw(data)
+            .parse_with(&ParseOptions::new().power_factor_policy(PowerFactorPolicy::Keep))
+            .unwrap();
+        assert_eq!(events[0].power_factor, 1.1);
+    }
+
+    #[test]
+    fn voltcraft_truncated() {
+        // Every truncation of a valid file keeps the complete readings before it, skipping an
+        // incomplete block header or reading
+        for len in 0..TESTDATA.len() {
+            let result = VoltcraftData::from_raw(TESTDATA[..len].to_vec()).parse();
+            if len < 3 {
+                assert_eq!(result.unwrap_err(), ParseError::InvalidHeader);
+                continue;
+            }
+            let (events, report) = result.unwrap();
+            assert!(report.missing_end_marker);
+            assert_eq!(events.len(), if len < 13 { 0 } else { 1 });
+            // Bytes of the incomplete block header, reading or end of data marker
+            let incomplete = match len {
+                3..=7 => len,
+                8..=12 => len - 8,
+                _ => len - 13,
+            };
+            assert_eq!(report.skipped_bytes(), incomplete);
+        }
+        let (_, report) = VoltcraftData::from_raw(TESTDATA.to_vec()).parse().unwrap();
+        assert!(!report.missing_end_marker);
+
+        let mut data = TESTDATA.to_vec();
+        data[3] = 13; // month
+        assert_eq!(
+            VoltcraftData::from_raw(data).parse().unwrap_err(),
+            ParseError::InvalidTimestamp { offset: 3 }
+        );
+    }
+
+    #[test]
+    fn voltcraft_lenient_resync() {
+        // A block with a corrupted timestamp (month 13) between two intact blocks
+        let mut data = TESTDATA[..13].to_vec();
+        data.extend_from_slice(&[0xE0, 0xC5, 0xEA, 0x0D, 0x0B, 0x0E, 0x12, 0x2B]);
+        data.extend_from_slice(&[0x08, 0xC6, 0x01, 0xBE, 0x57]);
+        data.extend_from_slice(&TESTDATA);
+
+        let result = VoltcraftData::from_raw(data.clone()).parse();
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::InvalidTimestamp { offset: 16 }
+        );
+
+        let (events, report) = VoltcraftData::from_raw(data)
+            .parse_with(&ParseOptions::new().mode(ParseMode::Lenient))
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(report.diagnostics[0].offset, 13);
+        assert_eq!(report.diagnostics[0].skipped, 13);
+        assert_eq!(report.skipped_bytes(), 13);
+
+        // A truncated file keeps the readings before the truncation
+        let (events, report) = VoltcraftData::from_raw(TESTDATA[..15].to_vec())
+            .parse_with(&ParseOptions::new().mode(ParseMode::Lenient))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(report.missing_end_marker);
+        assert_eq!(
+            report.diagnostics[0].error,
+            ParseError::UnexpectedEof { offset: 13 }
+        );
+    }
+
+    #[test]
+    fn voltcraft_voltage_validation() {
+        // Same data as above, but with a voltage of 120.0V
+        let mut data = TESTDATA.to_vec();
+        data[8..10].copy_from_slice(&1200u16.to_be_bytes());
+
+        let (events, report) = VoltcraftData::from_raw(data.clone()).parse().unwrap();
+        assert_eq!(report.implausible_voltages, 1);
+        assert_eq!(report.record_count, 1);
+        assert_eq!(report.dropped_samples, 0);
+        assert_eq!(events[0].voltage, 120.0);
+
+        let drop = ValidationConfig {
+            policy: ImplausiblePolicy::Drop,
+            ..ValidationConfig::default()
+        };
+        let (events, report) = VoltcraftData::from_raw(data.clone())
+            .parse_with(&ParseOptions::new().validation(drop))
+            .unwrap();
+        assert_eq!(report.implausible_voltages, 1);
+        assert_eq!(report.dropped_samples, 1);
+        assert!(events.is_empty());
+
+        let us_grid = ValidationConfig {
+            min_voltage: 100.0,
+            max_voltage: 140.0,
+            ..ValidationConfig::default()
+        };
+        let (events, report) = VoltcraftData::from_raw(data)
+            .parse_with(&ParseOptions::new().validation(us_grid))
+            .unwrap();
+        assert_eq!(report.implausible_voltages, 0);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn voltcraft_brownout_is_not_blackout() {
+        // Three readings a minute apart, the middle one a brown-out to 140.0V
+        let mut data = TESTDATA[..13].to_vec();
+        data.extend_from_slice(&1400u16.to_be_bytes());
+        data.extend_from_slice(&TESTDATA[10..13]);
+        data.extend_from_slice(&TESTDATA[8..]);
+
+        let (mut events, report) = VoltcraftData::from_raw(data).parse().unwrap();
+        assert_eq!(report.implausible_voltages, 1);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].voltage, 140.0);
+        let blackouts = VoltcraftStatistics::new(&mut events).blackout_stats();
+        assert_eq!(blackouts.blackout_count, 0);
+    }
+
+    #[test]
+    fn voltcraft_current_validation() {
+        // Same data as the test data, but with a current of 33.214A (a flipped bit)
+        let mut data = TESTDATA.to_vec();
+        data[10] |= 0x80;
+
+        let (events, report) = VoltcraftData::from_raw(data.clone()).parse().unwrap();
+        assert_eq!(report.implausible_currents, 1);
+        assert_eq!(report.dropped_samples, 1);
+        assert!(events.is_empty());
+
+        let flag = ValidationConfig {
+            current_policy: ImplausiblePolicy::Flag,
+            ..ValidationConfig::default()
+        };
+        let (events, report) = VoltcraftData::from_raw(data)
+            .parse_with(&ParseOptions::new().validation(flag))
+            .unwrap();
+        assert_eq!(report.implausible_currents, 1);
+        assert_eq!(report.dropped_samples, 0);
+        assert_eq!(events[0].current, 33.214);
+    }
+
+    #[test]
+    fn voltcraft_parse_options() {
+        let options = ParseOptions::new()
+            .timezone(FixedOffset::east_opt(3600).unwrap())
+            .sample_interval(Duration::minutes(2))
+            .nominal_voltage(460.0);
+        let mut data = TESTDATA[..13].to_vec();
+        data.extend_from_slice(&[0x11, 0x8C, 0x01, 0xBE, 0x57]); // 449.2V
+        data.extend_from_slice(&TESTDATA[13..]);
+
+        let (events, report) = VoltcraftData::from_raw(data).parse_with(&options).unwrap();
+        // 224.6V is below the plausible range of a 460V grid: flagged, but kept
+        assert_eq!(report.implausible_voltages, 1);
+        assert_eq!(events.len(), 2);
+        let expected = DateTime::parse_from_rfc3339("2014-09-11T18:45:00+01:00").unwrap();
+        assert_eq!(events[1].timestamp, expected);
+        assert_eq!(events[1].timestamp.offset(), expected.offset());
+    }
+
+    #[test]
+    fn voltcraft_stride() {
+        let data = crate::voltcraft::sample::sample_data();
+        let (events, report) = data.parse_with(&ParseOptions::new().stride(4)).unwrap();
+        // The first and fifth reading of the first block (six readings), the first of the second
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[1].timestamp - events[0].timestamp,
+            Duration::minutes(4)
+        );
+        assert_eq!(events[2].source.unwrap().offset, 46);
+        // The readings skipped are still counted
+        assert_eq!((report.record_count, report.stride), (9, 4));
+        assert_eq!(report.sessions[0].event_count, 6);
+    }
+
+    #[test]
+    fn derived_power() {
+        let (events, _) = VoltcraftData::from_raw(TESTDATA.to_vec()).parse().unwrap();
+        let mut event = events[0];
+        // Without reactive power
+        event.power = event.apparent_power;
+        let derived = event.derived();
+        assert_eq!((derived.reactive_power, derived.phase_angle), (0.0, 0.0));
+        assert_eq!(derived.tan_phi, Some(0.0));
+        // Without active power
+        event.power = 0.0;
+        let derived = event.derived();
+        assert_eq!(derived.reactive_power, event.apparent_power);
+        assert!((derived.phase_angle - 90.0).abs() < 1e-9);
+        assert_eq!(derived.tan_phi, None);
+        // Nor any power
+        event.apparent_power = 0.0;
+        assert_eq!(event.derived().phase_angle, 0.0);
+    }
+
+    #[test]
+    fn blackout_detection() {
+        use crate::voltcraft::stats::{BlackoutAlgorithm, BlackoutDetection, VoltcraftStatistics};
+
+        let data = crate::voltcraft::sample::sample_data();
+        let (mut events, _) = data.parse_with(&ParseOptions::new().stride(4)).unwrap();
+        // The minutes not decoded look like a blackout between the readings of the first block
+        let stats = VoltcraftStatistics::new(&mut events);
+        assert_eq!(stats.blackout_stats().blackout_count, 2);
+        // Unless only the new data blocks count
+        let stats = stats.blackout_detection(BlackoutDetection {
+            algorithm: BlackoutAlgorithm::Session,
+            ..Default::default()
+        });
+        let blackouts = stats.blackout_stats();
+        assert_eq!(blackouts.blackout_count, 1);
+        assert_eq!(blackouts.blackouts[0].after.source.unwrap().offset, 46);
+    }
+
+    #[test]
+    fn voltcraft_events_stream() {
         let vd = VoltcraftData::from_raw(TESTDATA.to_vec());
-        let offset_poweritem = 8;
-        let pw = vd.decode_power(offset_poweritem);
-        assert_eq!(pw.0, 224.6);
-        assert_eq!(pw.1, 0.446);
-        assert_eq!(pw.2, 0.87);
+        let events = vd.events().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].voltage, 224.6);
+
+        // A file ending without the end of data marker yields the readings before its end
+        let vd = VoltcraftData::from_raw(TESTDATA[..15].to_vec());
+        let mut events = vd.events();
+        assert!(events.next().unwrap().is_ok());
+        assert!(events.next().is_none());
+        assert_eq!(events.report().record_count, 1);
+        assert!(events.report().missing_end_marker);
+        assert_eq!(events.report().skipped_bytes(), 2);
+    }
+
+    #[test]
+    fn voltcraft_sessions() {
+        let (events, report) = crate::voltcraft::sample::sample_data().parse().unwrap();
+        let sessions = &report.sessions;
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].file_offset, sessions[0].event_count), (0, 6));
+        assert_eq!((sessions[1].file_offset, sessions[1].event_count), (38, 3));
+        assert_eq!(sessions[0].start, events[0].timestamp);
+        assert_eq!(sessions[0].end, events[5].timestamp);
+        assert_eq!(sessions[1].end - sessions[1].start, Duration::minutes(2));
+        assert_eq!(
+            report.time_range(),
+            Some((events[0].timestamp, events[8].timestamp))
+        );
+
+        // Each reading can be traced back to its file and offset
+        let (events, _) = crate::voltcraft::sample::sample_data()
+            .parse_with(&ParseOptions::new().source_file(3))
+            .unwrap();
+        let source = events[6].source.unwrap();
+        assert_eq!((source.file, source.offset), (3, 46));
+    }
+
+    #[test]
+    fn voltcraft_header_only() {
+        use crate::voltcraft::grid::GridProfile;
+        use crate::voltcraft::stats::VoltcraftStatistics;
+
+        // A data block header followed by the end of data marker: valid, without readings
+        let data = [&TESTDATA[..8], &[0xFF; 4]].concat();
+        let (mut events, report) = VoltcraftData::from_raw(data).parse().unwrap();
+        assert!(events.is_empty() && report.is_empty());
+        assert_eq!(report.sessions.len(), 1);
+        assert_eq!(report.sessions[0].event_count, 0);
+        assert_eq!(report.time_range(), None);
+        assert!(!report.missing_end_marker);
+
+        // Nor do the analyses fail on no readings at all
+        let stats = VoltcraftStatistics::new(&mut events);
+        assert!(stats.overall_stats().is_none());
+        assert!(stats.sampled_stats(4).is_none());
+        assert!(stats.daily_stats().is_empty() && stats.hourly_stats().is_empty());
+        assert_eq!(stats.blackout_stats().blackout_count, 0);
+        assert_eq!(
+            stats
+                .voltage_deviation(&GridProfile::default())
+                .minute_count,
+            0
+        );
+    }
+
+    #[test]
+    fn voltcraft_from_reader() {
+        let vd = VoltcraftData::from_reader(&TESTDATA[..], 1024).unwrap();
+        assert_eq!(vd.as_bytes(), &TESTDATA[..]);
+        assert_eq!(
+            VoltcraftData::from_reader(&TESTDATA[..], 10).err(),
+            Some(ParseError::TooLarge {
+                size: 17,
+                limit: 10
+            })
+        );
+        assert_eq!(
+            VoltcraftData::from_reader(&b"not a data file"[..], 1024).err(),
+            Some(ParseError::InvalidHeader)
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn voltcraft_gzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&TESTDATA).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let vd = VoltcraftData::from_reader(&compressed[..], 1024).unwrap();
+        assert_eq!(vd.as_bytes(), &TESTDATA[..]);
+        // The limit applies to the decompressed data
+        assert_eq!(
+            VoltcraftData::from_reader(&compressed[..], 16).err(),
+            Some(ParseError::TooLarge {
+                size: 17,
+                limit: 16
+            })
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn voltcraft_mmap() {
+        let file = "sample_data1/A04FC8D2.BIN";
+        let mapped = VoltcraftData::from_mmap(file).unwrap();
+        let read = VoltcraftData::from_file(file, 1024 * 1024).unwrap();
+        assert_eq!(mapped.as_bytes(), read.as_bytes());
+        assert_eq!(
+            mapped.parse().unwrap().0.len(),
+            read.parse().unwrap().0.len()
+        );
+        assert_eq!(
+            VoltcraftData::from_mmap("README.md").err(),
+            Some(ParseError::InvalidHeader)
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn voltcraft_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let file = "sample_data1/A04FC8D2.BIN";
+        let read = VoltcraftData::from_file(file, 1024 * 1024).unwrap();
+        let awaited = runtime
+            .block_on(VoltcraftData::from_file_async(file, 1024 * 1024))
+            .unwrap();
+        assert_eq!(awaited.as_bytes(), read.as_bytes());
+        let awaited = runtime
+            .block_on(VoltcraftData::from_reader_async(
+                read.as_bytes(),
+                1024 * 1024,
+            ))
+            .unwrap();
+        assert_eq!(awaited.as_bytes(), read.as_bytes());
+        assert_eq!(
+            runtime
+                .block_on(VoltcraftData::from_reader_async(read.as_bytes(), 100))
+                .err(),
+            Some(ParseError::TooLarge {
+                size: read.as_bytes().len() as u64,
+                limit: 100
+            })
+        );
+        assert_eq!(
+            runtime
+                .block_on(VoltcraftData::from_file_async("README.md", 1024 * 1024))
+                .err(),
+            Some(ParseError::InvalidHeader)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn voltcraft_serde() {
+        use crate::voltcraft::data::PowerEvent;
+        use crate::voltcraft::stats::{BlackoutInfo, VoltcraftStatistics};
+
+        let (mut events, _) = crate::voltcraft::sample::sample_data().parse().unwrap();
+        let json = serde_json::to_string(&events[0]).unwrap();
+        // Timestamps are RFC 3339 strings, the logger clock time followed by its UTC offset
+        assert!(json.starts_with(r#"{"timestamp":"2014-09-11T18:43:00"#));
+        let event: PowerEvent = serde_json::from_str(&json).unwrap();
+        assert_eq!(event.timestamp, events[0].timestamp);
+        assert_eq!(event.source, events[0].source);
+
+        let blackouts = VoltcraftStatistics::new(&mut events).blackout_stats();
+        let json = serde_json::to_string(&blackouts).unwrap();
+        let decoded: BlackoutInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.blackout_count, blackouts.blackout_count);
+        assert_eq!(
+            decoded.total_blackout_duration,
+            blackouts.total_blackout_duration
+        );
+        assert_eq!(
+            decoded.blackouts[0].timestamp,
+            blackouts.blackouts[0].timestamp
+        );
+        assert_eq!(decoded.by_hour, blackouts.by_hour);
+        assert_eq!(decoded.mtbf, blackouts.mtbf);
     }
 }
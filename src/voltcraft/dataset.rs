@@ -0,0 +1,612 @@
+use crate::voltcraft::data::{
+    Events, ParseError, ParseOptions, ParseReport, PowerEvent, VoltcraftData,
+};
+use crate::voltcraft::merge::ChronologicalMerge;
+use crate::voltcraft::transform::{
+    deduplicate, trim_overlaps_with, Conflict, DedupPolicy, Overlap, OverlapPolicy,
+};
+use chrono::{DateTime, FixedOffset};
+use itertools::Either;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// The data files of a folder decoded and merged into a single chronological series, as the
+/// command line tool does: where files cover the same minutes the readings of one of them are
+/// kept (see `OverlapPolicy`), and a single reading is kept for minutes read more than once (see
+/// `DedupPolicy`).
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::dataset::VoltcraftDataset;
+///
+/// let dataset = VoltcraftDataset::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/sample_data1"))
+///     .unwrap();
+/// assert_eq!(dataset.files.len(), 38);
+/// assert!(dataset.files.iter().all(|f| f.outcome.is_ok()));
+/// assert!(dataset.events.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+/// ```
+#[derive(Debug)]
+pub struct VoltcraftDataset {
+    pub events: Vec<PowerEvent>, // chronological, their source file indexes the files below
+    pub files: Vec<DatasetFile>, // in the order given, i.e. name (download) order for a folder
+    pub overlaps: Vec<Overlap>, // readings superseded by those of another file for the same minutes
+    pub duplicates: Vec<Overlap>, // readings dropped for minutes repeated in the merged series
+    pub conflicts: Vec<Conflict>, // readings of those dropped that differ from the ones kept
+}
+
+// Reasons a folder can't be loaded as a dataset
+#[derive(Debug, Error)]
+pub enum DatasetError {
+    #[error("cannot list the folder: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Conflict(#[from] Box<Conflict>), // with DedupPolicy::FailOnConflict
+}
+
+// A file of a dataset, and what decoding found in it
+#[derive(Debug)]
+pub struct DatasetFile {
+    pub name: String,                             // the path of the file, as shown
+    pub outcome: Result<ParseReport, ParseError>, // why the file couldn't be used otherwise
+    pub scan: Option<FileScan>,                   // of the readings, for a valid file
+}
+
+// What is known about the readings of a file after streaming through them once
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FileScan {
+    pub events: usize,
+    pub sorted: bool, // chronological, as files normally are
+    pub energy: f64,  // kWh
+    pub coverage: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>, // first and last event
+}
+
+impl FileScan {
+    fn scan(events: &mut Events) -> Result<FileScan, ParseError> {
+        let mut scan = FileScan::of(&mut *events)?;
+        // The readings skipped by a stride are assumed to be like the one decoded before them
+        scan.energy *= events.report().stride.max(1) as f64;
+        Ok(scan)
+    }
+
+    fn of(
+        events: impl Iterator<Item = Result<PowerEvent, ParseError>>,
+    ) -> Result<FileScan, ParseError> {
+        let mut scan = FileScan {
+            events: 0,
+            sorted: true,
+            energy: 0.0,
+            coverage: None,
+        };
+        let mut previous: Option<DateTime<FixedOffset>> = None;
+        for event in events {
+            let event = event?;
+            let timestamp = event.timestamp;
+            scan.events += 1;
+            scan.energy += event.power / 60.0;
+            scan.sorted &= previous.is_none_or(|p| p <= timestamp);
+            previous = Some(timestamp);
+            scan.coverage = Some(match scan.coverage {
+                Some((start, end)) => (start.min(timestamp), end.max(timestamp)),
+                None => (timestamp, timestamp),
+            });
+        }
+        Ok(scan)
+    }
+}
+
+/// Settings for loading a dataset, built by chaining setters on the defaults (those of the
+/// command line tool).
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::data::{ParseMode, ParseOptions};
+/// use voltcraft_energy_analyzer::voltcraft::dataset::DatasetOptions;
+/// use voltcraft_energy_analyzer::voltcraft::transform::{DedupPolicy, OverlapPolicy};
+///
+/// let options = DatasetOptions::new()
+///     .parse_options(ParseOptions::new().mode(ParseMode::Lenient))
+///     .max_file_size(4 * 1024 * 1024)
+///     .overlap_policy(OverlapPolicy::Older)
+///     .dedup_policy(DedupPolicy::Average);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DatasetOptions {
+    parse_options: ParseOptions, // the source file is set for each file
+    max_file_size: u64,          // bytes, larger files are rejected
+    overlap_policy: OverlapPolicy,
+    dedup_policy: DedupPolicy,
+}
+
+impl Default for DatasetOptions {
+    fn default() -> Self {
+        DatasetOptions {
+            parse_options: ParseOptions::new(),
+            max_file_size: 1024 * 1024,
+            overlap_policy: OverlapPolicy::Newer,
+            dedup_policy: DedupPolicy::KeepFirst,
+        }
+    }
+}
+
+impl DatasetOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.parse_options = options;
+        self
+    }
+
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+
+    pub fn overlap_policy(mut self, policy: OverlapPolicy) -> Self {
+        self.overlap_policy = policy;
+        self
+    }
+
+    pub fn dedup_policy(mut self, policy: DedupPolicy) -> Self {
+        self.dedup_policy = policy;
+        self
+    }
+}
+
+impl VoltcraftDataset {
+    // Load the files of a folder with the default settings
+    pub fn from_dir(path: impl AsRef<Path>) -> Result<VoltcraftDataset, DatasetError> {
+        VoltcraftDataset::from_dir_with(path, &DatasetOptions::new())
+    }
+
+    // Load the files of a folder (not its subfolders), failing only if the folder can't be listed
+    // or on conflicting readings with DedupPolicy::FailOnConflict
+    pub fn from_dir_with(
+        path: impl AsRef<Path>,
+        options: &DatasetOptions,
+    ) -> Result<VoltcraftDataset, DatasetError> {
+        let mut builder = DatasetBuilder::new(*options);
+        for path in list_files(path.as_ref())? {
+            let data = VoltcraftData::from_file(&path, options.max_file_size);
+            builder.add(path.display().to_string(), data);
+        }
+        Ok(builder.build()?)
+    }
+
+    // As from_dir_with, reading and decoding the files on all the cores; the dataset is the same,
+    // the files keeping their name order
+    #[cfg(feature = "parallel")]
+    pub fn load_parallel(
+        path: impl AsRef<Path>,
+        options: &DatasetOptions,
+    ) -> Result<VoltcraftDataset, DatasetError> {
+        let mut builder = DatasetBuilder::new(*options);
+        let scanned = list_files(path.as_ref())?
+            .into_par_iter()
+            .map(|path| {
+                let data = VoltcraftData::from_file(&path, options.max_file_size);
+                builder.scan(path.display().to_string(), data)
+            })
+            .collect::<Vec<_>>();
+        for file in scanned {
+            builder.add_scanned(file);
+        }
+        Ok(builder.build()?)
+    }
+
+    // As from_dir_with, for services running on tokio: the files are read without blocking, and
+    // decoded and merged on the blocking threads of the runtime
+    #[cfg(feature = "tokio")]
+    pub async fn from_dir_async(
+        path: impl AsRef<Path>,
+        options: &DatasetOptions,
+    ) -> Result<VoltcraftDataset, DatasetError> {
+        let mut entries = tokio::fs::read_dir(path).await?;
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        let mut files = Vec::new();
+        for path in paths {
+            let data = VoltcraftData::from_file_async(&path, options.max_file_size).await;
+            files.push((path.display().to_string(), data));
+        }
+        let options = *options;
+        tokio::task::spawn_blocking(move || {
+            let mut builder = DatasetBuilder::new(options);
+            for (name, data) in files {
+                builder.add(name, data);
+            }
+            builder.build()
+        })
+        .await
+        .map_err(io::Error::other)?
+        .map_err(DatasetError::from)
+    }
+
+    // The readings kept of each file once merged, and their energy (kWh, estimated for files
+    // decoded with a stride)
+    pub fn contributions(&self) -> Vec<(usize, f64)> {
+        let strides = self
+            .files
+            .iter()
+            .map(|f| f.outcome.as_ref().map_or(1, |r| r.stride.max(1)))
+            .collect::<Vec<_>>();
+        let mut contributions = vec![(0, 0.0); self.files.len()];
+        for event in &self.events {
+            if let Some(s) = event.source.filter(|s| s.file < contributions.len()) {
+                contributions[s.file].0 += 1;
+                contributions[s.file].1 += event.power / 60.0 * strides[s.file] as f64;
+            }
+        }
+        contributions
+    }
+}
+
+// The files of a folder (not its subfolders), in name order
+fn list_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|p| p.is_file());
+    paths.sort();
+    Ok(paths)
+}
+
+// A file streamed through but not yet added to a dataset (see DatasetBuilder::scan)
+pub struct ScannedFile {
+    file: DatasetFile,
+    data: Option<VoltcraftData>, // of a valid file
+}
+
+impl ScannedFile {
+    pub fn file(&self) -> &DatasetFile {
+        &self.file
+    }
+}
+
+// Builds a dataset file by file, from files read from anywhere (a folder, an SD card image),
+// e.g. to report on each file as it is decoded. The files are only decoded in full when merged.
+pub struct DatasetBuilder {
+    options: DatasetOptions,
+    files: Vec<DatasetFile>,
+    data: Vec<Option<FileData>>, // of the valid files
+}
+
+// The readings of a file, as read
+enum FileData {
+    Undecoded(VoltcraftData), // 5 bytes per reading
+    Decoded(Vec<PowerEvent>), // e.g. kept from a previous run (see DatasetState)
+}
+
+impl DatasetBuilder {
+    pub fn new(options: DatasetOptions) -> Self {
+        DatasetBuilder {
+            options,
+            files: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    // Add a file, or the reason it couldn't be read, streaming through its readings without
+    // keeping them
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        data: Result<VoltcraftData, ParseError>,
+    ) -> &DatasetFile {
+        let file = self.scan(name, data);
+        self.add_scanned(file)
+    }
+
+    // Stream through the readings of a file as add does, without adding it yet, e.g. to scan
+    // several files at once on other threads; the files are then added in their order
+    pub fn scan(
+        &self,
+        name: impl Into<String>,
+        data: Result<VoltcraftData, ParseError>,
+    ) -> ScannedFile {
+        let (outcome, scan, data) = match data {
+            Ok(data) => {
+                let mut events = data.events_with(&self.options.parse_options);
+                let scan = FileScan::scan(&mut events);
+                let report = events.into_report();
+                match scan {
+                    Ok(scan) => (Ok(report), Some(scan), Some(data)),
+                    Err(e) => (Err(e), None, None),
+                }
+            }
+            Err(e) => (Err(e), None, None),
+        };
+        ScannedFile {
+            file: DatasetFile {
+                name: name.into(),
+                outcome,
+                scan,
+            },
+            data,
+        }
+    }
+
+    pub fn add_scanned(&mut self, scanned: ScannedFile) -> &DatasetFile {
+        self.files.push(scanned.file);
+        self.data.push(scanned.data.map(FileData::Undecoded));
+        &self.files[self.files.len() - 1]
+    }
+
+    // Add a file decoded beforehand with the parse options of the dataset (e.g. kept from a
+    // previous run, see DatasetState), with its report and all its readings
+    pub fn add_decoded(
+        &mut self,
+        name: impl Into<String>,
+        report: ParseReport,
+        mut events: Vec<PowerEvent>,
+    ) -> &DatasetFile {
+        let index = self.files.len();
+        for event in &mut events {
+            if let Some(source) = &mut event.source {
+                source.file = index;
+            }
+        }
+        let scan = FileScan::of(events.iter().copied().map(Ok)).map(|mut scan| {
+            scan.energy *= report.stride.max(1) as f64;
+            scan
+        });
+        self.files.push(DatasetFile {
+            name: name.into(),
+            outcome: Ok(report),
+            scan: scan.ok(),
+        });
+        self.data.push(Some(FileData::Decoded(events)));
+        &self.files[index]
+    }
+
+    pub fn files(&self) -> &[DatasetFile] {
+        &self.files
+    }
+
+    // All the readings of a valid file, in file order, e.g. to keep them for the next run
+    pub fn events(&self, index: usize) -> Option<Vec<PowerEvent>> {
+        match self.data.get(index)?.as_ref()? {
+            FileData::Undecoded(data) => Some(
+                data.events_with(&self.options.parse_options.source_file(index))
+                    .filter_map(Result::ok)
+                    .collect(),
+            ),
+            FileData::Decoded(events) => Some(events.clone()),
+        }
+    }
+
+    // Merge the valid files into a single chronological series, failing on conflicting readings
+    // with DedupPolicy::FailOnConflict
+    pub fn build(self) -> Result<VoltcraftDataset, Box<Conflict>> {
+        let DatasetBuilder {
+            options,
+            files,
+            data,
+        } = self;
+        let events_of = |i: usize| {
+            data[i].iter().flat_map(move |d| match d {
+                FileData::Undecoded(data) => Either::Left(
+                    data.events_with(&options.parse_options.source_file(i))
+                        .filter_map(Result::ok),
+                ),
+                FileData::Decoded(events) => Either::Right(events.iter().copied()),
+            })
+        };
+        let coverage = |i: usize| files[i].scan.and_then(|s| s.coverage);
+        let valid = (0..files.len())
+            .filter(|&i| data[i].is_some())
+            .collect::<Vec<_>>();
+
+        // Files out of chronological order, or overlapping other files, are decoded in full to be
+        // sorted and trimmed; all the others are streamed straight into the merge
+        let decoded_files = valid
+            .iter()
+            .copied()
+            .filter(|&i| {
+                !files[i].scan.is_some_and(|s| s.sorted)
+                    || valid.iter().any(|&j| match (coverage(i), coverage(j)) {
+                        (Some((s1, e1)), Some((s2, e2))) => i != j && s1 <= e2 && s2 <= e1,
+                        _ => false,
+                    })
+            })
+            .collect::<Vec<_>>();
+        let mut decoded = decoded_files
+            .iter()
+            .map(|&i| {
+                let mut events = events_of(i).collect::<Vec<_>>();
+                events.sort_by_key(|e| e.timestamp);
+                events
+            })
+            .collect::<Vec<_>>();
+
+        // Keep a single source for minutes covered by several files (the logger rewrote old data),
+        // unless the deduplication policy says otherwise for the minutes whose readings differ
+        let trimmed =
+            trim_overlaps_with(&mut decoded, options.overlap_policy, options.dedup_policy)?;
+        let overlaps = trimmed
+            .dropped
+            .into_iter()
+            .map(|overlap| Overlap {
+                superseded: decoded_files[overlap.superseded],
+                by: decoded_files[overlap.by],
+                ..overlap
+            })
+            .collect();
+
+        let mut decoded = decoded_files
+            .into_iter()
+            .zip(decoded)
+            .collect::<HashMap<_, _>>();
+        let sources = valid
+            .iter()
+            .map(|&i| match decoded.remove(&i) {
+                Some(events) => Box::new(events.into_iter()) as Box<dyn Iterator<Item = _>>,
+                None => Box::new(events_of(i)),
+            })
+            .collect();
+        let mut events = ChronologicalMerge::new(sources).collect::<Vec<_>>();
+        let deduplication = deduplicate(&mut events, options.dedup_policy)?;
+        let mut conflicts = trimmed.conflicts;
+        conflicts.extend(deduplication.conflicts);
+        conflicts.sort_by_key(|c| c.first.timestamp);
+        Ok(VoltcraftDataset {
+            events,
+            files,
+            overlaps,
+            duplicates: deduplication.dropped,
+            conflicts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voltcraft::sample::sample_data;
+
+    #[test]
+    fn dataset_merge() {
+        let bytes = sample_data().as_bytes().to_vec();
+        let (sample, _) = sample_data().parse().unwrap();
+        // The same download copied twice, with a file that isn't a data file in between
+        let mut builder = DatasetBuilder::new(DatasetOptions::new());
+        builder.add("A.BIN", Ok(VoltcraftData::from_raw(bytes.clone())));
+        let failed = builder.add("B.BIN", VoltcraftData::from_reader(&b"not data"[..], 1024));
+        assert_eq!(
+            failed.outcome.as_ref().err(),
+            Some(&ParseError::InvalidHeader)
+        );
+        assert_eq!(failed.scan, None);
+        builder.add("C.BIN", Ok(VoltcraftData::from_raw(bytes)));
+        let scan = builder.files()[2].scan.unwrap();
+        assert_eq!(scan.events, sample.len());
+        assert!(scan.sorted);
+
+        let dataset = builder.build().unwrap();
+        assert_eq!(dataset.files.len(), 3);
+        assert_eq!(dataset.events.len(), sample.len());
+        // The newer file is kept
+        assert!(dataset.events.iter().all(|e| e.source.unwrap().file == 2));
+        assert_eq!(
+            dataset.overlaps,
+            vec![Overlap {
+                superseded: 0,
+                by: 2,
+                count: sample.len(),
+                differing: 0
+            }]
+        );
+        assert!(dataset.duplicates.is_empty());
+        let contributions = dataset.contributions();
+        assert_eq!((contributions[0].0, contributions[2].0), (0, sample.len()));
+        assert!((contributions[2].1 - scan.energy).abs() < 1e-12);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn dataset_load_parallel() {
+        let folder = concat!(env!("CARGO_MANIFEST_DIR"), "/sample_data1");
+        let options = DatasetOptions::new();
+        let sequential = VoltcraftDataset::from_dir_with(folder, &options).unwrap();
+        let parallel = VoltcraftDataset::load_parallel(folder, &options).unwrap();
+        let names =
+            |d: &VoltcraftDataset| d.files.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&parallel), names(&sequential));
+        assert_eq!(parallel.overlaps, sequential.overlaps);
+        assert_eq!(
+            format!("{:?}", parallel.events),
+            format!("{:?}", sequential.events)
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn dataset_from_dir_async() {
+        let folder = concat!(env!("CARGO_MANIFEST_DIR"), "/sample_data1");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let awaited = runtime
+            .block_on(VoltcraftDataset::from_dir_async(
+                folder,
+                &DatasetOptions::new(),
+            ))
+            .unwrap();
+        let dataset = VoltcraftDataset::from_dir(folder).unwrap();
+        assert_eq!(awaited.files.len(), dataset.files.len());
+        assert_eq!(awaited.events.len(), dataset.events.len());
+        assert_eq!(awaited.overlaps, dataset.overlaps);
+    }
+
+    #[test]
+    fn dataset_decoded_files() {
+        let data = sample_data();
+        let (events, report) = data.parse().unwrap();
+        // A file decoded beforehand (as the second file) merges as if it were decoded here
+        let mut builder = DatasetBuilder::new(DatasetOptions::new());
+        builder.add("A.BIN", VoltcraftData::from_reader(&b"not data"[..], 1024));
+        let added = builder.add_decoded("B.BIN", report, events.clone());
+        assert_eq!(added.scan.unwrap().events, events.len());
+        let decoded = builder.events(1).unwrap();
+        assert!(decoded.iter().all(|e| e.source.unwrap().file == 1));
+        assert!(builder.events(0).is_none());
+
+        let dataset = builder.build().unwrap();
+        assert_eq!(dataset.events.len(), events.len());
+        assert_eq!(dataset.contributions()[1].0, events.len());
+    }
+
+    #[test]
+    fn dataset_overlap_conflicts() {
+        let (sample, report) = sample_data().parse().unwrap();
+        // A newer download of the same minutes, one of them read 1V higher
+        let mut newer = sample.clone();
+        newer[2].voltage += 1.0;
+        let build = |policy| {
+            let mut builder = DatasetBuilder::new(DatasetOptions::new().dedup_policy(policy));
+            builder.add_decoded("A.BIN", report.clone(), sample.clone());
+            builder.add_decoded("B.BIN", report.clone(), newer.clone());
+            builder.build()
+        };
+
+        // The overlap policy decides which file is kept
+        for policy in [DedupPolicy::KeepFirst, DedupPolicy::KeepLast] {
+            let dataset = build(policy).unwrap();
+            assert_eq!(dataset.events.len(), sample.len());
+            assert_eq!(dataset.events[2].voltage, newer[2].voltage);
+            assert!(dataset.events.iter().all(|e| e.source.unwrap().file == 1));
+            assert_eq!(dataset.conflicts.len(), 1);
+            assert_eq!(dataset.overlaps[0].differing, 1);
+        }
+
+        let dataset = build(DedupPolicy::Average).unwrap();
+        assert_eq!(dataset.events.len(), sample.len());
+        assert_eq!(dataset.events[2].voltage, sample[2].voltage + 0.5);
+        assert_eq!(dataset.events[2].source.unwrap().file, 0);
+        assert_eq!(dataset.events[3].voltage, sample[3].voltage);
+        assert_eq!(dataset.conflicts.len(), 1);
+
+        let conflict = build(DedupPolicy::FailOnConflict).unwrap_err();
+        assert_eq!(conflict.first.timestamp, sample[2].timestamp);
+        assert_eq!(
+            (conflict.first.voltage, conflict.second.voltage),
+            (sample[2].voltage, newer[2].voltage)
+        );
+        // Identical overlaps are no conflict
+        let mut builder =
+            DatasetBuilder::new(DatasetOptions::new().dedup_policy(DedupPolicy::FailOnConflict));
+        builder.add_decoded("A.BIN", report.clone(), sample.clone());
+        builder.add_decoded("B.BIN", report, sample.clone());
+        assert!(builder.build().is_ok());
+    }
+}
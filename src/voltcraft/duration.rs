@@ -0,0 +1,106 @@
+use chrono::Duration;
+
+/// The unit labels of a formatted duration, English by default; set them to localize the reports.
+///
+/// ```
+/// use chrono::Duration;
+/// use voltcraft_energy_analyzer::voltcraft::duration::{format_duration_with, DurationLabels};
+///
+/// let german = DurationLabels {
+///     month: "Mo",
+///     day: "T",
+///     hour: "Std",
+///     minute: "Min",
+/// };
+/// let duration = Duration::days(1) + Duration::minutes(5);
+/// assert_eq!(format_duration_with(duration, &german), "01T:00Std:05Min");
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DurationLabels {
+    pub month: &'static str, // of 30 days
+    pub day: &'static str,
+    pub hour: &'static str,
+    pub minute: &'static str,
+}
+
+impl Default for DurationLabels {
+    fn default() -> Self {
+        DurationLabels {
+            month: "mo",
+            day: "d",
+            hour: "h",
+            minute: "m",
+        }
+    }
+}
+
+/// A duration to the minute, from its largest unit: months (of 30 days), days, hours and minutes,
+/// each of two digits at least, e.g. `01mo:24d:01h:49m`, `03h:05m` or `-12m` (a negative
+/// duration, such as a clock correction).
+///
+/// ```
+/// use chrono::Duration;
+/// use voltcraft_energy_analyzer::voltcraft::duration::format_duration;
+///
+/// assert_eq!(format_duration(Duration::minutes(6)), "06m");
+/// assert_eq!(format_duration(Duration::days(54) + Duration::minutes(109)), "01mo:24d:01h:49m");
+/// assert_eq!(format_duration(-Duration::minutes(90)), "-01h:30m");
+/// ```
+pub fn format_duration(duration: Duration) -> String {
+    format_duration_with(duration, &DurationLabels::default())
+}
+
+// As format_duration, with the given unit labels
+pub fn format_duration_with(duration: Duration, labels: &DurationLabels) -> String {
+    let seconds = duration.num_seconds();
+    // The magnitude, so that the shortest duration can't overflow when negated
+    let total_minutes = seconds.unsigned_abs() / 60;
+    let units = [
+        (total_minutes / (30 * 24 * 60), labels.month),
+        (total_minutes / (24 * 60) % 30, labels.day),
+        (total_minutes / 60 % 24, labels.hour),
+        (total_minutes % 60, labels.minute),
+    ];
+    // From the largest unit that isn't zero (minutes at least)
+    let first = units[..3]
+        .iter()
+        .position(|(value, _)| *value > 0)
+        .unwrap_or(3);
+    let sign = if seconds < 0 && total_minutes > 0 {
+        "-"
+    } else {
+        ""
+    };
+    let formatted = units[first..]
+        .iter()
+        .map(|(value, label)| format!("{:0>2}{}", value, label))
+        .collect::<Vec<_>>()
+        .join(":");
+    format!("{}{}", sign, formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_formatting() {
+        assert_eq!(format_duration(Duration::zero()), "00m");
+        assert_eq!(format_duration(Duration::seconds(59)), "00m");
+        assert_eq!(format_duration(-Duration::seconds(59)), "00m");
+        assert_eq!(format_duration(Duration::hours(3)), "03h:00m");
+        assert_eq!(format_duration(Duration::days(29)), "29d:00h:00m");
+        assert_eq!(format_duration(Duration::days(30)), "01mo:00d:00h:00m");
+        assert_eq!(
+            format_duration(Duration::days(400) + Duration::minutes(61)),
+            "13mo:10d:01h:01m"
+        );
+        assert_eq!(
+            format_duration(-(Duration::days(2) + Duration::minutes(3))),
+            "-02d:00h:03m"
+        );
+        // The extremes chrono allows
+        assert_eq!(format_duration(Duration::MAX).chars().next(), Some('3'));
+        assert!(format_duration(Duration::MIN).starts_with("-3"));
+    }
+}
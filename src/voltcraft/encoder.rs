@@ -0,0 +1,299 @@
+use crate::voltcraft::data::PowerEvent;
+use crate::voltcraft::raw::{
+    Registers, BLOCK_HEADER_SIZE, END_OF_DATA, MAGIC_NUMBER, READING_SIZE,
+};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDateTime, TimeZone, Timelike};
+use thiserror::Error;
+
+// Smallest file holding a reading: one data block with one reading, and the end of data marker
+pub const MIN_FILE_SIZE: usize = BLOCK_HEADER_SIZE + READING_SIZE + END_OF_DATA.len();
+
+/// Writes power events back into the binary format of the logger, e.g. to build test fixtures or
+/// to split a merged dataset into files the device software accepts. A new data block is started
+/// whenever a reading doesn't follow the previous one by the sample interval. Only the voltage,
+/// current and power factor are stored; the decoder computes the power from them. Readings that
+/// kept the values as stored (see `ParseOptions::keep_registers`) are written with those, so
+/// calibrated readings give back the original file bit for bit.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::encoder::VoltcraftEncoder;
+/// use voltcraft_energy_analyzer::voltcraft::sample::{sample_data, SAMPLE_FILE};
+///
+/// let (events, _) = sample_data().parse().unwrap();
+/// let bytes = VoltcraftEncoder::new().encode(&events).unwrap();
+/// assert_eq!(bytes, SAMPLE_FILE);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoltcraftEncoder {
+    timezone: Option<FixedOffset>, // zone of the logger clock (None: local time), as when decoding
+    sample_interval: Duration,     // time between two readings of a data block
+}
+
+impl Default for VoltcraftEncoder {
+    fn default() -> Self {
+        VoltcraftEncoder {
+            timezone: None,
+            sample_interval: Duration::minutes(1),
+        }
+    }
+}
+
+// Reasons power events cannot be written in the logger format
+#[derive(Debug, Error, Copy, Clone, PartialEq)]
+pub enum EncodeError {
+    #[error("no readings to encode")]
+    NoReadings,
+    #[error("the reading of {timestamp} starts a data block but not on a whole minute")]
+    UnalignedTimestamp { timestamp: DateTime<FixedOffset> },
+    #[error("the reading of {timestamp} cannot be dated by the logger clock")]
+    InvalidTimestamp { timestamp: DateTime<FixedOffset> },
+    #[error("the {quantity} of the reading of {timestamp} is out of the range of the file format")]
+    OutOfRange {
+        quantity: &'static str,
+        timestamp: DateTime<FixedOffset>,
+    },
+    #[error("files of {size} bytes cannot hold a reading (minimum {MIN_FILE_SIZE} bytes)")]
+    FileTooSmall { size: usize },
+}
+
+impl VoltcraftEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn timezone(mut self, timezone: FixedOffset) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    pub fn sample_interval(mut self, interval: Duration) -> Self {
+        self.sample_interval = interval;
+        self
+    }
+
+    // A single data file holding all the events, in the given order
+    pub fn encode(&self, events: &[PowerEvent]) -> Result<Vec<u8>, EncodeError> {
+        self.encode_chunks(events, usize::MAX)
+            .map(|mut files| files.remove(0))
+    }
+
+    // As many data files of at most max_size bytes as needed to hold the events, in order
+    pub fn encode_chunks(
+        &self,
+        events: &[PowerEvent],
+        max_size: usize,
+    ) -> Result<Vec<Vec<u8>>, EncodeError> {
+        if events.is_empty() {
+            return Err(EncodeError::NoReadings);
+        }
+        if max_size < MIN_FILE_SIZE {
+            return Err(EncodeError::FileTooSmall { size: max_size });
+        }
+        let mut files = Vec::new();
+        let mut file = Vec::new();
+        let mut previous: Option<&PowerEvent> = None;
+        for event in events {
+            let new_block =
+                previous.is_none_or(|p| event.timestamp - p.timestamp != self.sample_interval);
+            let size = READING_SIZE + if new_block { BLOCK_HEADER_SIZE } else { 0 };
+            // Room is kept for the end of data marker; the next file starts with a new block
+            let new_block = if file.len() + size + END_OF_DATA.len() > max_size {
+                file.extend(END_OF_DATA);
+                files.push(std::mem::take(&mut file));
+                true
+            } else {
+                new_block
+            };
+            if new_block {
+                file.extend(MAGIC_NUMBER);
+                file.extend(self.encode_timestamp(event.timestamp)?);
+            }
+            file.extend(encode_reading(event)?);
+            previous = Some(event);
+        }
+        file.extend(END_OF_DATA);
+        files.push(file);
+        Ok(files)
+    }
+
+    // The start time of a data block: month, day, year, hour and minute of the logger clock
+    fn encode_timestamp(&self, timestamp: DateTime<FixedOffset>) -> Result<[u8; 5], EncodeError> {
+        let clock = match self.timezone {
+            Some(timezone) => timestamp.with_timezone(&timezone).naive_local(),
+            None => timestamp.with_timezone(&Local).naive_local(),
+        };
+        if clock.second() != 0 || clock.nanosecond() != 0 {
+            return Err(EncodeError::UnalignedTimestamp { timestamp });
+        }
+        // The decoder takes the earliest of ambiguous local times (when the clocks go back)
+        if self.decode_clock(clock) != Some(timestamp) {
+            return Err(EncodeError::InvalidTimestamp { timestamp });
+        }
+        let year = u8::try_from(clock.year_ce().1.wrapping_sub(2000))
+            .map_err(|_| EncodeError::InvalidTimestamp { timestamp })?;
+        Ok([
+            clock.month() as u8,
+            clock.day() as u8,
+            year,
+            clock.hour() as u8,
+            clock.minute() as u8,
+        ])
+    }
+
+    fn decode_clock(&self, clock: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self.timezone {
+            Some(timezone) => timezone.from_local_datetime(&clock).single(),
+            None => Local
+                .from_local_datetime(&clock)
+                .earliest()
+                .map(|t| t.fixed_offset()),
+        }
+    }
+}
+
+// Voltage (V/10), current (mA) and power factor (1/100), as stored if the reading kept them
+fn encode_reading(event: &PowerEvent) -> Result<[u8; READING_SIZE], EncodeError> {
+    if let Some(registers) = event.registers {
+        return Ok(registers.encode());
+    }
+    let out_of_range = |quantity| EncodeError::OutOfRange {
+        quantity,
+        timestamp: event.timestamp,
+    };
+    let registers = Registers {
+        voltage: scale(event.voltage, 10.0).ok_or(out_of_range("voltage"))?,
+        current: scale(event.current, 1000.0).ok_or(out_of_range("current"))?,
+        power_factor: scale(event.power_factor, 100.0).ok_or(out_of_range("power factor"))?,
+    };
+    Ok(registers.encode())
+}
+
+// The value in the units of the file format, if it fits
+fn scale<T: TryFrom<u32>>(value: f64, factor: f64) -> Option<T> {
+    let scaled = (value * factor).round();
+    if !(0.0..=u32::MAX as f64).contains(&scaled) {
+        return None;
+    }
+    T::try_from(scaled as u32).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voltcraft::data::{ParseOptions, VoltcraftData};
+    use crate::voltcraft::sample::{sample_data, SAMPLE_FILE};
+
+    fn decode(bytes: Vec<u8>, options: &ParseOptions) -> Vec<PowerEvent> {
+        VoltcraftData::from_raw(bytes)
+            .parse_with(options)
+            .unwrap()
+            .0
+    }
+
+    fn readings(events: &[PowerEvent]) -> Vec<(DateTime<FixedOffset>, f64, f64, f64)> {
+        events
+            .iter()
+            .map(|e| (e.timestamp, e.voltage, e.current, e.power_factor))
+            .collect()
+    }
+
+    #[test]
+    fn encoder_round_trip() {
+        let (events, _) = sample_data().parse().unwrap();
+        assert_eq!(
+            VoltcraftEncoder::new().encode(&events).unwrap(),
+            SAMPLE_FILE
+        );
+
+        // The logger clock in another zone, with 30 second samples
+        let options = ParseOptions::new()
+            .timezone(FixedOffset::west_opt(5 * 3600).unwrap())
+            .sample_interval(Duration::seconds(30));
+        let events = decode(SAMPLE_FILE.to_vec(), &options);
+        let encoder = VoltcraftEncoder::new()
+            .timezone(FixedOffset::west_opt(5 * 3600).unwrap())
+            .sample_interval(Duration::seconds(30));
+        assert_eq!(encoder.encode(&events).unwrap(), SAMPLE_FILE);
+    }
+
+    #[test]
+    fn encoder_registers() {
+        use crate::voltcraft::data::{Calibration, Correction};
+
+        let calibration = Calibration {
+            voltage: Correction {
+                gain: 1.013,
+                offset: -0.4,
+            },
+            ..Default::default()
+        };
+        let options = ParseOptions::new().calibration(calibration);
+        let calibrated = decode(SAMPLE_FILE.to_vec(), &options);
+        assert!(calibrated.iter().all(|e| e.registers.is_none()));
+        assert_ne!(
+            VoltcraftEncoder::new().encode(&calibrated).unwrap(),
+            SAMPLE_FILE
+        );
+
+        // The values as stored give the file back, whatever the scaled ones
+        let kept = decode(SAMPLE_FILE.to_vec(), &options.keep_registers(true));
+        let registers = kept[0].registers.unwrap();
+        assert_eq!((registers.voltage, registers.current), (2246, 446));
+        assert_eq!(registers.reading().voltage, 224.6);
+        assert_eq!(readings(&kept), readings(&calibrated));
+        assert_eq!(VoltcraftEncoder::new().encode(&kept).unwrap(), SAMPLE_FILE);
+    }
+
+    #[test]
+    fn encoder_chunks() {
+        let (events, _) = sample_data().parse().unwrap();
+        let encoder = VoltcraftEncoder::new();
+        let files = encoder.encode_chunks(&events, 30).unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|f| f.len() <= 30));
+        let decoded = files
+            .into_iter()
+            .flat_map(|f| decode(f, &ParseOptions::new()))
+            .collect::<Vec<_>>();
+        assert_eq!(readings(&decoded), readings(&events));
+
+        assert_eq!(
+            encoder.encode_chunks(&events, 16),
+            Err(EncodeError::FileTooSmall { size: 16 })
+        );
+        assert_eq!(encoder.encode(&[]), Err(EncodeError::NoReadings));
+    }
+
+    #[test]
+    fn encoder_errors() {
+        let (events, _) = sample_data().parse().unwrap();
+        let encoder = VoltcraftEncoder::new();
+
+        let mut event = events[0];
+        event.timestamp += Duration::seconds(20);
+        assert!(matches!(
+            encoder.encode(&[event]),
+            Err(EncodeError::UnalignedTimestamp { .. })
+        ));
+
+        let mut event = events[0];
+        event.voltage = 7000.0;
+        assert!(matches!(
+            encoder.encode(&[event]),
+            Err(EncodeError::OutOfRange {
+                quantity: "voltage",
+                ..
+            })
+        ));
+
+        let mut event = events[0];
+        event.power_factor = -0.5;
+        assert!(matches!(
+            encoder.encode(&[event]),
+            Err(EncodeError::OutOfRange {
+                quantity: "power factor",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::voltcraft::data::PowerEvent;
+use crate::voltcraft::encoder::{EncodeError, VoltcraftEncoder};
+use chrono::{Duration, Local, NaiveDate, TimeZone, Timelike};
+use std::fmt;
+use std::str::FromStr;
+
+// Average household load (W) by hour of the day: the night base load, and the morning and evening peaks
+const HOUSEHOLD_LOAD: [f64; 24] = [
+    150.0, 120.0, 110.0, 110.0, 110.0, 130.0, 300.0, 900.0, 700.0, 350.0, 300.0, 350.0, 450.0,
+    400.0, 350.0, 350.0, 450.0, 700.0, 1200.0, 1500.0, 1300.0, 900.0, 500.0, 250.0,
+];
+
+// Shape of the synthesized load over the day
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadShape {
+    Flat,      // constant load
+    Fridge,    // compressor running 20 minutes each hour, idle otherwise
+    Household, // whole house, with morning and evening peaks
+}
+
+impl FromStr for LoadShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "flat" => Ok(LoadShape::Flat),
+            "fridge" => Ok(LoadShape::Fridge),
+            "household" => Ok(LoadShape::Household),
+            _ => Err(format!(
+                "Invalid load shape '{}' (expected flat, fridge or household)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LoadShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadShape::Flat => write!(f, "flat"),
+            LoadShape::Fridge => write!(f, "fridge"),
+            LoadShape::Household => write!(f, "household"),
+        }
+    }
+}
+
+/// Synthesizes logger recordings, for demos and tests that can't ship real household data. The
+/// output only depends on the settings (including the seed), so it is reproducible.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::data::VoltcraftData;
+/// use voltcraft_energy_analyzer::voltcraft::generate::{Generator, LoadShape};
+///
+/// let generator = Generator::new().days(2).load_shape(LoadShape::Fridge).seed(7);
+/// let bytes = generator.encode().unwrap();
+/// let (events, _) = VoltcraftData::from_raw(bytes).parse().unwrap();
+/// assert_eq!(events.len(), generator.events().len());
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Generator {
+    start: NaiveDate,       // first day recorded, from midnight (local time)
+    days: u32,              // days recorded
+    load_shape: LoadShape,  // shape of the load over the day
+    blackouts_per_day: f64, // average number of blackouts a day
+    voltage_noise: f64,     // volts, random variation around the expected voltage
+    nominal_voltage: f64,   // volts
+    seed: u64,              // of the random variations
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator {
+            start: NaiveDate::from_ymd_opt(2014, 9, 1).unwrap(),
+            days: 7,
+            load_shape: LoadShape::Household,
+            blackouts_per_day: 0.5,
+            voltage_noise: 2.0,
+            nominal_voltage: 230.0,
+            seed: 0,
+        }
+    }
+}
+
+impl Generator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(mut self, start: NaiveDate) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn days(mut self, days: u32) -> Self {
+        self.days = days;
+        self
+    }
+
+    pub fn load_shape(mut self, shape: LoadShape) -> Self {
+        self.load_shape = shape;
+        self
+    }
+
+    pub fn blackouts_per_day(mut self, rate: f64) -> Self {
+        self.blackouts_per_day = rate;
+        self
+    }
+
+    pub fn voltage_noise(mut self, volts: f64) -> Self {
+        self.voltage_noise = volts;
+        self
+    }
+
+    pub fn nominal_voltage(mut self, volts: f64) -> Self {
+        self.nominal_voltage = volts;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // The readings, one a minute outside the blackouts, at the resolution of the file format (so
+    // decoding the encoded readings gives them back)
+    pub fn events(&self) -> Vec<PowerEvent> {
+        let mut rng = Rng(self.seed);
+        let mut events = Vec::new();
+        let mut blackout_left = 0;
+        let midnight = self.start.and_hms_opt(0, 0, 0).unwrap();
+        for minute in 0..self.days as i64 * 1440 {
+            // Minutes skipped when the clocks go forward don't exist on the logger clock either
+            let Some(timestamp) = Local
+                .from_local_datetime(&(midnight + Duration::minutes(minute)))
+                .earliest()
+                .map(|t| t.fixed_offset())
+            else {
+                continue;
+            };
+            if blackout_left == 0 && rng.unit() < self.blackouts_per_day / 1440.0 {
+                blackout_left = 1 + rng.next() % 30; // minutes
+            }
+            if blackout_left > 0 {
+                blackout_left -= 1;
+                continue;
+            }
+
+            let (load, power_factor) = self.load(timestamp.hour(), minute, &mut rng);
+            // The voltage drops as the load rises, around the nominal voltage
+            let voltage = self.nominal_voltage * (1.01 - 0.03 * load / 2000.0)
+                + self.voltage_noise * (2.0 * rng.unit() - 1.0);
+            let current = load / (voltage * power_factor);
+
+            let voltage = (voltage * 10.0).round() / 10.0;
+            let current = (current * 1000.0).round() / 1000.0;
+            let power_factor = (power_factor * 100.0).round() / 100.0;
+            events.push(PowerEvent {
+                timestamp,
+                voltage,
+                current,
+                power_factor,
+                power: voltage * current * power_factor / 1000.0,
+                apparent_power: voltage * current / 1000.0,
+                source: None,
+                registers: None,
+                filled: false,
+            });
+        }
+        events
+    }
+
+    // The readings in the binary format of the logger, as a single data file
+    pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        VoltcraftEncoder::new().encode(&self.events())
+    }
+
+    // Active power (W) and power factor of the load at the given minute of the recording
+    fn load(&self, hour: u32, minute: i64, rng: &mut Rng) -> (f64, f64) {
+        match self.load_shape {
+            LoadShape::Flat => (200.0, 0.95),
+            LoadShape::Fridge if minute % 60 < 20 => (85.0 + 10.0 * rng.unit(), 0.86),
+            LoadShape::Fridge => (2.5 + rng.unit(), 0.30),
+            LoadShape::Household => (
+                HOUSEHOLD_LOAD[hour as usize] * (0.8 + 0.4 * rng.unit()),
+                0.88 + 0.08 * rng.unit(),
+            ),
+        }
+    }
+}
+
+// Small deterministic random number generator (SplitMix64), so the output only depends on the seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniformly distributed in [0, 1)
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voltcraft::data::VoltcraftData;
+    use crate::voltcraft::stats::VoltcraftStatistics;
+
+    fn readings(events: &[PowerEvent]) -> Vec<(i64, f64, f64, f64, f64)> {
+        events
+            .iter()
+            .map(|e| {
+                let t = e.timestamp.timestamp();
+                (t, e.voltage, e.current, e.power_factor, e.power)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generator_round_trip() {
+        let generator = Generator::new().days(3).blackouts_per_day(4.0).seed(42);
+        let events = generator.events();
+        let (decoded, report) = VoltcraftData::from_raw(generator.encode().unwrap())
+            .parse()
+            .unwrap();
+        assert_eq!(readings(&decoded), readings(&events));
+        // Each blackout starts a new data block
+        let blackouts = VoltcraftStatistics::new(&mut events.clone()).blackout_stats();
+        assert!(blackouts.blackout_count > 0);
+        assert_eq!(report.sessions.len(), blackouts.blackout_count + 1);
+    }
+
+    #[test]
+    fn generator_settings() {
+        let generator = Generator::new().days(2).blackouts_per_day(0.0);
+        assert_eq!(generator.events().len(), 2 * 1440);
+        // Reproducible for a seed, different otherwise
+        assert_eq!(
+            readings(&generator.events()),
+            readings(&generator.seed(0).events())
+        );
+        assert_ne!(
+            readings(&generator.events()),
+            readings(&generator.seed(1).events())
+        );
+
+        let flat = generator.load_shape(LoadShape::Flat).voltage_noise(0.0);
+        assert!(flat
+            .events()
+            .iter()
+            .all(|e| (e.power - 0.2).abs() < 0.001 && (e.voltage - 230.0).abs() < 3.0));
+        let fridge = generator.load_shape(LoadShape::Fridge).events();
+        let running = fridge.iter().filter(|e| e.power > 0.05).count();
+        assert_eq!(running, 2 * 24 * 20);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::voltcraft::data::ValidationConfig;
+use std::fmt;
+use std::str::FromStr;
+
+/// The supply the logger was plugged into: its nominal voltage and frequency, and the band around
+/// the nominal voltage it is meant to stay within. The thresholds of the analyses (plausible
+/// voltages, sags, flicker) follow from it, so 120V/60Hz installations get thresholds that make
+/// sense for them.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::grid::GridProfile;
+///
+/// let grid: GridProfile = "na".parse().unwrap();
+/// assert_eq!(grid, GridProfile::NORTH_AMERICA);
+/// assert_eq!((grid.low_voltage(), grid.high_voltage()), (114.0, 126.0));
+/// // Or any other supply, as <volts>/<hertz>[/<tolerance %>]
+/// let grid: GridProfile = "240V/50Hz/6%".parse().unwrap();
+/// assert_eq!(grid.to_string(), "240V/50Hz/6%");
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GridProfile {
+    pub nominal_voltage: f64, // V
+    pub frequency: f64,       // Hz
+    pub tolerance: f64,       // % of the nominal voltage, either way
+}
+
+impl GridProfile {
+    // 230V/50Hz, within 10% (EN 50160)
+    pub const EUROPE: GridProfile = GridProfile {
+        nominal_voltage: 230.0,
+        frequency: 50.0,
+        tolerance: 10.0,
+    };
+
+    // 120V/60Hz, within 5% (ANSI C84.1, range A)
+    pub const NORTH_AMERICA: GridProfile = GridProfile {
+        nominal_voltage: 120.0,
+        frequency: 60.0,
+        tolerance: 5.0,
+    };
+
+    // Lower end of the tolerance band: a minute below it is a sag
+    pub fn low_voltage(&self) -> f64 {
+        self.nominal_voltage * (100.0 - self.tolerance) / 100.0
+    }
+
+    // Upper end of the tolerance band: a minute above it is a swell
+    pub fn high_voltage(&self) -> f64 {
+        self.nominal_voltage * (100.0 + self.tolerance) / 100.0
+    }
+
+    // A voltage meant for 230V grids, scaled to this one (e.g. a flicker threshold)
+    pub fn scaled(&self, volts: f64) -> f64 {
+        volts * self.nominal_voltage / 230.0
+    }
+
+    // Deviation of a voltage from the nominal voltage, in %
+    pub fn deviation(&self, volts: f64) -> f64 {
+        (volts - self.nominal_voltage) * 100.0 / self.nominal_voltage
+    }
+
+    // The plausible voltage range of the decoder: from its minimum scaled to this grid, up to a
+    // margin beyond the tolerance band, so that the swells are kept and reported rather than taken
+    // for corrupted data (and the same way below the band, for wide bands)
+    pub fn validation(&self) -> ValidationConfig {
+        const MARGIN: f64 = 10.0; // % of the nominal voltage
+        let default = ValidationConfig::default();
+        let band = |percent: f64| self.nominal_voltage * percent / 100.0;
+        ValidationConfig {
+            min_voltage: self
+                .scaled(default.min_voltage)
+                .min(band(100.0 - self.tolerance - MARGIN))
+                .max(0.0),
+            max_voltage: band(100.0 + self.tolerance + MARGIN),
+            ..default
+        }
+    }
+}
+
+impl Default for GridProfile {
+    fn default() -> Self {
+        GridProfile::EUROPE
+    }
+}
+
+impl FromStr for GridProfile {
+    type Err = String;
+
+    // eu, na, or <volts>/<hertz>[/<tolerance %>] (units optional), e.g. 240/60/5
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid grid '{}' (expected eu, na or <volts>/<hertz>[/<tolerance %>])",
+                s
+            )
+        };
+        match s.trim().to_ascii_lowercase().as_str() {
+            "eu" => Ok(GridProfile::EUROPE),
+            "na" => Ok(GridProfile::NORTH_AMERICA),
+            profile => {
+                let values = profile
+                    .split('/')
+                    .zip(["v", "hz", "%"])
+                    .map(|(value, unit)| {
+                        let value = value.trim();
+                        value.strip_suffix(unit).unwrap_or(value).parse::<f64>()
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| invalid())?;
+                let grid = match values[..] {
+                    [nominal_voltage, frequency] if profile.matches('/').count() == 1 => {
+                        GridProfile {
+                            nominal_voltage,
+                            frequency,
+                            ..GridProfile::EUROPE
+                        }
+                    }
+                    [nominal_voltage, frequency, tolerance]
+                        if profile.matches('/').count() == 2 =>
+                    {
+                        GridProfile {
+                            nominal_voltage,
+                            frequency,
+                            tolerance,
+                        }
+                    }
+                    _ => return Err(invalid()),
+                };
+                if grid.nominal_voltage > 0.0
+                    && grid.frequency >= 0.0
+                    && (0.0..100.0).contains(&grid.tolerance)
+                {
+                    Ok(grid)
+                } else {
+                    Err(invalid())
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for GridProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}V/{}Hz/{}%",
+            self.nominal_voltage, self.frequency, self.tolerance
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voltcraft::data::{ParseOptions, VoltcraftData};
+    use crate::voltcraft::encoder::VoltcraftEncoder;
+    use crate::voltcraft::stats::VoltcraftStatistics;
+
+    #[test]
+    fn grid_profiles() {
+        assert_eq!("EU".parse(), Ok(GridProfile::EUROPE));
+        assert_eq!(GridProfile::EUROPE.low_voltage(), 207.0);
+        assert_eq!(GridProfile::EUROPE.scaled(5.0), 5.0);
+        assert_eq!(
+            GridProfile::EUROPE.validation(),
+            ValidationConfig::default()
+        );
+        let grid = GridProfile::NORTH_AMERICA;
+        assert_eq!(grid.to_string().parse(), Ok(grid));
+        assert_eq!(grid.deviation(126.0), 5.0);
+        let validation = grid.validation();
+        assert!(validation.min_voltage < 90.0 && validation.max_voltage > 130.0);
+        // The plausible range holds the band, however wide
+        let grid: GridProfile = "230/50/40".parse().unwrap();
+        let validation = grid.validation();
+        assert!(validation.min_voltage < grid.low_voltage());
+        assert!(validation.max_voltage > grid.high_voltage());
+        assert_eq!(
+            "120/60".parse(),
+            Ok(GridProfile {
+                tolerance: 10.0,
+                ..GridProfile::NORTH_AMERICA
+            })
+        );
+        for invalid in ["", "120", "120/60/5/1", "0/60", "120/60/100", "us"] {
+            assert!(invalid.parse::<GridProfile>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn grid_swells() {
+        // Readings of 252V, within the band, and of 260V, above it
+        let (mut events, _) = crate::voltcraft::sample::sample_data().parse().unwrap();
+        events.truncate(2);
+        events[0].voltage = 252.0;
+        events[1].voltage = 260.0;
+        let bytes = VoltcraftEncoder::new().encode(&events).unwrap();
+
+        let grid = GridProfile::EUROPE;
+        let options = ParseOptions::new().validation(grid.validation());
+        let (mut events, report) = VoltcraftData::from_raw(bytes).parse_with(&options).unwrap();
+        assert_eq!(report.implausible_voltages, 0);
+        assert_eq!(events.len(), 2);
+        let deviation = VoltcraftStatistics::new(&mut events).voltage_deviation(&grid);
+        assert_eq!(deviation.over_minutes, 1);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::voltcraft::data::PowerEvent;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// K-way merge of chronologically sorted power event sources into a single chronological stream.
+/// Events with identical timestamps are yielded in source order, so the merge is stable.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::merge::ChronologicalMerge;
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+///
+/// let (events, _) = sample_data().parse().unwrap();
+/// // The two data blocks of the sample, as if they came from separate files
+/// let (first, second) = events.split_at(6);
+/// let merged = ChronologicalMerge::new(vec![second.iter().copied(), first.iter().copied()])
+///     .collect::<Vec<_>>();
+/// assert!(merged.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+/// ```
+pub struct ChronologicalMerge<I: Iterator<Item = PowerEvent>> {
+    sources: Vec<I>,
+    heap: BinaryHeap<Reverse<HeapEntry>>,
+}
+
+struct HeapEntry {
+    event: PowerEvent,
+    source: usize,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.event
+            .timestamp
+            .cmp(&other.event.timestamp)
+            .then(self.source.cmp(&other.source))
+    }
+}
+
+impl<I: Iterator<Item = PowerEvent>> ChronologicalMerge<I> {
+    pub fn new(mut sources: Vec<I>) -> ChronologicalMerge<I> {
+        // Prime the heap with the first event of each source
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (source, it) in sources.iter_mut().enumerate() {
+            if let Some(event) = it.next() {
+                heap.push(Reverse(HeapEntry { event, source }));
+            }
+        }
+        ChronologicalMerge { sources, heap }
+    }
+}
+
+impl<I: Iterator<Item = PowerEvent>> Iterator for ChronologicalMerge<I> {
+    type Item = PowerEvent;
+
+    fn next(&mut self) -> Option<PowerEvent> {
+        let Reverse(entry) = self.heap.pop()?;
+        // Replace the yielded event with the next one from the same source
+        if let Some(event) = self.sources[entry.source].next() {
+            self.heap.push(Reverse(HeapEntry {
+                event,
+                source: entry.source,
+            }));
+        }
+        Some(entry.event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voltcraft::data::PowerEvent;
+    use crate::voltcraft::merge::ChronologicalMerge;
+    use chrono::{Duration, FixedOffset, TimeZone};
+
+    fn event(minute: i64, voltage: f64) -> PowerEvent {
+        PowerEvent {
+            timestamp: FixedOffset::east_opt(3 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2014, 9, 11, 12, 0, 0)
+                .unwrap()
+                + Duration::minutes(minute),
+            voltage,
+            current: 0.0,
+            power_factor: 0.0,
+            power: 0.0,
+            apparent_power: 0.0,
+            source: None,
+            registers: None,
+            filled: false,
+        }
+    }
+
+    #[test]
+    fn merge_is_chronological_and_stable() {
+        let a = vec![event(0, 1.0), event(2, 1.0), event(4, 1.0)];
+        let b = vec![event(1, 2.0), event(2, 2.0), event(5, 2.0)];
+        let merged = ChronologicalMerge::new(vec![a.into_iter(), b.into_iter()])
+            .map(|e| (e.timestamp.timestamp() / 60 % 60, e.voltage))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            merged,
+            vec![(0, 1.0), (1, 2.0), (2, 1.0), (2, 2.0), (4, 1.0), (5, 2.0)]
+        );
+    }
+}
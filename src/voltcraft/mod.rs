@@ -1,2 +1,95 @@
+#[cfg(feature = "std")]
+pub mod archive;
+#[cfg(feature = "std")]
 pub mod data;
+#[cfg(feature = "std")]
+pub mod dataset;
+#[cfg(feature = "std")]
+pub mod duration;
+#[cfg(feature = "std")]
+pub mod encoder;
+#[cfg(feature = "std")]
+pub mod generate;
+#[cfg(feature = "std")]
+pub mod grid;
+#[cfg(feature = "std")]
+pub mod merge;
+#[cfg(feature = "std")]
+pub mod phases;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod profile;
+// The byte-level codec, a crate of its own so that tools can read the files without the analyzer
+pub use voltcraft_format as raw;
+#[cfg(feature = "std")]
+pub mod resample;
+#[cfg(feature = "std")]
+pub mod sample;
+#[cfg(feature = "sdcard")]
+pub mod sdcard;
+#[cfg(feature = "std")]
+pub mod state;
+#[cfg(feature = "std")]
 pub mod stats;
+#[cfg(feature = "std")]
+pub mod tariff;
+#[cfg(feature = "std")]
+pub mod transform;
+#[cfg(feature = "zip")]
+pub mod ziparchive;
+
+// The optional features of the crate (see Cargo.toml), and whether this build includes them
+const OPTIONAL_FEATURES: &[(&str, bool)] = &[
+    ("bundle", cfg!(feature = "bundle")),
+    ("gzip", cfg!(feature = "gzip")),
+    ("mmap", cfg!(feature = "mmap")),
+    ("parallel", cfg!(feature = "parallel")),
+    ("sdcard", cfg!(feature = "sdcard")),
+    ("serde", cfg!(feature = "serde")),
+    ("tokio", cfg!(feature = "tokio")),
+    ("zip", cfg!(feature = "zip")),
+];
+
+/// The optional features compiled in, by name, so that callers can tell at runtime what the build
+/// supports (e.g. whether gzip-compressed files can be read, or the outputs bundled).
+///
+/// ```
+/// let gzip = voltcraft_energy_analyzer::voltcraft::capabilities().any(|f| f == "gzip");
+/// assert_eq!(gzip, cfg!(feature = "gzip"));
+/// ```
+pub fn capabilities() -> impl Iterator<Item = &'static str> {
+    OPTIONAL_FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voltcraft::capabilities;
+
+    #[test]
+    fn capabilities_in_name_order() {
+        assert!(capabilities()
+            .zip(capabilities().skip(1))
+            .all(|(name, next)| name < next));
+        assert_eq!(capabilities().any(|f| f == "zip"), cfg!(feature = "zip"));
+    }
+
+    // None of the optional features is a default one
+    #[test]
+    #[cfg(not(any(
+        feature = "bundle",
+        feature = "gzip",
+        feature = "mmap",
+        feature = "parallel",
+        feature = "sdcard",
+        feature = "serde",
+        feature = "tokio",
+        feature = "zip"
+    )))]
+    fn default_capabilities() {
+        assert_eq!(capabilities().count(), 0);
+    }
+}
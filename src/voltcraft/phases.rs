@@ -0,0 +1,288 @@
+use crate::voltcraft::data::PowerEvent;
+use chrono::{DateTime, FixedOffset};
+use std::collections::BTreeMap;
+
+// The readings of the loggers on each phase for the same minute
+#[derive(Debug, Clone)]
+pub struct PhaseReadings {
+    pub timestamp: DateTime<FixedOffset>, // of the reading on the first phase
+    pub phases: Vec<PowerEvent>,          // one per phase, in phase order
+}
+
+impl PhaseReadings {
+    // Combined active power of the phases (kW)
+    pub fn power(&self) -> f64 {
+        self.phases.iter().map(|e| e.power).sum()
+    }
+
+    // Phase current imbalance: largest deviation of a phase current from their mean, relative to
+    // the mean (None without current)
+    pub fn current_imbalance(&self) -> Option<f64> {
+        imbalance(&self.phases.iter().map(|e| e.current).collect::<Vec<_>>())
+    }
+
+    // Estimated current in the neutral conductor (A), None unless there are three phases. The
+    // loggers only measure the magnitude of the currents, so the phase currents are taken 120
+    // degrees apart (as with purely resistive loads): their sum then has the magnitude
+    // sqrt(I1² + I2² + I3² - I1·I2 - I2·I3 - I3·I1). Reactive or non-linear loads (e.g. the
+    // third harmonic of switching power supplies, which adds up in the neutral) make it higher.
+    pub fn neutral_current(&self) -> Option<f64> {
+        let [l1, l2, l3] = self.phases[..] else {
+            return None;
+        };
+        let (i1, i2, i3) = (l1.current, l2.current, l3.current);
+        let squared = i1 * i1 + i2 * i2 + i3 * i3 - i1 * i2 - i2 * i3 - i3 * i1;
+        // Rounding may leave a tiny negative value for balanced currents
+        Some(squared.max(0.0).sqrt())
+    }
+}
+
+// Limits above which a minute counts as unbalanced
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BalanceLimits {
+    pub max_imbalance: f64, // phase current imbalance (relative, see imbalance)
+    pub max_neutral_current: f64, // estimated neutral current (A)
+}
+
+impl Default for BalanceLimits {
+    fn default() -> Self {
+        BalanceLimits {
+            max_imbalance: 0.2,
+            max_neutral_current: 10.0,
+        }
+    }
+}
+
+// Figures of one phase, over the minutes recorded on all the phases
+#[derive(Debug, Copy, Clone)]
+pub struct PhaseInfo {
+    pub energy: f64,             // kWh
+    pub avg_current: f64,        // ampers
+    pub avg_voltage: f64,        // volts
+    pub min_voltage: PowerEvent, // lowest voltage
+    pub max_voltage: PowerEvent, // highest voltage
+}
+
+// Combined figures of the loggers on the phases of a three-phase supply
+#[derive(Debug, Clone)]
+pub struct ThreePhaseInfo {
+    pub aligned_minutes: usize, // minutes recorded on all the phases
+    pub unaligned: Vec<usize>,  // readings of each phase left out, as missing on another phase
+    pub phases: Vec<PhaseInfo>, // in phase order
+    pub total_energy: f64,      // kWh, all the phases together
+    pub max_power: (DateTime<FixedOffset>, f64), // highest combined active power (kW)
+    pub energy_imbalance: Option<f64>, // of the energy used on each phase, relative (see imbalance)
+    pub avg_current_imbalance: Option<f64>, // average of the minutes with current, relative
+    pub max_current_imbalance: Option<(DateTime<FixedOffset>, f64)>, // largest of a minute, relative
+    pub avg_neutral_current: Option<f64>, // estimated (A), see PhaseReadings::neutral_current
+    pub max_neutral_current: Option<(DateTime<FixedOffset>, f64)>, // largest estimate (A)
+    pub limits: BalanceLimits,
+    pub unbalanced_minutes: usize, // minutes with the current imbalance above the limit
+    pub neutral_overload_minutes: usize, // minutes with the neutral current above the limit
+}
+
+// Pair up the readings of the loggers on each phase by minute. The logger clocks are set by hand,
+// so readings less than half a minute apart are taken as simultaneous; the minutes missing on any
+// phase (e.g. a logger downloaded before the others) are left out. The readings of each phase are
+// in chronological order.
+pub fn align_phases(phases: &[&[PowerEvent]]) -> Vec<PhaseReadings> {
+    let minute = |e: &PowerEvent| (e.timestamp.timestamp() + 30).div_euclid(60);
+    let by_minute = phases
+        .iter()
+        .skip(1)
+        .map(|events| {
+            let mut minutes = BTreeMap::new();
+            for e in events.iter() {
+                minutes.entry(minute(e)).or_insert(*e);
+            }
+            minutes
+        })
+        .collect::<Vec<_>>();
+    let Some(first) = phases.first() else {
+        return Vec::new();
+    };
+    first
+        .iter()
+        .filter_map(|e| {
+            let others = by_minute
+                .iter()
+                .map(|minutes| minutes.get(&minute(e)).copied())
+                .collect::<Option<Vec<_>>>()?;
+            Some(PhaseReadings {
+                timestamp: e.timestamp,
+                phases: std::iter::once(*e).chain(others).collect(),
+            })
+        })
+        .collect()
+}
+
+// Combined figures of the phases, None if no minute was recorded on all of them
+pub fn three_phase_stats(
+    phases: &[&[PowerEvent]],
+    limits: BalanceLimits,
+) -> Option<ThreePhaseInfo> {
+    let readings = align_phases(phases);
+    let n = readings.len() as f64;
+    let first = readings.first()?;
+    let phase_info = (0..phases.len())
+        .map(|phase| {
+            let events = readings.iter().map(|r| r.phases[phase]);
+            let min_voltage = events
+                .clone()
+                .min_by(|a, b| a.voltage.total_cmp(&b.voltage));
+            let max_voltage = events
+                .clone()
+                .max_by(|a, b| a.voltage.total_cmp(&b.voltage));
+            PhaseInfo {
+                energy: events.clone().map(|e| e.power).sum::<f64>() / 60.0,
+                avg_current: events.clone().map(|e| e.current).sum::<f64>() / n,
+                avg_voltage: events.map(|e| e.voltage).sum::<f64>() / n,
+                min_voltage: min_voltage.unwrap_or(first.phases[phase]),
+                max_voltage: max_voltage.unwrap_or(first.phases[phase]),
+            }
+        })
+        .collect::<Vec<_>>();
+    let max_power = readings
+        .iter()
+        .map(|r| (r.timestamp, r.power()))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    let current_imbalances = readings
+        .iter()
+        .filter_map(|r| Some((r.timestamp, r.current_imbalance()?)))
+        .collect::<Vec<_>>();
+    let neutral_currents = readings
+        .iter()
+        .filter_map(|r| Some((r.timestamp, r.neutral_current()?)))
+        .collect::<Vec<_>>();
+    let average = |values: &[(DateTime<FixedOffset>, f64)]| {
+        (!values.is_empty()).then(|| values.iter().map(|v| v.1).sum::<f64>() / values.len() as f64)
+    };
+    let largest = |values: &[(DateTime<FixedOffset>, f64)]| {
+        values.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1))
+    };
+    let above = |values: &[(DateTime<FixedOffset>, f64)], limit| {
+        values.iter().filter(|v| v.1 > limit).count()
+    };
+    Some(ThreePhaseInfo {
+        aligned_minutes: readings.len(),
+        unaligned: phases
+            .iter()
+            .map(|events| events.len().saturating_sub(readings.len()))
+            .collect(),
+        total_energy: phase_info.iter().map(|p| p.energy).sum(),
+        energy_imbalance: imbalance(&phase_info.iter().map(|p| p.energy).collect::<Vec<_>>()),
+        avg_current_imbalance: average(&current_imbalances),
+        max_current_imbalance: largest(&current_imbalances),
+        avg_neutral_current: average(&neutral_currents),
+        max_neutral_current: largest(&neutral_currents),
+        limits,
+        unbalanced_minutes: above(&current_imbalances, limits.max_imbalance),
+        neutral_overload_minutes: above(&neutral_currents, limits.max_neutral_current),
+        phases: phase_info,
+        max_power,
+    })
+}
+
+// Imbalance of values shared among the phases: the largest deviation from their mean, relative to
+// the mean (as NEMA defines the voltage unbalance of motors), None if the mean is zero
+pub fn imbalance(values: &[f64]) -> Option<f64> {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    if mean <= 0.0 || !mean.is_finite() {
+        return None;
+    }
+    let deviation = values.iter().map(|v| (v - mean).abs()).fold(0.0, f64::max);
+    Some(deviation / mean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn event(second: i64, voltage: f64, current: f64) -> PowerEvent {
+        PowerEvent {
+            timestamp: FixedOffset::east_opt(3 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2014, 9, 11, 12, 0, 0)
+                .unwrap()
+                + Duration::seconds(second),
+            voltage,
+            current,
+            power_factor: 1.0,
+            power: voltage * current / 1000.0,
+            apparent_power: voltage * current / 1000.0,
+            source: None,
+            registers: None,
+            filled: false,
+        }
+    }
+
+    #[test]
+    fn phases_are_aligned_by_minute() {
+        let l1 = [
+            event(0, 230.0, 10.0),
+            event(60, 230.0, 10.0),
+            event(120, 230.0, 10.0),
+        ];
+        // A logger clock 20 seconds late, missing the last minute
+        let l2 = [event(20, 220.0, 5.0), event(80, 220.0, 5.0)];
+        let l3 = [
+            event(-60, 240.0, 0.0),
+            event(0, 240.0, 0.0),
+            event(60, 240.0, 0.0),
+        ];
+        let readings = align_phases(&[&l1, &l2, &l3]);
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[1].timestamp, l1[1].timestamp);
+        assert_eq!(readings[1].phases[1].timestamp, l2[1].timestamp);
+        assert!((readings[0].power() - 3.4).abs() < 1e-9);
+        assert_eq!(readings[0].current_imbalance(), Some(1.0));
+        // sqrt(100 + 25 + 0 - 50 - 0 - 0)
+        assert!((readings[0].neutral_current().unwrap() - 75f64.sqrt()).abs() < 1e-9);
+
+        let info = three_phase_stats(&[&l1, &l2, &l3], BalanceLimits::default()).unwrap();
+        assert_eq!(info.aligned_minutes, 2);
+        assert_eq!(info.unaligned, vec![1, 0, 1]);
+        assert!((info.total_energy - 2.0 * 3.4 / 60.0).abs() < 1e-9);
+        assert_eq!(info.phases[1].avg_voltage, 220.0);
+        // The phases used 4.6, 2.2 and 0 kW-minutes, 2.27 on average
+        let mean = 6.8 / 3.0;
+        assert!((info.energy_imbalance.unwrap() - (4.6 - mean) / mean).abs() < 1e-9);
+        assert_eq!(info.unbalanced_minutes, 2);
+        assert_eq!(info.neutral_overload_minutes, 0);
+        assert!((info.avg_neutral_current.unwrap() - 75f64.sqrt()).abs() < 1e-9);
+        assert!(three_phase_stats(&[&l1, &l2[..0], &l3], BalanceLimits::default()).is_none());
+    }
+
+    #[test]
+    fn neutral_current() {
+        let readings = |currents: [f64; 3]| PhaseReadings {
+            timestamp: event(0, 230.0, 0.0).timestamp,
+            phases: currents.iter().map(|&i| event(0, 230.0, i)).collect(),
+        };
+        // Balanced currents cancel out, a single loaded phase returns through the neutral
+        assert_eq!(readings([8.0, 8.0, 8.0]).neutral_current(), Some(0.0));
+        assert_eq!(readings([12.0, 0.0, 0.0]).neutral_current(), Some(12.0));
+        assert_eq!(readings([10.0, 10.0, 0.0]).neutral_current(), Some(10.0));
+
+        let l1 = [event(0, 230.0, 15.0), event(60, 230.0, 5.0)];
+        let l2 = [event(0, 230.0, 2.0), event(60, 230.0, 5.0)];
+        let l3 = [event(0, 230.0, 1.0), event(60, 230.0, 4.0)];
+        let limits = BalanceLimits {
+            max_imbalance: 0.5,
+            max_neutral_current: 10.0,
+        };
+        let info = three_phase_stats(&[&l1, &l2, &l3], limits).unwrap();
+        assert_eq!(info.unbalanced_minutes, 1);
+        assert_eq!(info.neutral_overload_minutes, 1);
+        assert_eq!(info.max_current_imbalance.unwrap().0, l1[0].timestamp);
+        assert!((info.max_neutral_current.unwrap().1 - 183f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn phase_imbalance() {
+        assert_eq!(imbalance(&[10.0, 10.0, 10.0]), Some(0.0));
+        assert_eq!(imbalance(&[12.0, 10.0, 8.0]), Some(0.2));
+        assert_eq!(imbalance(&[0.0, 0.0, 0.0]), None);
+    }
+}
@@ -0,0 +1,629 @@
+use crate::voltcraft::data::{ParseError, ParseOptions, PowerEvent, VoltcraftData};
+use crate::voltcraft::dataset::{DatasetError, DatasetOptions, VoltcraftDataset};
+use crate::voltcraft::resample::resample;
+use crate::voltcraft::transform::{
+    apply_deadband, exclude_periods, fill_gaps, remove_duplicates, smooth, ExcludedPeriod,
+    GapFilling,
+};
+use chrono::Duration;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Readings taken from an [`EventSource`], run through [`EventTransform`]s in the order they
+/// were added, and written to every [`EventSink`]: the flow of the command line tool, assembled
+/// from the pieces a program needs.
+///
+/// ```
+/// use chrono::Duration;
+/// use voltcraft_energy_analyzer::voltcraft::pipeline::{CsvSink, Dedup, Filter, Pipeline, Smooth};
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+///
+/// let mut csv = Vec::new();
+/// let events = Pipeline::new()
+///     .transform(Dedup)
+///     .transform(Filter(|e| e.power > 0.05)) // the compressor running
+///     .transform(Smooth(Duration::minutes(3)))
+///     .sink(CsvSink::new(&mut csv))
+///     .run(sample_data())
+///     .unwrap();
+/// assert_eq!(events.len(), 6);
+/// // A header, then a line for each reading
+/// assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 7);
+/// ```
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    transforms: Vec<Box<dyn EventTransform + 'a>>,
+    sinks: Vec<Box<dyn EventSink + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn transform(mut self, transform: impl EventTransform + 'a) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    pub fn sink(mut self, sink: impl EventSink + 'a) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    // Read the readings, transform them and write them out, stopping at the first error. Returns
+    // the readings as written, e.g. for the statistics.
+    pub fn run(self, mut source: impl EventSource) -> Result<Vec<PowerEvent>, PipelineError> {
+        let mut events = source.read_events()?;
+        for transform in &self.transforms {
+            transform.apply(&mut events);
+        }
+        for mut sink in self.sinks {
+            sink.write_events(&events)?;
+        }
+        Ok(events)
+    }
+}
+
+// Reasons a pipeline can't run through
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("cannot decode the data: {0}")]
+    Parse(#[from] ParseError),
+    #[error("cannot load the folder: {0}")]
+    Dataset(#[from] DatasetError),
+    #[error("cannot write the readings: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl From<csv::Error> for PipelineError {
+    fn from(error: csv::Error) -> Self {
+        PipelineError::Io(error.into())
+    }
+}
+
+// Where the readings of a pipeline come from
+pub trait EventSource {
+    // The readings, in chronological order
+    fn read_events(&mut self) -> Result<Vec<PowerEvent>, PipelineError>;
+}
+
+// A step turning the readings into others (fewer, smoothed, ...), still in chronological order
+pub trait EventTransform {
+    fn apply(&self, events: &mut Vec<PowerEvent>);
+}
+
+// Where the readings of a pipeline go
+pub trait EventSink {
+    fn write_events(&mut self, events: &[PowerEvent]) -> Result<(), PipelineError>;
+}
+
+// A sink borrowed for the run, e.g. to read what it collected afterwards
+impl<S: EventSink + ?Sized> EventSink for &mut S {
+    fn write_events(&mut self, events: &[PowerEvent]) -> Result<(), PipelineError> {
+        (**self).write_events(events)
+    }
+}
+
+// Readings already in memory, handed over as they are
+impl EventSource for Vec<PowerEvent> {
+    fn read_events(&mut self) -> Result<Vec<PowerEvent>, PipelineError> {
+        Ok(std::mem::take(self))
+    }
+}
+
+// A data file read beforehand, decoded with the default options
+impl EventSource for VoltcraftData {
+    fn read_events(&mut self) -> Result<Vec<PowerEvent>, PipelineError> {
+        Ok(self.parse()?.0)
+    }
+}
+
+// A data file, decoded as set (by default as the command line tool does)
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    path: PathBuf,
+    parse_options: ParseOptions,
+    max_file_size: u64, // bytes, larger files are rejected
+}
+
+impl FileSource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileSource {
+            path: path.as_ref().to_path_buf(),
+            parse_options: ParseOptions::new(),
+            max_file_size: 1024 * 1024,
+        }
+    }
+
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.parse_options = options;
+        self
+    }
+
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+}
+
+impl EventSource for FileSource {
+    fn read_events(&mut self) -> Result<Vec<PowerEvent>, PipelineError> {
+        let data = VoltcraftData::from_file(&self.path, self.max_file_size)?;
+        Ok(data.parse_with(&self.parse_options)?.0)
+    }
+}
+
+// A data file read from anywhere (a pipe, a socket), once
+pub struct ReaderSource<R: Read> {
+    reader: R,
+    parse_options: ParseOptions,
+    max_file_size: u64, // bytes, larger files are rejected
+}
+
+impl<R: Read> ReaderSource<R> {
+    pub fn new(reader: R) -> Self {
+        ReaderSource {
+            reader,
+            parse_options: ParseOptions::new(),
+            max_file_size: 1024 * 1024,
+        }
+    }
+
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.parse_options = options;
+        self
+    }
+
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+}
+
+impl<R: Read> EventSource for ReaderSource<R> {
+    fn read_events(&mut self) -> Result<Vec<PowerEvent>, PipelineError> {
+        let data = VoltcraftData::from_reader(&mut self.reader, self.max_file_size)?;
+        Ok(data.parse_with(&self.parse_options)?.0)
+    }
+}
+
+// The data files of a folder, merged as VoltcraftDataset::from_dir_with does (the files that
+// can't be decoded are left out, as there)
+#[derive(Debug, Clone)]
+pub struct DirSource {
+    path: PathBuf,
+    options: DatasetOptions,
+}
+
+impl DirSource {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        DirSource {
+            path: path.as_ref().to_path_buf(),
+            options: DatasetOptions::new(),
+        }
+    }
+
+    pub fn options(mut self, options: DatasetOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+impl EventSource for DirSource {
+    fn read_events(&mut self) -> Result<Vec<PowerEvent>, PipelineError> {
+        Ok(VoltcraftDataset::from_dir_with(&self.path, &self.options)?.events)
+    }
+}
+
+// A single reading for each minute, the first (see remove_duplicates)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Dedup;
+
+impl EventTransform for Dedup {
+    fn apply(&self, events: &mut Vec<PowerEvent>) {
+        remove_duplicates(events);
+    }
+}
+
+// No power below the threshold (kW), as --deadband (see apply_deadband)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Deadband(pub f64);
+
+impl EventTransform for Deadband {
+    fn apply(&self, events: &mut Vec<PowerEvent>) {
+        apply_deadband(events, self.0);
+    }
+}
+
+// The short gaps filled in, as --fill-gaps (see fill_gaps)
+impl EventTransform for GapFilling {
+    fn apply(&self, events: &mut Vec<PowerEvent>) {
+        fill_gaps(events, *self);
+    }
+}
+
+// The readings of the given days left out, as --exclude (see exclude_periods)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exclude(pub Vec<ExcludedPeriod>);
+
+impl EventTransform for Exclude {
+    fn apply(&self, events: &mut Vec<PowerEvent>) {
+        exclude_periods(events, &self.0);
+    }
+}
+
+// A centered rolling mean over a window of the given length, as --smooth (see smooth)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Smooth(pub Duration);
+
+impl EventTransform for Smooth {
+    fn apply(&self, events: &mut Vec<PowerEvent>) {
+        *events = smooth(events, self.0);
+    }
+}
+
+// A reading for each interval of the given length, at its start, averaging those of the interval
+// (see resample). As the statistics take each reading for a minute, this is for exports and plots.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Resample(Duration);
+
+impl Resample {
+    // None if the interval is shorter than a second
+    pub fn new(interval: Duration) -> Option<Self> {
+        (interval.num_seconds() > 0).then_some(Resample(interval))
+    }
+}
+
+impl EventTransform for Resample {
+    fn apply(&self, events: &mut Vec<PowerEvent>) {
+        *events = resample(events, self.0)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| PowerEvent {
+                timestamp: r.start,
+                voltage: r.avg_voltage,
+                current: r.avg_current,
+                power_factor: if r.avg_apparent_power > 0.0 {
+                    r.avg_power / r.avg_apparent_power
+                } else {
+                    0.0
+                },
+                power: r.avg_power,
+                apparent_power: r.avg_apparent_power,
+                source: None,
+                registers: None,
+                filled: false,
+            })
+            .collect();
+    }
+}
+
+// Only the readings the predicate holds for, e.g. Filter(|e| e.timestamp.hour() < 6)
+#[derive(Debug, Copy, Clone)]
+pub struct Filter<F: Fn(&PowerEvent) -> bool>(pub F);
+
+impl<F: Fn(&PowerEvent) -> bool> EventTransform for Filter<F> {
+    fn apply(&self, events: &mut Vec<PowerEvent>) {
+        events.retain(|e| (self.0)(e));
+    }
+}
+
+// The readings collected, added to those already there
+impl EventSink for Vec<PowerEvent> {
+    fn write_events(&mut self, events: &[PowerEvent]) -> Result<(), PipelineError> {
+        self.extend_from_slice(events);
+        Ok(())
+    }
+}
+
+// The readings as CSV, one line a minute, as voltcraft_history.csv: the quantities measured, and
+// on demand the derived ones and whether each reading was filled in
+pub struct CsvSink<W: Write> {
+    writer: csv::Writer<W>,
+    derived: bool,
+    filled: bool,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        CsvSink {
+            writer: csv::Writer::from_writer(writer),
+            derived: false,
+            filled: false,
+        }
+    }
+
+    // Add the reactive power, phase angle and tan phi of each reading (see PowerEvent::derived)
+    pub fn derived(mut self, derived: bool) -> Self {
+        self.derived = derived;
+        self
+    }
+
+    // Add whether each reading was filled in (see fill_gaps)
+    pub fn filled(mut self, filled: bool) -> Self {
+        self.filled = filled;
+        self
+    }
+}
+
+impl CsvSink<File> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, PipelineError> {
+        Ok(CsvSink::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> EventSink for CsvSink<W> {
+    fn write_events(&mut self, events: &[PowerEvent]) -> Result<(), PipelineError> {
+        let mut header = vec![
+            "Timestamp",
+            "Voltage (V)",
+            "Current (A)",
+            "cosPHI",
+            "Active Power (kW)",
+            "Apparent Power (kVA)",
+        ];
+        if self.derived {
+            header.extend(["Reactive Power (kvar)", "Phase Angle (deg)", "tanPHI"]);
+        }
+        if self.filled {
+            header.push("Filled");
+        }
+        self.writer.write_record(&header)?;
+        for pe in events {
+            let mut record = vec![
+                pe.timestamp.format("%Y-%m-%d %H:%M").to_string(),
+                pe.voltage.to_string(),
+                pe.current.to_string(),
+                pe.power_factor.to_string(),
+                pe.power.to_string(),
+                pe.apparent_power.to_string(),
+            ];
+            if self.derived {
+                let derived = pe.derived();
+                record.extend([
+                    format!("{:.4}", derived.reactive_power),
+                    format!("{:.2}", derived.phase_angle),
+                    derived
+                        .tan_phi
+                        .map_or(String::new(), |tan_phi| format!("{:.3}", tan_phi)),
+                ]);
+            }
+            if self.filled {
+                record.push(pe.filled.to_string());
+            }
+            self.writer.write_record(&record)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// The readings as text, one line a minute laid out as set, as voltcraft_history.txt
+pub struct TextSink<W: Write> {
+    writer: W,
+    layout: HistoryLayout,
+}
+
+impl<W: Write> TextSink<W> {
+    pub fn new(writer: W) -> Self {
+        TextSink {
+            writer,
+            layout: HistoryLayout::default(),
+        }
+    }
+
+    pub fn layout(mut self, layout: HistoryLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+
+impl TextSink<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, PipelineError> {
+        Ok(TextSink::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> EventSink for TextSink<W> {
+    fn write_events(&mut self, events: &[PowerEvent]) -> Result<(), PipelineError> {
+        let f = &mut self.writer;
+        writeln!(f, "== PARAMETER HISTORY ==")?;
+        writeln!(f)?;
+        for pe in events {
+            write!(f, "{}", pe.timestamp.format(&self.layout.timestamp_format))?;
+            for column in &self.layout.columns {
+                write!(f, " {}", column.format(pe))?;
+            }
+            // Whatever the layout, as the reading is an estimate (see fill_gaps)
+            if pe.filled {
+                write!(f, " FILLED")?;
+            }
+            writeln!(f)?;
+        }
+        f.flush()?;
+        Ok(())
+    }
+}
+
+// A quantity of the text parameter history
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HistoryColumn {
+    Voltage,
+    Current,
+    PowerFactor,
+    Power,
+    ApparentPower,
+    ReactivePower,
+    PhaseAngle,
+    TanPhi,
+    Registers,
+}
+
+impl HistoryColumn {
+    // The quantities derived from the measured ones (see PowerEvent::derived)
+    pub const DERIVED: [HistoryColumn; 3] = [
+        HistoryColumn::ReactivePower,
+        HistoryColumn::PhaseAngle,
+        HistoryColumn::TanPhi,
+    ];
+
+    pub const ALL: [HistoryColumn; 9] = [
+        HistoryColumn::Voltage,
+        HistoryColumn::Current,
+        HistoryColumn::PowerFactor,
+        HistoryColumn::Power,
+        HistoryColumn::ApparentPower,
+        HistoryColumn::ReactivePower,
+        HistoryColumn::PhaseAngle,
+        HistoryColumn::TanPhi,
+        HistoryColumn::Registers,
+    ];
+
+    fn format(&self, pe: &PowerEvent) -> String {
+        match self {
+            HistoryColumn::Voltage => format!("U={:.1}V", pe.voltage),
+            HistoryColumn::Current => format!("I={:.3}A", pe.current),
+            HistoryColumn::PowerFactor => format!("cosPHI={:.2}", pe.power_factor),
+            HistoryColumn::Power => format!("P={:.3}kW", pe.power),
+            HistoryColumn::ApparentPower => format!("S={:.3}kVA", pe.apparent_power),
+            HistoryColumn::ReactivePower => format!("Q={:.3}kvar", pe.derived().reactive_power),
+            HistoryColumn::PhaseAngle => format!("PHI={:.1}deg", pe.derived().phase_angle),
+            HistoryColumn::TanPhi => match pe.derived().tan_phi {
+                Some(tan_phi) => format!("tanPHI={:.2}", tan_phi),
+                None => String::from("tanPHI=-"),
+            },
+            // Voltage, current and power factor as stored, to audit the decoder
+            HistoryColumn::Registers => match pe.registers {
+                Some(r) => format!("RAW={}/{}/{}", r.voltage, r.current, r.power_factor),
+                None => String::from("RAW=-"),
+            },
+        }
+    }
+}
+
+impl FromStr for HistoryColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HistoryColumn::ALL
+            .into_iter()
+            .find(|c| c.to_string() == s.trim().to_ascii_lowercase())
+            .ok_or_else(|| {
+                format!(
+                    "Invalid history column '{}' (expected voltage, current, cosphi, power, apparent-power, reactive-power, phase-angle, tanphi or registers)",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for HistoryColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistoryColumn::Voltage => write!(f, "voltage"),
+            HistoryColumn::Current => write!(f, "current"),
+            HistoryColumn::PowerFactor => write!(f, "cosphi"),
+            HistoryColumn::Power => write!(f, "power"),
+            HistoryColumn::ApparentPower => write!(f, "apparent-power"),
+            HistoryColumn::ReactivePower => write!(f, "reactive-power"),
+            HistoryColumn::PhaseAngle => write!(f, "phase-angle"),
+            HistoryColumn::TanPhi => write!(f, "tanphi"),
+            HistoryColumn::Registers => write!(f, "registers"),
+        }
+    }
+}
+
+// How each reading is written to the text parameter history, e.g. to match the scripts reading it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryLayout {
+    pub timestamp_format: String, // strftime-like format of the timestamp (see chrono::format)
+    pub columns: Vec<HistoryColumn>, // quantities following the timestamp, in order
+}
+
+impl Default for HistoryLayout {
+    fn default() -> Self {
+        HistoryLayout {
+            timestamp_format: String::from("[%Y-%m-%d %H:%M]"),
+            // The quantities measured; the derived ones and the values as stored only on demand
+            columns: vec![
+                HistoryColumn::Voltage,
+                HistoryColumn::Current,
+                HistoryColumn::PowerFactor,
+                HistoryColumn::Power,
+                HistoryColumn::ApparentPower,
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voltcraft::sample::sample_data;
+    use chrono::Timelike;
+
+    #[test]
+    fn pipelines() {
+        let (events, _) = sample_data().parse().unwrap();
+        let mut repeated = events.clone();
+        repeated.insert(1, events[0]);
+
+        // Sources, as decoded or merged
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/sample_data1");
+        let merged = DirSource::new(path).read_events().unwrap();
+        let dataset = VoltcraftDataset::from_dir(path).unwrap();
+        assert_eq!(merged.len(), dataset.events.len());
+        let read = ReaderSource::new(sample_data().as_bytes())
+            .read_events()
+            .unwrap();
+        assert_eq!(read.len(), events.len());
+        assert!(matches!(
+            ReaderSource::new(&b"not a data file"[..]).read_events(),
+            Err(PipelineError::Parse(ParseError::InvalidHeader))
+        ));
+        assert!(matches!(
+            FileSource::new("missing.bin").read_events(),
+            Err(PipelineError::Parse(ParseError::Unreadable))
+        ));
+
+        // Transforms in order, written to every sink
+        let mut collected = Vec::new();
+        let mut text = Vec::new();
+        let written = Pipeline::new()
+            .transform(Dedup)
+            .transform(Filter(|e| e.timestamp.minute() != 46))
+            .transform(Resample::new(Duration::minutes(5)).unwrap())
+            .sink(&mut collected)
+            .sink(TextSink::new(&mut text).layout(HistoryLayout {
+                timestamp_format: String::from("%H:%M"),
+                columns: vec![HistoryColumn::Power],
+            }))
+            .run(repeated)
+            .unwrap();
+        assert_eq!(collected.len(), 3);
+        assert_eq!(Resample::new(Duration::seconds(30) / 60), None);
+        assert_eq!(written.len(), collected.len());
+        let text = String::from_utf8(text).unwrap();
+        let lines = text.lines().skip(2).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            ["18:40 P=0.088kW", "18:45 P=0.030kW", "18:55 P=0.060kW"]
+        );
+
+        // The transforms of the command line options
+        let mut zeroed = events.clone();
+        Deadband(0.01).apply(&mut zeroed);
+        assert_eq!(zeroed.iter().filter(|e| e.power == 0.0).count(), 3);
+        let mut filled = events.clone();
+        "10:zero".parse::<GapFilling>().unwrap().apply(&mut filled);
+        assert_eq!(filled.len(), events.len() + 6);
+        let mut excluded = events;
+        Exclude(vec!["2014-09-11".parse().unwrap()]).apply(&mut excluded);
+        assert!(excluded.is_empty());
+    }
+}
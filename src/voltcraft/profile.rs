@@ -0,0 +1,69 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Typical power draw and operating cycles of a kind of appliance, used as defaults by the
+// analyses that need to tell the appliance states apart
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ApplianceProfile {
+    pub name: &'static str,
+    pub standby_power: f64, // W, readings below are sensor noise or standby
+    pub running_power: f64, // W, the appliance is running above
+    pub max_power: f64,     // W, readings above are anomalies (faults, another load on the socket)
+    pub min_cycle: i64,     // minutes, shorter runs are start-up spikes rather than cycles
+    pub typical_cycle: (i64, i64), // minutes, usual range of a cycle duration
+    pub shiftable: bool,    // cycles can be scheduled (started at another time of day)
+}
+
+pub const APPLIANCE_PROFILES: [ApplianceProfile; 3] = [
+    ApplianceProfile {
+        name: "fridge",
+        standby_power: 2.0,
+        running_power: 40.0,
+        max_power: 400.0, // compressor start-up
+        min_cycle: 3,
+        typical_cycle: (10, 60),
+        shiftable: false, // runs on its thermostat
+    },
+    ApplianceProfile {
+        name: "washing-machine",
+        standby_power: 3.0,
+        running_power: 50.0,
+        max_power: 2500.0, // water heating
+        min_cycle: 15,
+        typical_cycle: (30, 180),
+        shiftable: true,
+    },
+    ApplianceProfile {
+        name: "ev-charger",
+        standby_power: 5.0,
+        running_power: 1000.0,
+        max_power: 3700.0, // 16A on a single 230V phase
+        min_cycle: 10,
+        typical_cycle: (60, 600),
+        shiftable: true,
+    },
+];
+
+impl FromStr for ApplianceProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        APPLIANCE_PROFILES
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "Invalid appliance '{}' (expected {})",
+                    s,
+                    APPLIANCE_PROFILES.map(|p| p.name).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for ApplianceProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
@@ -0,0 +1,164 @@
+use crate::voltcraft::data::PowerEvent;
+use chrono::{DateTime, Duration, FixedOffset};
+
+/// The readings of an interval (e.g. a quarter of an hour) aggregated into one, as given by
+/// [`resample`].
+///
+/// ```
+/// use chrono::Duration;
+/// use voltcraft_energy_analyzer::voltcraft::resample::resample;
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+///
+/// let (events, _) = sample_data().parse().unwrap();
+/// // Readings from 18:43 to 18:48 and from 18:55 to 18:57
+/// let resampled = resample(&events, Duration::minutes(5)).unwrap();
+/// assert_eq!(resampled.len(), 3);
+/// assert_eq!(resampled[0].start.format("%H:%M").to_string(), "18:40");
+/// assert_eq!(resampled.iter().map(|r| r.readings).sum::<usize>(), events.len());
+/// // The energy is kept: each reading stands for a minute
+/// let energy = events.iter().map(|e| e.power).sum::<f64>() / 60.0;
+/// let resampled_energy = resampled.iter().map(|r| r.energy).sum::<f64>();
+/// assert!((resampled_energy - energy).abs() < 1e-12);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResampledEvent {
+    pub start: DateTime<FixedOffset>, // of the interval, on the logger clock
+    pub readings: usize,              // in the interval, at most one a minute
+    pub avg_voltage: f64,             // volts
+    pub avg_current: f64,             // ampers
+    pub avg_power: f64,               // kW, over the minutes with readings
+    pub max_power: f64,               // kW, highest reading
+    pub avg_apparent_power: f64,      // kVA
+    pub avg_reactive_power: f64,      // kvar (see PowerEvent::derived)
+    pub energy: f64,                  // kWh, each reading standing for a minute
+}
+
+// Aggregate chronological readings into intervals of the given length (e.g. 5, 15 or 60 minutes),
+// aligned on the logger clock: 15 minute intervals start at :00, :15, :30 and :45, and daily ones
+// at midnight. Intervals without readings are left out. When the clocks go back, the hour read
+// twice gives intervals of its own for each UTC offset. None if the interval is shorter than a
+// second.
+pub fn resample(events: &[PowerEvent], interval: Duration) -> Option<Vec<ResampledEvent>> {
+    let step = interval.num_seconds();
+    if step <= 0 {
+        return None;
+    }
+    let mut resampled: Vec<ResampledEvent> = Vec::new();
+    let mut start_key = None;
+    for e in events {
+        let offset = e.timestamp.offset().local_minus_utc() as i64;
+        let local = e.timestamp.timestamp() + offset;
+        let key = (local.div_euclid(step), offset);
+        match resampled.last_mut() {
+            Some(last) if start_key == Some(key) => {
+                last.avg_voltage += e.voltage;
+                last.avg_current += e.current;
+                last.avg_power += e.power;
+                last.max_power = last.max_power.max(e.power);
+                last.avg_apparent_power += e.apparent_power;
+                last.avg_reactive_power += e.derived().reactive_power;
+                last.readings += 1;
+            }
+            _ => {
+                start_key = Some(key);
+                resampled.push(ResampledEvent {
+                    start: e.timestamp - Duration::seconds(local.rem_euclid(step)),
+                    readings: 1,
+                    avg_voltage: e.voltage,
+                    avg_current: e.current,
+                    avg_power: e.power,
+                    max_power: e.power,
+                    avg_apparent_power: e.apparent_power,
+                    avg_reactive_power: e.derived().reactive_power,
+                    energy: 0.0,
+                });
+            }
+        }
+    }
+    // The sums become averages
+    for r in &mut resampled {
+        let n = r.readings as f64;
+        r.energy = r.avg_power / 60.0;
+        r.avg_voltage /= n;
+        r.avg_current /= n;
+        r.avg_power /= n;
+        r.avg_apparent_power /= n;
+        r.avg_reactive_power /= n;
+    }
+    Some(resampled)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voltcraft::data::PowerEvent;
+    use crate::voltcraft::resample::resample;
+    use chrono::{Duration, FixedOffset, TimeZone};
+
+    fn event(offset: i32, hour: u32, minute: u32, power: f64) -> PowerEvent {
+        PowerEvent {
+            timestamp: FixedOffset::east_opt(offset * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2014, 10, 26, hour, minute, 0)
+                .unwrap(),
+            voltage: 230.0 + power,
+            current: power * 4.0,
+            power_factor: 0.8,
+            power,
+            apparent_power: power * 1.25,
+            source: None,
+            registers: None,
+            filled: false,
+        }
+    }
+
+    #[test]
+    fn resampling() {
+        let events = vec![
+            event(3, 2, 58, 1.0),
+            event(3, 3, 0, 2.0),
+            event(3, 3, 7, 4.0),
+            event(3, 3, 14, 0.0),
+            event(3, 3, 15, 3.0),
+        ];
+        let resampled = resample(&events, Duration::minutes(15)).unwrap();
+        assert_eq!(resampled.len(), 3);
+        let quarter = resampled[1];
+        assert_eq!(quarter.start, events[1].timestamp);
+        assert_eq!(quarter.readings, 3);
+        assert_eq!(quarter.avg_power, 2.0);
+        assert_eq!(quarter.max_power, 4.0);
+        assert_eq!(quarter.avg_voltage, 232.0);
+        assert_eq!(quarter.avg_current, 8.0);
+        assert_eq!(quarter.energy, 0.1);
+        assert_eq!(quarter.avg_apparent_power, 2.5);
+        assert_eq!(quarter.avg_reactive_power, 1.5);
+        assert_eq!(resampled[0].start, event(3, 2, 45, 0.0).timestamp);
+        assert_eq!(resampled[2].start, events[4].timestamp);
+
+        // Hourly, on the day the clocks went back: 03:00-03:59 is read twice
+        let mut events = events;
+        events.push(event(2, 3, 1, 5.0));
+        let resampled = resample(&events, Duration::hours(1)).unwrap();
+        let starts = resampled
+            .iter()
+            .map(|r| (r.start.to_rfc3339(), r.readings))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            starts,
+            [
+                (String::from("2014-10-26T02:00:00+03:00"), 1),
+                (String::from("2014-10-26T03:00:00+03:00"), 4),
+                (String::from("2014-10-26T03:00:00+02:00"), 1)
+            ]
+        );
+        assert_eq!(resample(&[], Duration::minutes(5)), Some(Vec::new()));
+        for interval in [
+            Duration::zero(),
+            Duration::milliseconds(999),
+            Duration::minutes(-5),
+        ] {
+            assert_eq!(resample(&events, interval), None, "{}", interval);
+        }
+    }
+}
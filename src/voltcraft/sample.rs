@@ -0,0 +1,31 @@
+use crate::voltcraft::data::VoltcraftData;
+
+// A miniature recording of a fridge, embedded for the examples and documentation: two data blocks,
+// 2014-09-11 18:43-18:48 and 18:55-18:57 (the logger clock, in local time), around a 6 minute blackout
+#[rustfmt::skip]
+pub const SAMPLE_FILE: &[u8] = &[
+    // Header (magic number), also starting the first data block
+    0xE0, 0xC5, 0xEA,
+    // Timestamp: month, day, year, hour, minute
+    0x09, 0x0B, 0x0E, 0x12, 0x2B,
+    // Power data: voltage, current, power factor (compressor running, then idle)
+    0x08, 0xC6, 0x01, 0xBE, 0x57,
+    0x08, 0xCB, 0x01, 0xC4, 0x58,
+    0x08, 0xBF, 0x01, 0xC8, 0x57,
+    0x08, 0xC2, 0x01, 0xB9, 0x56,
+    0x08, 0xCF, 0x00, 0x0C, 0x1E,
+    0x08, 0xC9, 0x00, 0x0A, 0x1C,
+    // Second data block, after the blackout
+    0xE0, 0xC5, 0xEA,
+    0x09, 0x0B, 0x0E, 0x12, 0x37,
+    0x08, 0xBE, 0x00, 0x0B, 0x1D,
+    0x08, 0xC4, 0x01, 0xD6, 0x58,
+    0x08, 0xCA, 0x01, 0xC0, 0x57,
+    // End of power data
+    0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+// The sample recording, ready to be decoded
+pub fn sample_data() -> VoltcraftData {
+    VoltcraftData::from_raw(SAMPLE_FILE.to_vec())
+}
@@ -0,0 +1,169 @@
+use crate::voltcraft::data::{ParseError, VoltcraftData};
+use fatfs::{FileSystem, FsOptions};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const SECTOR_SIZE: u64 = 512;
+// MBR partition types of the FAT file systems found on SD cards
+const FAT_PARTITION_TYPES: [u8; 6] = [0x01, 0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+// A raw image of the logger SD card (e.g. made with dd), read without mounting it
+pub struct SdCardImage {
+    fs: FileSystem<Partition>,
+}
+
+impl SdCardImage {
+    // Open the FAT file system of the image, either covering the whole image or in the first FAT
+    // partition of its MBR. The image is never written to.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<SdCardImage> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut sector = [0u8; SECTOR_SIZE as usize];
+        file.read_exact(&mut sector)?;
+        let (offset, len) = partition(&sector).unwrap_or((0, len));
+        let fs = FileSystem::new(Partition::new(file, offset, len)?, FsOptions::new())?;
+        Ok(SdCardImage { fs })
+    }
+
+    // Names of the logger data files in the root folder of the card, in download order
+    pub fn data_files(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in self.fs.root_dir().iter() {
+            let entry = entry?;
+            let name = entry.file_name();
+            if entry.is_file() && name.to_ascii_uppercase().ends_with(".BIN") {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    // Read one of the data files, like VoltcraftData::from_file
+    pub fn read(&self, name: &str, max_size: u64) -> Result<VoltcraftData, ParseError> {
+        let file = self
+            .fs
+            .root_dir()
+            .open_file(name)
+            .map_err(|_| ParseError::Unreadable)?;
+        VoltcraftData::from_reader(file, max_size)
+    }
+}
+
+// Offset and length (bytes) of the first FAT partition, if the sector is an MBR rather than the
+// boot sector of a file system covering the whole card
+fn partition(sector: &[u8; SECTOR_SIZE as usize]) -> Option<(u64, u64)> {
+    // Boot sectors start with a jump instruction, MBRs with code that never does
+    if sector[510..] != [0x55, 0xAA] || matches!(sector[0], 0xEB | 0xE9) {
+        return None;
+    }
+    sector[446..510].chunks(16).find_map(|entry| {
+        let start = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64;
+        let sectors = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as u64;
+        (FAT_PARTITION_TYPES.contains(&entry[4]) && sectors > 0)
+            .then_some((start * SECTOR_SIZE, sectors * SECTOR_SIZE))
+    })
+}
+
+// Read-only window on the partition holding the file system
+struct Partition {
+    file: File,
+    offset: u64,
+    len: u64,
+}
+
+impl Partition {
+    fn new(mut file: File, offset: u64, len: u64) -> io::Result<Partition> {
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Partition { file, offset, len })
+    }
+}
+
+impl Read for Partition {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = (self.offset + self.len).saturating_sub(self.file.stream_position()?);
+        let n = buf.len().min(left as usize);
+        self.file.read(&mut buf[..n])
+    }
+}
+
+impl Seek for Partition {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => {
+                (self.file.stream_position()? - self.offset).checked_add_signed(n)
+            }
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+        };
+        let target = target.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.file.seek(SeekFrom::Start(self.offset + target))? - self.offset)
+    }
+}
+
+// The file system only writes when files are modified, which never happens here
+impl Write for Partition {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "SD card images are read-only",
+        ))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fatfs::FormatVolumeOptions;
+    use std::io::Cursor;
+
+    // A 1 MiB FAT volume holding a data file and a stray text file
+    fn volume(data: &[u8]) -> Vec<u8> {
+        let mut volume = Cursor::new(vec![0u8; 1024 * 1024]);
+        fatfs::format_volume(&mut volume, FormatVolumeOptions::new()).unwrap();
+        {
+            let fs = FileSystem::new(&mut volume, FsOptions::new()).unwrap();
+            fs.root_dir()
+                .create_file("A04FC8D2.BIN")
+                .unwrap()
+                .write_all(data)
+                .unwrap();
+            fs.root_dir()
+                .create_file("README.TXT")
+                .unwrap()
+                .write_all(b"not a data file")
+                .unwrap();
+        }
+        volume.into_inner()
+    }
+
+    fn check_image(image: &[u8], data: &[u8]) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("card.img");
+        std::fs::write(&path, image).unwrap();
+        let card = SdCardImage::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(card.data_files().unwrap(), ["A04FC8D2.BIN"]);
+        let vd = card.read("A04FC8D2.BIN", 1024 * 1024).unwrap();
+        assert_eq!(vd.as_bytes(), data);
+    }
+
+    #[test]
+    fn sdcard_image() {
+        let data = crate::voltcraft::sample::SAMPLE_FILE;
+        // File system covering the whole card
+        check_image(&volume(data), data);
+        // Partitioned card, the file system starting at the second sector
+        let mut image = vec![0u8; SECTOR_SIZE as usize];
+        image[446 + 4] = 0x06;
+        image[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+        image[446 + 12..446 + 16].copy_from_slice(&2048u32.to_le_bytes());
+        image[510..].copy_from_slice(&[0x55, 0xAA]);
+        image.extend(volume(data));
+        check_image(&image, data);
+    }
+}
@@ -0,0 +1,494 @@
+use crate::voltcraft::data::{
+    Calibration, Correction, ParseDiagnostic, ParseError, ParseOptions, ParseReport, PowerEvent,
+    RecordingSession, SourceId,
+};
+use crate::voltcraft::raw::Registers;
+use chrono::{DateTime, Duration, FixedOffset};
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+const MAGIC: &[u8; 8] = b"VCSTATE\0";
+const VERSION: u32 = 3;
+
+/// The files decoded by a previous run, kept in a state file so that a growing archive is only
+/// decoded where it changed: a file whose content hash is unchanged is added to the next dataset
+/// with its cached report and readings (see `DatasetBuilder::add_decoded`). A state is only valid
+/// for the parse options it was made with; loaded with other options, it is empty.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::data::ParseOptions;
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+/// use voltcraft_energy_analyzer::voltcraft::state::{content_hash, CachedFile, DatasetState};
+///
+/// let options = ParseOptions::new();
+/// let data = sample_data();
+/// let (events, report) = data.parse_with(&options).unwrap();
+/// let mut state = DatasetState::new(&options);
+/// let hash = content_hash(data.as_bytes());
+/// state.insert(CachedFile {
+///     name: String::from("A04FC8D2.BIN"),
+///     hash,
+///     report,
+///     events,
+/// });
+/// assert_eq!(state.take("A04FC8D2.BIN", hash).unwrap().events.len(), 9);
+/// assert!(state.take("A04FC8D2.BIN", hash).is_none());
+/// ```
+#[derive(Debug, Clone)]
+pub struct DatasetState {
+    fingerprint: u64, // of the parse options
+    files: Vec<CachedFile>,
+    import_log: Option<ImportLog>,
+}
+
+// When readings newer than those known were last imported, to tell that the logger data is no
+// longer downloaded (e.g. the SD card was forgotten in the logger)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ImportLog {
+    pub latest_reading: DateTime<FixedOffset>, // of all the runs
+    pub imported_at: DateTime<FixedOffset>,    // time of the run that imported it
+}
+
+impl ImportLog {
+    // The log after a run at the given time whose readings end at latest_reading
+    pub fn update(
+        previous: Option<ImportLog>,
+        latest_reading: DateTime<FixedOffset>,
+        now: DateTime<FixedOffset>,
+    ) -> ImportLog {
+        match previous {
+            Some(previous) if previous.latest_reading >= latest_reading => previous,
+            _ => ImportLog {
+                latest_reading,
+                imported_at: now,
+            },
+        }
+    }
+
+    // Time since new readings were last imported
+    pub fn staleness(&self, now: DateTime<FixedOffset>) -> Duration {
+        now - self.imported_at
+    }
+}
+
+// A file as decoded by a previous run
+#[derive(Debug, Clone)]
+pub struct CachedFile {
+    pub name: String,
+    pub hash: u64, // of the content of the file (see content_hash)
+    pub report: ParseReport,
+    pub events: Vec<PowerEvent>,
+}
+
+// Reasons a state file can't be loaded
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("cannot read the state file: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a state file, or one written by another version")]
+    Invalid,
+}
+
+impl DatasetState {
+    pub fn new(options: &ParseOptions) -> Self {
+        DatasetState {
+            fingerprint: fingerprint(options),
+            files: Vec::new(),
+            import_log: None,
+        }
+    }
+
+    // Load a state file, without the files if it was made with other parse options (the import
+    // log still holds)
+    pub fn load(path: impl AsRef<Path>, options: &ParseOptions) -> Result<Self, StateError> {
+        let bytes = fs::read(path)?;
+        let state = decode(&bytes).ok_or(StateError::Invalid)?;
+        if state.fingerprint == fingerprint(options) {
+            Ok(state)
+        } else {
+            Ok(DatasetState {
+                import_log: state.import_log,
+                ..DatasetState::new(options)
+            })
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+
+    pub fn files(&self) -> &[CachedFile] {
+        &self.files
+    }
+
+    pub fn import_log(&self) -> Option<ImportLog> {
+        self.import_log
+    }
+
+    pub fn set_import_log(&mut self, log: ImportLog) {
+        self.import_log = Some(log);
+    }
+
+    // Replaces the file of the same name, if any
+    pub fn insert(&mut self, file: CachedFile) {
+        self.files.retain(|f| f.name != file.name);
+        self.files.push(file);
+    }
+
+    // Remove the file of the given name, if its content is unchanged
+    pub fn take(&mut self, name: &str, hash: u64) -> Option<CachedFile> {
+        let index = self
+            .files
+            .iter()
+            .position(|f| f.name == name && f.hash == hash)?;
+        Some(self.files.swap_remove(index))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Vec::new();
+        w.extend_from_slice(MAGIC);
+        w.extend_from_slice(&VERSION.to_le_bytes());
+        put_u64(&mut w, self.fingerprint);
+        match self.import_log {
+            Some(log) => {
+                w.push(1);
+                put_timestamp(&mut w, log.latest_reading);
+                put_timestamp(&mut w, log.imported_at);
+            }
+            None => w.push(0),
+        }
+        put_u64(&mut w, self.files.len() as u64);
+        for file in &self.files {
+            put_u64(&mut w, file.name.len() as u64);
+            w.extend_from_slice(file.name.as_bytes());
+            put_u64(&mut w, file.hash);
+            encode_report(&mut w, &file.report);
+            put_u64(&mut w, file.events.len() as u64);
+            for event in &file.events {
+                put_timestamp(&mut w, event.timestamp);
+                for value in [
+                    event.voltage,
+                    event.current,
+                    event.power_factor,
+                    event.power,
+                    event.apparent_power,
+                ] {
+                    put_u64(&mut w, value.to_bits());
+                }
+                match event.source {
+                    Some(source) => {
+                        w.push(1);
+                        put_u64(&mut w, source.file as u64);
+                        put_u64(&mut w, source.offset as u64);
+                    }
+                    None => w.push(0),
+                }
+                match event.registers {
+                    Some(registers) => {
+                        w.push(1);
+                        w.extend_from_slice(&registers.encode());
+                    }
+                    None => w.push(0),
+                }
+            }
+        }
+        w
+    }
+}
+
+// A hash of the content of a file (64-bit FNV-1a), stable across runs and builds
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// The readings cached depend on every parse option
+fn fingerprint(options: &ParseOptions) -> u64 {
+    content_hash(format!("{:?}", options.source_file(0)).as_bytes())
+}
+
+fn encode_report(w: &mut Vec<u8>, report: &ParseReport) {
+    for count in [
+        report.record_count,
+        report.power_factor_anomalies,
+        report.implausible_voltages,
+        report.implausible_currents,
+        report.dropped_samples,
+        report.stride,
+    ] {
+        put_u64(w, count as u64);
+    }
+    let Calibration {
+        voltage,
+        current,
+        power,
+    } = report.calibration;
+    for correction in [voltage, current, power] {
+        put_u64(w, correction.gain.to_bits());
+        put_u64(w, correction.offset.to_bits());
+    }
+    put_u64(w, report.diagnostics.len() as u64);
+    for diagnostic in &report.diagnostics {
+        put_u64(w, diagnostic.offset as u64);
+        put_u64(w, diagnostic.skipped as u64);
+        let (tag, a, b) = match diagnostic.error {
+            ParseError::Unreadable => (0, 0, 0),
+            ParseError::TooLarge { size, limit } => (1, size, limit),
+            ParseError::InvalidHeader => (2, 0, 0),
+            ParseError::UnexpectedEof { offset } => (3, offset as u64, 0),
+            ParseError::InvalidTimestamp { offset } => (4, offset as u64, 0),
+        };
+        w.push(tag);
+        put_u64(w, a);
+        put_u64(w, b);
+    }
+    put_u64(w, report.sessions.len() as u64);
+    for session in &report.sessions {
+        put_timestamp(w, session.start);
+        put_timestamp(w, session.end);
+        put_u64(w, session.event_count as u64);
+        put_u64(w, session.file_offset as u64);
+    }
+    put_u64(w, report.skipped_local_times.len() as u64);
+    for offset in &report.skipped_local_times {
+        put_u64(w, *offset as u64);
+    }
+    w.push(report.missing_end_marker as u8);
+}
+
+fn put_u64(w: &mut Vec<u8>, value: u64) {
+    w.extend_from_slice(&value.to_le_bytes());
+}
+
+// Seconds and nanoseconds since the epoch, and the UTC offset
+fn put_timestamp(w: &mut Vec<u8>, timestamp: DateTime<FixedOffset>) {
+    put_u64(w, timestamp.timestamp() as u64);
+    w.extend_from_slice(&timestamp.timestamp_subsec_nanos().to_le_bytes());
+    w.extend_from_slice(&timestamp.offset().local_minus_utc().to_le_bytes());
+}
+
+// Decoding stops at the first value missing or out of range, as the file is then truncated or
+// corrupted
+fn decode(bytes: &[u8]) -> Option<DatasetState> {
+    let mut r = Reader { bytes, offset: 0 };
+    if r.take::<8>()? != *MAGIC || u32::from_le_bytes(r.take()?) != VERSION {
+        return None;
+    }
+    let fingerprint = r.u64()?;
+    let import_log = match r.u8()? {
+        0 => None,
+        _ => Some(ImportLog {
+            latest_reading: r.timestamp()?,
+            imported_at: r.timestamp()?,
+        }),
+    };
+    let mut files = Vec::new();
+    for _ in 0..r.u64()? {
+        let length = r.usize()?;
+        let name = String::from_utf8(r.slice(length)?.to_vec()).ok()?;
+        let hash = r.u64()?;
+        let report = r.report()?;
+        let mut events = Vec::new();
+        for _ in 0..r.u64()? {
+            events.push(PowerEvent {
+                timestamp: r.timestamp()?,
+                voltage: r.f64()?,
+                current: r.f64()?,
+                power_factor: r.f64()?,
+                power: r.f64()?,
+                apparent_power: r.f64()?,
+                source: match r.u8()? {
+                    0 => None,
+                    _ => Some(SourceId {
+                        file: r.usize()?,
+                        offset: r.usize()?,
+                    }),
+                },
+                registers: match r.u8()? {
+                    0 => None,
+                    _ => Some(Registers::decode(r.take()?)),
+                },
+                filled: false,
+            });
+        }
+        files.push(CachedFile {
+            name,
+            hash,
+            report,
+            events,
+        });
+    }
+    (r.offset == bytes.len()).then_some(DatasetState {
+        fingerprint,
+        files,
+        import_log,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn slice(&mut self, length: usize) -> Option<&[u8]> {
+        let slice = self
+            .bytes
+            .get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(slice)
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.slice(N)?.try_into().ok()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take::<1>()?[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        self.u64()?.try_into().ok()
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_bits(self.u64()?))
+    }
+
+    fn timestamp(&mut self) -> Option<DateTime<FixedOffset>> {
+        let seconds = self.u64()? as i64;
+        let nanos = u32::from_le_bytes(self.take()?);
+        let offset = FixedOffset::east_opt(i32::from_le_bytes(self.take()?))?;
+        Some(DateTime::from_timestamp(seconds, nanos)?.with_timezone(&offset))
+    }
+
+    fn correction(&mut self) -> Option<Correction> {
+        Some(Correction {
+            gain: self.f64()?,
+            offset: self.f64()?,
+        })
+    }
+
+    fn report(&mut self) -> Option<ParseReport> {
+        let mut report = ParseReport {
+            record_count: self.usize()?,
+            power_factor_anomalies: self.usize()?,
+            implausible_voltages: self.usize()?,
+            implausible_currents: self.usize()?,
+            dropped_samples: self.usize()?,
+            stride: self.usize()?,
+            calibration: Calibration {
+                voltage: self.correction()?,
+                current: self.correction()?,
+                power: self.correction()?,
+            },
+            ..Default::default()
+        };
+        for _ in 0..self.u64()? {
+            let offset = self.usize()?;
+            let skipped = self.usize()?;
+            let (tag, a, b) = (self.u8()?, self.u64()?, self.u64()?);
+            let error = match tag {
+                0 => ParseError::Unreadable,
+                1 => ParseError::TooLarge { size: a, limit: b },
+                2 => ParseError::InvalidHeader,
+                3 => ParseError::UnexpectedEof {
+                    offset: a.try_into().ok()?,
+                },
+                4 => ParseError::InvalidTimestamp {
+                    offset: a.try_into().ok()?,
+                },
+                _ => return None,
+            };
+            report.diagnostics.push(ParseDiagnostic {
+                offset,
+                skipped,
+                error,
+            });
+        }
+        for _ in 0..self.u64()? {
+            report.sessions.push(RecordingSession {
+                start: self.timestamp()?,
+                end: self.timestamp()?,
+                event_count: self.usize()?,
+                file_offset: self.usize()?,
+            });
+        }
+        for _ in 0..self.u64()? {
+            report.skipped_local_times.push(self.usize()?);
+        }
+        report.missing_end_marker = self.u8()? != 0;
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voltcraft::data::ParseMode;
+    use crate::voltcraft::sample::sample_data;
+
+    #[test]
+    fn state_roundtrip() {
+        let options = ParseOptions::new().mode(ParseMode::Lenient);
+        // A corrupted copy of the sample data, so that the report holds a diagnostic
+        let mut bytes = sample_data().as_bytes().to_vec();
+        bytes.truncate(bytes.len() - 6);
+        let data = crate::voltcraft::data::VoltcraftData::from_raw(bytes);
+        let (events, report) = data.parse_with(&options).unwrap();
+        assert!(!report.diagnostics.is_empty());
+        let mut state = DatasetState::new(&options);
+        state.insert(CachedFile {
+            name: String::from("A.BIN"),
+            hash: content_hash(data.as_bytes()),
+            report: report.clone(),
+            events: events.clone(),
+        });
+
+        let now = events[0].timestamp + Duration::days(3);
+        let log = ImportLog::update(None, events.last().unwrap().timestamp, now);
+        state.set_import_log(log);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        state.save(file.path()).unwrap();
+        let mut loaded = DatasetState::load(file.path(), &options).unwrap();
+        let cached = loaded.take("A.BIN", content_hash(data.as_bytes())).unwrap();
+        assert_eq!(format!("{:?}", cached.report), format!("{:?}", report));
+        assert_eq!(format!("{:?}", cached.events), format!("{:?}", events));
+        assert_eq!(loaded.import_log(), Some(log));
+
+        // A state of other parse options is of no use, but for when readings were last imported
+        let loaded = DatasetState::load(file.path(), &ParseOptions::new()).unwrap();
+        assert!(loaded.files().is_empty());
+        assert_eq!(loaded.import_log(), state.import_log());
+        // Nor is a truncated one
+        let mut bytes = fs::read(file.path()).unwrap();
+        bytes.pop();
+        fs::write(file.path(), bytes).unwrap();
+        assert!(matches!(
+            DatasetState::load(file.path(), &options),
+            Err(StateError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn import_log() {
+        let (events, _) = sample_data().parse().unwrap();
+        let (first, last) = (events[0].timestamp, events[8].timestamp);
+        let log = ImportLog::update(None, first, first + Duration::days(1));
+        // Readings already imported are no news
+        let later = first + Duration::days(5);
+        assert_eq!(ImportLog::update(Some(log), first, later), log);
+        assert_eq!(log.staleness(later), Duration::days(4));
+        let log = ImportLog::update(Some(log), last, later);
+        assert_eq!((log.latest_reading, log.imported_at), (last, later));
+        assert_eq!(log.staleness(later), Duration::zero());
+    }
+}
@@ -1,13 +1,177 @@
 use crate::voltcraft::data::PowerEvent;
-use chrono::{Date, DateTime, Duration, Local};
+use crate::voltcraft::grid::GridProfile;
+use crate::voltcraft::profile::ApplianceProfile;
+use crate::voltcraft::raw::READING_SIZE;
+use crate::voltcraft::resample::resample;
+use crate::voltcraft::tariff::{BillingPeriod, BillingRounding, TimeOfUseTariff};
+use crate::voltcraft::transform::ExcludedPeriod;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, Timelike};
 use itertools::Itertools;
 use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
 
+/// Statistics over a chronologically sorted series of power events.
+///
+/// ```
+/// use chrono::Duration;
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+/// use voltcraft_energy_analyzer::voltcraft::stats::VoltcraftStatistics;
+///
+/// let (mut events, _) = sample_data().parse().unwrap();
+/// let stats = VoltcraftStatistics::new(&mut events);
+/// let blackouts = stats.blackout_stats();
+/// assert_eq!(blackouts.blackout_count, 1);
+/// assert_eq!(blackouts.total_blackout_duration, Duration::minutes(6));
+/// assert_eq!(stats.overall_stats().unwrap().stats.max_voltage.voltage, 225.5);
+/// ```
 pub struct VoltcraftStatistics<'a> {
     power_data: &'a Vec<PowerEvent>,
+    blackout_detection: BlackoutDetection,
+    excluded: Vec<ExcludedPeriod>,
+}
+
+// How blackouts are told from the gaps between the readings
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlackoutAlgorithm {
+    Gap, // every gap longer than the threshold
+    // Only the gaps after which the logger started a new data block, as it does when it starts
+    // recording again once the power is back: readings dropped within a block (by validation, or
+    // not decoded, see ParseOptions::stride) leave gaps while the logger kept recording. Readings
+    // without a source (see PowerEvent::source) are taken as starting a new block.
+    Session,
+}
+
+impl FromStr for BlackoutAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gap" => Ok(BlackoutAlgorithm::Gap),
+            "session" => Ok(BlackoutAlgorithm::Session),
+            _ => Err(format!(
+                "Invalid blackout detection '{}' (expected gap or session)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BlackoutAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlackoutAlgorithm::Gap => write!(f, "gap"),
+            BlackoutAlgorithm::Session => write!(f, "session"),
+        }
+    }
+}
+
+/// How blackouts are detected: by default, every gap of more than a minute between two readings.
+///
+/// ```
+/// use chrono::Duration;
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+/// use voltcraft_energy_analyzer::voltcraft::stats::{
+///     BlackoutAlgorithm, BlackoutDetection, VoltcraftStatistics,
+/// };
+///
+/// let (mut events, _) = sample_data().parse().unwrap();
+/// // The sample recording stops for 6 minutes, then the logger starts a new data block
+/// let detection = BlackoutDetection {
+///     threshold: Duration::minutes(10),
+///     algorithm: BlackoutAlgorithm::Gap,
+/// };
+/// let stats = VoltcraftStatistics::new(&mut events).blackout_detection(detection);
+/// assert_eq!(stats.blackout_stats().blackout_count, 0);
+/// let detection = BlackoutDetection {
+///     algorithm: BlackoutAlgorithm::Session,
+///     ..Default::default()
+/// };
+/// let stats = stats.blackout_detection(detection);
+/// assert_eq!(stats.blackout_stats().blackout_count, 1);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlackoutDetection {
+    pub threshold: Duration, // between two readings, above which the power was out
+    pub algorithm: BlackoutAlgorithm,
+}
+
+impl Default for BlackoutDetection {
+    fn default() -> Self {
+        BlackoutDetection {
+            threshold: Duration::minutes(1),
+            algorithm: BlackoutAlgorithm::Gap,
+        }
+    }
+}
+
+// An average that short spikes (a kettle, a motor starting) barely move: the median, or the mean of
+// what is left once the given share (%) of the lowest and of the highest values is cut off
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RobustAverage {
+    Median,
+    TrimmedMean(f64), // % cut off at each end, below 50
+}
+
+impl RobustAverage {
+    // The average of the values, None if there are none (the values are sorted in place)
+    pub fn of(&self, values: &mut [f64]) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let n = values.len();
+        match self {
+            // The middle value, or the mean of the two middle ones
+            RobustAverage::Median => Some((values[(n - 1) / 2] + values[n / 2]) / 2.0),
+            RobustAverage::TrimmedMean(percent) => {
+                let cut = (n as f64 * percent / 100.0).floor() as usize;
+                let kept = &values[cut..n - cut];
+                Some(kept.iter().sum::<f64>() / kept.len() as f64)
+            }
+        }
+    }
+}
+
+impl FromStr for RobustAverage {
+    type Err = String;
+
+    // median, or trimmed[:<percent>] (10% by default)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid robust average '{}' (expected median or trimmed[:<percent below 50>], e.g. trimmed:10)",
+                s
+            )
+        };
+        let s_lower = s.trim().to_ascii_lowercase();
+        match s_lower.split_once(':') {
+            None if s_lower == "median" => Ok(RobustAverage::Median),
+            None if s_lower == "trimmed" => Ok(RobustAverage::TrimmedMean(10.0)),
+            Some(("trimmed", percent)) => {
+                let percent = percent.trim();
+                match percent.strip_suffix('%').unwrap_or(percent).parse::<f64>() {
+                    Ok(p) if (0.0..50.0).contains(&p) => Ok(RobustAverage::TrimmedMean(p)),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for RobustAverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RobustAverage::Median => write!(f, "median"),
+            RobustAverage::TrimmedMean(percent) => write!(f, "trimmed:{}", percent),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PowerStats {
     pub total_active_power: f64,      // total active power (kWh)
     pub avg_active_power: f64,        // average active power (kW)
@@ -25,35 +189,386 @@ pub struct PowerStats {
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PowerBlackout {
-    pub timestamp: chrono::DateTime<Local>, // start of blackout
-    pub duration: chrono::Duration,         // duration
+    pub timestamp: chrono::DateTime<FixedOffset>, // start of blackout
+    pub duration: chrono::Duration,               // duration
+    pub before: PowerEvent,                       // last reading before the blackout
+    pub after: PowerEvent,                        // first reading after the blackout
+}
+
+#[derive(Debug, Clone)]
+pub struct FileCoverage {
+    pub file: String,                         // source data file
+    pub start: chrono::DateTime<FixedOffset>, // first power event in the file
+    pub end: chrono::DateTime<FixedOffset>,   // last power event in the file
+}
+
+#[derive(Debug, Clone)]
+pub struct FileGap {
+    pub previous_file: String, // file covering the period before the gap
+    pub next_file: String,     // file covering the period after the gap
+    pub timestamp: chrono::DateTime<FixedOffset>, // start of the gap
+    pub duration: chrono::Duration, // duration
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DailyPowerInfo {
-    pub date: Date<Local>,
+    pub date: NaiveDate,
+    pub stats: PowerStats,
+}
+
+#[derive(Debug)]
+pub struct HourlyPowerInfo {
+    pub hour: DateTime<FixedOffset>, // start of the hour
     pub stats: PowerStats,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OverallPowerInfo {
-    pub start: DateTime<Local>,
-    pub end: DateTime<Local>,
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
     pub stats: PowerStats,
     pub avg_daily_power_consumption: Option<f64>, // kWh
 }
 
 #[derive(Debug)]
+pub struct ConsistencyInfo {
+    pub tolerance: f64,       // relative tolerance for the apparent power check
+    pub checked_count: usize, // number of checked power events
+    pub active_exceeds_apparent: Vec<PowerEvent>, // events where P > S
+    pub apparent_mismatch: Vec<PowerEvent>, // events where S deviates from V*I beyond tolerance
+}
+
+#[derive(Debug)]
+pub struct CorrelationInfo {
+    pub sample_count: usize,
+    pub coefficient: Option<f64>, // Pearson correlation coefficient between active power and voltage
+    pub slope: Option<f64>,       // voltage change per kW of load (V/kW), from a least-squares fit
+}
+
+// Voltage sag minutes split by the monitored load at the time: sags that follow the load point at
+// the installation (wiring, connections), the others at the grid
+#[derive(Debug)]
+pub struct SagCoincidenceInfo {
+    pub sag_voltage: f64,         // voltage (V) below which a minute counts as a sag
+    pub high_load: f64,           // active power (kW) above which a minute counts as high load
+    pub minute_count: usize,      // minutes considered
+    pub high_load_minutes: usize, // minutes at high load
+    pub sag_minutes: usize,       // sag minutes
+    pub high_load_sags: usize,    // sag minutes at high load
+}
+
+impl SagCoincidenceInfo {
+    // Share of the minutes at high (or low) load that are sags
+    pub fn sag_rate(&self, high_load: bool) -> Option<f64> {
+        let (sags, minutes) = if high_load {
+            (self.high_load_sags, self.high_load_minutes)
+        } else {
+            (
+                self.sag_minutes - self.high_load_sags,
+                self.minute_count - self.high_load_minutes,
+            )
+        };
+        (minutes > 0).then(|| sags as f64 / minutes as f64)
+    }
+}
+
+// Minutes with the voltage outside the tolerance band of the grid
+#[derive(Debug)]
+pub struct VoltageDeviationInfo {
+    pub grid: GridProfile,
+    pub minute_count: usize,  // minutes considered
+    pub under_minutes: usize, // below the band (sags)
+    pub over_minutes: usize,  // above the band (swells)
+    pub lowest: Option<f64>,  // deviation of the lowest voltage from the nominal voltage, in %
+    pub highest: Option<f64>, // deviation of the highest voltage from the nominal voltage, in %
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct FlickerStreak {
+    pub timestamp: chrono::DateTime<FixedOffset>, // first minute of the streak
+    pub minutes: usize,                           // number of consecutive rapid variation minutes
+    pub max_change: f64,                          // largest minute to minute voltage change (V)
+}
+
+#[derive(Debug)]
+pub struct FlickerInfo {
+    pub threshold: f64, // voltage change (V) above which a minute counts as a rapid variation
+    pub minute_count: usize, // number of rapid variation minutes
+    pub compared_count: usize, // number of minutes compared with their predecessor
+    pub worst_streaks: Vec<FlickerStreak>, // longest streaks, worst first
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ApplianceCycle {
+    pub timestamp: DateTime<FixedOffset>, // start of the cycle
+    pub minutes: i64,                     // duration
+    pub energy: f64,                      // kWh
+}
+
+#[derive(Debug)]
+pub struct CycleInfo {
+    pub profile: ApplianceProfile,
+    pub cycles: Vec<ApplianceCycle>,
+    pub unusual_cycles: Vec<ApplianceCycle>, // cycles outside the typical duration of the profile
+    pub over_max_count: usize,               // readings above the maximum power of the profile
+}
+
+#[derive(Debug)]
+pub struct LoadShiftingInfo {
+    pub tariff: TimeOfUseTariff,
+    pub peak_cycles: usize,   // cycles started in peak hours
+    pub peak_energy: f64,     // kWh used by these cycles
+    pub savings: f64,         // over the period, had these cycles run in off-peak hours
+    pub monthly_savings: f64, // savings over 30 days at the same pace
+}
+
+// The energy of a billing period, as metered and as billed
+#[derive(Debug, Copy, Clone)]
+pub struct BilledPeriod {
+    pub start: NaiveDate,  // the day, or the first day of the month
+    pub energy: f64,       // kWh recorded
+    pub billed: f64,       // kWh once rounded
+    pub cost: Option<f64>, // of the billed energy, to the cent
+}
+
+#[derive(Debug)]
+pub struct BillingInfo {
+    pub rounding: BillingRounding,
+    pub price: Option<f64>, // per kWh
+    pub periods: Vec<BilledPeriod>,
+}
+
+impl BillingInfo {
+    pub fn energy(&self) -> f64 {
+        self.periods.iter().map(|p| p.energy).sum()
+    }
+
+    pub fn billed(&self) -> f64 {
+        self.periods.iter().map(|p| p.billed).sum()
+    }
+
+    // The sum of the costs of the periods, as on a bill
+    pub fn cost(&self) -> Option<f64> {
+        self.periods.iter().map(|p| p.cost).sum()
+    }
+}
+
+#[derive(Debug)]
+pub struct PeakShavingInfo {
+    pub limit: f64,                // kW
+    pub affected_minutes: usize,   // readings above the limit
+    pub affected_days: usize,      // days with at least one reading above the limit
+    pub excess_energy: f64,        // kWh above the limit, to be shifted or curtailed
+    pub worst: Option<PowerEvent>, // reading furthest above the limit
+}
+
+// How a battery backup would have fared in a blackout. The load isn't recorded while the power is
+// out, so it is estimated from the hour before the blackout.
+#[derive(Debug, Copy, Clone)]
+pub struct UpsBlackout {
+    pub blackout: PowerBlackout,
+    pub load: f64,                 // kW, average over the hour before the blackout
+    pub runtime: Option<Duration>, // how long the battery lasts at this load (None: no load)
+    pub carried: bool,             // whether the battery would have lasted the whole blackout
+}
+
+#[derive(Debug)]
+pub struct UpsInfo {
+    pub capacity: f64,   // kWh
+    pub efficiency: f64, // of the inverter (0-1)
+    pub blackouts: Vec<UpsBlackout>,
+    pub carried_count: usize,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlackoutInfo {
     pub blackout_count: usize,
     pub total_blackout_duration: chrono::Duration,
     pub blackouts: Vec<PowerBlackout>,
+    pub by_hour: [usize; 24],   // blackouts by hour of the day they started
+    pub by_weekday: [usize; 7], // blackouts by weekday they started, Monday first
+    pub mtbf: Option<Duration>, // mean time between failures: time powered per blackout
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct WeeklySlot {
+    pub mean: f64,    // average active power (kW)
+    pub max: f64,     // maximum active power (kW)
+    pub count: usize, // readings recorded in this minute of the week, over all weeks
+}
+
+// Active power by minute of the week, Monday 00:00 first. Computed once from the power data, so the
+// analyses looking for weekly patterns share the bucketing rather than each redoing it.
+#[derive(Debug, Clone)]
+pub struct WeeklyProfile {
+    slots: Vec<WeeklySlot>,
+}
+
+// Active, reactive and apparent energy over a period (see PowerEvent::derived for the reactive power)
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ReactiveLoad {
+    pub readings: usize,
+    pub active_energy: f64,   // kWh
+    pub reactive_energy: f64, // kvarh
+    pub apparent_energy: f64, // kVAh
+}
+
+impl ReactiveLoad {
+    // Share of the apparent energy that is reactive (Q/S, the sine of the phase angle)
+    pub fn reactive_share(&self) -> Option<f64> {
+        (self.apparent_energy > 0.0).then(|| self.reactive_energy / self.apparent_energy)
+    }
+
+    // Reactive over active energy, the tan phi utilities bill reactive energy by
+    pub fn tan_phi(&self) -> Option<f64> {
+        (self.active_energy > 0.0).then(|| self.reactive_energy / self.active_energy)
+    }
+
+    // Average reactive power over the minutes with readings (kvar)
+    pub fn avg_reactive_power(&self) -> Option<f64> {
+        (self.readings > 0).then(|| self.reactive_energy * 60.0 / self.readings as f64)
+    }
+}
+
+#[derive(Debug)]
+pub struct DailyReactiveInfo {
+    pub date: NaiveDate,
+    pub load: ReactiveLoad,
+}
+
+/// When reactive (e.g. inductive) loads run: the reactive energy by day, with its trend, and by
+/// hour of the week, to tell whether power factor correction is worth it.
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+/// use voltcraft_energy_analyzer::voltcraft::stats::VoltcraftStatistics;
+///
+/// let (mut events, _) = sample_data().parse().unwrap();
+/// let reactive = VoltcraftStatistics::new(&mut events).reactive_stats();
+/// // The sample recording is of a Thursday evening, 18:43 to 18:57
+/// assert_eq!(reactive.daily.len(), 1);
+/// assert_eq!(reactive.heatmap[3][18].readings, 9);
+/// let (weekday, hour, _) = reactive.peak().unwrap();
+/// assert_eq!((weekday, hour), (3, 18));
+/// // A fridge compressor: about as much reactive as active energy
+/// let tan_phi = reactive.total.tan_phi().unwrap();
+/// assert!(tan_phi > 0.5 && tan_phi < 1.5);
+/// ```
+#[derive(Debug)]
+pub struct ReactiveInfo {
+    pub total: ReactiveLoad,
+    pub daily: Vec<DailyReactiveInfo>,
+    pub heatmap: [[ReactiveLoad; 24]; 7], // by day of the week (Monday first) and hour of the day
+    pub trend: Option<f64>,               // change of the daily reactive share per 30 days
+}
+
+impl ReactiveInfo {
+    // The hour of the week with the highest average reactive power: (day from Monday, hour, kvar)
+    pub fn peak(&self) -> Option<(usize, usize, f64)> {
+        (0..7)
+            .cartesian_product(0..24)
+            .filter_map(|(day, hour)| {
+                let power = self.heatmap[day][hour].avg_reactive_power()?;
+                Some((day, hour, power))
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DailyRobustInfo {
+    pub date: NaiveDate,
+    pub power: f64,   // kW, robust average of the active power
+    pub voltage: f64, // V, robust average of the voltage
+}
+
+/// Daily power and voltage averaged so that short spikes don't skew them, to be read alongside
+/// the means of [`VoltcraftStatistics::daily_stats`].
+///
+/// ```
+/// use voltcraft_energy_analyzer::voltcraft::sample::sample_data;
+/// use voltcraft_energy_analyzer::voltcraft::stats::{RobustAverage, VoltcraftStatistics};
+///
+/// let (mut events, _) = sample_data().parse().unwrap();
+/// let stats = VoltcraftStatistics::new(&mut events);
+/// let median = stats.robust_stats(RobustAverage::Median);
+/// // The fridge compressor ran 6 of the 9 minutes recorded
+/// let mean = stats.daily_stats()[0].stats.avg_active_power;
+/// assert!(median.daily[0].power > mean);
+/// // Cutting off nothing gives the mean back
+/// let trimmed = stats.robust_stats("trimmed:0".parse().unwrap());
+/// assert!((trimmed.daily[0].power - mean).abs() < 1e-12);
+/// assert_eq!(RobustAverage::Median.of(&mut [3.0, 1.0, 100.0, 2.0]), Some(2.5));
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RobustInfo {
+    pub average: RobustAverage,
+    pub daily: Vec<DailyRobustInfo>,
+}
+
+impl PowerStats {
+    // Share of the apparent energy not converted to active energy (1 - P/S). The logger only records
+    // RMS values, so this is a rough proxy for reactive and non-linear (distorting) loads.
+    pub fn distortion_proxy(&self) -> Option<f64> {
+        if self.total_apparent_power > 0.0 {
+            Some(1.0 - self.total_active_power / self.total_apparent_power)
+        } else {
+            None
+        }
+    }
+}
+
+impl WeeklyProfile {
+    pub const SLOTS: usize = 7 * 24 * 60;
+
+    // Index of the slot the given time falls into
+    pub fn slot_index(timestamp: DateTime<FixedOffset>) -> usize {
+        let day = timestamp.weekday().num_days_from_monday() as usize;
+        (day * 24 + timestamp.hour() as usize) * 60 + timestamp.minute() as usize
+    }
+
+    pub fn slots(&self) -> &[WeeklySlot] {
+        &self.slots
+    }
+
+    pub fn slot(&self, timestamp: DateTime<FixedOffset>) -> &WeeklySlot {
+        &self.slots[WeeklyProfile::slot_index(timestamp)]
+    }
 }
 
 impl<'a> VoltcraftStatistics<'a> {
-    pub fn new(power_data: &mut Vec<PowerEvent>) -> VoltcraftStatistics {
-        VoltcraftStatistics { power_data }
+    pub fn new(power_data: &mut Vec<PowerEvent>) -> VoltcraftStatistics<'_> {
+        VoltcraftStatistics {
+            power_data,
+            blackout_detection: BlackoutDetection::default(),
+            excluded: Vec::new(),
+        }
+    }
+
+    pub fn blackout_detection(mut self, detection: BlackoutDetection) -> Self {
+        self.blackout_detection = detection;
+        self
+    }
+
+    // Periods whose readings were left out (see transform::exclude_periods): the gaps they leave
+    // aren't blackouts, and the averages over time don't count them
+    pub fn excluded(mut self, periods: &[ExcludedPeriod]) -> Self {
+        self.excluded = periods.to_vec();
+        self
+    }
+
+    // Time from start to end, but for the excluded periods
+    pub fn span(&self, start: DateTime<FixedOffset>, end: DateTime<FixedOffset>) -> Duration {
+        self.excluded
+            .iter()
+            .fold(end - start, |span, p| span - p.overlap(start, end))
     }
 
     pub fn daily_stats(&self) -> Vec<DailyPowerInfo> {
@@ -66,47 +581,410 @@ impl<'a> VoltcraftStatistics<'a> {
             .collect::<Vec<_>>()
     }
 
-    pub fn overall_stats(&self) -> OverallPowerInfo {
-        let mut avg_daily_power_consumption = Option::None;
-        let power_stats = VoltcraftStatistics::compute_stats(self.power_data);
+    pub fn hourly_stats(&self) -> Vec<HourlyPowerInfo> {
+        // Power data is chronologically sorted, so consecutive events sharing the same hour form a
+        // group. A corrected logger clock leaves readings off the whole minute, hence the seconds.
+        self.power_data
+            .iter()
+            .group_by(|e| {
+                e.timestamp
+                    .with_minute(0)
+                    .and_then(|t| t.with_second(0))
+                    .and_then(|t| t.with_nanosecond(0))
+                    .unwrap()
+            })
+            .into_iter()
+            .map(|(h, g)| (h, g.cloned().collect::<Vec<_>>())) // Gather the power items recorded within the current hour
+            .map(|(h, e)| HourlyPowerInfo {
+                hour: h,
+                stats: VoltcraftStatistics::compute_stats(&e),
+            })
+            .collect::<Vec<_>>()
+    }
 
+    // The statistics of the entire period, if there are readings at all (the files may hold
+    // only data block headers)
+    pub fn overall_stats(&self) -> Option<OverallPowerInfo> {
+        let mut avg_daily_power_consumption = Option::None;
         // Compute the start and end of the power data
-        let start = self.power_data.first().unwrap().timestamp;
-        let end = self.power_data.last().unwrap().timestamp;
+        let start = self.power_data.first()?.timestamp;
+        let end = self.power_data.last()?.timestamp;
+        let power_stats = VoltcraftStatistics::compute_stats(self.power_data);
         // Determine the average daily consumption
-        let total_duration = end - start;
+        let total_duration = self.span(start, end);
         if total_duration >= Duration::days(1) {
             // If we have more than one day worth of power data, we can do some additional power statistics
             avg_daily_power_consumption = Some(
                 power_stats.total_active_power / (total_duration.num_seconds() as f64 / 86400.0),
             );
         }
-        OverallPowerInfo {
+        Some(OverallPowerInfo {
             start,
             end,
             stats: power_stats,
             avg_daily_power_consumption,
+        })
+    }
+
+    // The overall statistics of readings decoded with a stride (see ParseOptions::stride), each
+    // standing for the given number of minutes: the energy and the time recorded are estimates, and
+    // the extremes those of the readings decoded only
+    pub fn sampled_stats(&self, every: usize) -> Option<OverallPowerInfo> {
+        let mut info = self.overall_stats()?;
+        let every = every.max(1);
+        info.stats.total_active_power *= every as f64;
+        info.stats.total_apparent_power *= every as f64;
+        info.stats.total_duration = Duration::minutes((self.power_data.len() * every) as i64);
+        info.avg_daily_power_consumption =
+            info.avg_daily_power_consumption.map(|d| d * every as f64);
+        Some(info)
+    }
+
+    // How long a battery of the given capacity (kWh), through an inverter of the given efficiency,
+    // would have carried the load in each blackout (at the average load of the hour before it, so
+    // the blackouts without readings in that hour are left out)
+    pub fn ups_runtime(&self, capacity: f64, efficiency: f64) -> UpsInfo {
+        let blackouts = self
+            .compute_blackouts()
+            .into_iter()
+            .filter_map(|blackout| {
+                let since = blackout.timestamp - Duration::hours(1);
+                let (sum, count) = self
+                    .power_data
+                    .iter()
+                    .filter(|pe| pe.timestamp >= since && pe.timestamp < blackout.timestamp)
+                    .fold((0.0, 0), |(sum, count), pe| (sum + pe.power, count + 1));
+                if count == 0 {
+                    return None;
+                }
+                let load = sum / count as f64;
+                let runtime = (load > 0.0).then(|| {
+                    Duration::seconds((capacity * efficiency / load * 3600.0).min(1e12) as i64)
+                });
+                Some(UpsBlackout {
+                    blackout,
+                    load,
+                    runtime,
+                    carried: runtime.is_none_or(|r| r >= blackout.duration),
+                })
+            })
+            .collect::<Vec<_>>();
+        UpsInfo {
+            capacity,
+            efficiency,
+            carried_count: blackouts.iter().filter(|b| b.carried).count(),
+            blackouts,
         }
     }
 
     pub fn blackout_stats(&self) -> BlackoutInfo {
-        let blackouts = &VoltcraftStatistics::compute_blackouts(self.power_data);
+        let blackouts = &self.compute_blackouts();
         let blackout_count = blackouts.len();
         let total_blackout_duration = blackouts
             .iter()
             .fold(Duration::zero(), |sum, x| sum + x.duration);
+        let mut by_hour = [0; 24];
+        let mut by_weekday = [0; 7];
+        for blackout in blackouts {
+            by_hour[blackout.timestamp.hour() as usize] += 1;
+            by_weekday[blackout.timestamp.weekday().num_days_from_monday() as usize] += 1;
+        }
+        let mtbf = match (self.power_data.first(), self.power_data.last()) {
+            (Some(first), Some(last)) if blackout_count > 0 => {
+                let period = self.span(first.timestamp, last.timestamp) + Duration::minutes(1);
+                Some((period - total_blackout_duration) / blackout_count as i32)
+            }
+            _ => None,
+        };
         BlackoutInfo {
             blackout_count,
             total_blackout_duration,
             blackouts: blackouts.to_vec(),
+            by_hour,
+            by_weekday,
+            mtbf,
+        }
+    }
+
+    pub fn consistency_stats(&self, tolerance: f64) -> ConsistencyInfo {
+        const EPSILON: f64 = 1e-9; // absorbs floating point noise
+        let active_exceeds_apparent = self
+            .power_data
+            .iter()
+            .filter(|e| e.power > e.apparent_power + EPSILON)
+            .cloned()
+            .collect::<Vec<_>>();
+        let apparent_mismatch = self
+            .power_data
+            .iter()
+            .filter(|e| {
+                // Recompute the apparent power (kVA) from voltage and current
+                let expected = e.voltage * e.current / 1000.0;
+                (e.apparent_power - expected).abs()
+                    > tolerance * expected.abs().max(e.apparent_power.abs()) + EPSILON
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        ConsistencyInfo {
+            tolerance,
+            checked_count: self.power_data.len(),
+            active_exceeds_apparent,
+            apparent_mismatch,
+        }
+    }
+
+    pub fn correlation_stats(&self) -> CorrelationInfo {
+        let n = self.power_data.len() as f64;
+        let mean_p = self.power_data.iter().fold(0f64, |sum, x| sum + x.power) / n;
+        let mean_v = self.power_data.iter().fold(0f64, |sum, x| sum + x.voltage) / n;
+        // Covariance and variances (the common 1/n factor cancels out)
+        let (cov, var_p, var_v) =
+            self.power_data
+                .iter()
+                .fold((0f64, 0f64, 0f64), |(c, p, v), x| {
+                    let dp = x.power - mean_p;
+                    let dv = x.voltage - mean_v;
+                    (c + dp * dv, p + dp * dp, v + dv * dv)
+                });
+        // Both series need some variation for the figures to make sense
+        let coefficient = if var_p > 0.0 && var_v > 0.0 {
+            Some(cov / (var_p * var_v).sqrt())
+        } else {
+            None
+        };
+        let slope = if var_p > 0.0 { Some(cov / var_p) } else { None };
+        CorrelationInfo {
+            sample_count: self.power_data.len(),
+            coefficient,
+            slope,
+        }
+    }
+
+    pub fn sag_coincidence(&self, sag_voltage: f64, high_load: f64) -> SagCoincidenceInfo {
+        let mut info = SagCoincidenceInfo {
+            sag_voltage,
+            high_load,
+            minute_count: self.power_data.len(),
+            high_load_minutes: 0,
+            sag_minutes: 0,
+            high_load_sags: 0,
+        };
+        for pe in self.power_data.iter() {
+            let (sag, high) = (pe.voltage < sag_voltage, pe.power > high_load);
+            info.high_load_minutes += high as usize;
+            info.sag_minutes += sag as usize;
+            info.high_load_sags += (sag && high) as usize;
+        }
+        info
+    }
+
+    pub fn voltage_deviation(&self, grid: &GridProfile) -> VoltageDeviationInfo {
+        let voltages = self.power_data.iter().map(|pe| pe.voltage);
+        VoltageDeviationInfo {
+            grid: *grid,
+            minute_count: self.power_data.len(),
+            under_minutes: voltages.clone().filter(|&v| v < grid.low_voltage()).count(),
+            over_minutes: voltages
+                .clone()
+                .filter(|&v| v > grid.high_voltage())
+                .count(),
+            lowest: voltages.clone().reduce(f64::min).map(|v| grid.deviation(v)),
+            highest: voltages.reduce(f64::max).map(|v| grid.deviation(v)),
+        }
+    }
+
+    pub fn flicker_stats(&self, threshold: f64) -> FlickerInfo {
+        const WORST_STREAKS: usize = 10;
+        let mut minute_count = 0;
+        let mut compared_count = 0;
+        let mut streaks = Vec::<FlickerStreak>::new();
+        let mut current: Option<FlickerStreak> = None;
+        for (pe1, pe2) in self.power_data.iter().tuple_windows() {
+            // Only compare subsequent minutes, a gap in the data breaks the streak
+            if !consecutive(pe1.timestamp, pe2.timestamp) {
+                streaks.extend(current.take());
+                continue;
+            }
+            compared_count += 1;
+            let change = (pe2.voltage - pe1.voltage).abs();
+            if change > threshold {
+                minute_count += 1;
+                let streak = current.get_or_insert(FlickerStreak {
+                    timestamp: pe2.timestamp,
+                    minutes: 0,
+                    max_change: 0.0,
+                });
+                streak.minutes += 1;
+                streak.max_change = streak.max_change.max(change);
+            } else {
+                streaks.extend(current.take());
+            }
+        }
+        streaks.extend(current.take());
+        // Longest streaks first, the ones with the largest swings breaking ties
+        streaks.sort_by(|a, b| {
+            b.minutes
+                .cmp(&a.minutes)
+                .then(b.max_change.partial_cmp(&a.max_change).unwrap())
+        });
+        streaks.truncate(WORST_STREAKS);
+        FlickerInfo {
+            threshold,
+            minute_count,
+            compared_count,
+            worst_streaks: streaks,
+        }
+    }
+
+    // Runs of consecutive readings where the appliance is running, according to its profile
+    pub fn cycle_stats(&self, profile: &ApplianceProfile) -> CycleInfo {
+        let mut cycles = Vec::<ApplianceCycle>::new();
+        let mut current: Option<ApplianceCycle> = None;
+        let mut previous: Option<&PowerEvent> = None;
+        for pe in self.power_data.iter() {
+            // A gap in the data ends the cycle
+            if previous.is_some_and(|p| !consecutive(p.timestamp, pe.timestamp)) {
+                cycles.extend(current.take());
+            }
+            previous = Some(pe);
+            if pe.power * 1000.0 >= profile.running_power {
+                let cycle = current.get_or_insert(ApplianceCycle {
+                    timestamp: pe.timestamp,
+                    minutes: 0,
+                    energy: 0.0,
+                });
+                cycle.minutes += 1;
+                cycle.energy += pe.power / 60.0;
+            } else {
+                cycles.extend(current.take());
+            }
         }
+        cycles.extend(current.take());
+        cycles.retain(|c| c.minutes >= profile.min_cycle);
+        let unusual_cycles = cycles
+            .iter()
+            .filter(|c| c.minutes < profile.typical_cycle.0 || c.minutes > profile.typical_cycle.1)
+            .copied()
+            .collect();
+        CycleInfo {
+            profile: *profile,
+            cycles,
+            unusual_cycles,
+            over_max_count: self
+                .power_data
+                .iter()
+                .filter(|pe| pe.power * 1000.0 > profile.max_power)
+                .count(),
+        }
+    }
+
+    // What capping the power at the given limit (kW) would have taken: the load above it would have
+    // to be shifted to later minutes or curtailed
+    pub fn peak_shaving(&self, limit: f64) -> PeakShavingInfo {
+        let above = self
+            .power_data
+            .iter()
+            .filter(|pe| pe.power > limit)
+            .collect::<Vec<_>>();
+        PeakShavingInfo {
+            limit,
+            affected_minutes: above.len(),
+            affected_days: above
+                .iter()
+                .map(|pe| pe.timestamp.date_naive())
+                .collect::<HashSet<_>>()
+                .len(),
+            excess_energy: above.iter().map(|pe| (pe.power - limit) / 60.0).sum(),
+            worst: above
+                .iter()
+                .max_by(|a, b| a.power.partial_cmp(&b.power).unwrap())
+                .map(|pe| **pe),
+        }
+    }
+
+    pub fn weekly_profile(&self) -> WeeklyProfile {
+        let mut slots = vec![WeeklySlot::default(); WeeklyProfile::SLOTS];
+        for pe in self.power_data.iter() {
+            let slot = &mut slots[WeeklyProfile::slot_index(pe.timestamp)];
+            slot.count += 1;
+            slot.mean += pe.power; // summed here, averaged below
+            slot.max = slot.max.max(pe.power);
+        }
+        for slot in slots.iter_mut().filter(|s| s.count > 0) {
+            slot.mean /= slot.count as f64;
+        }
+        WeeklyProfile { slots }
+    }
+
+    // The readings are bucketed by hour of the logger clock, each hour adding to its day and to
+    // its hour of the week
+    pub fn reactive_stats(&self) -> ReactiveInfo {
+        let mut total = ReactiveLoad::default();
+        let mut daily: Vec<DailyReactiveInfo> = Vec::new();
+        let mut heatmap = [[ReactiveLoad::default(); 24]; 7];
+        for hour in resample(self.power_data, Duration::hours(1)).unwrap_or_default() {
+            let load = ReactiveLoad {
+                readings: hour.readings,
+                active_energy: hour.energy,
+                reactive_energy: hour.avg_reactive_power * hour.readings as f64 / 60.0,
+                apparent_energy: hour.avg_apparent_power * hour.readings as f64 / 60.0,
+            };
+            let date = hour.start.date_naive();
+            if daily.last().is_none_or(|d| d.date != date) {
+                daily.push(DailyReactiveInfo {
+                    date,
+                    load: ReactiveLoad::default(),
+                });
+            }
+            let day = hour.start.weekday().num_days_from_monday() as usize;
+            for sum in [
+                &mut total,
+                &mut daily.last_mut().unwrap().load,
+                &mut heatmap[day][hour.start.hour() as usize],
+            ] {
+                sum.readings += load.readings;
+                sum.active_energy += load.active_energy;
+                sum.reactive_energy += load.reactive_energy;
+                sum.apparent_energy += load.apparent_energy;
+            }
+        }
+        let trend = daily_trend(
+            daily
+                .iter()
+                .filter_map(|d| d.load.reactive_share().map(|share| (d.date, share))),
+        );
+        ReactiveInfo {
+            total,
+            daily,
+            heatmap,
+            trend,
+        }
+    }
+
+    // The robust averages of each day with readings, in the order of daily_stats
+    pub fn robust_stats(&self, average: RobustAverage) -> RobustInfo {
+        let daily = self
+            .distinct_days()
+            .into_iter()
+            .filter_map(|date| {
+                let events = self.filter_power_data(&date);
+                let mut power = events.iter().map(|e| e.power).collect::<Vec<_>>();
+                let mut voltage = events.iter().map(|e| e.voltage).collect::<Vec<_>>();
+                Some(DailyRobustInfo {
+                    date,
+                    power: average.of(&mut power)?,
+                    voltage: average.of(&mut voltage)?,
+                })
+            })
+            .collect();
+        RobustInfo { average, daily }
     }
 
-    fn distinct_days(&self) -> Vec<Date<Local>> {
+    fn distinct_days(&self) -> Vec<NaiveDate> {
         let mut days = self
             .power_data
             .iter()
-            .map(|d| d.timestamp.date())
+            .map(|d| d.timestamp.date_naive())
             .collect::<HashSet<_>>()
             .into_iter()
             .collect::<Vec<_>>();
@@ -114,17 +992,17 @@ impl<'a> VoltcraftStatistics<'a> {
         days
     }
 
-    fn filter_power_data(&self, day: &Date<Local>) -> Vec<PowerEvent> {
+    fn filter_power_data(&self, day: &NaiveDate) -> Vec<PowerEvent> {
         let filtered_data = self
             .power_data
             .iter()
-            .filter(|d| *day == d.timestamp.date())
+            .filter(|d| *day == d.timestamp.date_naive())
             .cloned()
             .collect::<Vec<_>>();
         filtered_data
     }
 
-    // Compute power stats on the given power events
+    // Compute power stats on the given power events, of which there must be at least one
     fn compute_stats(power_items: &[PowerEvent]) -> PowerStats {
         // Total active power (in kWh) = (sum of instantaneous powers) / 60
         let power_sum = power_items.iter().fold(0f64, |sum, x| sum + x.power);
@@ -181,18 +1059,625 @@ impl<'a> VoltcraftStatistics<'a> {
         }
     }
 
-    // Compute blackout stats on the given power events
-    fn compute_blackouts(power_items: &[PowerEvent]) -> Vec<PowerBlackout> {
+    // Compute blackout stats on the power events
+    fn compute_blackouts(&self) -> Vec<PowerBlackout> {
+        let BlackoutDetection {
+            threshold,
+            algorithm,
+        } = self.blackout_detection;
         let mut blackouts = Vec::new();
-        for (pe1, pe2) in power_items.iter().tuple_windows() {
-            // If the gap between two subsequent timestamps is more than a minute, we've detected a blackout
-            if pe2.timestamp - pe1.timestamp > Duration::minutes(1) {
+        for (pe1, pe2) in self.power_data.iter().tuple_windows() {
+            // If the gap between two subsequent timestamps is more than the threshold, we've
+            // detected a blackout
+            let blackout = match algorithm {
+                BlackoutAlgorithm::Gap => {
+                    pe2.timestamp - pe1.timestamp > threshold
+                        && !consecutive(pe1.timestamp, pe2.timestamp)
+                }
+                BlackoutAlgorithm::Session => {
+                    pe2.timestamp - pe1.timestamp > threshold && !same_block(pe1, pe2)
+                }
+            };
+            let excluded = self
+                .excluded
+                .iter()
+                .any(|p| p.overlap(pe1.timestamp, pe2.timestamp) > Duration::zero());
+            if blackout && !excluded {
                 blackouts.push(PowerBlackout {
                     timestamp: pe1.timestamp + Duration::minutes(1),
                     duration: (pe2.timestamp - pe1.timestamp) - Duration::minutes(1),
+                    before: *pe1,
+                    after: *pe2,
                 })
             }
         }
         blackouts
     }
 }
+
+// Whether two readings were recorded in the same data block of the same file: the readings of a
+// block are stored one after the other, a sample (a minute) apart
+fn same_block(earlier: &PowerEvent, later: &PowerEvent) -> bool {
+    match (earlier.source, later.source) {
+        (Some(e), Some(l)) if e.file == l.file && l.offset > e.offset => {
+            let samples = (later.timestamp - earlier.timestamp).num_seconds() as f64 / 60.0;
+            l.offset - e.offset == samples.round() as usize * READING_SIZE
+        }
+        _ => false,
+    }
+}
+
+// Whether two readings are a sample (a minute) apart, give or take the seconds a clock drift
+// correction (see ParseOptions::time_correction) adds to or removes from each minute
+fn consecutive(earlier: DateTime<FixedOffset>, later: DateTime<FixedOffset>) -> bool {
+    let gap = later - earlier;
+    gap > Duration::seconds(30) && gap < Duration::seconds(90)
+}
+
+// Compute the periods not covered by any of the given data files (e.g. forgotten SD card downloads)
+pub fn compute_file_gaps(files: &[FileCoverage]) -> Vec<FileGap> {
+    let mut gaps = Vec::new();
+    let mut sorted = files.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|f| f.start);
+    let mut covered: Option<&FileCoverage> = None; // file reaching furthest in time so far
+    for f in sorted {
+        if let Some(c) = covered {
+            // If the next file starts more than a minute after the covered period, we've detected a gap
+            if f.start - c.end > Duration::minutes(1) && !consecutive(c.end, f.start) {
+                gaps.push(FileGap {
+                    previous_file: c.file.clone(),
+                    next_file: f.file.clone(),
+                    timestamp: c.end + Duration::minutes(1),
+                    duration: (f.start - c.end) - Duration::minutes(1),
+                });
+            }
+            if f.end <= c.end {
+                continue;
+            }
+        }
+        covered = Some(f);
+    }
+    gaps
+}
+
+// The energy billed for each day or month of the daily statistics, rounded as the utility does,
+// and its cost at the given price. Each period is priced on its own and rounded to the cent, as
+// the lines of a bill are.
+pub fn billed_energy(
+    daily: &[DailyPowerInfo],
+    rounding: &BillingRounding,
+    price: Option<f64>,
+) -> BillingInfo {
+    let periods = daily
+        .iter()
+        .group_by(|d| match rounding.period {
+            BillingPeriod::Day => d.date,
+            BillingPeriod::Month => d.date.with_day(1).unwrap(),
+        })
+        .into_iter()
+        .map(|(start, days)| {
+            let energy = days.map(|d| d.stats.total_active_power).sum::<f64>();
+            let billed = rounding.round(energy);
+            BilledPeriod {
+                start,
+                energy,
+                billed,
+                cost: price.map(|p| (billed * p * 100.0).round() / 100.0),
+            }
+        })
+        .collect();
+    BillingInfo {
+        rounding: *rounding,
+        price,
+        periods,
+    }
+}
+
+// What starting the appliance cycles recorded in peak hours in off-peak hours instead would save.
+// The cycles are priced as a whole at their start time, as shifting moves the entire cycle.
+pub fn load_shifting(
+    cycles: &CycleInfo,
+    tariff: &TimeOfUseTariff,
+    period: Duration,
+) -> LoadShiftingInfo {
+    let peak = cycles
+        .cycles
+        .iter()
+        .filter(|c| !tariff.off_peak_hours.contains(c.timestamp))
+        .collect::<Vec<_>>();
+    let peak_energy = peak.iter().map(|c| c.energy).sum::<f64>();
+    let savings = peak_energy * (tariff.peak_price - tariff.off_peak_price);
+    // Periods shorter than a day would blow up a single cycle into a monthly habit
+    let days = (period.num_minutes() as f64 / 1440.0).max(1.0);
+    LoadShiftingInfo {
+        tariff: *tariff,
+        peak_cycles: peak.len(),
+        peak_energy,
+        savings,
+        monthly_savings: savings / days * 30.0,
+    }
+}
+
+// Trend of the daily distortion proxy (change per 30 days), from a least-squares fit over the days
+pub fn distortion_trend(daily_stats: &[DailyPowerInfo]) -> Option<f64> {
+    daily_trend(
+        daily_stats
+            .iter()
+            .filter_map(|d| d.stats.distortion_proxy().map(|p| (d.date, p))),
+    )
+}
+
+// Change per 30 days of a daily figure, from a least-squares fit over the days
+fn daily_trend(days: impl Iterator<Item = (NaiveDate, f64)>) -> Option<f64> {
+    let points = days
+        .map(|(date, value)| (date.num_days_from_ce() as f64, value))
+        .collect::<Vec<_>>();
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().fold(0f64, |sum, p| sum + p.0) / n;
+    let mean_y = points.iter().fold(0f64, |sum, p| sum + p.1) / n;
+    let (cov, var) = points.iter().fold((0f64, 0f64), |(c, v), p| {
+        (
+            c + (p.0 - mean_x) * (p.1 - mean_y),
+            v + (p.0 - mean_x).powi(2),
+        )
+    });
+    Some(cov / var * 30.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voltcraft::data::{PowerEvent, TimeCorrection};
+    use crate::voltcraft::stats::{
+        compute_file_gaps, distortion_trend, FileCoverage, ReactiveLoad, RobustAverage,
+        VoltcraftStatistics,
+    };
+    use crate::voltcraft::transform::apply_deadband;
+    use chrono::{DateTime, Duration, FixedOffset, TimeZone};
+
+    // A reading at a power factor of 0.8, the given minutes after noon on 2014-09-11
+    fn event(minute: i64, voltage: f64, power: f64) -> PowerEvent {
+        PowerEvent {
+            timestamp: FixedOffset::east_opt(3 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2014, 9, 11, 12, 0, 0)
+                .unwrap()
+                + Duration::minutes(minute),
+            voltage,
+            current: power * 1.25 * 1000.0 / voltage,
+            power_factor: 0.8,
+            power,
+            apparent_power: power * 1.25,
+            source: None,
+            registers: None,
+            filled: false,
+        }
+    }
+
+    // Readings at 230V and the given power (kW), at the given minutes after noon on 2014-09-11
+    fn steady(minutes: impl IntoIterator<Item = i64>, power: f64) -> Vec<PowerEvent> {
+        minutes
+            .into_iter()
+            .map(|m| event(m, 230.0, power))
+            .collect()
+    }
+
+    fn at(minute: i64) -> DateTime<FixedOffset> {
+        event(minute, 230.0, 0.0).timestamp
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn power_stats() {
+        let mut events = vec![
+            event(0, 230.0, 0.6),
+            event(1, 228.0, 1.2),
+            event(2, 232.0, 0.0),
+            // The next day, 12 hours later
+            event(720, 226.0, 0.3),
+        ];
+        let stats = VoltcraftStatistics::new(&mut events);
+        let overall = stats.overall_stats().unwrap();
+        assert_close(overall.stats.total_active_power, 2.1 / 60.0);
+        assert_close(overall.stats.avg_active_power, 2.1 / 4.0);
+        assert_close(overall.stats.total_apparent_power, 2.1 * 1.25 / 60.0);
+        assert_eq!(overall.stats.max_active_power.power, 1.2);
+        assert_eq!(overall.stats.min_voltage.voltage, 226.0);
+        assert_eq!(overall.stats.max_voltage.voltage, 232.0);
+        assert_close(overall.stats.avg_voltage, 229.0);
+        assert_eq!(overall.stats.total_duration, Duration::minutes(721));
+        // Less than a day of readings
+        assert!(overall.avg_daily_power_consumption.is_none());
+
+        let daily = stats.daily_stats();
+        assert_eq!(daily.len(), 2);
+        assert_close(daily[0].stats.total_active_power, 1.8 / 60.0);
+        assert_eq!(daily[0].stats.total_duration, Duration::minutes(3));
+        assert_close(daily[1].stats.avg_voltage, 226.0);
+
+        let hourly = stats.hourly_stats();
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[0].hour, at(0));
+        assert_eq!(hourly[1].hour, at(720));
+
+        // A single reading stands for a minute
+        let mut events = steady([0], 0.6);
+        let overall = VoltcraftStatistics::new(&mut events)
+            .overall_stats()
+            .unwrap();
+        assert_close(overall.stats.total_active_power, 0.01);
+        assert_eq!(overall.stats.total_duration, Duration::minutes(1));
+        // Over more than a day, the consumption is projected to a day: 0.6kW all day long
+        let mut events = steady(0..1500, 0.6);
+        let overall = VoltcraftStatistics::new(&mut events)
+            .overall_stats()
+            .unwrap();
+        assert_close(
+            overall.avg_daily_power_consumption.unwrap(),
+            14.4 * 1500.0 / 1499.0,
+        );
+        // Files with only a data block header
+        assert!(VoltcraftStatistics::new(&mut Vec::new())
+            .overall_stats()
+            .is_none());
+    }
+
+    #[test]
+    fn hourly_stats_drifting_clock() {
+        // A logger clock gaining a minute a day, set right at noon: the corrected readings fall
+        // off the whole minute but still group by the hour
+        let correction =
+            TimeCorrection::from_references((at(0), at(0)), (at(1441), at(1440))).unwrap();
+        let mut events = (0..180)
+            .map(|m| {
+                let mut e = event(m, 230.0, 0.1);
+                e.timestamp = correction.apply(e.timestamp);
+                e
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(events[100].timestamp, at(100) - Duration::seconds(4));
+        let hourly = VoltcraftStatistics::new(&mut events).hourly_stats();
+        assert_eq!(hourly.len(), 3);
+        assert_eq!(hourly[1].hour, at(60));
+        assert_eq!(hourly[2].hour, at(120));
+    }
+
+    #[test]
+    fn deadband_totals() {
+        let mut raw = vec![
+            event(0, 230.0, 0.001),
+            event(1, 230.0, 0.002),
+            event(2, 230.0, 0.5),
+        ];
+        let mut filtered = raw.clone();
+        assert_eq!(apply_deadband(&mut filtered, 0.003), 2);
+        let raw_total = VoltcraftStatistics::new(&mut raw)
+            .overall_stats()
+            .unwrap()
+            .stats
+            .total_active_power;
+        let filtered_total = VoltcraftStatistics::new(&mut filtered)
+            .overall_stats()
+            .unwrap()
+            .stats
+            .total_active_power;
+        assert_close(raw_total, 0.503 / 60.0);
+        assert_close(filtered_total, 0.5 / 60.0);
+    }
+
+    #[test]
+    fn consistency() {
+        let mut events = vec![
+            event(0, 230.0, 0.5),
+            // P > S
+            PowerEvent {
+                power: 0.7,
+                ..event(1, 230.0, 0.5)
+            },
+            // S 10% above V*I
+            PowerEvent {
+                apparent_power: 0.5 * 1.25 * 1.1,
+                ..event(2, 230.0, 0.5)
+            },
+            // S 0.5% above V*I, within the tolerance
+            PowerEvent {
+                apparent_power: 0.5 * 1.25 * 1.005,
+                ..event(3, 230.0, 0.5)
+            },
+        ];
+        let stats = VoltcraftStatistics::new(&mut events);
+        let info = stats.consistency_stats(0.01);
+        assert_eq!(info.checked_count, 4);
+        assert_eq!(info.active_exceeds_apparent.len(), 1);
+        assert_eq!(info.active_exceeds_apparent[0].timestamp, at(1));
+        assert_eq!(info.apparent_mismatch.len(), 1);
+        assert_eq!(info.apparent_mismatch[0].timestamp, at(2));
+        // A looser tolerance lets it pass
+        assert!(stats.consistency_stats(0.2).apparent_mismatch.is_empty());
+
+        // Without any load, S = V*I = 0 is no mismatch
+        let mut events = steady([0], 0.0);
+        let info = VoltcraftStatistics::new(&mut events).consistency_stats(0.01);
+        assert_eq!(info.checked_count, 1);
+        assert!(info.apparent_mismatch.is_empty());
+    }
+
+    fn coverage(file: &str, start: i64, end: i64) -> FileCoverage {
+        FileCoverage {
+            file: String::from(file),
+            start: at(start),
+            end: at(end),
+        }
+    }
+
+    #[test]
+    fn file_gaps() {
+        let files = [
+            coverage("C.BIN", 300, 400),
+            coverage("A.BIN", 0, 100),
+            // Overlapping A, or within it
+            coverage("B.BIN", 90, 200),
+            coverage("D.BIN", 10, 20),
+            // Right after C
+            coverage("E.BIN", 401, 500),
+        ];
+        let gaps = compute_file_gaps(&files);
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(
+            (gaps[0].previous_file.as_str(), gaps[0].next_file.as_str()),
+            ("B.BIN", "C.BIN")
+        );
+        assert_eq!(gaps[0].timestamp, at(201));
+        assert_eq!(gaps[0].duration, Duration::minutes(99));
+
+        // The same download read twice leaves no gap
+        let files = [coverage("A.BIN", 0, 100), coverage("A.BIN", 0, 100)];
+        assert!(compute_file_gaps(&files).is_empty());
+    }
+
+    #[test]
+    fn correlation() {
+        // The voltage sags by 4 V per kW of load
+        let mut events = [0.0, 0.5, 1.0, 2.0]
+            .iter()
+            .enumerate()
+            .map(|(m, &p)| event(m as i64, 232.0 - 4.0 * p, p))
+            .collect();
+        let info = VoltcraftStatistics::new(&mut events).correlation_stats();
+        assert_eq!(info.sample_count, 4);
+        assert_close(info.coefficient.unwrap(), -1.0);
+        assert_close(info.slope.unwrap(), -4.0);
+
+        // The voltage moving on its own
+        let mut events = vec![
+            event(0, 230.0, 1.0),
+            event(1, 226.0, 0.0),
+            event(2, 226.0, 1.0),
+            event(3, 230.0, 0.0),
+        ];
+        let info = VoltcraftStatistics::new(&mut events).correlation_stats();
+        assert_close(info.coefficient.unwrap(), 0.0);
+        assert_close(info.slope.unwrap(), 0.0);
+
+        // A steady load gives no line to fit
+        let mut events = steady(0..5, 0.5);
+        let info = VoltcraftStatistics::new(&mut events).correlation_stats();
+        assert!(info.coefficient.is_none() && info.slope.is_none());
+        // A steady voltage whatever the load: no correlation to speak of, but a flat slope
+        let mut events = vec![event(0, 230.0, 0.5), event(1, 230.0, 1.5)];
+        let info = VoltcraftStatistics::new(&mut events).correlation_stats();
+        assert!(info.coefficient.is_none());
+        assert_close(info.slope.unwrap(), 0.0);
+    }
+
+    #[test]
+    fn flicker() {
+        let mut events = vec![
+            event(0, 230.0, 0.5),
+            event(1, 236.0, 0.5),
+            event(2, 229.0, 0.5),
+            event(3, 230.0, 0.5),
+            // After a gap, which is not a change
+            event(10, 240.0, 0.5),
+            event(11, 234.0, 0.5),
+            event(12, 234.0, 0.5),
+        ];
+        let info = VoltcraftStatistics::new(&mut events).flicker_stats(5.0);
+        assert_eq!(info.compared_count, 5);
+        assert_eq!(info.minute_count, 3);
+        let streaks = info
+            .worst_streaks
+            .iter()
+            .map(|s| (s.timestamp, s.minutes, s.max_change))
+            .collect::<Vec<_>>();
+        assert_eq!(streaks, [(at(1), 2, 7.0), (at(11), 1, 6.0)]);
+
+        // A change of just the threshold isn't flicker
+        let mut events = vec![event(0, 230.0, 0.5), event(1, 235.0, 0.5)];
+        let info = VoltcraftStatistics::new(&mut events).flicker_stats(5.0);
+        assert_eq!((info.compared_count, info.minute_count), (1, 0));
+        assert!(info.worst_streaks.is_empty());
+    }
+
+    #[test]
+    fn distortion() {
+        // S = 1.25 P on the first day, 1.5 P on the next
+        let mut events = vec![
+            event(0, 230.0, 0.5),
+            event(1, 230.0, 1.0),
+            PowerEvent {
+                apparent_power: 0.75,
+                ..event(1440, 230.0, 0.5)
+            },
+        ];
+        let stats = VoltcraftStatistics::new(&mut events);
+        let daily = stats.daily_stats();
+        assert_close(daily[0].stats.distortion_proxy().unwrap(), 0.2);
+        assert_close(daily[1].stats.distortion_proxy().unwrap(), 1.0 / 3.0);
+        // Up by 2/15 a day
+        assert_close(distortion_trend(&daily).unwrap(), 4.0);
+
+        // No load, no apparent power to compare with; and a single day makes no trend
+        let mut events = steady([0], 0.0);
+        let daily = VoltcraftStatistics::new(&mut events).daily_stats();
+        assert!(daily[0].stats.distortion_proxy().is_none());
+        assert!(distortion_trend(&daily[..1]).is_none());
+    }
+
+    #[test]
+    fn ups_runtime() {
+        // 1kW for an hour, 2kW for the last minute, then a blackout of two hours
+        let mut events = (0..60)
+            .map(|m| event(m, 230.0, if m == 59 { 2.0 } else { 1.0 }))
+            .chain([event(180, 230.0, 1.0)])
+            .collect::<Vec<_>>();
+        let ups = VoltcraftStatistics::new(&mut events).ups_runtime(3.0, 0.9);
+        assert_eq!(ups.blackouts.len(), 1);
+        let blackout = &ups.blackouts[0];
+        assert_close(blackout.load, 61.0 / 60.0);
+        assert_eq!(
+            blackout.runtime,
+            Some(Duration::seconds((2.7 / (61.0 / 60.0) * 3600.0) as i64))
+        );
+        assert!(blackout.carried);
+        assert_eq!(ups.carried_count, 1);
+        // A smaller battery doesn't last
+        let ups = VoltcraftStatistics::new(&mut events).ups_runtime(1.0, 0.9);
+        assert!(!ups.blackouts[0].carried);
+        assert_eq!(ups.carried_count, 0);
+
+        // A single reading in the hour before the blackout gives the load; without load, there
+        // is no runtime to speak of
+        let mut events = steady([0, 120], 0.5);
+        let ups = VoltcraftStatistics::new(&mut events).ups_runtime(1.0, 1.0);
+        assert_close(ups.blackouts[0].load, 0.5);
+        assert_eq!(ups.blackouts[0].runtime, Some(Duration::hours(2)));
+        let mut events = steady([0, 120], 0.0);
+        let ups = VoltcraftStatistics::new(&mut events).ups_runtime(1.0, 1.0);
+        assert_eq!(ups.blackouts[0].runtime, None);
+        assert!(ups.blackouts[0].carried);
+    }
+
+    #[test]
+    fn blackout_stats() {
+        // Blackouts of 20 minutes on Thursday at 12:10, and of 79 minutes on Friday at 00:01
+        let mut events = steady((0..10).chain(30..=720).chain([800]), 1.0);
+        let info = VoltcraftStatistics::new(&mut events).blackout_stats();
+        assert_eq!(info.blackout_count, 2);
+        assert_eq!(info.total_blackout_duration, Duration::minutes(99));
+        assert_eq!(info.blackouts[0].timestamp, at(10));
+        assert_eq!(info.blackouts[1].duration, Duration::minutes(79));
+        assert_eq!((info.by_hour[12], info.by_hour[0]), (1, 1));
+        assert_eq!(info.by_hour.iter().sum::<usize>(), 2);
+        assert_eq!(info.by_weekday, [0, 0, 0, 1, 1, 0, 0]);
+        // 801 minutes, 702 of them powered
+        assert_eq!(info.mtbf, Some(Duration::minutes(351)));
+
+        // Readings a minute apart leave no gap, and no time between failures
+        let mut events = steady(0..60, 1.0);
+        let info = VoltcraftStatistics::new(&mut events).blackout_stats();
+        assert_eq!(info.blackout_count, 0);
+        assert_eq!(info.mtbf, None);
+    }
+
+    #[test]
+    fn reactive_stats() {
+        // An hour at 1kW and cos phi 0.8 on Thursday, then half an hour at 2kW and cos phi 1 on
+        // Friday, both from noon
+        let mut events = steady(0..60, 1.0)
+            .into_iter()
+            .chain((1440..1470).map(|m| PowerEvent {
+                apparent_power: 2.0,
+                power_factor: 1.0,
+                ..event(m, 230.0, 2.0)
+            }))
+            .collect::<Vec<_>>();
+        let info = VoltcraftStatistics::new(&mut events).reactive_stats();
+        assert_eq!(info.total.readings, 90);
+        assert_close(info.total.active_energy, 2.0);
+        assert_close(info.total.reactive_energy, 0.75);
+        assert_close(info.total.apparent_energy, 2.25);
+        assert_close(info.total.tan_phi().unwrap(), 0.375);
+        assert_eq!(info.daily.len(), 2);
+        assert_close(info.daily[0].load.reactive_share().unwrap(), 0.6);
+        assert_close(info.daily[1].load.reactive_share().unwrap(), 0.0);
+        // The share dropped by 0.6 in a day
+        assert_close(info.trend.unwrap(), -18.0);
+        assert_eq!(info.heatmap[3][12].readings, 60);
+        assert_eq!(info.heatmap[4][12].readings, 30);
+        let (day, hour, power) = info.peak().unwrap();
+        assert_eq!((day, hour), (3, 12));
+        assert_close(power, 0.75);
+
+        // Without any load, there is no share nor tan phi, only no reactive power
+        let mut events = steady(0..10, 0.0);
+        let info = VoltcraftStatistics::new(&mut events).reactive_stats();
+        assert_eq!(info.total.reactive_share(), None);
+        assert_eq!(info.total.tan_phi(), None);
+        assert_eq!(info.total.avg_reactive_power(), Some(0.0));
+        assert_eq!(ReactiveLoad::default().avg_reactive_power(), None);
+    }
+
+    #[test]
+    fn robust_averages() {
+        let values = [3.0, 1.0, 100.0, 2.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0];
+        assert_eq!(RobustAverage::Median.of(&mut values.clone()), Some(5.5));
+        assert_eq!(
+            RobustAverage::Median.of(&mut values[..5].to_vec()),
+            Some(3.0)
+        );
+        // 10% of 10 values: the lowest and the highest cut off
+        assert_eq!(
+            RobustAverage::TrimmedMean(10.0).of(&mut values.clone()),
+            Some(5.5)
+        );
+        assert_close(
+            RobustAverage::TrimmedMean(0.0)
+                .of(&mut values.clone())
+                .unwrap(),
+            14.5,
+        );
+
+        assert_eq!("median".parse(), Ok(RobustAverage::Median));
+        assert_eq!("Trimmed".parse(), Ok(RobustAverage::TrimmedMean(10.0)));
+        assert_eq!("trimmed:25%".parse(), Ok(RobustAverage::TrimmedMean(25.0)));
+        for invalid in ["", "mean", "trimmed:50", "trimmed:-1", "median:10"] {
+            assert!(invalid.parse::<RobustAverage>().is_err(), "{}", invalid);
+        }
+        for average in [RobustAverage::Median, RobustAverage::TrimmedMean(2.5)] {
+            assert_eq!(average.to_string().parse(), Ok(average));
+        }
+
+        // Nothing to average; and a cut that rounds down to no value at all
+        assert_eq!(RobustAverage::Median.of(&mut []), None);
+        assert_eq!(
+            RobustAverage::TrimmedMean(10.0).of(&mut [1.0, 2.0, 6.0]),
+            Some(3.0)
+        );
+    }
+
+    #[test]
+    fn robust_stats() {
+        // A spike of 3kW in a day at 0.1kW, and a second day at 0.2kW
+        let mut events = (0..5)
+            .map(|m| event(m, 230.0, if m == 2 { 3.0 } else { 0.1 }))
+            .chain((1440..1443).map(|m| event(m, 231.0, 0.2)))
+            .collect::<Vec<_>>();
+        let info = VoltcraftStatistics::new(&mut events).robust_stats(RobustAverage::Median);
+        assert_eq!(info.average, RobustAverage::Median);
+        assert_eq!(info.daily.len(), 2);
+        assert_eq!(info.daily[0].date, at(0).date_naive());
+        assert_eq!((info.daily[0].power, info.daily[0].voltage), (0.1, 230.0));
+        assert_eq!((info.daily[1].power, info.daily[1].voltage), (0.2, 231.0));
+    }
+}
@@ -0,0 +1,192 @@
+use chrono::{DateTime, FixedOffset, Timelike};
+use std::fmt;
+use std::str::FromStr;
+
+// Hours of the day in which a cheaper off-peak (night) rate applies, possibly spanning midnight
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OffPeakHours {
+    pub start: u32, // first off-peak hour (0-23)
+    pub end: u32,   // first peak hour after the off-peak period (0-23)
+}
+
+impl OffPeakHours {
+    pub fn contains(&self, timestamp: DateTime<FixedOffset>) -> bool {
+        let hour = timestamp.hour();
+        if self.start <= self.end {
+            hour >= self.start && hour < self.end
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl FromStr for OffPeakHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hours = s
+            .split_once('-')
+            .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)));
+        match hours {
+            Some((start, end)) if start < 24 && end < 24 && start != end => {
+                Ok(OffPeakHours { start, end })
+            }
+            _ => Err(format!(
+                "Invalid off-peak hours '{}' (expected <start>-<end>, e.g. 22-6)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OffPeakHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+// Time-of-use tariff with a peak rate and a cheaper off-peak rate
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeOfUseTariff {
+    pub peak_price: f64,     // per kWh
+    pub off_peak_price: f64, // per kWh
+    pub off_peak_hours: OffPeakHours,
+}
+
+impl TimeOfUseTariff {
+    pub fn price_at(&self, timestamp: DateTime<FixedOffset>) -> f64 {
+        if self.off_peak_hours.contains(timestamp) {
+            self.off_peak_price
+        } else {
+            self.peak_price
+        }
+    }
+}
+
+// Period over which the utility meters and rounds the energy it bills
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BillingPeriod {
+    Day,
+    Month,
+}
+
+// How the energy of a billing period is brought to a multiple of the metering step
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoundingMode {
+    Nearest, // halves up
+    Up,
+    Down,
+}
+
+// Rounding rules of the billed energy, e.g. whole kWh per day rounded to the nearest, so that a
+// simulated bill matches the one of the utility rather than the raw totals
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BillingRounding {
+    pub period: BillingPeriod,
+    pub mode: RoundingMode,
+    pub step: f64, // kWh
+}
+
+impl BillingRounding {
+    // The energy (kWh) of a billing period as billed
+    pub fn round(&self, energy: f64) -> f64 {
+        // The sums of the readings are off by rounding errors, which must not push a whole number
+        // of steps to the next one
+        let steps = (energy / self.step * 1e6).round() / 1e6;
+        let steps = match self.mode {
+            RoundingMode::Nearest => steps.round(),
+            RoundingMode::Up => steps.ceil(),
+            RoundingMode::Down => steps.floor(),
+        };
+        steps * self.step
+    }
+}
+
+impl FromStr for BillingRounding {
+    type Err = String;
+
+    // <period>:<mode>[:<step kWh>], e.g. day:nearest or month:down:0.1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid billing rounding '{}' (expected <day|month>:<nearest|up|down>[:<kWh>])",
+                s
+            )
+        };
+        let lowercase = s.trim().to_ascii_lowercase();
+        let mut parts = lowercase.split(':');
+        let period = match parts.next() {
+            Some("day") => BillingPeriod::Day,
+            Some("month") => BillingPeriod::Month,
+            _ => return Err(invalid()),
+        };
+        let mode = match parts.next() {
+            Some("nearest") => RoundingMode::Nearest,
+            Some("up") => RoundingMode::Up,
+            Some("down") => RoundingMode::Down,
+            _ => return Err(invalid()),
+        };
+        let step = match parts.next() {
+            Some(step) => step.parse::<f64>().map_err(|_| invalid())?,
+            None => 1.0,
+        };
+        if parts.next().is_some() || !(step > 0.0 && step.is_finite()) {
+            return Err(invalid());
+        }
+        Ok(BillingRounding { period, mode, step })
+    }
+}
+
+impl fmt::Display for BillingRounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let period = match self.period {
+            BillingPeriod::Day => "day",
+            BillingPeriod::Month => "month",
+        };
+        let mode = match self.mode {
+            RoundingMode::Nearest => "nearest",
+            RoundingMode::Up => "up",
+            RoundingMode::Down => "down",
+        };
+        write!(f, "{}:{}:{}", period, mode, self.step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn off_peak_hours() {
+        let zone = FixedOffset::east_opt(3 * 3600).unwrap();
+        let at = |hour| zone.with_ymd_and_hms(2014, 9, 11, hour, 30, 0).unwrap();
+        let night = "22-6".parse::<OffPeakHours>().unwrap();
+        assert!(night.contains(at(23)) && night.contains(at(0)) && night.contains(at(5)));
+        assert!(!night.contains(at(6)) && !night.contains(at(21)));
+        let afternoon = "13-16".parse::<OffPeakHours>().unwrap();
+        assert!(afternoon.contains(at(13)) && !afternoon.contains(at(16)));
+        for invalid in ["22", "22-22", "24-6", "a-b"] {
+            assert!(invalid.parse::<OffPeakHours>().is_err());
+        }
+    }
+
+    #[test]
+    fn billing_rounding() {
+        let rounding = "day:nearest".parse::<BillingRounding>().unwrap();
+        assert_eq!((rounding.round(2.5), rounding.round(2.49)), (3.0, 2.0));
+        // Not pushed to the next kWh by the rounding errors of the sums
+        let rounding = "DAY:UP".parse::<BillingRounding>().unwrap();
+        assert_eq!(
+            (rounding.round(3.0000000001), rounding.round(3.01)),
+            (3.0, 4.0)
+        );
+        let rounding = "month:down:0.1".parse::<BillingRounding>().unwrap();
+        assert_eq!(rounding.period, BillingPeriod::Month);
+        assert!((rounding.round(12.39) - 12.3).abs() < 1e-9);
+        assert_eq!(rounding.to_string().parse(), Ok(rounding));
+        for invalid in ["", "day", "week:up", "day:even", "day:up:0", "day:up:1:2"] {
+            assert!(invalid.parse::<BillingRounding>().is_err(), "{}", invalid);
+        }
+    }
+}
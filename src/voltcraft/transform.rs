@@ -0,0 +1,782 @@
+use crate::voltcraft::data::PowerEvent;
+use chrono::{DateTime, Days, Duration, FixedOffset, NaiveDate, NaiveTime};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Which file to keep readings from when several files cover the same minutes (the logger
+// rewrote part of its memory after wrapping around)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    Newer, // the file downloaded last, i.e. the last one in name order
+    Older, // the file downloaded first
+}
+
+impl FromStr for OverlapPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "newer" => Ok(OverlapPolicy::Newer),
+            "older" => Ok(OverlapPolicy::Older),
+            _ => Err(format!(
+                "Invalid overlap policy '{}' (expected newer or older)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OverlapPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverlapPolicy::Newer => write!(f, "newer"),
+            OverlapPolicy::Older => write!(f, "older"),
+        }
+    }
+}
+
+// Which reading to keep for a minute read more than once once the files are merged (after the
+// overlapping files were trimmed, see OverlapPolicy), when the readings differ. Averages and
+// failures also apply to the differing readings of overlapping files, which are trimmed otherwise.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DedupPolicy {
+    KeepFirst,      // the first in merge order, i.e. of the file first in name order
+    KeepLast,       // the last in merge order
+    Average,        // the average of the readings of the minute
+    FailOnConflict, // stop: the data can't be trusted
+}
+
+impl FromStr for DedupPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "first" => Ok(DedupPolicy::KeepFirst),
+            "last" => Ok(DedupPolicy::KeepLast),
+            "average" => Ok(DedupPolicy::Average),
+            "fail" => Ok(DedupPolicy::FailOnConflict),
+            _ => Err(format!(
+                "Invalid deduplication policy '{}' (expected first, last, average or fail)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DedupPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DedupPolicy::KeepFirst => write!(f, "first"),
+            DedupPolicy::KeepLast => write!(f, "last"),
+            DedupPolicy::Average => write!(f, "average"),
+            DedupPolicy::FailOnConflict => write!(f, "fail"),
+        }
+    }
+}
+
+// A minute read more than once with different values: two of its readings, in file order (their
+// sources tell the files)
+#[derive(Debug, Error, Copy, Clone)]
+#[error("conflicting readings for {}: {}V {}A and {}V {}A", .first.timestamp.format("%Y-%m-%d %H:%M"), .first.voltage, .first.current, .second.voltage, .second.current)]
+pub struct Conflict {
+    pub first: PowerEvent,
+    pub second: PowerEvent,
+}
+
+// What trimming overlapping files or deduplicating the merged readings dropped
+#[derive(Debug, Clone, Default)]
+pub struct Deduplication {
+    pub dropped: Vec<Overlap>,    // by pair of files
+    pub conflicts: Vec<Conflict>, // readings dropped that differ from those kept
+}
+
+// Readings of one file superseded by the readings of another file for the same minutes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    pub superseded: usize, // index of the file whose readings were dropped
+    pub by: usize,         // index of the file whose readings were kept
+    pub count: usize,      // readings dropped
+    pub differing: usize,  // dropped readings whose values differ from the kept ones
+}
+
+// Zero out readings whose active power is below the given threshold (kW), which filters the
+// ghost readings reported by the logger when nothing is plugged in. Returns the number of zeroed readings.
+pub fn apply_deadband(power_events: &mut [PowerEvent], threshold: f64) -> usize {
+    let mut count = 0;
+    for pe in power_events.iter_mut() {
+        if pe.power.abs() < threshold && (pe.power != 0.0 || pe.apparent_power != 0.0) {
+            pe.current = 0.0;
+            pe.power = 0.0;
+            pe.apparent_power = 0.0;
+            count += 1;
+        }
+    }
+    count
+}
+
+// Days left out of the analyses (e.g. a party or construction work), parsed from <date> or
+// <first date>..<last date>, both included
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExcludedPeriod {
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+}
+
+impl ExcludedPeriod {
+    // Whether a reading falls in the period, going by the logger clock
+    pub fn contains(&self, timestamp: DateTime<FixedOffset>) -> bool {
+        (self.first..=self.last).contains(&timestamp.date_naive())
+    }
+
+    // How much of the time from start to end falls in the period
+    pub fn overlap(&self, start: DateTime<FixedOffset>, end: DateTime<FixedOffset>) -> Duration {
+        let from = self.first.and_time(NaiveTime::MIN).max(start.naive_local());
+        let to = (self.last + Days::new(1))
+            .and_time(NaiveTime::MIN)
+            .min(end.naive_local());
+        (to - from).max(Duration::zero())
+    }
+}
+
+impl FromStr for ExcludedPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, last) = s.split_once("..").unwrap_or((s, s));
+        let date = |d: &str| NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok();
+        match (date(first), date(last)) {
+            (Some(first), Some(last)) if first <= last => Ok(ExcludedPeriod { first, last }),
+            _ => Err(format!(
+                "Invalid period '{}' (expected <date> or <first date>..<last date>, e.g. 2023-12-24..2023-12-26)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ExcludedPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}..{}", self.first, self.last)
+        }
+    }
+}
+
+// Readings dropped for falling in an excluded period
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Exclusion {
+    pub period: ExcludedPeriod,
+    pub readings: usize,
+    pub energy: f64, // kWh
+}
+
+// Drop the readings falling in any of the given periods. Returns what was dropped for each period
+// (a reading in several periods counts for the first).
+pub fn exclude_periods(
+    power_events: &mut Vec<PowerEvent>,
+    periods: &[ExcludedPeriod],
+) -> Vec<Exclusion> {
+    let mut exclusions = periods
+        .iter()
+        .map(|&period| Exclusion {
+            period,
+            readings: 0,
+            energy: 0.0,
+        })
+        .collect::<Vec<_>>();
+    power_events.retain(|e| {
+        match exclusions
+            .iter_mut()
+            .find(|x| x.period.contains(e.timestamp))
+        {
+            Some(exclusion) => {
+                exclusion.readings += 1;
+                exclusion.energy += e.power / 60.0;
+                false
+            }
+            None => true,
+        }
+    });
+    exclusions
+}
+
+// What the minutes missing in a short gap are filled with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GapFill {
+    Interpolate, // each value interpolated linearly between the readings around the gap
+    Zero,        // no power drawn, at the interpolated voltage
+}
+
+// Which gaps between readings to fill (see fill_gaps), parsed from <minutes>[:interpolate|:zero]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GapFilling {
+    pub max_gap: i64, // minutes missing, longer gaps are left alone (e.g. blackouts)
+    pub fill: GapFill,
+}
+
+impl FromStr for GapFilling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid gap filling '{}' (expected <minutes>[:interpolate|:zero], e.g. 5:zero)",
+                s
+            )
+        };
+        let (minutes, fill) = match s.split_once(':') {
+            Some((minutes, fill)) => (minutes, fill),
+            None => (s, "interpolate"),
+        };
+        let fill = match fill.to_ascii_lowercase().as_str() {
+            "interpolate" => GapFill::Interpolate,
+            "zero" => GapFill::Zero,
+            _ => return Err(invalid()),
+        };
+        match minutes.parse::<i64>() {
+            Ok(max_gap) if max_gap > 0 => Ok(GapFilling { max_gap, fill }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for GapFilling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.fill {
+            GapFill::Interpolate => write!(f, "{}:interpolate", self.max_gap),
+            GapFill::Zero => write!(f, "{}:zero", self.max_gap),
+        }
+    }
+}
+
+// Insert a reading for each minute missing in the gaps of chronological readings of at most
+// filling.max_gap minutes, flagged as filled (PowerEvent::filled) and of no source, so that the
+// energy of the minutes lost (e.g. while the logger was unplugged and plugged back) is estimated
+// rather than left out. The filled minutes are no longer seen as blackouts. Returns the number of
+// readings inserted.
+pub fn fill_gaps(power_events: &mut Vec<PowerEvent>, filling: GapFilling) -> usize {
+    let mut events: Vec<PowerEvent> = Vec::with_capacity(power_events.len());
+    let mut count = 0;
+    for next in power_events.drain(..) {
+        if let Some(&last) = events.last() {
+            // Rounded, as the logger clock may have been corrected
+            let missing = ((next.timestamp - last.timestamp).num_seconds() + 30).div_euclid(60) - 1;
+            if (1..=filling.max_gap).contains(&missing) {
+                for minute in 1..=missing {
+                    let t = minute as f64 / (missing + 1) as f64;
+                    let lerp = |value: fn(&PowerEvent) -> f64| {
+                        value(&last) + (value(&next) - value(&last)) * t
+                    };
+                    let event = PowerEvent {
+                        timestamp: last.timestamp + Duration::minutes(minute),
+                        voltage: lerp(|e| e.voltage),
+                        current: lerp(|e| e.current),
+                        power_factor: lerp(|e| e.power_factor),
+                        power: lerp(|e| e.power),
+                        apparent_power: lerp(|e| e.apparent_power),
+                        source: None,
+                        registers: None,
+                        filled: true,
+                    };
+                    events.push(match filling.fill {
+                        GapFill::Interpolate => event,
+                        GapFill::Zero => PowerEvent {
+                            current: 0.0,
+                            power_factor: 0.0,
+                            power: 0.0,
+                            apparent_power: 0.0,
+                            ..event
+                        },
+                    });
+                }
+                count += missing as usize;
+            }
+        }
+        events.push(next);
+    }
+    *power_events = events;
+    count
+}
+
+// Centered rolling mean of chronological readings over a window of the given length: each reading
+// becomes the average of the readings at most half the window away (e.g. the two minutes either
+// side for 5 minutes), which tames the noise of the minute readings when looking at long trends.
+// The windows are cut short by gaps and at both ends rather than padded. The values as stored are
+// dropped, the smoothed readings no longer being any.
+pub fn smooth(power_events: &[PowerEvent], window: Duration) -> Vec<PowerEvent> {
+    let half = window / 2;
+    let values = |e: &PowerEvent| {
+        [
+            e.voltage,
+            e.current,
+            e.power_factor,
+            e.power,
+            e.apparent_power,
+        ]
+    };
+    let mut sums = [0.0; 5];
+    let (mut first, mut last) = (0, 0);
+    let mut smoothed = Vec::with_capacity(power_events.len());
+    for e in power_events {
+        // Slide the window [first, last) along: add the readings coming in, drop those going out
+        while last < power_events.len() && power_events[last].timestamp - e.timestamp <= half {
+            sums.iter_mut()
+                .zip(values(&power_events[last]))
+                .for_each(|(sum, value)| *sum += value);
+            last += 1;
+        }
+        while e.timestamp - power_events[first].timestamp > half {
+            sums.iter_mut()
+                .zip(values(&power_events[first]))
+                .for_each(|(sum, value)| *sum -= value);
+            first += 1;
+        }
+        let n = (last - first) as f64;
+        let [voltage, current, power_factor, power, apparent_power] = sums.map(|sum| sum / n);
+        smoothed.push(PowerEvent {
+            voltage,
+            current,
+            power_factor,
+            power,
+            apparent_power,
+            registers: None,
+            ..*e
+        });
+    }
+    smoothed
+}
+
+// Drop the readings of a file for minutes also covered by a preferred file (files are given in name,
+// i.e. download, order). Returns what was superseded, by pair of files, and each reading superseded
+// by a different one.
+pub fn trim_overlaps(files: &mut [Vec<PowerEvent>], policy: OverlapPolicy) -> Deduplication {
+    // Keeping the reading of the preferred file never fails
+    trim_overlaps_with(files, policy, DedupPolicy::KeepFirst).unwrap_or_else(|_| Deduplication {
+        dropped: Vec::new(),
+        conflicts: Vec::new(),
+    })
+}
+
+// As trim_overlaps, with the readings of the preferred file replaced by the average of the readings
+// of the minute when they differ with DedupPolicy::Average, and failing on the first minute whose
+// readings differ with DedupPolicy::FailOnConflict
+pub fn trim_overlaps_with(
+    files: &mut [Vec<PowerEvent>],
+    policy: OverlapPolicy,
+    dedup: DedupPolicy,
+) -> Result<Deduplication, Box<Conflict>> {
+    let order = match policy {
+        OverlapPolicy::Newer => (0..files.len()).rev().collect::<Vec<_>>(),
+        OverlapPolicy::Older => (0..files.len()).collect::<Vec<_>>(),
+    };
+    let mut kept = HashMap::<DateTime<FixedOffset>, (usize, PowerEvent)>::new();
+    let mut overlaps = BTreeMap::<(usize, usize), Overlap>::new();
+    let mut conflicts = Vec::new();
+    // The differing readings superseded, by minute, to be averaged with the one kept
+    let mut differing = HashMap::<DateTime<FixedOffset>, Vec<(usize, PowerEvent)>>::new();
+    for index in order {
+        files[index].retain(|e| match kept.get(&e.timestamp) {
+            Some((by, k)) if *by != index => {
+                let overlap = overlaps.entry((index, *by)).or_insert(Overlap {
+                    superseded: index,
+                    by: *by,
+                    count: 0,
+                    differing: 0,
+                });
+                overlap.count += 1;
+                if !same_values(e, k) {
+                    overlap.differing += 1;
+                    let (first, second) = if index < *by { (*e, *k) } else { (*k, *e) };
+                    conflicts.push(Conflict { first, second });
+                    differing.entry(e.timestamp).or_default().push((index, *e));
+                }
+                false
+            }
+            _ => true,
+        });
+        for e in &files[index] {
+            kept.entry(e.timestamp).or_insert((index, *e));
+        }
+    }
+    conflicts.sort_by_key(|c| c.first.timestamp);
+    match dedup {
+        DedupPolicy::FailOnConflict if !conflicts.is_empty() => {
+            return Err(Box::new(conflicts[0]));
+        }
+        DedupPolicy::Average => {
+            for (&timestamp, superseded) in &mut differing {
+                let (by, k) = kept[&timestamp];
+                superseded.push((by, k));
+                // In file order, the average being traced back to the first reading
+                superseded.sort_by_key(|(index, _)| *index);
+                let readings = superseded.iter().map(|(_, e)| *e).collect::<Vec<_>>();
+                if let Some(e) = files[by].iter_mut().find(|e| e.timestamp == timestamp) {
+                    *e = average(&readings);
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(Deduplication {
+        dropped: overlaps.into_values().collect(),
+        conflicts,
+    })
+}
+
+// Drop the readings for a minute already read, keeping the first (the readings are in chronological
+// order, e.g. merged). Returns what was dropped, by pair of files: a file with itself for readings
+// repeated within a file, and file 0 for readings of unknown origin.
+pub fn remove_duplicates(power_events: &mut Vec<PowerEvent>) -> Vec<Overlap> {
+    // Keeping the first reading never fails
+    deduplicate(power_events, DedupPolicy::KeepFirst).map_or_else(|_| Vec::new(), |d| d.dropped)
+}
+
+// Keep a single reading for each minute of chronological readings as the policy says, failing on
+// the first minute whose readings differ with DedupPolicy::FailOnConflict. The readings dropped
+// are reported as by remove_duplicates, as superseded by the first reading for an average.
+pub fn deduplicate(
+    power_events: &mut Vec<PowerEvent>,
+    policy: DedupPolicy,
+) -> Result<Deduplication, Box<Conflict>> {
+    let file = |e: &PowerEvent| e.source.map_or(0, |s| s.file);
+    let mut overlaps = BTreeMap::<(usize, usize), Overlap>::new();
+    let mut conflicts = Vec::new();
+    let mut kept = Vec::with_capacity(power_events.len());
+    for minute in power_events.chunk_by(|a, b| a.timestamp == b.timestamp) {
+        if let Some(other) = minute.iter().find(|e| !same_values(e, &minute[0])) {
+            let conflict = Conflict {
+                first: minute[0],
+                second: *other,
+            };
+            if policy == DedupPolicy::FailOnConflict {
+                return Err(Box::new(conflict));
+            }
+            conflicts.push(conflict);
+        }
+        let index = match policy {
+            DedupPolicy::KeepLast => minute.len() - 1,
+            _ => 0,
+        };
+        let k = &minute[index];
+        for (_, e) in minute.iter().enumerate().filter(|(i, _)| *i != index) {
+            let overlap = overlaps.entry((file(e), file(k))).or_insert(Overlap {
+                superseded: file(e),
+                by: file(k),
+                count: 0,
+                differing: 0,
+            });
+            overlap.count += 1;
+            if !same_values(e, k) {
+                overlap.differing += 1;
+            }
+        }
+        kept.push(match policy {
+            DedupPolicy::Average if minute.len() > 1 => average(minute),
+            _ => *k,
+        });
+    }
+    *power_events = kept;
+    Ok(Deduplication {
+        dropped: overlaps.into_values().collect(),
+        conflicts,
+    })
+}
+
+// The average of readings for the same minute, traced back to the first of them (but for the
+// values as stored, which it doesn't have)
+fn average(readings: &[PowerEvent]) -> PowerEvent {
+    let mean = |value: fn(&PowerEvent) -> f64| {
+        readings.iter().map(value).sum::<f64>() / readings.len() as f64
+    };
+    PowerEvent {
+        voltage: mean(|e| e.voltage),
+        current: mean(|e| e.current),
+        power_factor: mean(|e| e.power_factor),
+        power: mean(|e| e.power),
+        apparent_power: mean(|e| e.apparent_power),
+        registers: None,
+        ..readings[0]
+    }
+}
+
+// Whether two readings hold the same measurements (the power is computed from them)
+fn same_values(a: &PowerEvent, b: &PowerEvent) -> bool {
+    a.voltage == b.voltage && a.current == b.current && a.power_factor == b.power_factor
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voltcraft::data::{PowerEvent, SourceId};
+    use crate::voltcraft::stats::VoltcraftStatistics;
+    use crate::voltcraft::transform::{
+        deduplicate, exclude_periods, fill_gaps, remove_duplicates, smooth, trim_overlaps,
+        DedupPolicy, ExcludedPeriod, GapFill, GapFilling, Overlap, OverlapPolicy,
+    };
+    use chrono::{Duration, FixedOffset, TimeZone};
+
+    fn event(minute: i64, voltage: f64) -> PowerEvent {
+        PowerEvent {
+            timestamp: FixedOffset::east_opt(3 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2014, 9, 11, 12, 0, 0)
+                .unwrap()
+                + Duration::minutes(minute),
+            voltage,
+            current: 0.0,
+            power_factor: 0.0,
+            power: 0.0,
+            apparent_power: 0.0,
+            source: None,
+            registers: None,
+            filled: false,
+        }
+    }
+
+    #[test]
+    fn overlaps_prefer_the_policy_source() {
+        let older = vec![event(0, 230.0), event(1, 230.0), event(2, 230.0)];
+        let newer = vec![event(1, 230.0), event(2, 231.0), event(3, 231.0)];
+
+        let mut files = vec![older.clone(), newer.clone()];
+        let trimmed = trim_overlaps(&mut files, OverlapPolicy::Newer);
+        assert_eq!(
+            trimmed.dropped,
+            vec![Overlap {
+                superseded: 0,
+                by: 1,
+                count: 2,
+                differing: 1
+            }]
+        );
+        assert_eq!(files[0].len(), 1);
+        assert_eq!(files[1].len(), 3);
+        // The minute read differently, older file first
+        let conflict = trimmed.conflicts[0];
+        assert_eq!(trimmed.conflicts.len(), 1);
+        assert_eq!(
+            (conflict.first.voltage, conflict.second.voltage),
+            (230.0, 231.0)
+        );
+
+        let mut files = vec![older, newer];
+        let overlaps = trim_overlaps(&mut files, OverlapPolicy::Older).dropped;
+        assert_eq!((overlaps[0].superseded, overlaps[0].by), (1, 0));
+        assert_eq!(files[0].len(), 3);
+        assert_eq!(files[1].len(), 1);
+    }
+
+    #[test]
+    fn duplicates_by_file() {
+        let from = |file, e: PowerEvent| PowerEvent {
+            source: Some(SourceId { file, offset: 0 }),
+            ..e
+        };
+        let mut events = vec![
+            from(0, event(0, 230.0)),
+            from(0, event(0, 230.0)),
+            from(0, event(1, 230.0)),
+            from(1, event(1, 230.0)),
+            from(2, event(1, 231.0)),
+            from(2, event(2, 231.0)),
+        ];
+        let duplicates = remove_duplicates(&mut events);
+        assert_eq!(
+            duplicates,
+            vec![
+                Overlap {
+                    superseded: 0,
+                    by: 0,
+                    count: 1,
+                    differing: 0
+                },
+                Overlap {
+                    superseded: 1,
+                    by: 0,
+                    count: 1,
+                    differing: 0
+                },
+                Overlap {
+                    superseded: 2,
+                    by: 0,
+                    count: 1,
+                    differing: 1
+                },
+            ]
+        );
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn deduplication_policies() {
+        let from = |file, e: PowerEvent| PowerEvent {
+            source: Some(SourceId { file, offset: 0 }),
+            ..e
+        };
+        let events = vec![
+            from(0, event(0, 230.0)),
+            from(0, event(1, 230.0)),
+            from(1, event(1, 232.0)),
+            from(1, event(2, 231.0)),
+        ];
+        let voltages = |policy| {
+            let mut events = events.clone();
+            deduplicate(&mut events, policy).map(|d| {
+                assert_eq!(d.conflicts.len(), 1);
+                assert_eq!(d.dropped[0].differing, 1);
+                events.iter().map(|e| e.voltage).collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            voltages(DedupPolicy::KeepFirst).unwrap(),
+            vec![230.0, 230.0, 231.0]
+        );
+        assert_eq!(
+            voltages(DedupPolicy::KeepLast).unwrap(),
+            vec![230.0, 232.0, 231.0]
+        );
+        assert_eq!(
+            voltages(DedupPolicy::Average).unwrap(),
+            vec![230.0, 231.0, 231.0]
+        );
+        let conflict = voltages(DedupPolicy::FailOnConflict).unwrap_err();
+        assert_eq!(
+            (conflict.first.voltage, conflict.second.voltage),
+            (230.0, 232.0)
+        );
+        assert_eq!(conflict.second.source.unwrap().file, 1);
+
+        // Identical readings aren't conflicts
+        let mut events = vec![event(0, 230.0), event(0, 230.0)];
+        let deduplication = deduplicate(&mut events, DedupPolicy::FailOnConflict).unwrap();
+        assert!(deduplication.conflicts.is_empty());
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn gaps_filled() {
+        let powered = |minute, power| PowerEvent {
+            power,
+            current: power * 4.0,
+            ..event(minute, 230.0 + power)
+        };
+        // Two minutes missing, then a blackout of five
+        let events = vec![powered(0, 1.0), powered(3, 4.0), powered(9, 4.0)];
+        let filling = "2".parse::<GapFilling>().unwrap();
+        assert_eq!(filling.fill, GapFill::Interpolate);
+        let mut filled = events.clone();
+        assert_eq!(fill_gaps(&mut filled, filling), 2);
+        let values = filled
+            .iter()
+            .map(|e| {
+                (
+                    e.timestamp - events[0].timestamp,
+                    e.power,
+                    e.voltage,
+                    e.filled,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                (Duration::minutes(0), 1.0, 231.0, false),
+                (Duration::minutes(1), 2.0, 232.0, true),
+                (Duration::minutes(2), 3.0, 233.0, true),
+                (Duration::minutes(3), 4.0, 234.0, false),
+                (Duration::minutes(9), 4.0, 234.0, false)
+            ]
+        );
+        assert!(filled[1].source.is_none());
+
+        let filling = "5:ZERO".parse::<GapFilling>().unwrap();
+        assert_eq!(filling.to_string(), "5:zero");
+        let mut filled = events.clone();
+        assert_eq!(fill_gaps(&mut filled, filling), 7);
+        // The blackout too, now that it is short enough
+        assert_eq!(filled.len(), 10);
+        assert!(filled[1..3]
+            .iter()
+            .all(|e| e.filled && e.power == 0.0 && e.current == 0.0));
+        assert_eq!((filled[1].voltage, filled[2].voltage), (232.0, 233.0));
+        for invalid in ["", "0", "-1", "5:linear", "five"] {
+            assert!(invalid.parse::<GapFilling>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn periods_excluded() {
+        let day = |days: i64| event(days * 1440, 230.0);
+        let period = "2014-09-12..2014-09-13".parse::<ExcludedPeriod>().unwrap();
+        assert_eq!(period.to_string(), "2014-09-12..2014-09-13");
+        assert_eq!(
+            "2014-09-12".parse::<ExcludedPeriod>().unwrap().to_string(),
+            "2014-09-12"
+        );
+        for invalid in ["", "2014-09-13..2014-09-12", "2014-09-12..", "12.09.2014"] {
+            assert!(invalid.parse::<ExcludedPeriod>().is_err(), "{}", invalid);
+        }
+        // Readings at noon: a day before the period, two in it and two after
+        let mut events = (0..5).map(day).collect::<Vec<_>>();
+        events[1].power = 0.6;
+        let exclusions = exclude_periods(&mut events, &[period]);
+        assert_eq!(
+            (exclusions[0].readings, exclusions[0].energy),
+            (2, 0.6 / 60.0)
+        );
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            period.overlap(events[0].timestamp, events[1].timestamp),
+            Duration::days(2)
+        );
+
+        // The gap left isn't a blackout, nor counted in the time covered
+        let (first, last) = (events[0].timestamp, events[2].timestamp);
+        let stats = VoltcraftStatistics::new(&mut events).excluded(&[period]);
+        assert_eq!(stats.blackout_stats().blackout_count, 1);
+        assert_eq!(stats.span(first, last), Duration::days(2));
+        let stats = VoltcraftStatistics::new(&mut events);
+        assert_eq!(stats.blackout_stats().blackout_count, 2);
+    }
+
+    #[test]
+    fn rolling_mean() {
+        let powered = |minute, power| PowerEvent {
+            power,
+            ..event(minute, 230.0)
+        };
+        // A spike, then a gap of a few minutes
+        let events = [
+            powered(0, 1.0),
+            powered(1, 1.0),
+            powered(2, 4.0),
+            powered(3, 1.0),
+            powered(4, 1.0),
+            powered(8, 3.0),
+            powered(9, 5.0),
+        ];
+        let smoothed = smooth(&events, Duration::minutes(3));
+        let powers = smoothed.iter().map(|e| e.power).collect::<Vec<_>>();
+        assert_eq!(powers, [1.0, 2.0, 2.0, 2.0, 1.0, 4.0, 4.0]);
+        assert!(smoothed.iter().all(|e| e.voltage == 230.0));
+        assert!(smoothed
+            .iter()
+            .zip(&events)
+            .all(|(s, e)| s.timestamp == e.timestamp));
+        // A 5 minute window: the two minutes either side, cut short at the ends and by the gap
+        let smoothed = smooth(&events, Duration::minutes(5));
+        assert_eq!(smoothed[2].power, 8.0 / 5.0);
+        assert_eq!(smoothed[0].power, 2.0);
+        assert_eq!(smoothed[4].power, 2.0);
+        // A minute: the readings as they are
+        let smoothed = smooth(&events, Duration::minutes(1));
+        assert!(smoothed
+            .iter()
+            .zip(&events)
+            .all(|(s, e)| s.power == e.power));
+        assert!(smooth(&[], Duration::minutes(5)).is_empty());
+    }
+}
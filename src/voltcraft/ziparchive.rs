@@ -0,0 +1,85 @@
+use crate::voltcraft::data::{ParseError, VoltcraftData};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use zip::ZipArchive;
+
+// A ZIP archive of data files (as the vendor software exports them), read without extracting it
+pub struct ZipBundle {
+    archive: RefCell<ZipArchive<File>>,
+}
+
+impl ZipBundle {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<ZipBundle> {
+        let archive = ZipArchive::new(File::open(path)?)?;
+        Ok(ZipBundle {
+            archive: RefCell::new(archive),
+        })
+    }
+
+    // Names of the files of the archive, subfolders included, in name (download) order. Like the
+    // files of an input folder, they are only told apart from other files by decoding them.
+    pub fn data_files(&self) -> Vec<String> {
+        let archive = self.archive.borrow();
+        let mut names = archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    // Read one of the files, like VoltcraftData::from_file
+    pub fn read(&self, name: &str, max_size: u64) -> Result<VoltcraftData, ParseError> {
+        let mut archive = self.archive.borrow_mut();
+        let file = archive.by_name(name).map_err(|_| ParseError::Unreadable)?;
+        if file.size() > max_size {
+            return Err(ParseError::TooLarge {
+                size: file.size(),
+                limit: max_size,
+            });
+        }
+        VoltcraftData::from_reader(file, max_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn zip_bundle() {
+        let data = crate::voltcraft::sample::SAMPLE_FILE;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default();
+        zip.start_file("logger/A04FC8D3.BIN", options).unwrap();
+        zip.write_all(data).unwrap();
+        zip.add_directory("logger/empty/", options).unwrap();
+        zip.start_file("A04FC8D2.BIN", options).unwrap();
+        zip.write_all(data).unwrap();
+        zip.start_file("README.TXT", options).unwrap();
+        zip.write_all(b"not a data file").unwrap();
+        zip.finish().unwrap();
+
+        let bundle = ZipBundle::open(&path).unwrap();
+        let files = bundle.data_files();
+        assert_eq!(files, ["A04FC8D2.BIN", "README.TXT", "logger/A04FC8D3.BIN"]);
+        assert_eq!(bundle.read(&files[2], 1024).unwrap().as_bytes(), data);
+        assert_eq!(
+            bundle.read(&files[1], 1024).err(),
+            Some(ParseError::InvalidHeader)
+        );
+        assert!(matches!(
+            bundle.read(&files[0], 16),
+            Err(ParseError::TooLarge { limit: 16, .. })
+        ));
+        assert!(ZipBundle::open(dir.path().join("missing.zip")).is_err());
+    }
+}